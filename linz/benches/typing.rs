@@ -0,0 +1,38 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use linz::{lang::Ast, parser::parse_expr, typing};
+
+/// n 個の un 型の変数を定義し, else 節に if 式を n 段ネストしたプログラムを生成
+///
+/// ```text
+/// let x0 : un bool = un true;
+/// ...
+/// if x0 { un true } else { if x1 { un true } else { ... } }
+/// ```
+fn if_chain(n: usize) -> Ast {
+    let mut src = String::new();
+    for i in 0..n {
+        src.push_str(&format!("let x{i} : un bool = un true;\n"));
+    }
+    for i in 0..n {
+        src.push_str(&format!("if x{i} {{ un true }} else {{ "));
+    }
+    src.push_str("un false");
+    src.push_str(&" }".repeat(n));
+
+    let (_, expr) = parse_expr(&src).unwrap();
+    expr
+}
+
+fn typing_if(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Typing If");
+
+    for n in [50, 100, 200, 400] {
+        let expr = if_chain(n);
+        g.bench_with_input(BenchmarkId::from_parameter(n), &expr, |b, expr| {
+            b.iter(|| typing::typing(expr, &mut typing::TypeEnv::new(), 0).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, typing_if);
+criterion_main!(benches);
@@ -0,0 +1,134 @@
+//! コマンドライン引数の解析
+//!
+//! ```text
+//! linz [--parse-only | --type-only] [--derivation] [--width=<N>] <FILE>
+//! ```
+
+/// どの段階まで処理を行うか
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stage {
+    Parse, // パースのみ行い, AST を表示
+    Type,  // 型付けまで行う
+    All,   // すべての段階を行う
+}
+
+/// 導出木を出力する際の 1 行の最大文字数のデフォルト値
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// コマンドライン引数
+#[derive(Debug, PartialEq, Eq)]
+pub struct Args {
+    pub stage: Stage,
+    pub derivation: bool, // 型付けの導出木を表示するか
+    pub width: usize,     // 導出木の 1 行の最大文字数
+    pub file: String,
+}
+
+/// 使い方
+pub fn usage(prog: &str) -> String {
+    format!(
+        r#"使い方: {prog} [--parse-only | --type-only] [--derivation] [--width=<N>] <FILE>
+  --parse-only : パースのみ行い, AST をコメントも含めて整形して表示
+  --type-only  : 型付けまで行う
+  --derivation : 型付けの導出木を表示
+  --width=<N>  : 導出木の 1 行の最大文字数 (デフォルト: {DEFAULT_WIDTH})
+例: cargo run codes/ex1.lin"#
+    )
+}
+
+/// コマンドライン引数を解析
+/// args にはプログラム名を除いた引数を指定
+pub fn parse<I>(args: I) -> Result<Args, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut stage = Stage::All;
+    let mut derivation = false;
+    let mut width = DEFAULT_WIDTH;
+    let mut file = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "--parse-only" => stage = Stage::Parse,
+            "--type-only" => stage = Stage::Type,
+            "--derivation" => derivation = true,
+            opt if opt.starts_with("--width=") => {
+                width = match opt["--width=".len()..].parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => return Err(format!("不正な幅: {opt}")),
+                };
+            }
+            opt if opt.starts_with("--") => return Err(format!("不明なオプション: {opt}")),
+            _ => {
+                if file.is_some() {
+                    return Err("ファイルは 1 つだけ指定してください".to_string());
+                }
+                file = Some(arg);
+            }
+        }
+    }
+
+    match file {
+        Some(file) => Ok(Args {
+            stage,
+            derivation,
+            width,
+            file,
+        }),
+        None => Err("ファイル名が指定されていない".to_string()),
+    }
+}
+#[cfg(test)]
+mod parse {
+    use super::*;
+
+    fn args(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(args(&["a.lin"])),
+            Ok(Args {
+                stage: Stage::All,
+                derivation: false,
+                width: DEFAULT_WIDTH,
+                file: "a.lin".to_string()
+            })
+        );
+        assert_eq!(
+            parse(args(&["--parse-only", "a.lin"])),
+            Ok(Args {
+                stage: Stage::Parse,
+                derivation: false,
+                width: DEFAULT_WIDTH,
+                file: "a.lin".to_string()
+            })
+        );
+        assert_eq!(
+            parse(args(&["a.lin", "--type-only"])),
+            Ok(Args {
+                stage: Stage::Type,
+                derivation: false,
+                width: DEFAULT_WIDTH,
+                file: "a.lin".to_string()
+            })
+        );
+        assert_eq!(
+            parse(args(&["--derivation", "--width=40", "a.lin"])),
+            Ok(Args {
+                stage: Stage::All,
+                derivation: true,
+                width: 40,
+                file: "a.lin".to_string()
+            })
+        );
+        assert!(parse(args(&["--width=0", "a.lin"])).is_err());
+        assert!(parse(args(&["--width=x", "a.lin"])).is_err());
+        assert!(parse(args(&[])).is_err());
+        assert!(parse(args(&["--parse-only"])).is_err());
+        assert!(parse(args(&["a.lin", "b.lin"])).is_err());
+        assert!(parse(args(&["--foo", "a.lin"])).is_err());
+    }
+}
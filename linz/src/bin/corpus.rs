@@ -0,0 +1,64 @@
+//! 小さなプログラムを列挙し, 型付けの結果を表として出力する
+//!
+//! ```text
+//! cargo run -p linz --bin linz-corpus -- [--size=<N>] [--format=md|csv]
+//! ```
+//!
+//! 大きさが N 以下のプログラムをすべて列挙し, 型付けに成功したものは型を,
+//! 失敗したものは理由を標準出力に出力する。詳細は [linz::corpus] を参照。
+use linz::{corpus, lang::ExprArena};
+use std::{env, process};
+
+/// 列挙するプログラムの大きさの上限のデフォルト値
+const DEFAULT_SIZE: usize = 3;
+
+/// 出力形式
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Format {
+    Markdown,
+    Csv,
+}
+
+fn main() {
+    let (size, format) = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n使い方: linz-corpus [--size=<N>] [--format=md|csv]");
+            process::exit(2);
+        }
+    };
+
+    let mut arena = ExprArena::new();
+    let cases: Vec<_> = corpus::enumerate(size, &mut arena)
+        .into_iter()
+        .map(|id| corpus::classify(&arena, id))
+        .collect();
+    match format {
+        Format::Markdown => print!("{}", corpus::to_markdown(&cases)),
+        Format::Csv => print!("{}", corpus::to_csv(&cases)),
+    }
+}
+
+/// コマンドライン引数を解析し, (大きさの上限, 出力形式) を返す
+fn parse_args<I>(args: I) -> Result<(usize, Format), String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut size = DEFAULT_SIZE;
+    let mut format = Format::Markdown;
+
+    for arg in args {
+        if let Some(n) = arg.strip_prefix("--size=") {
+            size = n.parse().map_err(|_| format!("不正な大きさ: {n}"))?;
+        } else if let Some(f) = arg.strip_prefix("--format=") {
+            format = match f {
+                "md" => Format::Markdown,
+                "csv" => Format::Csv,
+                _ => return Err(format!("不明な出力形式: {f}")),
+            };
+        } else {
+            return Err(format!("不明な引数: {arg}"));
+        }
+    }
+    Ok((size, format))
+}
@@ -0,0 +1,322 @@
+//! パーサ, 型付け, 整形のファジング
+//!
+//! ```text
+//! cargo run --release -p linz --features fuzz --bin linz-fuzz -- [--seed=<N>] [--iters=<N>]
+//! ```
+//!
+//! 以下の 2 種類の入力を乱数で生成し, 各段階でパニックしないことを確認する。
+//!
+//! - トークン列: 文法を無視してキーワードや記号を並べた文字列。パースに成功した場合は
+//!   型付けと整形も行い, 整形結果を再度パースすると同じ AST になることを確認する
+//! - AST: 文法上正しい AST。整形した結果をパースすると元の AST に戻ることを確認する
+//!
+//! 失敗した場合は入力を表示して異常終了する。同じシードを指定すると同じ入力を再現できる。
+use linz::{derivation, lang::*, parser, pretty, typing};
+use std::{
+    env, panic, process,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// 試行回数のデフォルト値
+const DEFAULT_ITERS: usize = 10000;
+
+/// 生成する AST の最大の深さ
+const MAX_DEPTH: usize = 6;
+
+/// トークン列の最大の長さ
+const MAX_TOKENS: usize = 30;
+
+/// トークン列に用いるトークン
+const TOKENS: &[&str] = &[
+    "let", "if", "else", "split", "as", "free", "lin", "un", "fn", "true", "false", "bool", "(",
+    ")", "{", "}", "<", ">", ",", ";", ":", "=", "*", "->", "x", "y", "z", "//c\n", "/*c*/", "/*",
+    "*/", "//",
+];
+
+/// トークンの区切り. 空文字列の場合はトークンが連結される
+const SEPARATORS: &[&str] = &["", " ", " ", "\n", "\t"];
+
+/// 変数名. キーワードと前方一致しないものを用いる
+const VARS: &[&str] = &["x", "y", "z", "a0", "_b"];
+
+/// 実行中の入力. パニックした際に表示する
+static CURRENT: Mutex<String> = Mutex::new(String::new());
+
+/// xorshift64 による疑似乱数生成器
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // 状態が 0 だと常に 0 を返すため避ける
+        Self(seed.max(1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// 0 以上 n 未満の値を返す
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// 確率 1 / n で真を返す
+    fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+
+    fn choose<'a, T>(&mut self, v: &'a [T]) -> &'a T {
+        &v[self.below(v.len())]
+    }
+}
+
+fn main() {
+    let (seed, iters) = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n使い方: linz-fuzz [--seed=<N>] [--iters=<N>]");
+            process::exit(2);
+        }
+    };
+
+    // パニックした場合は, 再現のために入力を表示する
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if let Ok(input) = CURRENT.try_lock() {
+            eprintln!("入力:\n{input}");
+        }
+        hook(info);
+    }));
+
+    println!("seed = {seed}");
+    let mut rng = Rng::new(seed);
+    for n in 0..iters {
+        let src = gen_tokens(&mut rng);
+        set_current(&src);
+        check_source(&src);
+
+        let mut arena = ExprArena::new();
+        let root = gen_expr(&mut rng, MAX_DEPTH, &mut arena);
+        let expr = Ast::new(arena, root);
+        let src = pretty::pretty_print(&expr);
+        set_current(&src);
+        check_roundtrip(&src, &expr);
+
+        if (n + 1) % 1000 == 0 {
+            println!("{} 回成功", n + 1);
+        }
+    }
+    println!("すべて成功");
+}
+
+/// コマンドライン引数を解析し, (シード, 試行回数) を返す
+fn parse_args<I>(args: I) -> Result<(u64, usize), String>
+where
+    I: Iterator<Item = String>,
+{
+    let mut seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(1);
+    let mut iters = DEFAULT_ITERS;
+
+    for arg in args {
+        if let Some(n) = arg.strip_prefix("--seed=") {
+            seed = n.parse().map_err(|_| format!("不正なシード: {n}"))?;
+        } else if let Some(n) = arg.strip_prefix("--iters=") {
+            iters = n.parse().map_err(|_| format!("不正な試行回数: {n}"))?;
+        } else {
+            return Err(format!("不明な引数: {arg}"));
+        }
+    }
+    Ok((seed, iters))
+}
+
+fn set_current(src: &str) {
+    *CURRENT.lock().unwrap() = src.to_string();
+}
+
+/// 入力を表示して異常終了
+fn fail(msg: &str) -> ! {
+    eprintln!("{msg}\n入力:\n{}", CURRENT.lock().unwrap());
+    process::exit(1);
+}
+
+/// ソースコードをパースし, 成功した場合は型付けと整形を行う
+fn check_source(src: &str) {
+    let Ok((_, expr)) = parser::parse_expr_with_comments(src) else {
+        return;
+    };
+
+    // 型付けの結果は問わず, パニックしないことのみ確認する
+    let _ = typing::typing(&expr, &mut typing::TypeEnv::new(), 0);
+    let mut d = derivation::Derivation::new();
+    let _ = typing::typing_traced(
+        &expr.arena,
+        expr.root,
+        &mut typing::TypeEnv::new(),
+        0,
+        &mut d,
+    );
+    d.render(40);
+
+    check_roundtrip(&pretty::pretty_print(&expr), &expr);
+}
+
+/// 整形結果 src をパースすると expr に戻ることを確認
+fn check_roundtrip(src: &str, expr: &Ast) {
+    match parser::parse_expr_with_comments(src) {
+        Ok(("", e)) if e == *expr => (),
+        Ok((rest, e)) => fail(&format!(
+            "整形結果のパース結果が一致しない\n残り: {rest:?}\n期待: {expr:?}\n結果: {e:?}\n整形結果:\n{src}"
+        )),
+        Err(rest) => fail(&format!(
+            "整形結果のパースに失敗\n残り: {rest:?}\n整形結果:\n{src}"
+        )),
+    }
+}
+
+/// トークンをランダムに並べた文字列を生成
+fn gen_tokens(rng: &mut Rng) -> String {
+    let len = rng.below(MAX_TOKENS) + 1;
+    let mut src = String::new();
+    for _ in 0..len {
+        src.push_str(rng.choose::<&str>(TOKENS));
+        src.push_str(rng.choose::<&str>(SEPARATORS));
+    }
+    src
+}
+
+/// 文法上正しい式をランダムに生成してアリーナ a に追加し, その ID を返す
+/// depth が 0 になると子を持たない式のみ生成する
+fn gen_expr(rng: &mut Rng, depth: usize, a: &mut ExprArena) -> ExprId {
+    let expr = gen_bare_expr(rng, depth, a);
+    // 連続したコメントはパースすると 1 つにまとまるため, 入れ子にはしない
+    if rng.one_in(8) {
+        let comments = (0..rng.below(2) + 1).map(|_| gen_comment(rng)).collect();
+        a.alloc(Expr::Comment(CommentExpr { comments, expr }))
+    } else {
+        expr
+    }
+}
+
+fn gen_bare_expr(rng: &mut Rng, depth: usize, a: &mut ExprArena) -> ExprId {
+    if depth == 0 {
+        let e = match rng.below(2) {
+            0 => Expr::Var(gen_var(rng)),
+            _ => Expr::QVal(QValExpr {
+                qual: gen_qual(rng),
+                val: ValExpr::Bool(rng.one_in(2)),
+            }),
+        };
+        return a.alloc(e);
+    }
+
+    let mut sub = |rng: &mut Rng| gen_expr(rng, depth - 1, a);
+    let e = match rng.below(10) {
+        0 => {
+            let (expr1, expr2) = (sub(rng), sub(rng));
+            Expr::Let(LetExpr {
+                var: gen_var(rng),
+                ty: gen_type(rng, 2),
+                expr1,
+                expr2,
+            })
+        }
+        1 => Expr::If(IfExpr {
+            cond_expr: sub(rng),
+            then_expr: sub(rng),
+            else_expr: sub(rng),
+        }),
+        2 => {
+            let (expr, body) = (sub(rng), sub(rng));
+            Expr::Split(SplitExpr {
+                expr,
+                left: gen_var(rng),
+                right: gen_var(rng),
+                body,
+            })
+        }
+        3 => {
+            let expr = sub(rng);
+            Expr::Free(FreeExpr {
+                var: gen_var(rng),
+                expr,
+            })
+        }
+        4 => Expr::App(AppExpr {
+            expr1: sub(rng),
+            expr2: sub(rng),
+        }),
+        5 => {
+            let (e1, e2) = (sub(rng), sub(rng));
+            Expr::QVal(QValExpr {
+                qual: gen_qual(rng),
+                val: ValExpr::Pair(e1, e2),
+            })
+        }
+        6 => {
+            let expr = sub(rng);
+            Expr::QVal(QValExpr {
+                qual: gen_qual(rng),
+                val: ValExpr::Fun(FnExpr {
+                    var: gen_var(rng),
+                    ty: gen_type(rng, 2),
+                    expr,
+                }),
+            })
+        }
+        7 => Expr::Drop(DropExpr {
+            expr1: sub(rng),
+            expr2: sub(rng),
+        }),
+        _ => return gen_bare_expr(rng, 0, a),
+    };
+    a.alloc(e)
+}
+
+/// 型をランダムに生成
+fn gen_type(rng: &mut Rng, depth: usize) -> TypeExpr {
+    let qual = gen_qual(rng);
+    let prim = match rng.below(3) {
+        _ if depth == 0 => PrimType::Bool,
+        0 => PrimType::Bool,
+        1 => PrimType::Pair(
+            Box::new(gen_type(rng, depth - 1)),
+            Box::new(gen_type(rng, depth - 1)),
+        ),
+        _ => PrimType::Arrow(
+            Box::new(gen_type(rng, depth - 1)),
+            Box::new(gen_type(rng, depth - 1)),
+        ),
+    };
+    TypeExpr { qual, prim }
+}
+
+fn gen_qual(rng: &mut Rng) -> Qual {
+    if rng.one_in(2) {
+        Qual::Lin
+    } else {
+        Qual::Un
+    }
+}
+
+fn gen_var(rng: &mut Rng) -> String {
+    rng.choose(VARS).to_string()
+}
+
+/// コメントをランダムに生成
+/// 行コメントは改行を, ブロックコメントは `*/` を含まない
+fn gen_comment(rng: &mut Rng) -> Comment {
+    const TEXTS: &[&str] = &["", " c ", "x * y", "/", "* /", "// a", "λ"];
+    let text = rng.choose(TEXTS).to_string();
+    if rng.one_in(2) {
+        Comment::Line(text)
+    } else {
+        Comment::Block(text)
+    }
+}
@@ -0,0 +1,142 @@
+//! ソースコードの検査
+//!
+//! パースと型付けを行い, 失敗した場合は診断 ([Diagnostic]) を返す。
+//! ファイルや標準出力を扱わないため, コマンドラインと WASM の双方から用いる。
+use crate::{lang, parser, typing};
+use std::fmt;
+
+/// 失敗した段階
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Phase {
+    Parse, // パース
+    Type,  // 型付け
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::Type => "type",
+        }
+    }
+}
+
+/// 検査に失敗した理由
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Diagnostic {
+    pub phase: Phase,
+    pub message: String,
+    pub pos: Option<(usize, usize)>, // 位置 (1 始まりの行, 1 始まりの文字単位の列)。型付けの場合は None
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.phase {
+            Phase::Parse => write!(f, "パースエラー")?,
+            Phase::Type => write!(f, "型エラー")?,
+        }
+        if let Some((line, col)) = self.pos {
+            write!(f, " ({line}:{col})")?;
+        }
+        write!(f, ":\n{}", self.message)
+    }
+}
+
+/// src 中のバイト位置 offset の行と列を返す
+fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, col)
+}
+
+/// パース. with_comments が true の場合は式の直前のコメントを保持する
+pub fn parse(src: &str, with_comments: bool) -> Result<lang::Ast, Diagnostic> {
+    let ret = if with_comments {
+        parser::parse_expr_with_comments(src)
+    } else {
+        parser::parse_expr(src)
+    };
+    ret.map(|(_, ast)| ast).map_err(|rest| {
+        // パーサは失敗した位置以降の入力を返す
+        let message = match rest.lines().next() {
+            Some(near) if !near.trim().is_empty() => {
+                format!("「{}」の付近をパースできません", near.trim())
+            }
+            _ => "入力の途中で終わっています".to_string(),
+        };
+        Diagnostic {
+            phase: Phase::Parse,
+            message,
+            pos: Some(line_col(src, src.len() - rest.len())),
+        }
+    })
+}
+
+/// ソースコードの型付けの結果を検査するマクロ. 型付け規則のドキュメントの例で用いる
+///
+/// `dsl!(src => "型")` は型付けに成功してその型になること,
+/// `dsl!(src => err "文字列")` は型付けに失敗し, エラーメッセージがその文字列を含むことを検査する。
+/// パースに失敗した場合はいずれも panic する。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("lin true" => "lin bool");
+/// dsl!("x" => err "定義されていない");
+/// ```
+#[macro_export]
+macro_rules! dsl {
+    ($src:expr => err $msg:expr) => {{
+        let src: &str = $src;
+        match $crate::check::check_source(src) {
+            Ok(t) => panic!("{src:?} の型付けに成功した: {t}"),
+            Err(d) => {
+                assert_eq!(d.phase, $crate::check::Phase::Type, "{src:?}: {d}");
+                assert!(d.message.contains($msg), "{src:?}: {}", d.message);
+            }
+        }
+    }};
+    ($src:expr => $ty:expr) => {{
+        let src: &str = $src;
+        match $crate::check::check_source(src) {
+            Ok(t) => assert_eq!(t.to_string(), $ty, "{src:?}"),
+            Err(d) => panic!("{src:?}: {d}"),
+        }
+    }};
+}
+
+/// パースと型付けを行い, 式の型を返す
+pub fn check_source(src: &str) -> Result<lang::TypeExpr, Diagnostic> {
+    let ast = parse(src, false)?;
+    typing::typing(&ast, &mut typing::TypeEnv::new(), 0).map_err(|e| Diagnostic {
+        phase: Phase::Type,
+        message: e.into_owned(),
+        pos: None,
+    })
+}
+#[cfg(test)]
+mod check_source {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let t = check_source("lin fn x : lin bool { x }").unwrap();
+        assert_eq!(t.to_string(), "lin (lin bool -> lin bool)");
+
+        let d = check_source("let x : lin bool = lin true;\n  lin false").unwrap_err();
+        assert_eq!(d.phase, Phase::Type);
+        assert_eq!(d.pos, None);
+
+        let d = check_source("let x : lin bool = lin true;\n  lin fn").unwrap_err();
+        assert_eq!(d.phase, Phase::Parse);
+        assert_eq!(d.pos, Some((2, 9)));
+        assert_eq!(d.message, "入力の途中で終わっています");
+    }
+
+    #[test]
+    fn test_line_col() {
+        assert_eq!(line_col("abc", 0), (1, 1));
+        assert_eq!(line_col("abc", 2), (1, 3));
+        assert_eq!(line_col("ab\nあいう", 9), (2, 3));
+    }
+}
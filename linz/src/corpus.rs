@@ -0,0 +1,332 @@
+//! 小さなプログラムの列挙と型付けによる分類
+//!
+//! 大きさ (式の節点の数) が上限以下のプログラムをすべて列挙し, 型付けに成功するか,
+//! 失敗した場合はその理由とともに分類する。結果は Markdown か CSV の表として出力する。
+//!
+//! 演習問題の作成や, 型付けを変更した際の差分の確認に用いる。
+//! 列挙の数を抑えるため, 変数名は x と y, 型は lin bool と un bool, 真偽値は true のみとする。
+use crate::{lang::*, pretty, typing};
+use std::{cmp::Reverse, collections::BTreeMap};
+
+/// 列挙に用いる変数名
+const VARS: &[&str] = &["x", "y"];
+
+/// 列挙に用いる修飾子
+const QUALS: &[Qual] = &[Qual::Lin, Qual::Un];
+
+/// 型付けの結果
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Verdict {
+    WellTyped(TypeExpr), // 型付けに成功した場合の型
+    IllTyped(String),    // 型付けに失敗した理由
+}
+
+/// 列挙したプログラムと, その型付けの結果
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Case {
+    pub expr: Ast,
+    pub verdict: Verdict,
+}
+
+impl Case {
+    /// 整形したプログラムを 1 行にまとめて返す
+    pub fn source(&self) -> String {
+        pretty::pretty_print(&self.expr)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// 列挙に用いる型
+fn types() -> Vec<TypeExpr> {
+    QUALS
+        .iter()
+        .map(|&qual| TypeExpr {
+            qual,
+            prim: PrimType::Bool,
+        })
+        .collect()
+}
+
+/// 大きさが max_size 以下のプログラムを, 大きさの昇順にすべて列挙する
+///
+/// 式はアリーナ a に追加し, 大きいプログラムは小さいプログラムを部分式として共有する。
+pub fn enumerate(max_size: usize, a: &mut ExprArena) -> Vec<ExprId> {
+    // table[n] は大きさがちょうど n の式
+    let mut table: Vec<Vec<ExprId>> = vec![Vec::new()];
+    for n in 1..=max_size {
+        let exprs = enumerate_size(n, &table, a);
+        table.push(exprs);
+    }
+    table.into_iter().flatten().collect()
+}
+
+/// 大きさが n 未満の式の表 table から, 大きさがちょうど n の式を列挙する
+fn enumerate_size(n: usize, table: &[Vec<ExprId>], a: &mut ExprArena) -> Vec<ExprId> {
+    let mut out = Vec::new();
+    let mut push = |e| out.push(a.alloc(e));
+
+    if n == 1 {
+        for v in VARS {
+            push(Expr::Var(v.to_string()));
+        }
+        for &qual in QUALS {
+            push(Expr::QVal(QValExpr {
+                qual,
+                val: ValExpr::Bool(true),
+            }));
+        }
+        return out;
+    }
+
+    // 子が 1 つの式
+    for e in &table[n - 1] {
+        for v in VARS {
+            push(Expr::Free(FreeExpr {
+                var: v.to_string(),
+                expr: *e,
+            }));
+        }
+        for &qual in QUALS {
+            for v in VARS {
+                for ty in types() {
+                    push(Expr::QVal(QValExpr {
+                        qual,
+                        val: ValExpr::Fun(FnExpr {
+                            var: v.to_string(),
+                            ty,
+                            expr: *e,
+                        }),
+                    }));
+                }
+            }
+        }
+    }
+
+    // 子が 2 つの式
+    for k in 1..n - 1 {
+        for e1 in &table[k] {
+            for e2 in &table[n - 1 - k] {
+                for v in VARS {
+                    for ty in types() {
+                        push(Expr::Let(LetExpr {
+                            var: v.to_string(),
+                            ty,
+                            expr1: *e1,
+                            expr2: *e2,
+                        }));
+                    }
+                }
+                for left in VARS {
+                    for right in VARS {
+                        push(Expr::Split(SplitExpr {
+                            expr: *e1,
+                            left: left.to_string(),
+                            right: right.to_string(),
+                            body: *e2,
+                        }));
+                    }
+                }
+                push(Expr::Drop(DropExpr {
+                    expr1: *e1,
+                    expr2: *e2,
+                }));
+                push(Expr::App(AppExpr {
+                    expr1: *e1,
+                    expr2: *e2,
+                }));
+                for &qual in QUALS {
+                    push(Expr::QVal(QValExpr {
+                        qual,
+                        val: ValExpr::Pair(*e1, *e2),
+                    }));
+                }
+            }
+        }
+    }
+
+    // 子が 3 つの式
+    for k1 in 1..n - 1 {
+        for k2 in 1..n - 1 - k1 {
+            let k3 = n - 1 - k1 - k2;
+            for e1 in &table[k1] {
+                for e2 in &table[k2] {
+                    for e3 in &table[k3] {
+                        push(Expr::If(IfExpr {
+                            cond_expr: *e1,
+                            then_expr: *e2,
+                            else_expr: *e3,
+                        }));
+                    }
+                }
+            }
+        }
+    }
+
+    out
+}
+#[cfg(test)]
+mod enumerate {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test() {
+        let count = |n| enumerate(n, &mut ExprArena::new()).len();
+        assert_eq!(count(0), 0);
+        // x, y, lin true, un true
+        assert_eq!(count(1), 4);
+        // free: 2 * 4, fn: 2 * 2 * 2 * 4
+        assert_eq!(count(2) - count(1), 40);
+        // let: 2 * 2 * 16, split: 4 * 16, drop: 16, app: 16, pair: 2 * 16, free: 2 * 40, fn: 8 * 40
+        assert_eq!(count(3) - count(2), 592);
+
+        // 大きさの昇順に並び, 重複しない
+        let mut a = ExprArena::new();
+        let exprs = enumerate(3, &mut a);
+        assert_eq!(a[exprs[0]], Expr::Var("x".to_string()));
+        let sources: HashSet<_> = exprs
+            .iter()
+            .map(|&id| pretty::pretty_print_expr(&a, id))
+            .collect();
+        assert_eq!(sources.len(), exprs.len());
+
+        // 部分式を共有するため, アリーナの式の数は列挙したプログラムの数と等しい
+        assert_eq!(a.len(), exprs.len());
+    }
+}
+
+/// アリーナ a 中の式 id を型付けして分類する
+pub fn classify(a: &ExprArena, id: ExprId) -> Case {
+    let expr = a.extract(id);
+    let verdict = match typing::typing(&expr, &mut typing::TypeEnv::new(), 0) {
+        Ok(t) => Verdict::WellTyped(t),
+        Err(e) => Verdict::IllTyped(e.to_string()),
+    };
+    Case { expr, verdict }
+}
+#[cfg(test)]
+mod classify {
+    use super::*;
+    use crate::parser::parse_expr;
+
+    #[test]
+    fn test() {
+        let case = |src| {
+            let (_, ast) = parse_expr(src).unwrap();
+            classify(&ast.arena, ast.root)
+        };
+
+        let c = case("lin fn x : lin bool { x }");
+        assert_eq!(c.source(), "lin fn x : lin bool { x }");
+        match c.verdict {
+            Verdict::WellTyped(t) => assert_eq!(t.to_string(), "lin (lin bool -> lin bool)"),
+            Verdict::IllTyped(e) => panic!("{e}"),
+        }
+        assert!(matches!(
+            case("un fn x : lin bool { un true }").verdict,
+            Verdict::IllTyped(_)
+        ));
+        assert!(matches!(case("x").verdict, Verdict::IllTyped(_)));
+    }
+}
+
+/// 分類の集計を (型付けに失敗した理由, 件数) の件数の降順で返す
+/// 型付けに成功したものは含めない
+fn reasons(cases: &[Case]) -> Vec<(&str, usize)> {
+    let mut counts = BTreeMap::new();
+    for c in cases {
+        if let Verdict::IllTyped(reason) = &c.verdict {
+            *counts.entry(reason.as_str()).or_insert(0) += 1;
+        }
+    }
+    let mut v: Vec<_> = counts.into_iter().collect();
+    v.sort_by_key(|&(_, n)| Reverse(n));
+    v
+}
+
+/// 分類の結果を Markdown の表に変換する
+pub fn to_markdown(cases: &[Case]) -> String {
+    let well = cases
+        .iter()
+        .filter(|c| matches!(c.verdict, Verdict::WellTyped(_)))
+        .count();
+
+    let mut out = String::from("# linz プログラムの型付け\n\n");
+    out.push_str(&format!(
+        "全 {} 件, 型付けに成功: {well} 件, 失敗: {} 件\n\n",
+        cases.len(),
+        cases.len() - well
+    ));
+
+    out.push_str("## 失敗の理由\n\n| 理由 | 件数 |\n| --- | ---: |\n");
+    for (reason, n) in reasons(cases) {
+        out.push_str(&format!("| {reason} | {n} |\n"));
+    }
+
+    out.push_str(
+        "\n## プログラム\n\n| # | プログラム | 結果 | 型または理由 |\n| ---: | --- | --- | --- |\n",
+    );
+    for (i, c) in cases.iter().enumerate() {
+        let (result, detail) = match &c.verdict {
+            Verdict::WellTyped(t) => ("成功", format!("`{t}`")),
+            Verdict::IllTyped(reason) => ("失敗", reason.clone()),
+        };
+        out.push_str(&format!(
+            "| {} | `{}` | {result} | {detail} |\n",
+            i + 1,
+            c.source()
+        ));
+    }
+    out
+}
+
+/// 分類の結果を CSV に変換する
+pub fn to_csv(cases: &[Case]) -> String {
+    // 区切り文字や引用符を含むフィールドは引用符で囲み, 引用符は 2 つ重ねる
+    let field = |s: &str| {
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    };
+
+    let mut out = String::from("id,program,result,detail\n");
+    for (i, c) in cases.iter().enumerate() {
+        let (result, detail) = match &c.verdict {
+            Verdict::WellTyped(t) => ("well-typed", t.to_string()),
+            Verdict::IllTyped(reason) => ("ill-typed", reason.clone()),
+        };
+        out.push_str(&format!(
+            "{},{},{result},{}\n",
+            i + 1,
+            field(&c.source()),
+            field(&detail)
+        ));
+    }
+    out
+}
+#[cfg(test)]
+mod to_csv {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let cases: Vec<Case> = ["lin true", "x"]
+            .iter()
+            .map(|s| {
+                let (_, ast) = crate::parser::parse_expr(s).unwrap();
+                classify(&ast.arena, ast.root)
+            })
+            .collect();
+        assert_eq!(
+            to_csv(&cases),
+            "id,program,result,detail\n\
+             1,lin true,well-typed,lin bool\n\
+             2,x,ill-typed,\"\"\"x\"\"という変数は定義されていないか、利用済みか、キャプチャできない\"\n"
+        );
+        assert!(to_markdown(&cases).contains("| 2 | `x` | 失敗 |"));
+    }
+}
@@ -0,0 +1,193 @@
+//! 型付けの導出木をテキスト形式で出力
+//!
+//! 型付けのフックから導出木を組み立て, 各ノードの型判断 Γ ⊢ e : T を
+//! 罫線を用いた木の形式で 1 行ずつ出力する。
+//! 式は pretty_print で整形した後に 1 行にまとめ, 行が指定の幅に収まらない場合は省略する。
+use crate::{lang, pretty, typing};
+
+/// 式を省略する場合でも最低限表示する文字数
+const MIN_EXPR_WIDTH: usize = 10;
+
+/// 導出木のノード
+#[derive(Debug)]
+struct Node {
+    rule: &'static str,    // 型付け規則の名前
+    ctx: String,           // 型環境 Γ
+    expr: String,          // 式 e
+    ty: Option<String>,    // 型 T. 型付けに失敗した場合は None
+    error: Option<String>, // このノードで発生したエラー
+    children: Vec<Node>,   // 前提となる型判断
+}
+
+/// 型付けの導出木を組み立てるフック
+#[derive(Debug, Default)]
+pub struct Derivation {
+    stack: Vec<Node>,   // 型付け中のノード
+    root: Option<Node>, // 型付けが終了した根ノード
+}
+
+impl Derivation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 導出木を文字列に変換
+    /// width は 1 行の最大文字数
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        if let Some(root) = &self.root {
+            write_node(root, "", "", width, &mut out);
+        }
+        out
+    }
+}
+
+impl typing::Trace for Derivation {
+    fn enter(&mut self, a: &lang::ExprArena, id: lang::ExprId, env: &typing::TypeEnv) {
+        let ctx = env
+            .context()
+            .iter()
+            .map(|(k, t)| format!("{k} : {t}"))
+            .collect::<Vec<_>>();
+        let ctx = if ctx.is_empty() {
+            "∅".to_string()
+        } else {
+            ctx.join(", ")
+        };
+
+        self.stack.push(Node {
+            rule: rule_name(a, id),
+            ctx,
+            expr: one_line(&pretty::pretty_print_expr(a, id)),
+            ty: None,
+            error: None,
+            children: Vec::new(),
+        });
+    }
+
+    fn exit(&mut self, ret: Result<&lang::TypeExpr, &str>) {
+        let Some(mut node) = self.stack.pop() else {
+            return;
+        };
+
+        match ret {
+            Ok(t) => node.ty = Some(t.to_string()),
+            Err(e) => {
+                // エラーは親ノードにも伝播するため, 最初に発生したノードにのみ記録する
+                if node.children.iter().all(|c| c.ty.is_some()) {
+                    node.error = Some(e.to_string());
+                }
+            }
+        }
+
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.root = Some(node),
+        }
+    }
+}
+
+/// 式に対応する型付け規則の名前
+fn rule_name(a: &lang::ExprArena, id: lang::ExprId) -> &'static str {
+    match &a[id] {
+        lang::Expr::Let(_) => "T-Let",
+        lang::Expr::If(_) => "T-If",
+        lang::Expr::Split(_) => "T-Split",
+        lang::Expr::Free(_) => "T-Free",
+        lang::Expr::Drop(_) => "T-Drop",
+        lang::Expr::App(_) => "T-App",
+        lang::Expr::Var(_) => "T-Var",
+        lang::Expr::QVal(e) => match e.val {
+            lang::ValExpr::Bool(_) => "T-Bool",
+            lang::ValExpr::Pair(..) => "T-Pair",
+            lang::ValExpr::Fun(_) => "T-Abs",
+        },
+        lang::Expr::Comment(e) => rule_name(a, e.expr),
+    }
+}
+
+/// 改行と字下げを空白 1 文字にまとめる
+fn one_line(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// ノードを 1 行出力し, 子ノードを再帰的に出力
+/// head はこのノードの行頭, prefix は子ノードの行頭に付ける罫線
+fn write_node(node: &Node, head: &str, prefix: &str, width: usize, out: &mut String) {
+    let ty = node.ty.as_deref().unwrap_or("?");
+    let rest = format!(" : {ty}");
+    let fixed = format!("{head}[{}] {} ⊢ ", node.rule, node.ctx);
+
+    // 行が width に収まるように式を省略
+    let avail = width
+        .saturating_sub(fixed.chars().count() + rest.chars().count())
+        .max(MIN_EXPR_WIDTH);
+    let expr = if node.expr.chars().count() > avail {
+        let mut e = node.expr.chars().take(avail - 1).collect::<String>();
+        e.push('…');
+        e
+    } else {
+        node.expr.clone()
+    };
+
+    out.push_str(&format!("{fixed}{expr}{rest}"));
+    if let Some(e) = &node.error {
+        out.push_str(&format!("  ✗ {e}"));
+    }
+    out.push('\n');
+
+    let n = node.children.len();
+    for (i, child) in node.children.iter().enumerate() {
+        let last = i + 1 == n;
+        let head = format!("{prefix}{}", if last { "└── " } else { "├── " });
+        let next = format!("{prefix}{}", if last { "    " } else { "│   " });
+        write_node(child, &head, &next, width, out);
+    }
+}
+#[cfg(test)]
+mod render {
+    use super::*;
+    use crate::parser::parse_expr;
+
+    fn derive(src: &str, width: usize) -> String {
+        let (_, expr) = parse_expr(src).unwrap();
+        let mut d = Derivation::new();
+        let _ = typing::typing_traced(
+            &expr.arena,
+            expr.root,
+            &mut typing::TypeEnv::new(),
+            0,
+            &mut d,
+        );
+        d.render(width)
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            derive("let x : un bool = un true; x", 80),
+            "[T-Let] ∅ ⊢ let x : un bool = un true; x : un bool
+├── [T-Bool] ∅ ⊢ un true : un bool
+└── [T-Var] x : un bool ⊢ x : un bool
+"
+        );
+
+        // 幅に収まらない式は省略する
+        assert_eq!(
+            derive("let x : un bool = un true; x", 30),
+            "[T-Let] ∅ ⊢ let x : u… : un bool
+├── [T-Bool] ∅ ⊢ un true : un bool
+└── [T-Var] x : un bool ⊢ x : un bool
+"
+        );
+
+        // エラーは最初に発生したノードに表示する
+        let out = derive("lin fn x : lin bool { lin true }", 80);
+        assert_eq!(
+            out,
+            "[T-Abs] ∅ ⊢ lin fn x : lin bool { lin true } : ?  ✗ 関数定義内でlin型の変数\"x\"を消費していない
+└── [T-Bool] x : lin bool ⊢ lin true : lin bool
+"
+        );
+    }
+}
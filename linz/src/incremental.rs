@@ -0,0 +1,340 @@
+//! トップレベルの定義ごとの型付けのキャッシュ
+//!
+//! REPL やサーバのモードで, 変更された定義とそれに依存する定義のみを型付けし直すために用いる。
+//! 各定義は, 本体の自由変数のうち他の定義の名前であるものに依存し,
+//! 依存する定義の型を持つ変数を与えた型環境で独立に型付けする。
+//!
+//! 型付けの結果は, コメントを除いた本体と依存する定義の型付けの結果から計算したキーとともに保持する。
+//! 定義を変更した場合は, その定義と推移的に依存する定義の結果を破棄する。
+//!
+//! ```
+//! use linz::{incremental::Workspace, parser};
+//! let mut ws = Workspace::new();
+//! ws.define("t", parser::parse_expr("un true").unwrap().1);
+//! ws.define("p", parser::parse_expr("un <t, t>").unwrap().1);
+//! assert_eq!(ws.check("p").unwrap().to_string(), "un (un bool * un bool)");
+//! ```
+use crate::{lang, pretty, typing};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet},
+    hash::{Hash, Hasher},
+};
+
+/// トップレベルの定義
+#[derive(Debug, Clone)]
+struct Def {
+    ast: lang::Ast,
+    hash: u64,              // コメントを除いた本体のハッシュ値
+    free: BTreeSet<String>, // 本体の自由変数
+    cached: Option<(u64, Result<lang::TypeExpr, String>)>, // キーと型付けの結果
+}
+
+/// トップレベルの定義の集まりと, その型付けの結果のキャッシュ
+#[derive(Debug, Default, Clone)]
+pub struct Workspace {
+    defs: BTreeMap<String, Def>,
+    typings: usize, // 型付けを行った回数
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// name を ast で定義する。すでにある場合は置き換える
+    /// コメント以外が変わった場合は, name と推移的に依存する定義の型付けの結果を破棄する
+    pub fn define(&mut self, name: &str, ast: lang::Ast) {
+        let hash = hash_ast(&ast);
+        if let Some(def) = self.defs.get_mut(name) {
+            if def.hash == hash {
+                def.ast = ast;
+                return;
+            }
+        }
+        let free = free_vars(&ast.arena, ast.root);
+        self.defs.insert(
+            name.to_string(),
+            Def {
+                ast,
+                hash,
+                free,
+                cached: None,
+            },
+        );
+        self.invalidate(name);
+    }
+
+    /// name の定義を削除し, name に依存する定義の型付けの結果を破棄する
+    pub fn remove(&mut self, name: &str) -> Option<lang::Ast> {
+        let def = self.defs.remove(name)?;
+        self.invalidate(name);
+        Some(def.ast)
+    }
+
+    /// name の定義
+    pub fn get(&self, name: &str) -> Option<&lang::Ast> {
+        self.defs.get(name).map(|def| &def.ast)
+    }
+
+    /// name と, name に推移的に依存する定義の型付けの結果を破棄する
+    fn invalidate(&mut self, name: &str) {
+        let mut work = vec![name.to_string()];
+        let mut seen = BTreeSet::new();
+        while let Some(n) = work.pop() {
+            if !seen.insert(n.clone()) {
+                continue;
+            }
+            if let Some(def) = self.defs.get_mut(&n) {
+                def.cached = None;
+            }
+            for (k, def) in &self.defs {
+                if def.free.contains(&n) {
+                    work.push(k.clone());
+                }
+            }
+        }
+    }
+
+    /// name が直接依存する定義の名前を返す
+    pub fn deps(&self, name: &str) -> Vec<&str> {
+        self.defs.get(name).map_or_else(Vec::new, |def| {
+            def.free
+                .iter()
+                .filter(|v| self.defs.contains_key(*v))
+                .map(String::as_str)
+                .collect()
+        })
+    }
+
+    /// name の定義を型付けし, 型を返す
+    /// キャッシュした結果のキーが一致する場合は型付けし直さない
+    pub fn check(&mut self, name: &str) -> Result<lang::TypeExpr, String> {
+        self.check_def(name, &mut Vec::new())
+    }
+
+    /// 型付けを行った回数。キャッシュした結果を返した場合は数えない
+    pub fn typings(&self) -> usize {
+        self.typings
+    }
+
+    /// name を型付けする。 visiting は型付け中の定義で, 循環の検出に用いる
+    fn check_def(
+        &mut self,
+        name: &str,
+        visiting: &mut Vec<String>,
+    ) -> Result<lang::TypeExpr, String> {
+        if visiting.iter().any(|v| v == name) {
+            return Err(format!(r#"定義"{name}"が循環している"#));
+        }
+        let deps: Vec<String> = self.deps(name).into_iter().map(String::from).collect();
+        let Some(def) = self.defs.get(name) else {
+            return Err(format!(r#""{name}"という定義はない"#));
+        };
+
+        // 依存する定義を先に型付けし, その結果もキーに含める
+        let mut hasher = DefaultHasher::new();
+        def.hash.hash(&mut hasher);
+        visiting.push(name.to_string());
+        let deps: Vec<_> = deps
+            .into_iter()
+            .map(|dep| {
+                let ret = self.check_def(&dep, visiting);
+                dep.hash(&mut hasher);
+                match &ret {
+                    Ok(t) => t.to_string().hash(&mut hasher),
+                    Err(e) => e.hash(&mut hasher),
+                }
+                (dep, ret)
+            })
+            .collect();
+        visiting.pop();
+        let key = hasher.finish();
+
+        let def = self.defs.get_mut(name).unwrap();
+        if let Some((k, ret)) = &def.cached {
+            if *k == key {
+                return ret.clone();
+            }
+        }
+
+        let mut vars = Vec::new();
+        let mut ret = None;
+        for (dep, t) in deps {
+            match t {
+                Ok(t) => vars.push((dep, t)),
+                Err(_) => {
+                    ret.get_or_insert(Err(format!(r#"依存する定義"{dep}"の型付けに失敗した"#)));
+                }
+            }
+        }
+        let ret = ret.unwrap_or_else(|| {
+            self.typings += 1;
+            let mut env = typing::TypeEnv::with_vars(vars);
+            typing::typing(&def.ast, &mut env, 0).map_err(|e| e.into_owned())
+        });
+        def.cached = Some((key, ret.clone()));
+        ret
+    }
+}
+
+#[cfg(test)]
+mod workspace {
+    use super::*;
+    use crate::parser;
+
+    fn ast(src: &str) -> lang::Ast {
+        parser::parse_expr(src).unwrap().1
+    }
+
+    #[test]
+    fn test() {
+        let mut ws = Workspace::new();
+        ws.define("t", ast("un true"));
+        ws.define("id", ast("un fn x : un bool { x }"));
+        ws.define("p", ast("un <(id t), t>"));
+        ws.define("q", ast("lin <p, un false>"));
+        assert_eq!(ws.deps("p"), vec!["id", "t"]);
+        assert_eq!(
+            ws.check("q").unwrap().to_string(),
+            "lin (un (un bool * un bool) * un bool)"
+        );
+        assert_eq!(ws.typings(), 4);
+
+        // 変更がなければ型付けし直さない
+        assert!(ws.check("q").is_ok());
+        assert_eq!(ws.typings(), 4);
+
+        // コメントのみの変更では型付けし直さない
+        ws.define(
+            "t",
+            parser::parse_expr_with_comments("// 真\nun true")
+                .unwrap()
+                .1,
+        );
+        assert!(ws.check("q").is_ok());
+        assert_eq!(ws.typings(), 4);
+
+        // 変更した定義と, 推移的に依存する定義のみを型付けし直す
+        ws.define("id", ast("un fn y : un bool { y }"));
+        assert!(ws.check("q").is_ok());
+        assert_eq!(ws.typings(), 7);
+        ws.define("t", ast("un false"));
+        assert!(ws.check("id").is_ok());
+        assert_eq!(ws.typings(), 7);
+
+        // 依存する定義の型が変わると, 依存する側も型エラーとなる
+        ws.define("t", ast("lin true"));
+        assert_eq!(ws.check("t").unwrap().to_string(), "lin bool");
+        assert!(ws.check("p").unwrap_err().contains("引数の型が異なる"));
+        assert_eq!(
+            ws.check("q").unwrap_err(),
+            r#"依存する定義"p"の型付けに失敗した"#
+        );
+
+        // 削除した定義は, 定義されていない変数となる
+        assert!(ws.remove("t").is_some());
+        assert!(ws
+            .check("p")
+            .unwrap_err()
+            .contains(r#""t"という変数は定義されていない"#));
+        assert_eq!(ws.check("t").unwrap_err(), r#""t"という定義はない"#);
+
+        // 後から定義した名前にも依存する
+        ws.define("t", ast("un true"));
+        assert!(ws.check("q").is_ok());
+
+        // 循環する定義
+        ws.define("a", ast("(b un true)"));
+        ws.define("b", ast("(a un true)"));
+        assert!(ws.check("a").is_err());
+        assert!(ws.check("b").is_err());
+    }
+}
+
+/// コメントを除いた AST のハッシュ値
+fn hash_ast(ast: &lang::Ast) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    pretty::pretty_print(&ast.strip_comments()).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// アリーナ a 中の式 id の自由変数を返す
+pub fn free_vars(a: &lang::ExprArena, id: lang::ExprId) -> BTreeSet<String> {
+    let mut free = BTreeSet::new();
+    collect_free(a, id, &mut Vec::new(), &mut free);
+    free
+}
+
+/// 束縛された変数 bound を除いて, 式 id の自由変数を free に加える
+fn collect_free(
+    a: &lang::ExprArena,
+    id: lang::ExprId,
+    bound: &mut Vec<String>,
+    free: &mut BTreeSet<String>,
+) {
+    match &a[id] {
+        lang::Expr::Var(v) => {
+            if !bound.contains(v) {
+                free.insert(v.clone());
+            }
+        }
+        lang::Expr::Let(e) => {
+            collect_free(a, e.expr1, bound, free);
+            collect_scoped(a, &[&e.var], e.expr2, bound, free);
+        }
+        lang::Expr::Split(e) => {
+            collect_free(a, e.expr, bound, free);
+            collect_scoped(a, &[&e.left, &e.right], e.body, bound, free);
+        }
+        lang::Expr::QVal(lang::QValExpr {
+            val: lang::ValExpr::Fun(f),
+            ..
+        }) => collect_scoped(a, &[&f.var], f.expr, bound, free),
+        lang::Expr::Free(e) => {
+            if !bound.contains(&e.var) {
+                free.insert(e.var.clone());
+            }
+            collect_free(a, e.expr, bound, free);
+        }
+        e => {
+            e.map_children(|c| {
+                collect_free(a, c, bound, free);
+                c
+            });
+        }
+    }
+}
+
+/// vars を束縛した上で, 式 body の自由変数を free に加える
+fn collect_scoped(
+    a: &lang::ExprArena,
+    vars: &[&String],
+    body: lang::ExprId,
+    bound: &mut Vec<String>,
+    free: &mut BTreeSet<String>,
+) {
+    let n = bound.len();
+    bound.extend(vars.iter().map(|v| v.to_string()));
+    collect_free(a, body, bound, free);
+    bound.truncate(n);
+}
+#[cfg(test)]
+mod free_vars {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn test() {
+        let fv = |src: &str| {
+            let ast = parser::parse_expr(src).unwrap().1;
+            free_vars(&ast.arena, ast.root)
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fv("(f un true)"), vec!["f"]);
+        assert_eq!(fv("let x : un bool = x; (x y)"), vec!["x", "y"]);
+        assert_eq!(fv("split p as a, b { (a lin <b, c>) }"), vec!["c", "p"]);
+        assert_eq!(fv("lin fn x : lin bool { free y; (x z) }"), vec!["y", "z"]);
+        assert!(fv("un fn x : un bool { x }").is_empty());
+    }
+}
@@ -1,55 +1,259 @@
-use std::fmt;
+use std::{fmt, ops::Index};
 
-/// 抽象構文木
+/// 式の節点. 子の式は [ExprArena] 中の [ExprId] で参照する
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Expr {
     Let(LetExpr),
     If(IfExpr),
     Split(SplitExpr),
     Free(FreeExpr),
+    Drop(DropExpr),
     App(AppExpr),
     Var(String),
     QVal(QValExpr),
+    Comment(CommentExpr),
 }
 
+impl Expr {
+    /// 子の式の ID を, 式中に現れる順に f で置き換えた式を返す
+    pub fn map_children(&self, mut f: impl FnMut(ExprId) -> ExprId) -> Expr {
+        match self {
+            Expr::Let(e) => Expr::Let(LetExpr {
+                var: e.var.clone(),
+                ty: e.ty.clone(),
+                expr1: f(e.expr1),
+                expr2: f(e.expr2),
+            }),
+            Expr::If(e) => Expr::If(IfExpr {
+                cond_expr: f(e.cond_expr),
+                then_expr: f(e.then_expr),
+                else_expr: f(e.else_expr),
+            }),
+            Expr::Split(e) => Expr::Split(SplitExpr {
+                expr: f(e.expr),
+                left: e.left.clone(),
+                right: e.right.clone(),
+                body: f(e.body),
+            }),
+            Expr::Free(e) => Expr::Free(FreeExpr {
+                var: e.var.clone(),
+                expr: f(e.expr),
+            }),
+            Expr::Drop(e) => Expr::Drop(DropExpr {
+                expr1: f(e.expr1),
+                expr2: f(e.expr2),
+            }),
+            Expr::App(e) => Expr::App(AppExpr {
+                expr1: f(e.expr1),
+                expr2: f(e.expr2),
+            }),
+            Expr::Var(v) => Expr::Var(v.clone()),
+            Expr::QVal(e) => Expr::QVal(QValExpr {
+                qual: e.qual,
+                val: match &e.val {
+                    ValExpr::Bool(b) => ValExpr::Bool(*b),
+                    ValExpr::Pair(e1, e2) => {
+                        let e1 = f(*e1);
+                        ValExpr::Pair(e1, f(*e2))
+                    }
+                    ValExpr::Fun(fun) => ValExpr::Fun(FnExpr {
+                        var: fun.var.clone(),
+                        ty: fun.ty.clone(),
+                        expr: f(fun.expr),
+                    }),
+                },
+            }),
+            Expr::Comment(e) => Expr::Comment(CommentExpr {
+                comments: e.comments.clone(),
+                expr: f(e.expr),
+            }),
+        }
+    }
+}
+
+/// 式の ID. 式を格納した [ExprArena] 中の位置を表す
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord)]
+pub struct ExprId(u32);
+
+impl ExprId {
+    /// アリーナ中の位置. 式ごとの情報を Vec で持つ場合の添字に用いる
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// 式を格納するアリーナ
+///
+/// 式は追加した順に並び, 削除はしない。パーサは子の式を親より先に追加する。
+#[derive(Debug, Default, Clone)]
+pub struct ExprArena {
+    exprs: Vec<Expr>,
+}
+
+impl ExprArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 式を追加し, その ID を返す
+    pub fn alloc(&mut self, expr: Expr) -> ExprId {
+        let id = u32::try_from(self.exprs.len()).expect("too many expressions");
+        self.exprs.push(expr);
+        ExprId(id)
+    }
+
+    /// 格納している式の数
+    pub fn len(&self) -> usize {
+        self.exprs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exprs.is_empty()
+    }
+
+    /// id を根とする部分木のみを格納した AST を返す
+    pub fn extract(&self, id: ExprId) -> Ast {
+        let mut arena = ExprArena::new();
+        let root = arena.copy_tree(self, id, true);
+        Ast { arena, root }
+    }
+
+    /// src の id を根とする部分木をこのアリーナに複製する
+    /// keep_comments が偽の場合はコメントを取り除く
+    fn copy_tree(&mut self, src: &ExprArena, id: ExprId, keep_comments: bool) -> ExprId {
+        match &src[id] {
+            Expr::Comment(e) if !keep_comments => self.copy_tree(src, e.expr, keep_comments),
+            e => {
+                let e = e.map_children(|c| self.copy_tree(src, c, keep_comments));
+                self.alloc(e)
+            }
+        }
+    }
+}
+
+impl Index<ExprId> for ExprArena {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id.index()]
+    }
+}
+
+/// 抽象構文木. 式を格納したアリーナと, 根の式の ID の組
+#[derive(Debug, Clone)]
+pub struct Ast {
+    pub arena: ExprArena,
+    pub root: ExprId,
+}
+
+impl Ast {
+    pub fn new(arena: ExprArena, root: ExprId) -> Self {
+        Self { arena, root }
+    }
+
+    /// 根の式
+    pub fn expr(&self) -> &Expr {
+        &self.arena[self.root]
+    }
+
+    /// コメントを取り除いた AST を返す
+    pub fn strip_comments(&self) -> Ast {
+        let mut arena = ExprArena::new();
+        let root = arena.copy_tree(&self.arena, self.root, false);
+        Ast { arena, root }
+    }
+}
+
+/// アリーナ中の配置によらず, 木の構造が等しければ等しい
+impl PartialEq for Ast {
+    fn eq(&self, other: &Self) -> bool {
+        fn same(a: &ExprArena, x: ExprId, b: &ExprArena, y: ExprId) -> bool {
+            let (mut xs, mut ys) = (Vec::new(), Vec::new());
+            // 子の ID をそろえて, 節点のみを比較する
+            let ex = a[x].map_children(|c| {
+                xs.push(c);
+                ExprId(0)
+            });
+            let ey = b[y].map_children(|c| {
+                ys.push(c);
+                ExprId(0)
+            });
+            ex == ey && xs.iter().zip(&ys).all(|(&x, &y)| same(a, x, b, y))
+        }
+        same(&self.arena, self.root, &other.arena, other.root)
+    }
+}
+
+impl Eq for Ast {}
+
 /// let 式
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct LetExpr {
     pub var: String,
     pub ty: TypeExpr,
-    pub expr1: Box<Expr>,
-    pub expr2: Box<Expr>,
+    pub expr1: ExprId,
+    pub expr2: ExprId,
 }
 
 /// if 式
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct IfExpr {
-    pub cond_expr: Box<Expr>,
-    pub then_expr: Box<Expr>,
-    pub else_expr: Box<Expr>,
+    pub cond_expr: ExprId,
+    pub then_expr: ExprId,
+    pub else_expr: ExprId,
 }
 
 /// split 式
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SplitExpr {
-    pub expr: Box<Expr>,
+    pub expr: ExprId,
     pub left: String,
     pub right: String,
-    pub body: Box<Expr>,
+    pub body: ExprId,
 }
 
 /// free 文
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct FreeExpr {
     pub var: String,
-    pub expr: Box<Expr>,
+    pub expr: ExprId,
+}
+
+/// drop 文. un 型の値 expr1 を評価して捨て, expr2 を評価する
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DropExpr {
+    pub expr1: ExprId,
+    pub expr2: ExprId,
 }
 
 /// 関数適用
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct AppExpr {
-    pub expr1: Box<Expr>,
-    pub expr2: Box<Expr>,
+    pub expr1: ExprId,
+    pub expr2: ExprId,
+}
+
+/// コメント
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Comment {
+    Line(String),  // `//` から行末まで. 区切り記号は含まない
+    Block(String), // `/*` から `*/` まで. 区切り記号は含まない
+}
+impl fmt::Display for Comment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Comment::Line(s) => write!(f, "//{s}"),
+            Comment::Block(s) => write!(f, "/*{s}*/"),
+        }
+    }
+}
+
+/// 直前にコメントのある式
+/// 型付けではコメントを無視し, expr のみを扱う
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CommentExpr {
+    pub comments: Vec<Comment>,
+    pub expr: ExprId,
 }
 
 /// 修飾子付き値
@@ -63,7 +267,7 @@ pub struct QValExpr {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ValExpr {
     Bool(bool),
-    Pair(Box<Expr>, Box<Expr>),
+    Pair(ExprId, ExprId),
     Fun(FnExpr),
 }
 
@@ -79,7 +283,7 @@ pub enum Qual {
 pub struct FnExpr {
     pub var: String,
     pub ty: TypeExpr,
-    pub expr: Box<Expr>,
+    pub expr: ExprId,
 }
 
 /// 修飾子付き型
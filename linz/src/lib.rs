@@ -0,0 +1,24 @@
+//! # 線形型言語 linz の処理系
+//!
+//! ## 利用例
+//!
+//! ```
+//! use linz::{parser, typing};
+//! let (_, expr) = parser::parse_expr("lin fn x : lin bool { x }").unwrap();
+//! let t = typing::typing(&expr, &mut typing::TypeEnv::new(), 0).unwrap();
+//! assert_eq!(t.to_string(), "lin (lin bool -> lin bool)");
+//! ```
+pub use parser_combinator;
+
+pub mod args;
+pub mod check;
+pub mod corpus;
+pub mod derivation;
+pub mod helper;
+pub mod incremental;
+pub mod lang;
+pub mod parser;
+pub mod pretty;
+pub mod typing;
+#[cfg(feature = "wasm")]
+pub mod wasm;
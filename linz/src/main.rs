@@ -1,39 +1,55 @@
-pub use parser_combinator;
+use linz::{args, check, derivation, helper, pretty, typing};
 use std::{env, fs};
 
-mod helper;
-mod lang;
-mod parser;
-mod typing;
-
 fn main() -> Result<(), helper::DynError> {
     // コマンドライン引数の検査
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("以下のようにファイル名を指定して実行してください\ncargo run codes/ex1.lin");
-        return Err("引数が不足".into());
-    }
+    let mut argv = env::args();
+    let prog = argv.next().unwrap_or_else(|| "linz".to_string());
+    let args = match args::parse(argv) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n{}", args::usage(&prog));
+            return Err(e.into());
+        }
+    };
 
     // ファイル読み込み
-    let content = fs::read_to_string(&args[1])?;
+    let content = fs::read_to_string(&args.file)?;
 
     // パース
-    let ast = parser::parse_expr(&content);
+    // パースのみの場合は, 整形して表示するためにコメントを残す
+    let ast = check::parse(&content, args.stage == args::Stage::Parse);
     // println!("AST:\n{ast:#?}");
     match ast {
-        Ok((_, expr)) => {
+        Ok(expr) => {
+            if args.stage == args::Stage::Parse {
+                // パースのみの場合は整形した AST を表示して終了
+                println!("{}", pretty::pretty_print(&expr));
+                return Ok(());
+            }
+
             let mut ctx = typing::TypeEnv::new();
             println!("式:\n{content}");
 
             // 型付け
-            let a = typing::typing(&expr, &mut ctx, 0)?;
+            // 評価器はまだないので, Stage::Type と Stage::All は同じ動作となる
+            let a = if args.derivation {
+                // 型付けの導出木を組み立てて表示
+                let mut d = derivation::Derivation::new();
+                let ret = typing::typing_traced(&expr.arena, expr.root, &mut ctx, 0, &mut d);
+                println!("導出木:\n{}", d.render(args.width));
+                ret?
+            } else {
+                typing::typing(&expr, &mut ctx, 0)?
+            };
+            for w in ctx.warnings() {
+                eprintln!("警告: {w}");
+            }
             println!("の型は\n{a}\nです。");
         }
-        Err(e) => {
-            // TODO: エラーの位置を表示する
-            let msg = format!("{e}");
-            eprintln!("パースエラー:\n{msg}");
-            return Err(msg.into());
+        Err(d) => {
+            eprintln!("{d}");
+            return Err(d.message.into());
         }
     }
 
@@ -5,12 +5,13 @@
 //! ```text
 //! <VAR>   := [a-zA-Z_][a-zA-Z0-9_]*
 //!
-//! <E>     := <LET> | <IF> | <SPLIT> | <FREE> | <APP> | <VAR> | <QVAL>
+//! <E>     := <LET> | <IF> | <SPLIT> | <FREE> | <DROP> | <APP> | <VAR> | <QVAL>
 //!
 //! <LET>   := let <VAR> : <T> = <E>; <E>
 //! <IF>    := if <E> { <E> } else { <E> }
 //! <SPLIT> := split <E> as <VAR>, <VAR> { <E> }
 //! <FREE>  := free <E>; <E>
+//! <DROP>  := drop <E>; <E>
 //! <APP>   := ( <E> <E> )
 //! <Q>     := lin | un
 //!
@@ -24,135 +25,292 @@
 //! 型
 //! <T>     := <Q> <P>
 //! <P>     := bool | ( <T> * <T> ) | ( <T> -> <T> )
+//!
+//! コメント (空白と同様にトークンの間に書ける)
+//! <COMMENT> := // 行末まで | /* */ で囲まれた範囲 (入れ子不可)
 //! ```
+//!
+//! 式の直前にあるコメントは [Expr::Comment] として式に付加する。
+//! それ以外の位置 (型の中や `;`, `}` の直前など) のコメントは読み飛ばすのみで保持しない。
+//!
+//! 式は [ExprArena] に子から順に追加し, 各パース関数は追加した式の [ExprId] を返す。
 use crate::lang::*;
 use parser_combinator::*;
 
-pub fn parse_expr(i: &str) -> ParseResult<Expr> {
-    let (i, _) = space0().parse(i)?;
+/// 式をパース. コメントは読み飛ばし, AST には含めない
+pub fn parse_expr(i: &str) -> ParseResult<'_, Ast> {
+    let (i, ast) = parse_expr_with_comments(i)?;
+    Ok((i, ast.strip_comments()))
+}
+
+/// 式をパース. 式の直前のコメントを AST に保持する
+pub fn parse_expr_with_comments(i: &str) -> ParseResult<'_, Ast> {
+    let mut arena = ExprArena::new();
+    let (i, root) = parse_commented(i, &mut arena)?;
+    Ok((i, Ast::new(arena, root)))
+}
+
+/// 直前のコメントを含めて式をパース
+fn parse_commented<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
+    let (i, comments) = trivia0(i)?;
+    let (i, expr) = parse_bare_expr(i, a)?;
+    if comments.is_empty() {
+        Ok((i, expr))
+    } else {
+        Ok((i, a.alloc(Expr::Comment(CommentExpr { comments, expr }))))
+    }
+}
+
+/// 先頭にコメントのない式をパース
+fn parse_bare_expr<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
     let (next_i, tok) = first_token(i)?;
 
     match tok {
-        "let" => parse_let(i),
-        "if" => parse_if(i),
-        "split" => parse_split(i),
-        "free" => parse_free(i),
-        "lin" | "un" => parse_qval(i),
-        "(" => parse_app(i),
-        _ => Ok((next_i, Expr::Var(tok.to_string()))),
+        "let" => parse_let(i, a),
+        "if" => parse_if(i, a),
+        "split" => parse_split(i, a),
+        "free" => parse_free(i, a),
+        "drop" => parse_drop(i, a),
+        "lin" | "un" => parse_qval(i, a),
+        "(" => parse_app(i, a),
+        _ => Ok((next_i, a.alloc(Expr::Var(tok.to_string())))),
     }
 }
+
+/// パース関数 p で i をパースし, 返された式を根とする AST を返す
+#[cfg(test)]
+fn parse_ast<'a>(
+    p: impl Fn(&'a str, &mut ExprArena) -> ParseResult<'a, ExprId>,
+    i: &'a str,
+) -> ParseResult<'a, Ast> {
+    let mut a = ExprArena::new();
+    let (i, root) = p(i, &mut a)?;
+    Ok((i, Ast::new(a, root)))
+}
 #[cfg(test)]
 mod parse_expr {
     use super::*;
 
     #[test]
     fn test_parse_expr() {
+        let lin = |b| {
+            Expr::QVal(QValExpr {
+                qual: Qual::Lin,
+                val: ValExpr::Bool(b),
+            })
+        };
+        let var = |v: &str| Expr::Var(v.to_string());
+
+        let mut a = ExprArena::new();
+        let expr1 = a.alloc(lin(true));
+        let expr2 = a.alloc(var("x"));
+        let root = a.alloc(Expr::Let(LetExpr {
+            var: "x".to_string(),
+            ty: TypeExpr {
+                qual: Qual::Un,
+                prim: PrimType::Bool,
+            },
+            expr1,
+            expr2,
+        }));
         assert_eq!(
             parse_expr("let x: un bool = lin true; x"),
-            Ok((
-                "",
-                Expr::Let(LetExpr {
-                    var: "x".to_string(),
-                    ty: TypeExpr {
-                        qual: Qual::Un,
-                        prim: PrimType::Bool
-                    },
-                    expr1: Box::new(Expr::QVal(QValExpr {
-                        qual: Qual::Lin,
-                        val: ValExpr::Bool(true)
-                    })),
-                    expr2: Box::new(Expr::Var("x".to_string())),
-                })
-            ))
+            Ok(("", Ast::new(a, root)))
         );
+
+        let mut a = ExprArena::new();
+        let cond_expr = a.alloc(lin(true));
+        let then_expr = a.alloc(lin(false));
+        let else_expr = a.alloc(lin(true));
+        let root = a.alloc(Expr::If(IfExpr {
+            cond_expr,
+            then_expr,
+            else_expr,
+        }));
         assert_eq!(
             parse_expr("if lin true { lin false } else { lin true }"),
-            Ok((
-                "",
-                Expr::If(IfExpr {
-                    cond_expr: Box::new(Expr::QVal(QValExpr {
-                        qual: Qual::Lin,
-                        val: ValExpr::Bool(true)
-                    })),
-                    then_expr: Box::new(Expr::QVal(QValExpr {
-                        qual: Qual::Lin,
-                        val: ValExpr::Bool(false)
-                    })),
-                    else_expr: Box::new(Expr::QVal(QValExpr {
-                        qual: Qual::Lin,
-                        val: ValExpr::Bool(true)
-                    })),
-                })
-            ))
+            Ok(("", Ast::new(a, root)))
         );
+
+        let mut a = ExprArena::new();
+        let expr = a.alloc(var("v"));
+        let body = a.alloc(var("x"));
+        let root = a.alloc(Expr::Split(SplitExpr {
+            expr,
+            left: "x".to_string(),
+            right: "y".to_string(),
+            body,
+        }));
         assert_eq!(
             parse_expr("split v as x, y { x }"),
-            Ok((
-                "",
-                Expr::Split(SplitExpr {
-                    expr: Box::new(Expr::Var("v".to_string())),
-                    left: "x".to_string(),
-                    right: "y".to_string(),
-                    body: Box::new(Expr::Var("x".to_string())),
-                })
-            ))
+            Ok(("", Ast::new(a, root)))
         );
+
+        let mut a = ExprArena::new();
+        let expr = a.alloc(var("x"));
+        let root = a.alloc(Expr::Free(FreeExpr {
+            var: "x".to_string(),
+            expr,
+        }));
+        assert_eq!(parse_expr("free x; x"), Ok(("", Ast::new(a, root))));
+
+        let mut a = ExprArena::new();
+        let root = a.alloc(lin(true));
+        assert_eq!(parse_expr("lin true"), Ok(("", Ast::new(a, root))));
+
+        let mut a = ExprArena::new();
+        let e1 = a.alloc(lin(true));
+        let e2 = a.alloc(Expr::QVal(QValExpr {
+            qual: Qual::Un,
+            val: ValExpr::Bool(false),
+        }));
+        let root = a.alloc(Expr::QVal(QValExpr {
+            qual: Qual::Un,
+            val: ValExpr::Pair(e1, e2),
+        }));
         assert_eq!(
-            parse_expr("free x; x"),
-            Ok((
-                "",
-                Expr::Free(FreeExpr {
-                    var: "x".to_string(),
-                    expr: Box::new(Expr::Var("x".to_string())),
-                })
-            ))
+            parse_expr("un <lin true, un false>"),
+            Ok(("", Ast::new(a, root)))
         );
+
+        let mut a = ExprArena::new();
+        let root = a.alloc(var("abc"));
+        let abc = Ast::new(a, root);
+        assert_eq!(parse_expr("abc"), Ok(("", abc.clone())));
+        assert_eq!(parse_expr("abc!"), Ok(("!", abc)));
+
+        // コメントは読み飛ばす
         assert_eq!(
-            parse_expr("lin true"),
-            Ok((
-                "",
-                Expr::QVal(QValExpr {
-                    qual: Qual::Lin,
-                    val: ValExpr::Bool(true)
-                })
-            ))
+            parse_expr("// c1\nfree /* c2 */ x /* c3 */; /* c4 */ x"),
+            parse_expr("free x; x")
         );
+    }
+}
+#[cfg(test)]
+mod parse_expr_with_comments {
+    use super::*;
+
+    #[test]
+    fn test_parse_expr_with_comments() {
+        let mut a = ExprArena::new();
+        let x = a.alloc(Expr::Var("x".to_string()));
+        let expr = a.alloc(Expr::Comment(CommentExpr {
+            comments: vec![Comment::Block("c3".to_string())],
+            expr: x,
+        }));
+        let expr = a.alloc(Expr::Free(FreeExpr {
+            var: "x".to_string(),
+            expr,
+        }));
+        let root = a.alloc(Expr::Comment(CommentExpr {
+            comments: vec![
+                Comment::Line(" c1".to_string()),
+                Comment::Block(" c2 ".to_string()),
+            ],
+            expr,
+        }));
+        let ast = Ast::new(a, root);
         assert_eq!(
-            parse_expr("un false"),
-            Ok((
-                "",
-                Expr::QVal(QValExpr {
-                    qual: Qual::Un,
-                    val: ValExpr::Bool(false)
-                })
-            ))
+            parse_expr_with_comments("// c1\n/* c2 */ free x; /*c3*/ x // c4"),
+            Ok((" // c4", ast.clone()))
         );
+        // コメントを取り除くと parse_expr の結果と同じになる
+        assert_eq!(Ok(("", ast.strip_comments())), parse_expr("free x; x"));
+        // 閉じていないブロックコメントはエラー
+        assert!(parse_expr_with_comments("/* x").is_err());
+    }
+}
+
+/// 空白とコメントを読み飛ばし, 読み飛ばしたコメントを返す
+fn trivia0(i: &str) -> ParseResult<'_, Vec<Comment>> {
+    let mut comments = Vec::new();
+    let (mut i, _) = space0().parse(i)?;
+    while let Ok((next_i, c)) = parse_comment(i) {
+        comments.push(c);
+        (i, _) = space0().parse(next_i)?;
+    }
+    Ok((i, comments))
+}
+
+/// trivia0 と同様だが, 空白かコメントが 1 つ以上必要
+fn trivia1(i: &str) -> ParseResult<'_, Vec<Comment>> {
+    match trivia0(i)? {
+        (next_i, _) if next_i.len() == i.len() => Err(i),
+        ok => Ok(ok),
+    }
+}
+#[cfg(test)]
+mod trivia {
+    use super::*;
+
+    #[test]
+    fn test_trivia() {
         assert_eq!(
-            parse_expr("un <lin true, un false>"),
+            trivia0(" // a\n  /*b*/x"),
             Ok((
-                "",
-                Expr::QVal(QValExpr {
-                    qual: Qual::Un,
-                    val: ValExpr::Pair(
-                        Box::new(Expr::QVal(QValExpr {
-                            qual: Qual::Lin,
-                            val: ValExpr::Bool(true)
-                        })),
-                        Box::new(Expr::QVal(QValExpr {
-                            qual: Qual::Un,
-                            val: ValExpr::Bool(false)
-                        })),
-                    )
-                })
+                "x",
+                vec![
+                    Comment::Line(" a".to_string()),
+                    Comment::Block("b".to_string())
+                ]
             ))
         );
-        assert_eq!(parse_expr("abc"), Ok(("", Expr::Var("abc".to_string()))));
-        assert_eq!(parse_expr("abc!"), Ok(("!", Expr::Var("abc".to_string()))));
+        assert_eq!(trivia0("x"), Ok(("x", vec![])));
+        assert_eq!(
+            trivia1("/**/x"),
+            Ok(("x", vec![Comment::Block("".to_string())]))
+        );
+        assert_eq!(trivia1("x"), Err("x"));
     }
 }
 
-fn parse_var(input: &str) -> ParseResult<&str> {
+/// コメントを 1 つパース
+fn parse_comment(i: &str) -> ParseResult<'_, Comment> {
+    let line = literal("//")
+        .skip(any_char.pred(|c| *c != '\n').many0())
+        .map(|cs| Comment::Line(cs.into_iter().collect()));
+    match line.parse(i) {
+        ok @ Ok(_) => ok,
+        Err(_) => {
+            let (body, _) = literal("/*").parse(i)?;
+            match body.find("*/") {
+                Some(n) => Ok((&body[n + 2..], Comment::Block(body[..n].to_string()))),
+                None => Err(i),
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod parse_comment {
+    use super::*;
+
+    #[test]
+    fn test_parse_comment() {
+        assert_eq!(
+            parse_comment("// abc\nx"),
+            Ok(("\nx", Comment::Line(" abc".to_string())))
+        );
+        assert_eq!(parse_comment("//"), Ok(("", Comment::Line("".to_string()))));
+        assert_eq!(
+            parse_comment("/* a\n * b */x"),
+            Ok(("x", Comment::Block(" a\n * b ".to_string())))
+        );
+        assert_eq!(parse_comment("/* a"), Err("/* a"));
+        assert_eq!(parse_comment("/ a"), Err("/ a"));
+    }
+}
+
+/// { e } の形式のブロックをパース
+fn parse_block<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
+    let (i, _) = trivia0(i)?;
+    let (i, _) = char('{').parse(i)?;
+    let (i, e) = parse_commented(i, a)?;
+    let (i, _) = trivia0(i)?;
+    let (i, _) = char('}').parse(i)?;
+    Ok((i, e))
+}
+
+fn parse_var(input: &str) -> ParseResult<'_, &str> {
     let mut pos = 0;
     let mut chars = input.chars();
 
@@ -161,7 +319,7 @@ fn parse_var(input: &str) -> ParseResult<&str> {
         _ => return Err(input),
     }
 
-    while let Some(next) = chars.next() {
+    for next in chars {
         if next.is_alphanumeric() || next == '_' {
             pos += 1;
         } else {
@@ -188,11 +346,12 @@ mod parse_var {
     }
 }
 
-fn first_token(i: &str) -> ParseResult<&str> {
+fn first_token(i: &str) -> ParseResult<'_, &str> {
     match keyword("let")
         .or_else(keyword("if"))
         .or_else(keyword("split"))
         .or_else(keyword("free"))
+        .or_else(keyword("drop"))
         .or_else(keyword("lin"))
         .or_else(keyword("un"))
         .or_else(keyword("("))
@@ -218,6 +377,7 @@ mod first_token {
             Ok((" v as x,y { e }", "split"))
         );
         assert_eq!(first_token("free x; e"), Ok((" x; e", "free")));
+        assert_eq!(first_token("drop x; e"), Ok((" x; e", "drop")));
         assert_eq!(first_token("lin true"), Ok((" true", "lin")));
         assert_eq!(first_token("un false"), Ok((" false", "un")));
         assert_eq!(
@@ -229,36 +389,35 @@ mod first_token {
     }
 }
 
-fn parse_let(i: &str) -> ParseResult<Expr> {
+fn parse_let<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
     let (i, _) = keyword("let").parse(i)?;
-    let (i, _) = space1().parse(i)?;
+    let (i, _) = trivia1(i)?;
 
     let (i, var) = parse_var(i)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char(':').parse(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
 
     let (i, ty) = parse_type(i)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char('=').parse(i)?;
-    let (i, _) = space0().parse(i)?;
 
-    let (i, e1) = parse_expr(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, e1) = parse_commented(i, a)?;
+    let (i, _) = trivia0(i)?;
 
     let (i, _) = char(';').parse(i)?;
-    let (i, e2) = parse_expr(i)?;
+    let (i, e2) = parse_commented(i, a)?;
 
     Ok((
         i,
-        Expr::Let(LetExpr {
+        a.alloc(Expr::Let(LetExpr {
             var: var.to_string(),
             ty,
-            expr1: Box::new(e1),
-            expr2: Box::new(e2),
-        }),
+            expr1: e1,
+            expr2: e2,
+        })),
     ))
 }
 #[cfg(test)]
@@ -267,46 +426,48 @@ mod parse_let {
 
     #[test]
     fn test_parse_let() {
+        let mut a = ExprArena::new();
+        let expr1 = a.alloc(Expr::Var("e1".to_string()));
+        let expr2 = a.alloc(Expr::Var("e2".to_string()));
+        let root = a.alloc(Expr::Let(LetExpr {
+            var: "x".to_string(),
+            ty: TypeExpr {
+                qual: Qual::Lin,
+                prim: PrimType::Bool,
+            },
+            expr1,
+            expr2,
+        }));
         assert_eq!(
-            parse_let("let x : lin bool = e1; e2"),
-            Ok((
-                "",
-                Expr::Let(LetExpr {
-                    var: "x".to_string(),
-                    ty: TypeExpr {
-                        qual: Qual::Lin,
-                        prim: PrimType::Bool
-                    },
-                    expr1: Box::new(Expr::Var("e1".to_string())),
-                    expr2: Box::new(Expr::Var("e2".to_string())),
-                })
-            ))
+            parse_ast(parse_let, "let x : lin bool = e1; e2"),
+            Ok(("", Ast::new(a, root)))
         );
     }
 }
 
-fn parse_if(i: &str) -> ParseResult<Expr> {
+fn parse_if<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
     let (i, _) = keyword("if").parse(i)?;
-    let (i, _) = space1().parse(i)?;
+    // 式の直前のコメントは式に付加するため, 区切りがあることのみ確認する
+    trivia1(i)?;
 
-    let (i, e1) = parse_expr(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, e1) = parse_commented(i, a)?;
+    let (i, _) = trivia0(i)?;
 
-    let (i, e2) = braces(parse_expr).parse(i)?;
+    let (i, e2) = parse_block(i, a)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = keyword("else").parse(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
 
-    let (i, e3) = braces(parse_expr).parse(i)?;
+    let (i, e3) = parse_block(i, a)?;
 
     Ok((
         i,
-        Expr::If(IfExpr {
-            cond_expr: Box::new(e1),
-            then_expr: Box::new(e2),
-            else_expr: Box::new(e3),
-        }),
+        a.alloc(Expr::If(IfExpr {
+            cond_expr: e1,
+            then_expr: e2,
+            else_expr: e3,
+        })),
     ))
 }
 #[cfg(test)]
@@ -315,49 +476,52 @@ mod parse_if {
 
     #[test]
     fn test_parse_if() {
+        let mut a = ExprArena::new();
+        let cond_expr = a.alloc(Expr::Var("e1".to_string()));
+        let then_expr = a.alloc(Expr::Var("e2".to_string()));
+        let else_expr = a.alloc(Expr::Var("e3".to_string()));
+        let root = a.alloc(Expr::If(IfExpr {
+            cond_expr,
+            then_expr,
+            else_expr,
+        }));
         assert_eq!(
-            parse_if("if e1 { e2 } else { e3 }"),
-            Ok((
-                "",
-                Expr::If(IfExpr {
-                    cond_expr: Box::new(Expr::Var("e1".to_string())),
-                    then_expr: Box::new(Expr::Var("e2".to_string())),
-                    else_expr: Box::new(Expr::Var("e3".to_string())),
-                })
-            ))
+            parse_ast(parse_if, "if e1 { e2 } else { e3 }"),
+            Ok(("", Ast::new(a, root)))
         );
     }
 }
 
-fn parse_split(i: &str) -> ParseResult<Expr> {
+fn parse_split<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
     let (i, _) = keyword("split").parse(i)?;
-    let (i, _) = space1().parse(i)?;
+    // 式の直前のコメントは式に付加するため, 区切りがあることのみ確認する
+    trivia1(i)?;
 
-    let (i, e1) = parse_expr(i)?;
+    let (i, e1) = parse_commented(i, a)?;
 
-    let (i, _) = space1().parse(i)?;
+    let (i, _) = trivia1(i)?;
     let (i, _) = keyword("as").parse(i)?;
-    let (i, _) = space1().parse(i)?;
+    let (i, _) = trivia1(i)?;
 
     let (i, var1) = parse_var(i)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char(',').parse(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
 
     let (i, var2) = parse_var(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
 
-    let (i, e2) = braces(parse_expr).parse(i)?;
+    let (i, e2) = parse_block(i, a)?;
 
     Ok((
         i,
-        Expr::Split(SplitExpr {
-            expr: Box::new(e1),
+        a.alloc(Expr::Split(SplitExpr {
+            expr: e1,
             left: var1.to_string(),
             right: var2.to_string(),
-            body: Box::new(e2),
-        }),
+            body: e2,
+        })),
     ))
 }
 #[cfg(test)]
@@ -366,36 +530,37 @@ mod parse_split {
 
     #[test]
     fn test_parse_split() {
+        let mut a = ExprArena::new();
+        let expr = a.alloc(Expr::Var("e1".to_string()));
+        let body = a.alloc(Expr::Var("e2".to_string()));
+        let root = a.alloc(Expr::Split(SplitExpr {
+            expr,
+            left: "x".to_string(),
+            right: "y".to_string(),
+            body,
+        }));
         assert_eq!(
-            parse_split("split e1 as x, y { e2 }"),
-            Ok((
-                "",
-                Expr::Split(SplitExpr {
-                    expr: Box::new(Expr::Var("e1".to_string())),
-                    left: "x".to_string(),
-                    right: "y".to_string(),
-                    body: Box::new(Expr::Var("e2".to_string())),
-                })
-            ))
+            parse_ast(parse_split, "split e1 as x, y { e2 }"),
+            Ok(("", Ast::new(a, root)))
         );
     }
 }
 
-fn parse_free(i: &str) -> ParseResult<Expr> {
+fn parse_free<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
     let (i, _) = keyword("free").parse(i)?;
-    let (i, _) = space1().parse(i)?;
+    let (i, _) = trivia1(i)?;
 
     let (i, var) = parse_var(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char(';').parse(i)?;
 
-    let (i, e) = parse_expr(i)?;
+    let (i, e) = parse_commented(i, a)?;
     Ok((
         i,
-        Expr::Free(FreeExpr {
+        a.alloc(Expr::Free(FreeExpr {
             var: var.to_string(),
-            expr: Box::new(e),
-        }),
+            expr: e,
+        })),
     ))
 }
 #[cfg(test)]
@@ -404,26 +569,57 @@ mod parse_free {
 
     #[test]
     fn test_parse_free() {
+        let mut a = ExprArena::new();
+        let expr = a.alloc(Expr::Var("e".to_string()));
+        let root = a.alloc(Expr::Free(FreeExpr {
+            var: "x".to_string(),
+            expr,
+        }));
         assert_eq!(
-            parse_free("free x; e"),
-            Ok((
-                "",
-                Expr::Free(FreeExpr {
-                    var: "x".to_string(),
-                    expr: Box::new(Expr::Var("e".to_string())),
-                })
-            ))
+            parse_ast(parse_free, "free x; e"),
+            Ok(("", Ast::new(a, root)))
+        );
+    }
+}
+
+fn parse_drop<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
+    let (i, _) = keyword("drop").parse(i)?;
+    // 式の直前のコメントは式に付加するため, 区切りがあることのみ確認する
+    trivia1(i)?;
+
+    let (i, expr1) = parse_commented(i, a)?;
+    let (i, _) = trivia0(i)?;
+    let (i, _) = char(';').parse(i)?;
+
+    let (i, expr2) = parse_commented(i, a)?;
+    Ok((i, a.alloc(Expr::Drop(DropExpr { expr1, expr2 }))))
+}
+#[cfg(test)]
+mod parse_drop {
+    use super::*;
+
+    #[test]
+    fn test_parse_drop() {
+        let mut a = ExprArena::new();
+        let expr1 = a.alloc(Expr::Var("x".to_string()));
+        let expr2 = a.alloc(Expr::Var("e".to_string()));
+        let root = a.alloc(Expr::Drop(DropExpr { expr1, expr2 }));
+        assert_eq!(
+            parse_ast(parse_drop, "drop x; e"),
+            Ok(("", Ast::new(a, root)))
         );
+        // drop の後には式が必要
+        assert!(parse_ast(parse_drop, "drop ; e").is_err());
     }
 }
 
-fn parse_qval(i: &str) -> ParseResult<Expr> {
+fn parse_qval<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
     let (i, q) = parse_qual(i)?;
-    let (i, _) = space1().parse(i)?;
+    let (i, _) = trivia1(i)?;
 
-    let (i, v) = parse_val(i)?;
+    let (i, v) = parse_val(i, a)?;
 
-    Ok((i, Expr::QVal(QValExpr { qual: q, val: v })))
+    Ok((i, a.alloc(Expr::QVal(QValExpr { qual: q, val: v }))))
 }
 #[cfg(test)]
 mod parse_qval {
@@ -431,27 +627,27 @@ mod parse_qval {
 
     #[test]
     fn test_parse_qval() {
+        let mut a = ExprArena::new();
+        let expr = a.alloc(Expr::Var("e".to_string()));
+        let root = a.alloc(Expr::QVal(QValExpr {
+            qual: Qual::Lin,
+            val: ValExpr::Fun(FnExpr {
+                var: "x".to_string(),
+                ty: TypeExpr {
+                    qual: Qual::Un,
+                    prim: PrimType::Bool,
+                },
+                expr,
+            }),
+        }));
         assert_eq!(
-            parse_qval("lin fn x : un bool { e }"),
-            Ok((
-                "",
-                Expr::QVal(QValExpr {
-                    qual: Qual::Lin,
-                    val: ValExpr::Fun(FnExpr {
-                        var: "x".to_string(),
-                        ty: TypeExpr {
-                            qual: Qual::Un,
-                            prim: PrimType::Bool
-                        },
-                        expr: Box::new(Expr::Var("e".to_string())),
-                    }),
-                })
-            ))
+            parse_ast(parse_qval, "lin fn x : un bool { e }"),
+            Ok(("", Ast::new(a, root)))
         );
     }
 }
 
-fn parse_val(i: &str) -> ParseResult<ValExpr> {
+fn parse_val<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ValExpr> {
     let (next_i, tok) = keyword("fn")
         .or_else(keyword("true"))
         .or_else(keyword("false"))
@@ -459,10 +655,10 @@ fn parse_val(i: &str) -> ParseResult<ValExpr> {
         .parse(i)?;
 
     match tok {
-        "fn" => parse_fn(i),
+        "fn" => parse_fn(i, a),
         "true" => Ok((next_i, ValExpr::Bool(true))),
         "false" => Ok((next_i, ValExpr::Bool(false))),
-        "<" => parse_pair(i),
+        "<" => parse_pair(i, a),
         _ => unreachable!(),
     }
 }
@@ -472,56 +668,51 @@ mod parse_val {
 
     #[test]
     fn test_parse_val() {
-        assert_eq!(
-            parse_val("fn x : un bool { e }"),
-            Ok((
-                "",
-                ValExpr::Fun(FnExpr {
-                    var: "x".to_string(),
-                    ty: TypeExpr {
-                        qual: Qual::Un,
-                        prim: PrimType::Bool
-                    },
-                    expr: Box::new(Expr::Var("e".to_string())),
-                })
-            ))
-        );
-        assert_eq!(parse_val("true"), Ok(("", ValExpr::Bool(true))));
-        assert_eq!(parse_val("false"), Ok(("", ValExpr::Bool(false))));
-        assert_eq!(
-            parse_val("<x, y>"),
-            Ok((
-                "",
-                ValExpr::Pair(
-                    Box::new(Expr::Var("x".to_string())),
-                    Box::new(Expr::Var("y".to_string()))
-                )
-            ))
-        );
+        // 値の中の式はアリーナに追加し, 値はその ID を持つ
+        let mut a = ExprArena::new();
+        let (i, val) = parse_val("fn x : un bool { e }", &mut a).unwrap();
+        let ValExpr::Fun(f) = val else {
+            panic!("{val:?}");
+        };
+        assert_eq!((i, f.var.as_str()), ("", "x"));
+        assert_eq!(a[f.expr], Expr::Var("e".to_string()));
+
+        let mut a = ExprArena::new();
+        assert_eq!(parse_val("true", &mut a), Ok(("", ValExpr::Bool(true))));
+        assert_eq!(parse_val("false", &mut a), Ok(("", ValExpr::Bool(false))));
+        assert!(a.is_empty());
+
+        let (i, val) = parse_val("<x, y>", &mut a).unwrap();
+        let ValExpr::Pair(e1, e2) = val else {
+            panic!("{val:?}");
+        };
+        assert_eq!(i, "");
+        assert_eq!(a[e1], Expr::Var("x".to_string()));
+        assert_eq!(a[e2], Expr::Var("y".to_string()));
     }
 }
 
-fn parse_fn(i: &str) -> ParseResult<ValExpr> {
+fn parse_fn<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ValExpr> {
     let (i, _) = keyword("fn").parse(i)?;
-    let (i, _) = space1().parse(i)?;
+    let (i, _) = trivia1(i)?;
 
     let (i, var) = parse_var(i)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char(':').parse(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
 
     let (i, ty) = parse_type(i)?;
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
 
-    let (i, expr) = braces(parse_expr).parse(i)?;
+    let (i, expr) = parse_block(i, a)?;
 
     Ok((
         i,
         ValExpr::Fun(FnExpr {
             var: var.to_string(),
             ty,
-            expr: Box::new(expr),
+            expr,
         }),
     ))
 }
@@ -531,39 +722,38 @@ mod parse_fn {
 
     #[test]
     fn test_parse_fn() {
+        let mut a = ExprArena::new();
+        let (i, val) = parse_fn("fn x : un bool { e }", &mut a).unwrap();
+        let ValExpr::Fun(f) = val else {
+            panic!("{val:?}");
+        };
+        assert_eq!(i, "");
+        assert_eq!(f.var, "x");
         assert_eq!(
-            parse_fn("fn x : un bool { e }"),
-            Ok((
-                "",
-                ValExpr::Fun(FnExpr {
-                    var: "x".to_string(),
-                    ty: TypeExpr {
-                        qual: Qual::Un,
-                        prim: PrimType::Bool
-                    },
-                    expr: Box::new(Expr::Var("e".to_string())),
-                })
-            ))
+            f.ty,
+            TypeExpr {
+                qual: Qual::Un,
+                prim: PrimType::Bool
+            }
         );
+        assert_eq!(a[f.expr], Expr::Var("e".to_string()));
     }
 }
 
-fn parse_pair(i: &str) -> ParseResult<ValExpr> {
+fn parse_pair<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ValExpr> {
     let (i, _) = char('<').parse(i)?;
-    let (i, _) = space0().parse(i)?;
 
-    let (i, e1) = parse_expr(i)?;
+    let (i, e1) = parse_commented(i, a)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char(',').parse(i)?;
-    let (i, _) = space0().parse(i)?;
 
-    let (i, e2) = parse_expr(i)?;
+    let (i, e2) = parse_commented(i, a)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char('>').parse(i)?;
 
-    Ok((i, ValExpr::Pair(Box::new(e1), Box::new(e2))))
+    Ok((i, ValExpr::Pair(e1, e2)))
 }
 #[cfg(test)]
 mod parse_pair {
@@ -571,37 +761,35 @@ mod parse_pair {
 
     #[test]
     fn test_parse_pair() {
-        assert_eq!(
-            parse_pair("<x, y>"),
-            Ok((
-                "",
-                ValExpr::Pair(
-                    Box::new(Expr::Var("x".to_string())),
-                    Box::new(Expr::Var("y".to_string()))
-                )
-            ))
-        );
+        let mut a = ExprArena::new();
+        let (i, val) = parse_pair("<x, y>", &mut a).unwrap();
+        let ValExpr::Pair(e1, e2) = val else {
+            panic!("{val:?}");
+        };
+        assert_eq!(i, "");
+        assert_eq!(a[e1], Expr::Var("x".to_string()));
+        assert_eq!(a[e2], Expr::Var("y".to_string()));
     }
 }
 
-fn parse_app(i: &str) -> ParseResult<Expr> {
+fn parse_app<'a>(i: &'a str, a: &mut ExprArena) -> ParseResult<'a, ExprId> {
     let (i, _) = char('(').parse(i)?;
-    let (i, _) = space0().parse(i)?;
-    let (i, e1) = parse_expr(i)?;
+    let (i, e1) = parse_commented(i, a)?;
 
-    let (i, _) = space1().parse(i)?;
+    // 式の直前のコメントは式に付加するため, 区切りがあることのみ確認する
+    trivia1(i)?;
 
-    let (i, e2) = parse_expr(i)?;
+    let (i, e2) = parse_commented(i, a)?;
 
-    let (i, _) = space0().parse(i)?;
+    let (i, _) = trivia0(i)?;
     let (i, _) = char(')').parse(i)?;
 
     Ok((
         i,
-        Expr::App(AppExpr {
-            expr1: Box::new(e1),
-            expr2: Box::new(e2),
-        }),
+        a.alloc(Expr::App(AppExpr {
+            expr1: e1,
+            expr2: e2,
+        })),
     ))
 }
 #[cfg(test)]
@@ -610,22 +798,17 @@ mod parse_app {
 
     #[test]
     fn test_parse_app() {
-        assert_eq!(
-            parse_app("(e1 e2)"),
-            Ok((
-                "",
-                Expr::App(AppExpr {
-                    expr1: Box::new(Expr::Var("e1".to_string())),
-                    expr2: Box::new(Expr::Var("e2".to_string())),
-                })
-            ))
-        );
+        let mut a = ExprArena::new();
+        let expr1 = a.alloc(Expr::Var("e1".to_string()));
+        let expr2 = a.alloc(Expr::Var("e2".to_string()));
+        let root = a.alloc(Expr::App(AppExpr { expr1, expr2 }));
+        assert_eq!(parse_ast(parse_app, "(e1 e2)"), Ok(("", Ast::new(a, root))));
     }
 }
 
-fn parse_type(i: &str) -> ParseResult<TypeExpr> {
+fn parse_type(i: &str) -> ParseResult<'_, TypeExpr> {
     let (i, qual) = parse_qual(i)?;
-    let (i, _) = space1().parse(i)?;
+    let (i, _) = trivia1(i)?;
     let (i, val) = keyword("bool").or_else(keyword("(")).parse(i)?;
     if val == "bool" {
         Ok((
@@ -636,15 +819,15 @@ fn parse_type(i: &str) -> ParseResult<TypeExpr> {
             },
         ))
     } else {
-        let (i, _) = space0().parse(i)?;
+        let (i, _) = trivia0(i)?;
         let (i, t1) = parse_type(i)?;
-        let (i, _) = space0().parse(i)?;
+        let (i, _) = trivia0(i)?;
 
         let (i, op) = keyword("*").or_else(keyword("->")).parse(i)?;
 
-        let (i, _) = space0().parse(i)?;
+        let (i, _) = trivia0(i)?;
         let (i, t2) = parse_type(i)?;
-        let (i, _) = space0().parse(i)?;
+        let (i, _) = trivia0(i)?;
 
         let (i, _) = char(')').parse(i)?;
 
@@ -725,11 +908,11 @@ mod parse_type {
                 }
             ))
         );
-        assert_eq!(parse_type("un (lin bool -> )"), Err(")".into()),)
+        assert_eq!(parse_type("un (lin bool -> )"), Err(")"),)
     }
 }
 
-fn parse_qual(i: &str) -> ParseResult<Qual> {
+fn parse_qual(i: &str) -> ParseResult<'_, Qual> {
     let (i, q) = keyword("lin").or_else(keyword("un")).parse(i)?;
     match q {
         "lin" => Ok((i, Qual::Lin)),
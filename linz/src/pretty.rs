@@ -0,0 +1,143 @@
+//! AST をソースコードの形式に整形して出力
+//!
+//! ブロック `{ }` の中身は 1 段ずつ字下げ(空白 4 文字)して出力する。
+use crate::lang::*;
+
+const INDENT: &str = "    ";
+
+/// AST を整形して文字列に変換
+pub fn pretty_print(ast: &Ast) -> String {
+    pretty_print_expr(&ast.arena, ast.root)
+}
+
+/// アリーナ a 中の式 id を整形して文字列に変換
+pub fn pretty_print_expr(a: &ExprArena, id: ExprId) -> String {
+    let mut out = String::new();
+    pp_expr(a, id, 0, &mut out);
+    out
+}
+
+/// 改行して depth 段の字下げを行う
+fn newline(depth: usize, out: &mut String) {
+    out.push('\n');
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+/// { e } の形式でブロックを出力
+fn pp_block(a: &ExprArena, id: ExprId, depth: usize, out: &mut String) {
+    out.push('{');
+    newline(depth + 1, out);
+    pp_expr(a, id, depth + 1, out);
+    newline(depth, out);
+    out.push('}');
+}
+
+fn pp_expr(a: &ExprArena, id: ExprId, depth: usize, out: &mut String) {
+    match &a[id] {
+        Expr::Let(e) => {
+            out.push_str(&format!("let {} : {} = ", e.var, e.ty));
+            pp_expr(a, e.expr1, depth, out);
+            out.push(';');
+            newline(depth, out);
+            pp_expr(a, e.expr2, depth, out);
+        }
+        Expr::If(e) => {
+            out.push_str("if ");
+            pp_expr(a, e.cond_expr, depth, out);
+            out.push(' ');
+            pp_block(a, e.then_expr, depth, out);
+            out.push_str(" else ");
+            pp_block(a, e.else_expr, depth, out);
+        }
+        Expr::Split(e) => {
+            out.push_str("split ");
+            pp_expr(a, e.expr, depth, out);
+            out.push_str(&format!(" as {}, {} ", e.left, e.right));
+            pp_block(a, e.body, depth, out);
+        }
+        Expr::Free(e) => {
+            out.push_str(&format!("free {};", e.var));
+            newline(depth, out);
+            pp_expr(a, e.expr, depth, out);
+        }
+        Expr::Drop(e) => {
+            out.push_str("drop ");
+            pp_expr(a, e.expr1, depth, out);
+            out.push(';');
+            newline(depth, out);
+            pp_expr(a, e.expr2, depth, out);
+        }
+        Expr::App(e) => {
+            out.push('(');
+            pp_expr(a, e.expr1, depth, out);
+            out.push(' ');
+            pp_expr(a, e.expr2, depth, out);
+            out.push(')');
+        }
+        Expr::Var(v) => out.push_str(v),
+        Expr::Comment(e) => {
+            // コメントは 1 つずつ改行して, 式の前に出力する
+            for c in &e.comments {
+                out.push_str(&c.to_string());
+                newline(depth, out);
+            }
+            pp_expr(a, e.expr, depth, out);
+        }
+        Expr::QVal(e) => {
+            match e.qual {
+                Qual::Lin => out.push_str("lin "),
+                Qual::Un => out.push_str("un "),
+            }
+            match &e.val {
+                ValExpr::Bool(b) => out.push_str(&b.to_string()),
+                ValExpr::Pair(e1, e2) => {
+                    out.push('<');
+                    pp_expr(a, *e1, depth, out);
+                    out.push_str(", ");
+                    pp_expr(a, *e2, depth, out);
+                    out.push('>');
+                }
+                ValExpr::Fun(f) => {
+                    out.push_str(&format!("fn {} : {} ", f.var, f.ty));
+                    pp_block(a, f.expr, depth, out);
+                }
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod pretty_print {
+    use super::*;
+    use crate::parser::{parse_expr, parse_expr_with_comments};
+    use std::fs;
+
+    #[test]
+    fn test_pretty_print() {
+        // 整形済みのサンプルコードは, パースして整形すると元に戻る
+        for n in 1..=8 {
+            let content = fs::read_to_string(format!("codes/ex{n}.lin")).unwrap();
+            let (_, expr) = parse_expr(&content).unwrap();
+            assert_eq!(pretty_print(&expr), content.trim_end());
+        }
+
+        // 整形結果を再度パースすると同じ AST になる
+        let (_, expr) = parse_expr("let x:un bool=un true;(un fn y:un bool{y} x)").unwrap();
+        assert_eq!(
+            pretty_print(&expr),
+            "let x : un bool = un true;\n(un fn y : un bool {\n    y\n} x)"
+        );
+        assert_eq!(parse_expr(&pretty_print(&expr)), Ok(("", expr)));
+
+        // 式の直前のコメントは整形後も残る
+        let src = "// f\nlin fn x : lin bool { /* a */ free x; // b\n lin false }";
+        let (_, expr) = parse_expr_with_comments(src).unwrap();
+        let pp = pretty_print(&expr);
+        assert_eq!(
+            pp,
+            "// f\nlin fn x : lin bool {\n    /* a */\n    free x;\n    // b\n    lin false\n}"
+        );
+        assert_eq!(parse_expr_with_comments(&pp), Ok(("", expr)));
+    }
+}
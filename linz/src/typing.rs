@@ -1,6 +1,15 @@
+//! 型付け
+//!
+//! 型付け規則ごとに typing_* 関数を持ち, [typing_traced] が式の種類に応じて呼び分ける。
+//! 各規則の関数のドキュメントには [dsl](crate::dsl) マクロで書いた例があり, `cargo test` で検査する。
 use crate::helper::*;
 use crate::lang;
-use std::{borrow::Cow, cmp::Ordering, collections::BTreeMap, mem};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    mem,
+};
 
 type VarToType = BTreeMap<String, Option<lang::TypeExpr>>;
 
@@ -44,10 +53,17 @@ impl TypeEnvStack {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// lin 型の変数の消費の記録
+/// (変数を定義した型環境の depth, 変数名, 消費前の型)
+type Consumed = (usize, String, lang::TypeExpr);
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct TypeEnv {
     env_lin: TypeEnvStack,
     env_un: TypeEnvStack,
+    journal: Vec<Consumed>,          // lin 型の変数を消費した順の記録
+    used: BTreeSet<(usize, String)>, // 利用された un 型の変数 (定義した型環境の depth, 変数名)
+    warnings: Vec<String>,           // 型付けは成功するが注意すべき点
 }
 
 impl TypeEnv {
@@ -55,6 +71,63 @@ impl TypeEnv {
         Self {
             env_lin: TypeEnvStack::new(),
             env_un: TypeEnvStack::new(),
+            journal: Vec::new(),
+            used: BTreeSet::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// 外側で定義された変数とその型を与えた型環境を返す
+    /// 変数は depth 0 の型環境に追加するため, 型付けは depth 0 から始める
+    pub fn with_vars(vars: impl IntoIterator<Item = (String, lang::TypeExpr)>) -> Self {
+        let mut env = Self::new();
+        env.push(0);
+        for (k, t) in vars {
+            env.insert(k, t);
+        }
+        env
+    }
+
+    /// 型付け中に出た警告を出現順に返す
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// lin 型の変数の消費を記録
+    fn record(&mut self, depth: usize, key: &str, ty: lang::TypeExpr) {
+        self.journal.push((depth, key.to_string(), ty));
+    }
+
+    /// 現在の記録の位置を返す
+    /// rollback と changes に渡して, この位置以降の消費を扱う
+    fn mark(&self) -> usize {
+        self.journal.len()
+    }
+
+    /// mark 以降に消費された変数のうち, depth 以下の型環境で定義されたものを返す
+    /// depth より深い型環境はすでに pop されているため含めない
+    fn changes(&self, mark: usize, depth: usize) -> BTreeSet<(usize, String)> {
+        self.journal[mark..]
+            .iter()
+            .filter(|(d, _, _)| *d <= depth)
+            .map(|(d, k, _)| (*d, k.clone()))
+            .collect()
+    }
+
+    /// mark 以降の消費を取り消し, 型環境を mark の時点に戻す
+    fn rollback(&mut self, mark: usize, depth: usize) {
+        for (d, k, ty) in self.journal.drain(mark..).rev() {
+            if d > depth {
+                continue;
+            }
+            if let Some(it) = self
+                .env_lin
+                .vars
+                .get_mut(&d)
+                .and_then(|env| env.get_mut(&k))
+            {
+                *it = Some(ty);
+            }
         }
     }
 
@@ -66,6 +139,7 @@ impl TypeEnv {
 
     /// 型環境を pop
     fn pop(&mut self, depth: usize) -> (Option<VarToType>, Option<VarToType>) {
+        self.used.retain(|(d, _)| *d != depth);
         let t1 = self.env_lin.pop(depth);
         let t2 = self.env_un.pop(depth);
 
@@ -80,16 +154,39 @@ impl TypeEnv {
         }
     }
 
-    /// lin と un の型環境から get_mut を呼び出し depth が大きい方を返す
-    fn get_mut(&mut self, key: &str) -> Option<&mut Option<lang::TypeExpr>> {
+    /// 現在利用できる変数とその型を, 変数名の順に返す
+    /// シャドウイングされた変数や, 消費済みの lin 型の変数は含まない
+    pub fn context(&self) -> Vec<(&str, &lang::TypeExpr)> {
+        // 変数名ごとに, 最も depth が大きい束縛を選ぶ
+        let mut vars: BTreeMap<&str, (usize, &Option<lang::TypeExpr>)> = BTreeMap::new();
+        for stack in [&self.env_lin, &self.env_un] {
+            for (depth, env) in stack.vars.iter() {
+                for (k, v) in env.iter() {
+                    match vars.get(k.as_str()) {
+                        Some((d, _)) if d > depth => (),
+                        _ => {
+                            vars.insert(k, (*depth, v));
+                        }
+                    }
+                }
+            }
+        }
+
+        vars.into_iter()
+            .filter_map(|(k, (_, v))| v.as_ref().map(|t| (k, t)))
+            .collect()
+    }
+
+    /// lin と un の型環境から get_mut を呼び出し depth が大きい方を, その depth とともに返す
+    fn get_mut(&mut self, key: &str) -> Option<(usize, &mut Option<lang::TypeExpr>)> {
         match (self.env_lin.get_mut(key), self.env_un.get_mut(key)) {
             (Some((d1, t1)), Some((d2, t2))) => match d1.cmp(&d2) {
-                Ordering::Less => Some(t2),
-                Ordering::Greater => Some(t1),
+                Ordering::Less => Some((d2, t2)),
+                Ordering::Greater => Some((d1, t1)),
                 Ordering::Equal => panic!("invalid type environment"),
             },
-            (Some((_, t1)), None) => Some(t1),
-            (None, Some((_, t2))) => Some(t2),
+            (Some(t1), None) => Some(t1),
+            (None, Some(t2)) => Some(t2),
             _ => None,
         }
     }
@@ -97,24 +194,79 @@ impl TypeEnv {
 
 type TResult<'a> = Result<lang::TypeExpr, Cow<'a, str>>;
 
+/// 型付けの過程を観察するためのフック
+///
+/// 部分式を含むすべての式について, 型付けの開始時に enter, 終了時に exit が呼ばれる
+pub trait Trace {
+    /// 式の型付けを開始する直前に, その時点の型環境とともに呼ばれる
+    /// 式はアリーナ a 中の id で与える
+    fn enter(&mut self, a: &lang::ExprArena, id: lang::ExprId, env: &TypeEnv);
+
+    /// 式の型付けが終了した直後に, 型付けの結果とともに呼ばれる
+    fn exit(&mut self, ret: Result<&lang::TypeExpr, &str>);
+}
+
+/// 何もしないフック
+impl Trace for () {
+    fn enter(&mut self, _a: &lang::ExprArena, _id: lang::ExprId, _env: &TypeEnv) {}
+    fn exit(&mut self, _ret: Result<&lang::TypeExpr, &str>) {}
+}
+
 /// 型付け関数
-/// 式を受け取り, 型を返す
-pub fn typing<'a>(expr: &lang::Expr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
-    match expr {
-        lang::Expr::App(e) => typing_app(e, env, depth),
-        lang::Expr::QVal(e) => typing_qval(e, env, depth),
-        lang::Expr::Free(e) => typing_free(e, env, depth),
-        lang::Expr::If(e) => typing_if(e, env, depth),
-        lang::Expr::Split(e) => typing_split(e, env, depth),
-        lang::Expr::Var(e) => typing_var(e, env, depth),
-        lang::Expr::Let(e) => typing_let(e, env, depth),
+/// AST を受け取り, 根の式の型を返す
+pub fn typing<'a>(ast: &lang::Ast, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
+    typing_traced(&ast.arena, ast.root, env, depth, &mut ())
+}
+
+/// フック付きの型付け関数
+/// アリーナ a 中の式 id を typing と同様に型付けし, 各部分式の型付けの前後で tr を呼び出す
+pub fn typing_traced<'a>(
+    a: &lang::ExprArena,
+    id: lang::ExprId,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
+    // コメントは型付けに影響しないため, 導出木にも現れないよう読み飛ばす
+    if let lang::Expr::Comment(e) = &a[id] {
+        return typing_traced(a, e.expr, env, depth, tr);
     }
+
+    tr.enter(a, id, env);
+    let ret = match &a[id] {
+        lang::Expr::App(e) => typing_app(a, e, env, depth, tr),
+        lang::Expr::QVal(e) => typing_qval(a, e, env, depth, tr),
+        lang::Expr::Free(e) => typing_free(a, e, env, depth, tr),
+        lang::Expr::Drop(e) => typing_drop(a, e, env, depth, tr),
+        lang::Expr::If(e) => typing_if(a, e, env, depth, tr),
+        lang::Expr::Split(e) => typing_split(a, e, env, depth, tr),
+        lang::Expr::Var(e) => typing_var(e, env, depth, tr),
+        lang::Expr::Let(e) => typing_let(a, e, env, depth, tr),
+        lang::Expr::Comment(_) => unreachable!(),
+    };
+    tr.exit(ret.as_ref().map_err(|e| e.as_ref()));
+    ret
 }
 
-/// 関数適用の型付け
-fn typing_app<'a>(expr: &lang::AppExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
+/// 関数適用の型付け (T-App)
+///
+/// 関数部分は関数型で, 引数の型は関数の引数の型と一致する必要がある。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("(lin fn x : lin bool { x } lin true)" => "lin bool");
+/// dsl!("(lin true un true)" => err "関数型でない");
+/// dsl!("(lin fn x : lin bool { x } un true)" => err "引数の型が異なる");
+/// ```
+fn typing_app<'a>(
+    a: &lang::ExprArena,
+    expr: &lang::AppExpr,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
     // 関数部分
-    let t1 = typing(&expr.expr1, env, depth)?;
+    let t1 = typing_traced(a, expr.expr1, env, depth, tr)?;
     let t_arg;
     let t_ret;
     match t1.prim {
@@ -126,7 +278,7 @@ fn typing_app<'a>(expr: &lang::AppExpr, env: &mut TypeEnv, depth: usize) -> TRes
     }
 
     // 引数部分
-    let t2 = typing(&expr.expr2, env, depth)?;
+    let t2 = typing_traced(a, expr.expr2, env, depth, tr)?;
 
     // 引数の型が一致しているかチェック
     if *t_arg == t2 {
@@ -136,15 +288,34 @@ fn typing_app<'a>(expr: &lang::AppExpr, env: &mut TypeEnv, depth: usize) -> TRes
     }
 }
 
-/// 修飾子付き値の型付け
-fn typing_qval<'a>(expr: &lang::QValExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
+/// 修飾子付き値の型付け (T-Bool, T-Pair, T-Abs)
+///
+/// un 型のペアは lin 型の値を含めない。
+/// 関数は本体で引数の lin 型の変数を消費する必要があり, un 型の関数は外側の lin 型の変数を利用できない。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("un true" => "un bool");
+/// dsl!("lin <lin true, un false>" => "lin (lin bool * un bool)");
+/// dsl!("un <lin true, un false>" => err "un型のペア内でlin型を利用している");
+/// dsl!("lin fn x : lin bool { x }" => "lin (lin bool -> lin bool)");
+/// dsl!("lin fn x : lin bool { un true }" => err r#"lin型の変数"x"を消費していない"#);
+/// dsl!("lin fn x : lin bool { un fn y : un bool { x } }" => err "キャプチャできない");
+/// ```
+fn typing_qval<'a>(
+    a: &lang::ExprArena,
+    expr: &lang::QValExpr,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
     // プリミティブ型を計算
     let p = match &expr.val {
         lang::ValExpr::Bool(_) => lang::PrimType::Bool,
         lang::ValExpr::Pair(e1, e2) => {
             // 式 e1 と e2 を typing により型付け
-            let t1 = typing(e1, env, depth)?;
-            let t2 = typing(e2, env, depth)?;
+            let t1 = typing_traced(a, *e1, env, depth, tr)?;
+            let t2 = typing_traced(a, *e2, env, depth, tr)?;
 
             // un 型のペアは lin 型の値を内包できないという制約がある
             if expr.qual == lang::Qual::Un
@@ -177,7 +348,7 @@ fn typing_qval<'a>(expr: &lang::QValExpr, env: &mut TypeEnv, depth: usize) -> TR
             env.insert(e.var.clone(), e.ty.clone());
 
             // 関数中の式を型付け
-            let t = typing(&e.expr, env, depth)?;
+            let t = typing_traced(a, e.expr, env, depth, tr)?;
 
             // スタックを pop し, pop した型環境の中に lin 型が含まれていた場合は
             // 消費されなかったということなのでエラー
@@ -207,12 +378,27 @@ fn typing_qval<'a>(expr: &lang::QValExpr, env: &mut TypeEnv, depth: usize) -> TR
     })
 }
 
-/// free 式の型付け
-fn typing_free<'a>(expr: &lang::FreeExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
-    if let Some((_, t)) = env.env_lin.get_mut(&expr.var) {
-        if t.is_some() {
-            *t = None;
-            return typing(&expr.expr, env, depth);
+/// free 式の型付け (T-Free)
+///
+/// 消費していない lin 型の変数のみを free できる。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("lin fn x : lin bool { free x; un true }" => "lin (lin bool -> un bool)");
+/// dsl!("lin fn x : lin bool { free x; free x; un true }" => err "すでにfree");
+/// dsl!("un fn x : un bool { free x; x }" => err "lin型ではない変数");
+/// ```
+fn typing_free<'a>(
+    a: &lang::ExprArena,
+    expr: &lang::FreeExpr,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
+    if let Some((d, t)) = env.env_lin.get_mut(&expr.var) {
+        if let Some(ty) = t.take() {
+            env.record(d, &expr.var, ty);
+            return typing_traced(a, expr.expr, env, depth, tr);
         }
     }
     Err(format!(
@@ -222,37 +408,123 @@ fn typing_free<'a>(expr: &lang::FreeExpr, env: &mut TypeEnv, depth: usize) -> TR
     .into())
 }
 
-/// if 式の型付け
-fn typing_if<'a>(expr: &lang::IfExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
-    let t1 = typing(&expr.cond_expr, env, depth)?;
+/// drop 式の型付け (T-Drop)
+///
+/// un 型の値のみを捨てられる。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("drop un true; lin false" => "lin bool");
+/// dsl!("drop lin true; un true" => err "lin型の値をdropしている");
+/// ```
+fn typing_drop<'a>(
+    a: &lang::ExprArena,
+    expr: &lang::DropExpr,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
+    // 捨てられるのは un 型の値のみ. lin 型の値は free で解放する
+    let t1 = typing_traced(a, expr.expr1, env, depth, tr)?;
+    if t1.qual == lang::Qual::Lin {
+        return Err(format!("lin型の値をdropしている (型は{t1})").into());
+    }
+    typing_traced(a, expr.expr2, env, depth, tr)
+}
+#[cfg(test)]
+mod typing_drop {
+    use super::*;
+    use crate::parser::parse_expr;
+
+    fn check(src: &str) -> TResult<'static> {
+        let (_, expr) = parse_expr(src).unwrap();
+        typing(&expr, &mut TypeEnv::new(), 0)
+    }
+
+    #[test]
+    fn test_typing_drop() {
+        let t = check("drop un <un true, un false>; lin true").unwrap();
+        assert_eq!(t.to_string(), "lin bool");
+        let t = check("lin fn x : lin bool { drop un fn y : un bool { y }; x }").unwrap();
+        assert_eq!(t.to_string(), "lin (lin bool -> lin bool)");
+
+        // lin 型の値は drop できない
+        assert!(check("drop lin true; un true").is_err());
+        assert!(check("lin fn x : lin bool { drop x; un true }").is_err());
+        // 捨てる式の中で lin 型の変数を消費してもよい
+        assert!(check(
+            "lin fn x : lin bool { drop (un fn y : lin bool { free y; un true } x); un true }"
+        )
+        .is_ok());
+    }
+}
+
+/// if 式の型付け (T-If)
+///
+/// 条件式は bool で, then 節と else 節は同じ型かつ同じ lin 型の変数を消費する必要がある。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("if un true { lin true } else { lin false }" => "lin bool");
+/// dsl!("if un <un true, un true> { un true } else { un true }" => err "boolでない");
+/// dsl!("if un true { lin true } else { un false }" => err "型が異なる");
+/// ```
+fn typing_if<'a>(
+    a: &lang::ExprArena,
+    expr: &lang::IfExpr,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
+    let t1 = typing_traced(a, expr.cond_expr, env, depth, tr)?;
     //条件式の型は bool
     if t1.prim != lang::PrimType::Bool {
         return Err("ifの条件式がboolでない".into());
     }
 
     // then と else で別々の式を同じ型環境で検査するため
-    // 型環境を clone してからそれぞれの式の型付けを行う
-    let mut e = env.clone();
-    let t2 = typing(&expr.then_expr, &mut e, depth)?;
-    let t3 = typing(&expr.else_expr, env, depth)?;
+    // then 節で消費した変数を記録しておき, else 節の型付けの前に取り消す
+    // 型環境を clone すると変数の数に比例した時間がかかるため, 消費の記録のみを扱う
+    let mark = env.mark();
+    let t2 = typing_traced(a, expr.then_expr, env, depth, tr)?;
+    let then_changes = env.changes(mark, depth);
+    env.rollback(mark, depth);
+    let t3 = typing_traced(a, expr.else_expr, env, depth, tr)?;
+    let else_changes = env.changes(mark, depth);
 
     // then と else 式の型は同じで
-    // then と else 式の評価後の型環境が同じかチェック
-    if t2 != t3 || e != *env {
+    // then と else 式で消費した変数が同じ (評価後の型環境が同じ) かチェック
+    if t2 != t3 || then_changes != else_changes {
         return Err("if式のthen節とelse節の式の型が異なる".into());
     }
 
     Ok(t2)
 }
 
-/// split 式の型付け
-fn typing_split<'a>(expr: &lang::SplitExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
+/// split 式の型付け (T-Split)
+///
+/// ペアを 2 つの変数に分解する。本体では lin 型の変数を消費する必要がある。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("split lin <lin true, un false> as x, y { x }" => "lin bool");
+/// dsl!("split lin <lin true, lin false> as x, y { x }" => err r#"lin型の変数"y"を消費していない"#);
+/// dsl!("split un true as x, y { x }" => err "ペア型でない");
+/// dsl!("split lin <lin true, un false> as x, x { x }" => err "変数名が同じ");
+/// ```
+fn typing_split<'a>(
+    a: &lang::ExprArena,
+    expr: &lang::SplitExpr,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
     // 同じ変数名は使えない制約がある
     if expr.left == expr.right {
         return Err("splitの変数名が同じ".into());
     }
 
-    let t1 = typing(&expr.expr, env, depth)?;
+    let t1 = typing_traced(a, expr.expr, env, depth, tr)?;
     let mut depth = depth;
     safe_add(&mut depth, &1, || "変数スコープのネストが深すぎる")?;
 
@@ -268,7 +540,7 @@ fn typing_split<'a>(expr: &lang::SplitExpr, env: &mut TypeEnv, depth: usize) ->
         }
     }
 
-    let ret = typing(&expr.body, env, depth);
+    let ret = typing_traced(a, expr.body, env, depth, tr);
 
     // 型環境をポップする(ローカル変数を削除)
     let (elin, _) = env.pop(depth);
@@ -284,10 +556,24 @@ fn typing_split<'a>(expr: &lang::SplitExpr, env: &mut TypeEnv, depth: usize) ->
     ret
 }
 
-/// 変数の型付け
-fn typing_var<'a>(expr: &str, env: &mut TypeEnv, _depth: usize) -> TResult<'a> {
+/// 変数の型付け (T-Var)
+///
+/// lin 型の変数は 1 度だけ, un 型の変数は何度でも利用できる。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("un fn x : un bool { un <x, x> }" => "un (un bool -> un (un bool * un bool))");
+/// dsl!("lin fn x : lin bool { lin <x, x> }" => err "利用済み");
+/// dsl!("x" => err "定義されていない");
+/// ```
+fn typing_var<'a>(
+    expr: &str,
+    env: &mut TypeEnv,
+    _depth: usize,
+    _tr: &mut dyn Trace,
+) -> TResult<'a> {
     let ret = env.get_mut(expr);
-    if let Some(it) = ret {
+    if let Some((d, it)) = ret {
         // 定義されている
         if let Some(t) = it {
             // 消費されていない
@@ -296,10 +582,13 @@ fn typing_var<'a>(expr: &str, env: &mut TypeEnv, _depth: usize) -> TResult<'a> {
                     // lin 型
                     let eret = t.clone();
                     *it = None; // lin を消費
+                    env.record(d, expr, eret.clone()); // if 式で取り消せるように記録
                     return Ok(eret);
                 }
                 lang::Qual::Un => {
-                    return Ok(t.clone());
+                    let eret = t.clone();
+                    env.used.insert((d, expr.to_string()));
+                    return Ok(eret);
                 }
             }
         }
@@ -312,10 +601,25 @@ fn typing_var<'a>(expr: &str, env: &mut TypeEnv, _depth: usize) -> TResult<'a> {
     .into())
 }
 
-/// let 式の型付け
-fn typing_let<'a>(expr: &lang::LetExpr, env: &mut TypeEnv, depth: usize) -> TResult<'a> {
+/// let 式の型付け (T-Let)
+///
+/// 束縛する式は指定した型で, 本体では lin 型の変数を消費する必要がある。
+///
+/// ```
+/// # use linz::dsl;
+/// dsl!("let x : un bool = un true; un <x, x>" => "un (un bool * un bool)");
+/// dsl!("let x : lin bool = un true; x" => err r#"変数"x"の型が異なる"#);
+/// dsl!("let x : lin bool = lin true; un true" => err "消費していない");
+/// ```
+fn typing_let<'a>(
+    a: &lang::ExprArena,
+    expr: &lang::LetExpr,
+    env: &mut TypeEnv,
+    depth: usize,
+    tr: &mut dyn Trace,
+) -> TResult<'a> {
     // 変数束縛
-    let t1 = typing(&expr.expr1, env, depth)?;
+    let t1 = typing_traced(a, expr.expr1, env, depth, tr)?;
     // 束縛変数の型をチェック
     if t1 != expr.ty {
         return Err(format!(r#"変数"{}"の型が異なる"#, expr.var).into());
@@ -326,7 +630,19 @@ fn typing_let<'a>(expr: &lang::LetExpr, env: &mut TypeEnv, depth: usize) -> TRes
     safe_add(&mut depth, &1, || "変数スコープのネストが深すぎる")?;
     env.push(depth);
     env.insert(expr.var.clone(), t1); // 変数の型を insert
-    let t2 = typing(&expr.expr2, env, depth)?;
+    let t2 = typing_traced(a, expr.expr2, env, depth, tr)?;
+
+    // un 型の値を束縛したまま使わないと, 値が黙って捨てられる
+    // 捨てることを明示する場合は drop を, 意図して使わない場合は _ で始まる変数名を使う
+    if expr.ty.qual == lang::Qual::Un
+        && !expr.var.starts_with('_')
+        && !env.used.contains(&(depth, expr.var.clone()))
+    {
+        env.warnings.push(format!(
+            r#"un型の変数"{}"を利用していない。値を捨てる場合はdropを使う"#,
+            expr.var
+        ));
+    }
 
     // ポップした型環境の中に lin 型の変数が残っていないかをチェック
     // 残っていたら消費していない lin 型の値があるということなのでエラー
@@ -339,3 +655,79 @@ fn typing_let<'a>(expr: &lang::LetExpr, env: &mut TypeEnv, depth: usize) -> TRes
 
     Ok(t2)
 }
+#[cfg(test)]
+mod typing_let {
+    use super::*;
+    use crate::parser::parse_expr;
+
+    fn warnings(src: &str) -> Vec<String> {
+        let (_, expr) = parse_expr(src).unwrap();
+        let mut env = TypeEnv::new();
+        typing(&expr, &mut env, 0).unwrap();
+        env.warnings().to_vec()
+    }
+
+    #[test]
+    fn test_warnings() {
+        // 利用していない un 型の変数
+        assert_eq!(
+            warnings("let x : un bool = un true; lin false"),
+            vec![r#"un型の変数"x"を利用していない。値を捨てる場合はdropを使う"#]
+        );
+        // 利用している, drop している, _ で始まる変数名の場合は警告しない
+        assert!(warnings("let x : un bool = un true; x").is_empty());
+        assert!(warnings("let x : un bool = un true; drop x; lin false").is_empty());
+        assert!(warnings("let _x : un bool = un true; lin false").is_empty());
+        // 関数内や if 式の片方での利用も利用とみなす
+        assert!(warnings("let x : un bool = un true; un fn y : un bool { x }").is_empty());
+        assert!(
+            warnings("let x : un bool = un true; if un true { x } else { un false }").is_empty()
+        );
+        // シャドウイングした内側の変数の利用は, 外側の変数の利用とみなさない
+        assert_eq!(
+            warnings("let x : un bool = un true; let x : un bool = un false; x").len(),
+            1
+        );
+        // lin 型の変数は消費しないとエラーになるため警告しない
+        assert!(warnings("let x : lin bool = lin true; x").is_empty());
+    }
+}
+#[cfg(test)]
+mod typing_if {
+    use super::*;
+    use crate::parser::parse_expr;
+
+    fn check(src: &str) -> bool {
+        let (_, expr) = parse_expr(src).unwrap();
+        typing(&expr, &mut TypeEnv::new(), 0).is_ok()
+    }
+
+    #[test]
+    fn test_typing_if() {
+        // then 節と else 節で同じ変数を消費する
+        assert!(check(
+            "lin fn x : lin bool { if un true { free x; un true } else { free x; un false } }"
+        ));
+        // 消費する変数が異なる
+        assert!(!check(
+            "lin fn x : lin bool { lin fn y : lin bool { if un true { free x; y } else { free y; lin true } } }"
+        ));
+        // then 節でのみ消費する
+        assert!(!check(
+            "lin fn x : lin bool { if un true { free x; un true } else { un false } }"
+        ));
+
+        // ネストした if 式でも, then 節の消費は else 節の型付けの前に取り消される
+        assert!(check(
+            "lin fn x : lin bool { if un true { if un true { x } else { x } } else { x } }"
+        ));
+        assert!(!check(
+            "lin fn x : lin bool { if un true { if un true { x } else { lin true } } else { x } }"
+        ));
+
+        // then 節内のスコープで定義して消費した変数は比較しない
+        assert!(check(
+            "lin fn x : lin bool { if un true { let y : lin bool = lin true; free y; x } else { x } }"
+        ));
+    }
+}
@@ -0,0 +1,87 @@
+//! ブラウザ上のプレイグラウンド向けの API
+//!
+//! 検査の結果を JSON の文字列として返すため, JavaScript からは `JSON.parse` するだけで扱える。
+//! wasm32 向けにビルドした場合は wasm-bindgen により JavaScript へ公開する。
+//!
+//! ```text
+//! cargo rustc -p linz --lib --release --features wasm --target wasm32-unknown-unknown --crate-type cdylib
+//! ```
+//!
+//! `wasm` フィーチャを有効にした場合のみコンパイルする。
+use crate::check::{self, Diagnostic};
+use std::fmt::Write;
+
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// JSON の文字列リテラルとしてエスケープする
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 診断を JSON のオブジェクトに変換する
+fn diagnostic_json(d: &Diagnostic) -> String {
+    let pos = match d.pos {
+        Some((line, col)) => format!("\"line\":{line},\"column\":{col}"),
+        None => "\"line\":null,\"column\":null".to_string(),
+    };
+    format!(
+        "{{\"phase\":\"{}\",\"message\":{},{pos}}}",
+        d.phase.as_str(),
+        escape(&d.message)
+    )
+}
+
+/// ソースコードを検査し, 結果を JSON で返す
+///
+/// 成功した場合は `{"type":"lin bool","diagnostics":[]}`,
+/// 失敗した場合は `{"type":null,"diagnostics":[{"phase":"parse","message":"...","line":1,"column":5}]}`
+/// となる。型付けの診断は位置を持たないため line と column は null とする。
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn check_source_json(src: &str) -> String {
+    match check::check_source(src) {
+        Ok(t) => format!("{{\"type\":{},\"diagnostics\":[]}}", escape(&t.to_string())),
+        Err(d) => format!(
+            "{{\"type\":null,\"diagnostics\":[{}]}}",
+            diagnostic_json(&d)
+        ),
+    }
+}
+#[cfg(test)]
+mod check_source_json {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            check_source_json("lin fn x : lin bool { x }"),
+            r#"{"type":"lin (lin bool -> lin bool)","diagnostics":[]}"#
+        );
+
+        let json = check_source_json("let x : lin bool = lin true;\n  lin false");
+        assert!(json.starts_with(r#"{"type":null,"diagnostics":[{"phase":"type","message":""#));
+        assert!(json.ends_with(r#","line":null,"column":null}]}"#));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\"b\\c\nd\u{1}"), r#""a\"b\\c\nd\u0001""#);
+        assert_eq!(escape("型"), "\"型\"");
+    }
+}
@@ -172,7 +172,7 @@ mod literal {
 }
 
 #[allow(dead_code)]
-fn identifier(input: &str) -> ParseResult<String> {
+fn identifier(input: &str) -> ParseResult<'_, String> {
     let mut matched = String::new();
     let mut chars = input.chars();
 
@@ -181,7 +181,7 @@ fn identifier(input: &str) -> ParseResult<String> {
         _ => return Err(input),
     }
 
-    while let Some(next) = chars.next() {
+    for next in chars {
         if next.is_alphabetic() || next == '-' {
             matched.push(next);
         } else {
@@ -287,9 +287,9 @@ mod map {
     }
 }
 
-pub fn int32<'a>(i: &str) -> ParseResult<i32> {
+pub fn int32(i: &str) -> ParseResult<'_, i32> {
     let (i, sign) = opt(char('-')).parse(i)?;
-    let (i, digits) = one_or_more(pred(any_char, |c| c.is_digit(10))).parse(i)?;
+    let (i, digits) = one_or_more(pred(any_char, |c| c.is_ascii_digit())).parse(i)?;
     let num = digits
         .into_iter()
         .collect::<String>()
@@ -443,7 +443,7 @@ mod zero_or_more {
     }
 }
 
-pub fn any_char(input: &str) -> ParseResult<char> {
+pub fn any_char(input: &str) -> ParseResult<'_, char> {
     match input.chars().next() {
         Some(next) => Ok((&input[next.len_utf8()..], next)),
         _ => Err(input),
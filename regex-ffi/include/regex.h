@@ -0,0 +1,25 @@
+/* regex クレートの C ABI。 src/lib.rs の関数のシグネチャを変更した場合はこちらも合わせて変更する */
+#ifndef REGEX_H
+#define REGEX_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* コンパイル済みの正規表現 */
+typedef struct Regex Regex;
+
+/* 正規表現をコンパイルする。 expr が NULL, UTF-8 でない, 構文エラーの場合は NULL を返す */
+Regex *regex_compile(const char *expr);
+
+/* マッチする場合は 1, しない場合は 0, 引数が不正またはマッチングに失敗した場合は -1 を返す */
+int regex_is_match(const Regex *re, const char *text);
+
+/* regex_compile が返した正規表現を解放する。 NULL の場合は何もしない */
+void regex_free(Regex *re);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* REGEX_H */
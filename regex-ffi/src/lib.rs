@@ -0,0 +1,115 @@
+//! regex クレートを C から呼び出すための最小限の ABI。
+//!
+//! 共有ライブラリ `libregex_ffi.so` として生成する。
+//! [Regex] を不透明なポインタとして扱い、コンパイル, マッチング, 解放の 3 つの関数のみを公開する。
+//! 文字列は NUL 終端の UTF-8 とする。ヘッダは `include/regex.h` に置く。
+use regex::Regex;
+use std::ffi::{c_char, c_int, CStr};
+
+/// C 用のヘッダ `include/regex.h` 。関数のシグネチャを変更した場合はヘッダも合わせて変更する
+pub const HEADER: &str = include_str!("../include/regex.h");
+
+/// NUL 終端の文字列を &str に変換する。 NULL や UTF-8 でない場合は None
+///
+/// # Safety
+///
+/// s は NULL または NUL 終端の文字列を指すこと。
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// 正規表現をコンパイルする。失敗した場合は NULL を返す。
+///
+/// # Safety
+///
+/// expr は NULL または NUL 終端の文字列を指すこと。
+/// 返したポインタは [regex_free] で解放すること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_compile(expr: *const c_char) -> *mut Regex {
+    match to_str(expr).map(Regex::new) {
+        Some(Ok(re)) => Box::into_raw(Box::new(re)),
+        _ => std::ptr::null_mut(),
+    }
+}
+
+/// マッチする場合は 1, しない場合は 0, 引数が不正またはマッチングに失敗した場合は -1 を返す。
+///
+/// # Safety
+///
+/// re は NULL または [regex_compile] が返した未解放のポインタ、
+/// text は NULL または NUL 終端の文字列を指すこと。
+#[no_mangle]
+pub unsafe extern "C" fn regex_is_match(re: *const Regex, text: *const c_char) -> c_int {
+    let (Some(re), Some(text)) = (re.as_ref(), to_str(text)) else {
+        return -1;
+    };
+    match re.is_match(text) {
+        Ok(true) => 1,
+        Ok(false) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// [regex_compile] が返した正規表現を解放する。 NULL の場合は何もしない。
+///
+/// # Safety
+///
+/// re は NULL または [regex_compile] が返した未解放のポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_free(re: *mut Regex) {
+    if !re.is_null() {
+        drop(Box::from_raw(re));
+    }
+}
+#[cfg(test)]
+mod regex_is_match {
+    use super::*;
+    use std::{ffi::CString, ptr};
+
+    #[test]
+    fn test() {
+        let expr = CString::new("a(bc)+").unwrap();
+        let yes = CString::new("xabcbc").unwrap();
+        let no = CString::new("xac").unwrap();
+        unsafe {
+            let re = regex_compile(expr.as_ptr());
+            assert!(!re.is_null());
+            assert_eq!(regex_is_match(re, yes.as_ptr()), 1);
+            assert_eq!(regex_is_match(re, no.as_ptr()), 0);
+
+            // NULL は不正な引数
+            assert_eq!(regex_is_match(re, ptr::null()), -1);
+            assert_eq!(regex_is_match(ptr::null(), yes.as_ptr()), -1);
+
+            // UTF-8 でない文字列は不正な引数
+            let invalid = CString::new(vec![b'a', 0xff, b'b']).unwrap();
+            assert_eq!(regex_is_match(re, invalid.as_ptr()), -1);
+            assert!(regex_compile(invalid.as_ptr()).is_null());
+
+            regex_free(re);
+        }
+    }
+
+    #[test]
+    fn test_compile_error() {
+        let expr = CString::new("a(b").unwrap();
+        unsafe {
+            assert!(regex_compile(expr.as_ptr()).is_null());
+            assert!(regex_compile(ptr::null()).is_null());
+
+            // NULL の解放は何もしない
+            regex_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        // 公開しているすべての関数をヘッダで宣言しているか
+        for f in ["regex_compile", "regex_is_match", "regex_free"] {
+            assert!(HEADER.contains(&format!("{f}(")), "{f}");
+        }
+    }
+}
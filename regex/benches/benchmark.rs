@@ -1,8 +1,20 @@
-use criterion::{criterion_group, criterion_main, Criterion};
-use regex::do_matching;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use regex::{do_matching, MatchStrategy};
 use std::time::Duration;
 
+/// 比較するマッチングの方式
+const STRATEGIES: &[(&str, MatchStrategy)] = &[
+    ("Depth First", MatchStrategy::Dfs),
+    ("Width First", MatchStrategy::Bfs),
+    ("Memoized", MatchStrategy::Memo),
+    ("DFA", MatchStrategy::Dfa),
+    ("Hybrid", MatchStrategy::Hybrid),
+    ("Derivatives", MatchStrategy::Derivatives),
+];
+
 /// (計測の id, a?^n a^n という正規表現, 文字列) というタプル
+///
+/// 深さ優先探索ではバックトラックが 2^n 回となる。
 const INPUTS: &[(&str, &str, &str)] = &[
     ("n = 2", "a?a?aa", "aa"),
     ("n = 4", "a?a?a?a?aaaa", "aaaa"),
@@ -11,27 +23,50 @@ const INPUTS: &[(&str, &str, &str)] = &[
     ("n = 10", "a?a?a?a?a?a?a?a?a?a?aaaaaaaaaa", "aaaaaaaaaa"),
 ];
 
-fn depth_first(c: &mut Criterion) {
-    let mut g = c.benchmark_group("Depth First");
+/// (計測の id, 正規表現, 文字列) というタプル
+///
+/// 文字列は実行時に生成するため、 (先頭, 繰り返す部分, 繰り返す回数, 末尾) で指定する。
+type Realistic<'a> = (&'a str, &'a str, (&'a str, &'a str, usize, &'a str));
+
+const REALISTIC: &[Realistic] = &[
+    (
+        "keyword",
+        "(fn|let|match|impl|struct) ",
+        ("", "", 0, "match x {"),
+    ),
+    (
+        "request",
+        "(GET|POST|PUT) /(a|b|c|/)* HTTP",
+        ("GET /", "a/bc/", 20, " HTTP/1.1"),
+    ),
+    ("repeat", "(ab|cd)+e", ("", "abcd", 100, "e")),
+];
+
+fn pathological(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Pathological");
     g.measurement_time(Duration::from_secs(12));
 
-    for i in INPUTS {
-        g.bench_with_input(i.0, &(i.1, i.2), |b, args| {
-            b.iter(|| do_matching(args.0, args.1, true))
-        });
+    for (name, strategy) in STRATEGIES {
+        for i in INPUTS {
+            g.bench_with_input(BenchmarkId::new(*name, i.0), &(i.1, i.2), |b, args| {
+                b.iter(|| do_matching(args.0, args.1, *strategy))
+            });
+        }
     }
 }
 
-fn width_first(c: &mut Criterion) {
-    let mut g = c.benchmark_group("Width First");
-    g.measurement_time(Duration::from_secs(12));
+fn realistic(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Realistic");
 
-    for i in INPUTS {
-        g.bench_with_input(i.0, &(i.1, i.2), |b, args| {
-            b.iter(|| do_matching(args.0, args.1, false))
-        });
+    for (name, strategy) in STRATEGIES {
+        for (id, expr, (head, body, n, tail)) in REALISTIC {
+            let line = format!("{head}{}{tail}", body.repeat(*n));
+            g.bench_with_input(BenchmarkId::new(*name, id), &(*expr, line), |b, args| {
+                b.iter(|| do_matching(args.0, &args.1, *strategy))
+            });
+        }
     }
 }
 
-criterion_group!(benches, width_first, depth_first);
+criterion_group!(benches, pathological, realistic);
 criterion_main!(benches);
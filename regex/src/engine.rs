@@ -1,32 +1,38 @@
-use std::fmt::Display;
+use std::{io::BufRead, ops::Range};
 
+pub mod ast;
+pub mod captures;
+pub mod casefold;
+pub mod class;
 pub mod codegen;
-pub mod evaluator;
+pub mod compile;
+pub mod derivative;
+pub mod dfa;
+pub mod error;
+#[cfg(feature = "oracle")]
+pub mod oracle;
 pub mod parser;
+pub mod pretty;
+mod selfcheck;
+pub mod spec;
+pub mod vm;
 use crate::helper::DynError;
+use parser::AST;
 
-#[derive(Debug)]
-pub enum Instruction {
-    Char(char),
-    Match,
-    Jump(usize),
-    Split(usize, usize),
-}
-
-impl Display for Instruction {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Instruction::Char(c) => write!(f, "char {c}"),
-            Instruction::Match => write!(f, "match"),
-            Instruction::Jump(addr) => write!(f, "jump {addr:>04}"),
-            Instruction::Split(addr1, addr2) => write!(f, "split {addr1:>04} {addr2:>04}"),
-        }
-    }
-}
+pub use captures::Captures;
+pub use compile::Instruction;
+pub use error::Span;
+pub use selfcheck::self_check;
+pub use vm::MatchStats;
+
+#[cfg(feature = "oracle")]
+pub use oracle::oracle_check;
 
 /// 正規表現をパースしてコード生成し、
 /// ASTと命令列を標準出力に表示。
 ///
+/// AST は木の形式で、命令列はジャンプ先にラベルを付けた形式で表示する。
+///
 /// # 利用例
 ///
 /// ```
@@ -40,32 +46,91 @@ impl Display for Instruction {
 pub fn print(expr: &str) -> Result<(), DynError> {
     println!("expr: {expr}");
     let ast = parser::parse(expr)?;
-    println!("AST: {ast:?}");
+    println!("AST:");
+    print!("{}", pretty::ast_tree(&ast));
 
     println!();
     println!("code:");
     let code = codegen::get_code(&ast)?;
-    for (n, c) in code.iter().enumerate() {
-        println!("{n:>04}: {c}");
-    }
+    print!("{}", pretty::code_listing(&code));
 
     Ok(())
 }
 
-/// 正規表現と文字列をマッチング。
+/// 正規表現をパースしてコード生成し、命令列が表すオートマトンを Graphviz の DOT 形式で返す。
 ///
 /// # 利用例
 ///
 /// ```
 /// use regex;
-/// regex::do_matching("abc|(de|cd)+", "decddede", true);
+/// let dot = regex::to_dot("a(bc)*").unwrap();
+/// assert!(dot.starts_with("digraph regex {"));
+/// ```
+///
+/// 出力を `dot -Tpng` などに渡すと画像として表示できる。
+///
+/// # 返り値
+///
+/// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合は Err を返す。
+pub fn to_dot(expr: &str) -> Result<String, DynError> {
+    let ast = parser::parse(expr)?;
+    let code = codegen::get_code(&ast)?;
+    Ok(pretty::code_to_dot(&code))
+}
+
+/// マッチングの方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchStrategy {
+    /// 深さ優先探索。バックトラックが指数的に増えるパターンでは遅くなる。
+    Dfs,
+    /// 幅優先探索。ただし、この実装は未探索の分岐を保持しておき、失敗した場合は最後に保持した分岐から
+    /// 再開するため、深さ優先探索と同じ順序で探索する。 Dfs と同様に、バックトラックが指数的に増える
+    /// パターンでは遅くなる。
+    Bfs,
+    /// メモ化した深さ優先探索。探索済みの (プログラムカウンタ, 入力文字列の位置) を再び探索しない。
+    Memo,
+    /// DFA 。構成に時間がかかるが、マッチングは入力文字列の長さに比例する時間で終了する。
+    /// アサーションや文字クラスを含む正規表現には使えない。
+    Dfa,
+    /// DFA を構成できる場合は DFA 、できない場合はメモ化した深さ優先探索を用いる。
+    ///
+    /// 交差や補集合を含み、 DFA で扱えないアサーションや文字クラスも含む場合は Brzozowski の微分を用いる。
+    /// DFA の状態数が上限を超える場合、交差や補集合を含む正規表現は [dfa::DfaError::TooManyStates] となる。
+    Hybrid,
+    /// Brzozowski の微分。命令列を生成せず、 AST を入力文字で順に微分してマッチングする。
+    /// 交差、補集合、アサーション、文字クラスのいずれも扱える。
+    Derivatives,
+}
+
+impl From<bool> for MatchStrategy {
+    /// 従来の is_depth 引数からの変換。 true は深さ優先探索、 false は幅優先探索。
+    fn from(is_depth: bool) -> Self {
+        if is_depth {
+            MatchStrategy::Dfs
+        } else {
+            MatchStrategy::Bfs
+        }
+    }
+}
+
+/// 正規表現と文字列をマッチング。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::MatchStrategy;
+/// regex::do_matching("abc|(de|cd)+", "decddede", MatchStrategy::Dfs);
+///
+/// for s in [MatchStrategy::Bfs, MatchStrategy::Memo, MatchStrategy::Dfa, MatchStrategy::Hybrid, MatchStrategy::Derivatives] {
+///     assert!(regex::do_matching("a?a?aa", "aa", s).unwrap());
+/// }
 /// ```
 ///
 /// # 引数
 ///
-/// expr に正規表現、 line にマッチング対象の文字列を指定。
-/// is_depth に true を指定すると深さ優先探索、 false を指定すると幅優先探索でマッチングを行う。
+/// expr に正規表現、 line にマッチング対象の文字列、 strategy にマッチングの方式を指定。
 ///
+/// `&` と `~` は通常の文字とする。交差と補集合の演算子として扱う場合は [do_matching_extended] を用いる。
 ///
 /// # 戻り値
 ///
@@ -73,9 +138,514 @@ pub fn print(expr: &str) -> Result<(), DynError> {
 /// エラーなく実行でき、かつマッチングに **失敗** した場合は Ok(false) を返す。
 ///
 /// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合は Err を返す。
-pub fn do_matching(expr: &str, line: &str, is_depth: bool) -> Result<bool, DynError> {
+/// [MatchStrategy::Dfa] を指定し、正規表現がアサーションや文字クラスを含む場合も Err を返す。
+pub fn do_matching(expr: &str, line: &str, strategy: MatchStrategy) -> Result<bool, DynError> {
+    match_ast(&parser::parse(expr)?, line, strategy)
+}
+
+/// 交差 `&` と補集合 `~` を演算子として扱い、正規表現と文字列をマッチング (実験的)。
+///
+/// 正規表現が交差や補集合を含む場合は、 [MatchStrategy::Derivatives] と [MatchStrategy::Hybrid]
+/// 以外では strategy によらず DFA でマッチングを行う。その他は [do_matching] と同じ。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::MatchStrategy;
+/// // a と b の並びのうち、 aa を含まないもの
+/// let expr = "((a|b)*&~((a|b)*aa(a|b)*))$";
+/// assert!(regex::do_matching_extended(expr, "abab", MatchStrategy::Hybrid).unwrap());
+/// assert!(!regex::do_matching_extended(expr, "abaa", MatchStrategy::Derivatives).unwrap());
+/// ```
+pub fn do_matching_extended(
+    expr: &str,
+    line: &str,
+    strategy: MatchStrategy,
+) -> Result<bool, DynError> {
+    match_ast(&parser::parse_extended(expr)?, line, strategy)
+}
+
+/// パースした正規表現と文字列を strategy でマッチング。
+fn match_ast(ast: &AST, line: &str, strategy: MatchStrategy) -> Result<bool, DynError> {
+    let line = line.chars().collect::<Vec<char>>();
+    let strategy = match strategy {
+        MatchStrategy::Derivatives => return Ok(derivative::is_match(ast, &line)),
+        MatchStrategy::Hybrid => match dfa::Dfa::new(ast) {
+            Ok(dfa) => return Ok(dfa.is_match(&line)),
+            Err(dfa::DfaError::UnsupportedAssertion | dfa::DfaError::UnsupportedClass)
+                if ast.requires_dfa() =>
+            {
+                return Ok(derivative::is_match(ast, &line))
+            }
+            Err(e) if ast.requires_dfa() => return Err(e.into()),
+            Err(_) => MatchStrategy::Memo,
+        },
+        _ if ast.requires_dfa() => MatchStrategy::Dfa,
+        s => s,
+    };
+    if strategy == MatchStrategy::Dfa {
+        return Ok(dfa::Dfa::new(ast)?.is_match(&line));
+    }
+
+    let code = codegen::get_code(ast)?;
+    let end = match strategy {
+        MatchStrategy::Dfs => vm::find(&code, &line, true)?,
+        MatchStrategy::Bfs => vm::find(&code, &line, false)?,
+        MatchStrategy::Memo => vm::find_memo_at(&code, &line, 0)?,
+        MatchStrategy::Dfa | MatchStrategy::Hybrid | MatchStrategy::Derivatives => unreachable!(),
+    };
+    Ok(end.is_some())
+}
+
+/// is_depth で探索方法を指定して正規表現と文字列をマッチング。
+///
+/// is_depth が true の場合は [MatchStrategy::Dfs] 、 false の場合は [MatchStrategy::Bfs] を
+/// 指定して [do_matching] を呼び出す。
+#[deprecated(note = "use `do_matching` with `MatchStrategy` instead")]
+pub fn do_matching_bool(expr: &str, line: &str, is_depth: bool) -> Result<bool, DynError> {
+    do_matching(expr, line, is_depth.into())
+}
+
+/// 正規表現と文字列をマッチング。マッチング方式の指定を省略した [do_matching] 。
+///
+/// [MatchStrategy::Hybrid] を指定して [do_matching] を呼び出す。 [do_matching] と同様に文字列の先頭からマッチングする。
+/// アサーション, 文字クラスを含む正規表現も扱える。 [do_matching] と同様に `&` と `~` は通常の文字とする。
+///
+/// # 利用例
+///
+/// ```
+/// # fn main() -> Result<(), regex::helper::DynError> {
+/// assert!(regex::matched("a(bc)+", "abcbcx")?);
+/// assert!(!regex::matched("bc", "abcbcx")?);
+/// assert!(regex::matched("a(b", "ab").is_err());
+/// # Ok(())
+/// # }
+/// ```
+pub fn matched(expr: &str, line: &str) -> Result<bool, DynError> {
+    do_matching(expr, line, MatchStrategy::Hybrid)
+}
+
+/// トレースモードで正規表現と文字列をマッチング。
+///
+/// [do_matching] と同様にマッチングを行い、 VM が命令を 1 つ実行するごとに、
+/// スレッド ID 、プログラムカウンタ、命令、入力文字列の位置を引数として trace を呼び出す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex;
+/// let mut steps = Vec::new();
+/// let m = regex::do_matching_traced("ab|ac", "ac", true, |step| steps.push(step.to_string()));
+/// assert!(m.unwrap());
+/// for s in &steps {
+///     println!("{s}");
+/// }
+/// assert_eq!(steps.last().unwrap(), "thread 1: 0006: match (sp = 2)");
+/// ```
+///
+/// # 戻り値
+///
+/// [do_matching] と同じ。
+pub fn do_matching_traced<F>(
+    expr: &str,
+    line: &str,
+    is_depth: bool,
+    mut trace: F,
+) -> Result<bool, DynError>
+where
+    F: FnMut(&vm::Step),
+{
     let ast = parser::parse(expr)?;
     let code = codegen::get_code(&ast)?;
     let line = line.chars().collect::<Vec<char>>();
-    Ok(evaluator::eval(&code, &line, is_depth)?)
+    Ok(vm::find_traced(&code, &line, is_depth, &mut trace)?.is_some())
+}
+
+/// 行頭から 1 文字ずつずらしてマッチングを行い、いずれかにマッチした場合に true を返す。
+///
+/// 例えば、 abcd という文字列があった場合、 abcd, bcd, cd, d の順にマッチングを行う。
+/// マッチングにかかった手間は stats に加算する。
+fn match_line(
+    code: &[Instruction],
+    line: &[char],
+    stats: &mut MatchStats,
+) -> Result<bool, DynError> {
+    for j in 0..line.len() {
+        if vm::find_at_stats(code, line, j, true, stats)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// コンパイル済みの正規表現。
+///
+/// 一度コンパイルした命令列を使い回してマッチングを行う。
+/// serde フィーチャを有効にすると、命令列をファイルに保存して後から読み込むことができる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::Regex;
+/// let re = Regex::new("a(bc)+").unwrap();
+/// assert!(re.is_match("xabcbc").unwrap());
+/// assert!(!re.is_match("xac").unwrap());
+/// ```
+#[derive(Debug, PartialEq, Eq)]
+pub struct Regex {
+    code: Vec<Instruction>,
+}
+
+impl Regex {
+    /// 正規表現をパースしてコード生成する。
+    ///
+    /// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合は Err を返す。
+    pub fn new(expr: &str) -> Result<Self, DynError> {
+        Self::with_options(expr, &MatchOptions::default())
+    }
+
+    /// オプションを指定してコード生成する。 opts の invert は無視する。
+    pub fn with_options(expr: &str, opts: &MatchOptions) -> Result<Self, DynError> {
+        Ok(Self {
+            code: compile::compile(expr, opts)?,
+        })
+    }
+
+    /// 命令列を返す。
+    pub fn code(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    /// 文字列中のいずれかの位置からマッチする場合に true を返す。
+    pub fn is_match(&self, line: &str) -> Result<bool, DynError> {
+        self.is_match_with_stats(line, &mut MatchStats::default())
+    }
+
+    /// is_match と同様にマッチングを行い、かかった手間を stats に加算する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::{MatchStats, Regex};
+    /// let re = Regex::new("a?a?a?aaa").unwrap();
+    /// let mut stats = MatchStats::default();
+    /// assert!(re.is_match_with_stats("aaa", &mut stats).unwrap());
+    /// assert_eq!(stats.backtracks, 7);
+    /// ```
+    pub fn is_match_with_stats(
+        &self,
+        line: &str,
+        stats: &mut MatchStats,
+    ) -> Result<bool, DynError> {
+        let line = line.chars().collect::<Vec<char>>();
+        match_line(&self.code, &line, stats)
+    }
+
+    /// 重なりのないすべてのマッチの位置を返す。
+    ///
+    /// 多くの行に対して用いる場合に、行ごとにコード生成しなくて済む。その他は [find_all] と同じ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// let re = Regex::new("ab").unwrap();
+    /// assert_eq!(re.find_all("xabyab").unwrap(), vec![1..3, 4..6]);
+    /// assert_eq!(re.find_all("ba").unwrap(), vec![]);
+    /// ```
+    pub fn find_all(&self, line: &str) -> Result<Vec<Range<usize>>, DynError> {
+        let chars = line.chars().collect::<Vec<char>>();
+
+        // 文字単位の位置からバイト単位の位置への変換表
+        let mut offsets = line.char_indices().map(|(i, _)| i).collect::<Vec<usize>>();
+        offsets.push(line.len());
+
+        let mut result = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            match vm::find_at(&self.code, &chars, start, true)? {
+                Some(end) if end > start => {
+                    result.push(offsets[start]..offsets[end]);
+                    start = end;
+                }
+                _ => start += 1,
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// マッチ全体を含めたキャプチャグループの数を返す。
+    pub fn captures_len(&self) -> usize {
+        let slots = self.code.iter().filter_map(|inst| match inst {
+            Instruction::Save(n) => Some(*n / 2),
+            _ => None,
+        });
+        slots.max().unwrap_or(0) + 1
+    }
+
+    /// 最も左にあるマッチについて、各キャプチャグループにマッチした部分文字列を返す。
+    ///
+    /// 行頭から 1 文字ずつずらしてマッチングを行い、長さ 0 のマッチも返す。
+    /// マッチしなかった場合は Ok(None) を返す。
+    pub fn captures<'t>(&self, text: &'t str) -> Result<Option<Captures<'t>>, DynError> {
+        let chars = text.chars().collect::<Vec<char>>();
+
+        // 文字単位の位置からバイト単位の位置への変換表
+        let mut offsets = text.char_indices().map(|(i, _)| i).collect::<Vec<usize>>();
+        offsets.push(text.len());
+
+        let slots = self.captures_len() * 2;
+        for start in 0..=chars.len() {
+            if let Some((end, mut saved)) = vm::captures_at(&self.code, &chars, start, slots)? {
+                // グループ 0 はマッチ全体
+                saved[0] = Some(start);
+                saved[1] = Some(end);
+                return Ok(Some(Captures::new(text, &saved, &offsets)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// 命令列を JSON 形式で path に保存する。
+    #[cfg(feature = "serde")]
+    pub fn save_program<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), DynError> {
+        let f = std::io::BufWriter::new(std::fs::File::create(path)?);
+        serde_json::to_writer(f, &self.code)?;
+        Ok(())
+    }
+
+    /// save_program で保存した命令列を path から読み込む。
+    ///
+    /// 命令列の内容は検証しないため、不正なジャンプ先などはマッチング時に EvalError となる。
+    #[cfg(feature = "serde")]
+    pub fn load_program<P: AsRef<std::path::Path>>(path: P) -> Result<Self, DynError> {
+        let f = std::io::BufReader::new(std::fs::File::open(path)?);
+        Ok(Self {
+            code: serde_json::from_reader(f)?,
+        })
+    }
+}
+
+/// [match_reader_with] などに指定するマッチングのオプション。
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MatchOptions {
+    /// true の場合、大文字と小文字を区別せずにマッチングを行う。
+    pub ignore_case: bool,
+    /// true の場合、マッチ **しなかった** 行を返す。
+    pub invert: bool,
+    /// true の場合、行末の \r\n を改行として扱う。
+    /// `$` は末尾の \r の直前でもマッチし、 [match_reader_with] は行末の \r を取り除いて返す。
+    pub crlf: bool,
+    /// true の場合、 [match_reader_with] は \n の代わりに NUL で区切って読み込む。
+    /// `find -print0` の出力など、改行を含みうるファイル名の並びに用いる。 crlf は無視する。
+    pub null_data: bool,
+}
+
+/// [match_reader] が返す、マッチした行を順に返すイテレータ。
+///
+/// 要素は (行番号, 行) のタプルで、行番号は 1 から始まる。
+pub struct MatchLines<R> {
+    code: Vec<Instruction>,
+    reader: R,
+    line_no: usize,
+    opts: MatchOptions,
+    stats: Option<MatchStats>, // 統計を集計する場合は Some
+}
+
+impl<R> MatchLines<R> {
+    /// マッチングにかかった手間の統計を集計するようにする。
+    ///
+    /// 集計した統計は [MatchLines::stats] で取得できる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// let input = "aa\nb\n".as_bytes();
+    /// let mut lines = regex::match_reader("a?a?aa", input).unwrap().with_stats();
+    /// assert_eq!(lines.next().unwrap().unwrap(), (1, "aa".to_string()));
+    /// assert!(lines.next().is_none());
+    /// assert!(lines.stats().unwrap().backtracks > 0);
+    /// ```
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(MatchStats::default());
+        self
+    }
+
+    /// それまでに読み込んだ行の統計を返す。 with_stats を呼んでいない場合は None を返す。
+    pub fn stats(&self) -> Option<MatchStats> {
+        self.stats
+    }
+}
+
+impl<R: BufRead> MatchLines<R> {
+    /// 1 行読み込み、行末の \n を取り除いて返す。 crlf の場合は続けて行末の \r も取り除く。
+    /// null_data の場合は NUL までを 1 行とし、末尾の NUL を取り除く。
+    ///
+    /// UTF-8 として不正な行は [BufRead::read_line] と同様に InvalidData のエラーとする。
+    fn read_line(&mut self) -> Option<std::io::Result<String>> {
+        let delim = if self.opts.null_data { b'\0' } else { b'\n' };
+        let mut buf = Vec::new();
+        match self.reader.read_until(delim, &mut buf) {
+            Ok(0) => return None,
+            Ok(_) => (),
+            Err(e) => return Some(Err(e)),
+        }
+        if buf.last() == Some(&delim) {
+            buf.pop();
+            if !self.opts.null_data && self.opts.crlf && buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+        }
+        Some(
+            String::from_utf8(buf)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        )
+    }
+}
+
+impl<R: BufRead> Iterator for MatchLines<R> {
+    type Item = Result<(usize, String), DynError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(line) = self.read_line() {
+            self.line_no += 1;
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e.into())),
+            };
+
+            let chars = line.chars().collect::<Vec<char>>();
+            let mut stats = MatchStats::default();
+            let m = match_line(&self.code, &chars, &mut stats);
+            if let Some(total) = &mut self.stats {
+                *total += stats;
+            }
+            match m {
+                Ok(m) if m != self.opts.invert => return Some(Ok((self.line_no, line))),
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+/// 正規表現をコンパイルし、 reader から読み込んだ行のうちマッチする行を返すイテレータを生成。
+///
+/// 行の読み込みとマッチングはイテレータが進むごとに 1 行ずつ行われる。
+/// マッチングは深さ優先探索で、行頭から 1 文字ずつずらして行う。
+///
+/// 行末の \n は取り除くが、 \r は行の一部として残す。
+/// \r\n を改行として扱う場合は [match_reader_with] に [MatchOptions::crlf] を指定する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex;
+/// let input = "abc\ndef\nabcabc\n".as_bytes();
+/// let lines = regex::match_reader("(abc)+", input).unwrap();
+/// for m in lines {
+///     let (n, line) = m.unwrap();
+///     println!("{n}:{line}");
+/// }
+/// ```
+///
+/// # 返り値
+///
+/// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合は Err を返す。
+pub fn match_reader<R: BufRead>(expr: &str, reader: R) -> Result<MatchLines<R>, DynError> {
+    match_reader_with(expr, reader, MatchOptions::default())
+}
+
+/// オプションを指定して [match_reader] と同様のイテレータを生成。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::MatchOptions;
+/// let input = "ABC\ndef\n".as_bytes();
+/// let opts = MatchOptions {
+///     ignore_case: true,
+///     ..Default::default()
+/// };
+/// let mut lines = regex::match_reader_with("abc", input, opts).unwrap();
+/// assert_eq!(lines.next().unwrap().unwrap(), (1, "ABC".to_string()));
+/// assert!(lines.next().is_none());
+///
+/// // crlf の場合は行末の \r を取り除き、 $ は \r の直前にマッチする
+/// let input = "abc\r\nabcd\r\n".as_bytes();
+/// let opts = MatchOptions {
+///     crlf: true,
+///     ..Default::default()
+/// };
+/// let mut lines = regex::match_reader_with("bc$", input, opts).unwrap();
+/// assert_eq!(lines.next().unwrap().unwrap(), (1, "abc".to_string()));
+/// assert!(lines.next().is_none());
+///
+/// // null_data の場合は NUL で区切り、改行は行の一部となる
+/// let input = "a\nb\0c\0".as_bytes();
+/// let opts = MatchOptions {
+///     null_data: true,
+///     ..Default::default()
+/// };
+/// let mut lines = regex::match_reader_with("b", input, opts).unwrap();
+/// assert_eq!(lines.next().unwrap().unwrap(), (1, "a\nb".to_string()));
+/// assert!(lines.next().is_none());
+/// ```
+pub fn match_reader_with<R: BufRead>(
+    expr: &str,
+    reader: R,
+    opts: MatchOptions,
+) -> Result<MatchLines<R>, DynError> {
+    let code = compile::compile(expr, &opts)?;
+    Ok(MatchLines {
+        code,
+        reader,
+        line_no: 0,
+        opts,
+        stats: None,
+    })
+}
+
+/// 正規表現と文字列をマッチングし、最も左にあるマッチの位置を返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex;
+/// assert_eq!(regex::find("b(cd)+", "abcdcde").unwrap(), Some(1..6));
+/// assert_eq!(regex::find("xyz", "abcdcde").unwrap(), None);
+/// ```
+///
+/// # 返り値
+///
+/// マッチした場合は、 line におけるマッチ部分のバイト単位の範囲を Ok(Some(range)) として返す。
+/// マッチしなかった場合は Ok(None) を返す。
+/// マッチングは深さ優先探索で、行頭から 1 文字ずつずらして行う。
+///
+/// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合は Err を返す。
+pub fn find(expr: &str, line: &str) -> Result<Option<Range<usize>>, DynError> {
+    Ok(find_all(expr, line, MatchOptions::default())?
+        .into_iter()
+        .next())
+}
+
+/// 正規表現と文字列をマッチングし、重なりのないすべてのマッチの位置を返す。
+///
+/// マッチの位置は [find] と同様に、 line におけるバイト単位の範囲で表す。
+/// 長さ 0 のマッチは返さない。 opts の invert は無視する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::MatchOptions;
+/// let opts = MatchOptions {
+///     ignore_case: true,
+///     ..Default::default()
+/// };
+/// assert_eq!(regex::find_all("ab", "xAbyab", opts).unwrap(), vec![1..3, 4..6]);
+/// ```
+pub fn find_all(expr: &str, line: &str, opts: MatchOptions) -> Result<Vec<Range<usize>>, DynError> {
+    Regex::with_options(expr, &opts)?.find_all(line)
 }
@@ -0,0 +1,55 @@
+//! 正規表現の抽象構文木。
+use super::class::CharClass;
+use std::fmt::{self, Display};
+
+/// 文字を消費せずに、入力文字列の位置の条件を検査するアサーション。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Assertion {
+    WordBoundary,    // \b 単語の境界
+    NotWordBoundary, // \B 単語の境界以外
+    End,             // $ 文字列の末尾
+    EndCrlf, // $ 文字列の末尾か、末尾の \r の直前。 crlf を指定してコンパイルした場合のみ現れる
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::WordBoundary => write!(f, "\\b"),
+            Assertion::NotWordBoundary => write!(f, "\\B"),
+            Assertion::End => write!(f, "$"),
+            Assertion::EndCrlf => write!(f, "$(crlf)"),
+        }
+    }
+}
+
+/// 抽象構文木を表現するための型。
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AST {
+    Char(char),
+    Class(CharClass),
+    Assert(Assertion),
+    Plus(Box<AST>),
+    Star(Box<AST>),
+    Question(Box<AST>),
+    Or(Box<AST>, Box<AST>),
+    Seq(Vec<AST>),
+    Capture(usize, Box<AST>), // キャプチャグループ。 グループ番号は 1 から始まる
+    And(Box<AST>, Box<AST>),  // 交差 (実験的)
+    Not(Box<AST>),            // 補集合 (実験的)
+}
+
+impl AST {
+    /// 交差か補集合を含み、 DFA でマッチングする必要がある場合に true を返す。
+    pub fn requires_dfa(&self) -> bool {
+        match self {
+            AST::Char(_) | AST::Class(_) | AST::Assert(_) => false,
+            AST::And(..) | AST::Not(_) => true,
+            AST::Plus(e) | AST::Star(e) | AST::Question(e) | AST::Capture(_, e) => e.requires_dfa(),
+            AST::Or(e1, e2) => e1.requires_dfa() || e2.requires_dfa(),
+            AST::Seq(es) => es.iter().any(|e| e.requires_dfa()),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! キャプチャグループにマッチした部分文字列。
+//!
+//! 評価器は入力文字列を char の列として扱うため、 save 命令は文字単位の位置を記録する。
+//! これをバイト単位の位置に変換して保持し、各グループを入力文字列の部分文字列
+//! `&'t str` として返す。部分文字列は入力文字列を借用するため、コピーは行わない。
+use std::ops::{Index, Range};
+
+/// [Regex::captures](crate::Regex::captures) が返す、各グループにマッチした部分文字列。
+///
+/// グループ 0 はマッチ全体、グループ n は n 番目の開き括弧に対応する。
+/// `(?:...)` は非キャプチャグループのため番号を持たない。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::Regex;
+/// let re = Regex::new("(a+)(?:-)(b|c)?").unwrap();
+/// let caps = re.captures("xaa-c").unwrap().unwrap();
+/// assert_eq!(&caps[0], "aa-c");
+/// assert_eq!(caps.get(1), Some("aa"));
+/// assert_eq!(caps.span(2), Some(4..5));
+///
+/// // グループは入力文字列の部分文字列で、 caps より長く生存できる
+/// let text = String::from("a-b");
+/// let group = {
+///     let caps = re.captures(&text).unwrap().unwrap();
+///     caps.get(1).unwrap()
+/// };
+/// assert_eq!(group, "a");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures<'t> {
+    text: &'t str,
+    spans: Vec<Option<Range<usize>>>, // グループごとのバイト単位の範囲
+}
+
+impl<'t> Captures<'t> {
+    /// save 命令で記録したスロットから生成する。
+    ///
+    /// slots[2n] と slots[2n + 1] はグループ n の文字単位の開始位置と終了位置。
+    /// offsets は文字単位の位置からバイト単位の位置への変換表。
+    pub(crate) fn new(text: &'t str, slots: &[Option<usize>], offsets: &[usize]) -> Self {
+        let spans = slots
+            .chunks(2)
+            .map(|s| match s {
+                [Some(start), Some(end)] if start <= end => Some(offsets[*start]..offsets[*end]),
+                _ => None,
+            })
+            .collect();
+        Self { text, spans }
+    }
+
+    /// グループ i にマッチした部分文字列を返す。
+    ///
+    /// グループが存在しないか、マッチに関与しなかった場合は None を返す。
+    pub fn get(&self, i: usize) -> Option<&'t str> {
+        self.span(i).map(|r| &self.text[r])
+    }
+
+    /// グループ i にマッチした部分の、入力文字列におけるバイト単位の範囲を返す。
+    pub fn span(&self, i: usize) -> Option<Range<usize>> {
+        self.spans.get(i).cloned().flatten()
+    }
+
+    /// マッチ全体を含めたグループの数。
+    pub fn len(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// グループがない場合に true を返す。マッチ全体が必ずあるため、常に false となる。
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// グループ 0 から順に、マッチした部分文字列を返すイテレータ。
+    pub fn iter(&self) -> impl Iterator<Item = Option<&'t str>> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+}
+
+impl Index<usize> for Captures<'_> {
+    type Output = str;
+
+    /// グループ i にマッチした部分文字列を返す。
+    ///
+    /// グループが存在しないか、マッチに関与しなかった場合は panic する。
+    fn index(&self, i: usize) -> &str {
+        self.get(i)
+            .unwrap_or_else(|| panic!("no match for capture group {i}"))
+    }
+}
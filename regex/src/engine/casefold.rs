@@ -0,0 +1,93 @@
+//! 大文字小文字を区別しないマッチングのための Unicode の単純ケースフォールディング。
+//!
+//! Unicode の CaseFolding.txt で定められた単純ケースフォールディング (ステータス C と S) のうち、
+//! char::to_lowercase と結果が異なる文字だけを表で持ち、それ以外は to_lowercase で正規化する。
+//! 例えばギリシャ文字の語末のシグマ ς は、 to_lowercase では変化しないが σ に正規化する。
+//!
+//! 文字クラスの範囲と比較するため、同じ文字に正規化される文字をすべて求める [fold_closure] も提供する。
+use std::{collections::HashMap, sync::OnceLock};
+
+/// to_lowercase とは異なる正規化を行う文字の表。
+///
+/// (元の文字, 正規化後の文字) の組で、元の文字の昇順に並べる。
+const SPECIAL_FOLDING: &[(char, char)] = &[
+    ('\u{00B5}', '\u{03BC}'), // µ MICRO SIGN → μ
+    ('\u{017F}', '\u{0073}'), // ſ LATIN SMALL LETTER LONG S → s
+    ('\u{0345}', '\u{03B9}'), // COMBINING GREEK YPOGEGRAMMENI → ι
+    ('\u{03C2}', '\u{03C3}'), // ς GREEK SMALL LETTER FINAL SIGMA → σ
+    ('\u{03D0}', '\u{03B2}'), // ϐ GREEK BETA SYMBOL → β
+    ('\u{03D1}', '\u{03B8}'), // ϑ GREEK THETA SYMBOL → θ
+    ('\u{03D5}', '\u{03C6}'), // ϕ GREEK PHI SYMBOL → φ
+    ('\u{03D6}', '\u{03C0}'), // ϖ GREEK PI SYMBOL → π
+    ('\u{03F0}', '\u{03BA}'), // ϰ GREEK KAPPA SYMBOL → κ
+    ('\u{03F1}', '\u{03C1}'), // ϱ GREEK RHO SYMBOL → ρ
+    ('\u{03F5}', '\u{03B5}'), // ϵ GREEK LUNATE EPSILON SYMBOL → ε
+    ('\u{1C80}', '\u{0432}'), // ᲀ CYRILLIC SMALL LETTER ROUNDED VE → в
+    ('\u{1C81}', '\u{0434}'), // ᲁ CYRILLIC SMALL LETTER LONG-LEGGED DE → д
+    ('\u{1C82}', '\u{043E}'), // ᲂ CYRILLIC SMALL LETTER NARROW O → о
+    ('\u{1C83}', '\u{0441}'), // ᲃ CYRILLIC SMALL LETTER WIDE ES → с
+    ('\u{1C84}', '\u{0442}'), // ᲄ CYRILLIC SMALL LETTER TALL TE → т
+    ('\u{1C85}', '\u{0442}'), // ᲅ CYRILLIC SMALL LETTER THREE-LEGGED TE → т
+    ('\u{1C86}', '\u{044A}'), // ᲆ CYRILLIC SMALL LETTER TALL HARD SIGN → ъ
+    ('\u{1C87}', '\u{0463}'), // ᲇ CYRILLIC SMALL LETTER TALL YAT → ѣ
+    ('\u{1C88}', '\u{A64B}'), // ᲈ CYRILLIC SMALL LETTER UNBLENDED UK → ꙋ
+    ('\u{1E9B}', '\u{1E61}'), // ẛ LATIN SMALL LETTER LONG S WITH DOT ABOVE → ṡ
+    ('\u{1FBE}', '\u{03B9}'), // ι GREEK PROSGEGRAMMENI → ι
+];
+
+/// 大文字小文字を区別しない比較のために文字を正規化する。
+///
+/// 単純ケースフォールディングで同じ文字に畳み込まれる文字は、同じ文字に正規化される。
+/// 小文字に変換した結果が 1 文字にならない場合 (İ など) は、元の文字をそのまま返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::casefold::fold_case;
+/// assert_eq!(fold_case('A'), 'a');
+/// assert_eq!(fold_case('Σ'), fold_case('ς'));
+/// assert_eq!(fold_case('Ж'), 'ж');
+/// ```
+pub fn fold_case(c: char) -> char {
+    if let Ok(i) = SPECIAL_FOLDING.binary_search_by_key(&c, |(from, _)| *from) {
+        return SPECIAL_FOLDING[i].1;
+    }
+
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(l), None) => l,
+        _ => c,
+    }
+}
+
+/// 正規化後の文字から、その文字に正規化される他の文字への逆引きの表。
+static CLOSURE: OnceLock<HashMap<char, Vec<char>>> = OnceLock::new();
+
+/// c と同じ文字に正規化される文字をすべて返す。正規化後の文字が最初となり、 c 自身も含む。
+///
+/// 文字クラスの範囲は正規化後の文字で表せないため、範囲に含まれるかをこれらの文字で判定する。
+/// 初回の呼び出しで、すべての文字を正規化して逆引きの表を作る。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::casefold::fold_closure;
+/// let mut sigma = fold_closure('ς').collect::<Vec<char>>();
+/// sigma.sort();
+/// assert_eq!(sigma, vec!['Σ', 'ς', 'σ']);
+/// assert_eq!(fold_closure('s').collect::<String>(), "sSſ");
+/// ```
+pub fn fold_closure(c: char) -> impl Iterator<Item = char> {
+    let table = CLOSURE.get_or_init(|| {
+        let mut table: HashMap<char, Vec<char>> = HashMap::new();
+        for x in (0..=char::MAX as u32).filter_map(char::from_u32) {
+            let f = fold_case(x);
+            if f != x {
+                table.entry(f).or_default().push(x);
+            }
+        }
+        table
+    });
+    let f = fold_case(c);
+    std::iter::once(f).chain(table.get(&f).into_iter().flatten().copied())
+}
@@ -0,0 +1,152 @@
+//! 文字クラス `[...]` 。
+//!
+//! `[abc]`, `[a-z]`, `[^0-9]` のような文字の集合に加えて、
+//! シェルスクリプトなどで使われる `[[:alpha:]]` のような POSIX の文字クラス名を扱う。
+//! POSIX の文字クラスは char::is_alphabetic などの判定関数に対応付ける。
+use super::casefold::fold_closure;
+use std::fmt::{self, Display};
+
+/// POSIX の文字クラス名。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PosixClass {
+    Alnum,  // 英数字
+    Alpha,  // 英字
+    Blank,  // 空白とタブ
+    Cntrl,  // 制御文字
+    Digit,  // 数字 (0-9)
+    Graph,  // 空白以外の表示可能な文字
+    Lower,  // 小文字
+    Print,  // 表示可能な文字
+    Punct,  // 記号
+    Space,  // 空白文字
+    Upper,  // 大文字
+    Xdigit, // 16 進数の数字
+}
+
+/// (クラス名, クラス) の表。
+const POSIX_CLASSES: &[(&str, PosixClass)] = &[
+    ("alnum", PosixClass::Alnum),
+    ("alpha", PosixClass::Alpha),
+    ("blank", PosixClass::Blank),
+    ("cntrl", PosixClass::Cntrl),
+    ("digit", PosixClass::Digit),
+    ("graph", PosixClass::Graph),
+    ("lower", PosixClass::Lower),
+    ("print", PosixClass::Print),
+    ("punct", PosixClass::Punct),
+    ("space", PosixClass::Space),
+    ("upper", PosixClass::Upper),
+    ("xdigit", PosixClass::Xdigit),
+];
+
+impl PosixClass {
+    /// クラス名から POSIX の文字クラスを返す。未知の名前の場合は None 。
+    pub fn from_name(name: &str) -> Option<Self> {
+        POSIX_CLASSES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, class)| *class)
+    }
+
+    /// クラス名を返す。
+    pub fn name(&self) -> &'static str {
+        POSIX_CLASSES
+            .iter()
+            .find(|(_, class)| class == self)
+            .map(|(n, _)| *n)
+            .unwrap()
+    }
+
+    /// 文字 c がこのクラスに含まれるかを判定する。
+    pub fn contains(&self, c: char) -> bool {
+        match self {
+            PosixClass::Alnum => c.is_alphanumeric(),
+            PosixClass::Alpha => c.is_alphabetic(),
+            PosixClass::Blank => c == ' ' || c == '\t',
+            PosixClass::Cntrl => c.is_control(),
+            PosixClass::Digit => c.is_ascii_digit(),
+            PosixClass::Graph => !c.is_control() && !c.is_whitespace(),
+            PosixClass::Lower => c.is_lowercase(),
+            PosixClass::Print => !c.is_control(),
+            PosixClass::Punct => c.is_ascii_punctuation(),
+            PosixClass::Space => c.is_whitespace(),
+            PosixClass::Upper => c.is_uppercase(),
+            PosixClass::Xdigit => c.is_ascii_hexdigit(),
+        }
+    }
+}
+
+/// 文字クラスの要素。
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClassItem {
+    Char(char),        // 1 文字
+    Range(char, char), // a-z のような範囲
+    Posix(PosixClass), // [:alpha:] のような POSIX の文字クラス
+}
+
+impl ClassItem {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            ClassItem::Char(i) => *i == c,
+            ClassItem::Range(from, to) => (*from..=*to).contains(&c),
+            ClassItem::Posix(class) => class.contains(c),
+        }
+    }
+}
+
+/// 文字クラス。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::class::{CharClass, ClassItem, PosixClass};
+/// let class = CharClass {
+///     negated: false,
+///     items: vec![ClassItem::Posix(PosixClass::Digit), ClassItem::Char('_')],
+/// };
+/// assert!(class.contains('7'));
+/// assert!(class.contains('_'));
+/// assert!(!class.contains('a'));
+/// assert_eq!(class.to_string(), "[[:digit:]_]");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CharClass {
+    pub negated: bool,         // [^...] の場合 true
+    pub items: Vec<ClassItem>, // 要素
+}
+
+impl CharClass {
+    /// 文字 c が文字クラスにマッチするかを判定する。
+    pub fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|i| i.contains(c)) != self.negated
+    }
+
+    /// 大文字小文字を区別せずに、文字 c が文字クラスにマッチするかを判定する。
+    ///
+    /// 文字の場合と同様に単純ケースフォールディングで比較し、 c と同じ文字に正規化される文字
+    /// ([fold_closure]) のいずれかが要素に含まれればマッチとする。
+    pub fn contains_fold(&self, c: char) -> bool {
+        let found = fold_closure(c).any(|x| self.items.iter().any(|i| i.contains(x)));
+        found != self.negated
+    }
+}
+
+impl Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        if self.negated {
+            write!(f, "^")?;
+        }
+        for item in &self.items {
+            match item {
+                ClassItem::Char(c) => write!(f, "{c}")?,
+                ClassItem::Range(from, to) => write!(f, "{from}-{to}")?,
+                ClassItem::Posix(class) => write!(f, "[:{}:]", class.name())?,
+            }
+        }
+        write!(f, "]")
+    }
+}
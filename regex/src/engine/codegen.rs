@@ -1,26 +1,11 @@
-use super::{parser::AST, Instruction};
-use crate::helper::safe_add;
-use std::{
-    error::Error,
-    fmt::{self, Display},
+use super::{
+    class::CharClass,
+    parser::{Assertion, AST},
+    Instruction,
 };
+use crate::helper::safe_add;
 
-/// コード生成エラーを表す型
-#[derive(Debug)]
-pub enum CodeGenError {
-    PCoverFlow,
-    FailStar,
-    FailOr,
-    FailQuestion,
-}
-
-impl Display for CodeGenError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "CodeGenError: {self:?}")
-    }
-}
-
-impl Error for CodeGenError {}
+pub use super::error::CodeGenError;
 
 /// コード生成器。
 #[derive(Default, Debug)]
@@ -53,11 +38,15 @@ impl Generator {
     fn gen_expr(&mut self, ast: &AST) -> Result<(), CodeGenError> {
         match ast {
             AST::Char(c) => self.gen_char(*c)?,
+            AST::Class(c) => self.gen_class(c)?,
+            AST::Assert(a) => self.gen_assert(*a)?,
             AST::Or(e1, e2) => self.gen_or(e1, e2)?,
             AST::Plus(e) => self.gen_plus(e)?,
             AST::Star(e) => self.gen_star(e)?,
             AST::Question(e) => self.gen_question(e)?,
             AST::Seq(es) => self.gen_seq(es)?,
+            AST::Capture(n, e) => self.gen_capture(*n, e)?,
+            AST::And(..) | AST::Not(_) => return Err(CodeGenError::RequiresDfa),
         }
 
         Ok(())
@@ -70,6 +59,39 @@ impl Generator {
         Ok(())
     }
 
+    /// class 命令生成関数。
+    fn gen_class(&mut self, c: &CharClass) -> Result<(), CodeGenError> {
+        self.insts.push(Instruction::Class(c.clone()));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// assert 命令生成関数。
+    fn gen_assert(&mut self, a: Assertion) -> Result<(), CodeGenError> {
+        self.insts.push(Instruction::Assert(a));
+        self.inc_pc()?;
+        Ok(())
+    }
+
+    /// キャプチャグループのコード生成。
+    ///
+    /// 以下のようなコードを生成する。
+    /// グループ番号 n の開始位置を 2n 番目、終了位置を 2n + 1 番目のスロットに記録する。
+    ///
+    /// ```text
+    ///     save 2n
+    ///     e のコード
+    ///     save 2n+1
+    /// ```
+    fn gen_capture(&mut self, n: usize, e: &AST) -> Result<(), CodeGenError> {
+        self.insts.push(Instruction::Save(2 * n));
+        self.inc_pc()?;
+        self.gen_expr(e)?;
+        self.insts.push(Instruction::Save(2 * n + 1));
+        self.inc_pc()?;
+        Ok(())
+    }
+
     /// Or 演算子のコード生成。
     ///
     /// 以下のようなコードを生成する。
@@ -145,7 +167,7 @@ impl Generator {
     /// ```text
     /// L1: split L2, L3
     /// L2: e1 のコード
-    ///	    jump L1
+    ///     jump L1
     /// L3:
     /// ```
     fn gen_star(&mut self, e1: &AST) -> Result<(), CodeGenError> {
@@ -0,0 +1,107 @@
+//! 正規表現から VM の命令列へのコンパイル。
+//!
+//! パースした AST を [codegen] で命令列に変換し、マッチングのオプションを適用する。
+//! 各命令の元になった正規表現中の範囲も求められる。
+use super::{
+    casefold, class, codegen,
+    error::Span,
+    parser::{self, Assertion},
+    MatchOptions,
+};
+use crate::helper::DynError;
+use std::fmt::Display;
+
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Instruction {
+    Char(char),
+    FoldChar(char), // 大文字小文字を区別しない文字。 casefold::fold_case で正規化した文字を持つ
+    Class(class::CharClass), // 文字クラス
+    FoldClass(class::CharClass), // 大文字小文字を区別しない文字クラス
+    Assert(Assertion), // 文字を消費しないアサーション
+    Save(usize),    // 入力文字列の位置をスロットに記録する。キャプチャグループに用いる
+    Match,
+    Jump(usize),
+    Split(usize, usize),
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Instruction::Char(c) => write!(f, "char {c}"),
+            Instruction::FoldChar(c) => write!(f, "fchar {c}"),
+            Instruction::Class(c) => write!(f, "class {c}"),
+            Instruction::FoldClass(c) => write!(f, "fclass {c}"),
+            Instruction::Assert(a) => write!(f, "assert {a}"),
+            Instruction::Save(n) => write!(f, "save {n}"),
+            Instruction::Match => write!(f, "match"),
+            Instruction::Jump(addr) => write!(f, "jump {addr:>04}"),
+            Instruction::Split(addr1, addr2) => write!(f, "split {addr1:>04} {addr2:>04}"),
+        }
+    }
+}
+
+/// 正規表現をパースしてコード生成する。
+///
+/// ignore_case が指定された場合は、 char 命令と class 命令を
+/// 大文字小文字を区別しない fchar 命令と fclass 命令に置き換える。
+/// crlf が指定された場合は、 `$` のアサーションを末尾の \r の直前でも成り立つものに置き換える。
+pub fn compile(expr: &str, opts: &MatchOptions) -> Result<Vec<Instruction>, DynError> {
+    let ast = parser::parse(expr)?;
+    let mut code = codegen::get_code(&ast)?;
+    if opts.ignore_case {
+        for inst in code.iter_mut() {
+            match inst {
+                Instruction::Char(c) => *inst = Instruction::FoldChar(casefold::fold_case(*c)),
+                Instruction::Class(c) => *inst = Instruction::FoldClass(c.clone()),
+                _ => (),
+            }
+        }
+    }
+    if opts.crlf {
+        for inst in code.iter_mut() {
+            if *inst == Instruction::Assert(Assertion::End) {
+                *inst = Instruction::Assert(Assertion::EndCrlf);
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// [compile] と同様にコード生成し、各命令の元になった正規表現中の範囲を命令列と同じ順に返す。
+///
+/// 文字を消費する命令とアサーションの命令には元になったアトムの範囲を、
+/// split や jump などの制御の命令には None を対応付ける。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::{engine::compile::compile_with_spans, MatchOptions, Span};
+/// let (code, spans) = compile_with_spans("ab|c", &MatchOptions::default()).unwrap();
+/// assert_eq!(code.len(), spans.len());
+/// assert_eq!(code[1].to_string(), "char a");
+/// assert_eq!(spans[1], Some(Span::new(0, 1)));
+/// assert_eq!(spans[0], None); // split
+/// ```
+pub fn compile_with_spans(
+    expr: &str,
+    opts: &MatchOptions,
+) -> Result<(Vec<Instruction>, Vec<Option<Span>>), DynError> {
+    let code = compile(expr, opts)?;
+    let (_, atoms) = parser::parse_with_spans(expr)?;
+
+    // コード生成はアトムを正規表現中に現れる順に 1 つずつ命令に変換する
+    let mut atoms = atoms.into_iter();
+    let spans = code
+        .iter()
+        .map(|inst| match inst {
+            Instruction::Char(_)
+            | Instruction::FoldChar(_)
+            | Instruction::Class(_)
+            | Instruction::FoldClass(_)
+            | Instruction::Assert(_) => atoms.next(),
+            _ => None,
+        })
+        .collect();
+    Ok((code, spans))
+}
@@ -0,0 +1,186 @@
+//! Brzozowski の微分によるマッチング。
+//!
+//! 正規表現 r の文字 c による微分 ∂c(r) は、 r にマッチする文字列のうち c で始まるものから、
+//! 先頭の c を取り除いた文字列にマッチする正規表現である。入力文字列の文字で順に微分し、
+//! 得られた正規表現が空文字列にマッチするかを調べることでマッチングを行う。
+//!
+//! 命令列や状態遷移表を構成せず、 AST から直接マッチングを行うため、 VM や DFA と比較できる。
+//! 交差 `&` と補集合 `~` は微分がそのまま分配されるため、特別な構成なしに扱える。
+//! アサーションは文字を消費しないため、空文字列にマッチするかの判定で前後の文字を調べる。
+//! キャプチャグループは区別せず、内側の正規表現として扱う。
+//!
+//! 微分を繰り返すと正規表現が大きくなるため、空集合や空文字列を取り除き、
+//! 選択の重複を除いて簡約する。
+use super::{
+    class::CharClass,
+    parser::{Assertion, AST},
+    vm::assert_holds,
+};
+
+/// 微分の対象とする正規表現
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Re {
+    Empty, // 何にもマッチしない
+    Eps,   // 空文字列にのみマッチする
+    Char(char),
+    Class(CharClass),
+    Assert(Assertion),
+    Seq(Vec<Re>), // 2 つ以上の連接
+    Or(Vec<Re>),  // 2 つ以上の選択。重複を含まない
+    And(Box<Re>, Box<Re>),
+    Not(Box<Re>),
+    Star(Box<Re>),
+}
+
+impl Re {
+    /// 連接。ネストした連接は平坦にする
+    fn seq(rs: Vec<Re>) -> Re {
+        let mut v = Vec::new();
+        for r in rs {
+            match r {
+                Re::Empty => return Re::Empty,
+                Re::Eps => (),
+                Re::Seq(rs) => v.extend(rs),
+                r => v.push(r),
+            }
+        }
+        match v.len() {
+            0 => Re::Eps,
+            1 => v.pop().unwrap(),
+            _ => Re::Seq(v),
+        }
+    }
+
+    /// 選択。ネストした選択は平坦にし、重複を除く
+    fn or(rs: Vec<Re>) -> Re {
+        let mut v = Vec::new();
+        for r in rs.into_iter().flat_map(|r| match r {
+            Re::Or(rs) => rs,
+            r => vec![r],
+        }) {
+            if r != Re::Empty && !v.contains(&r) {
+                v.push(r);
+            }
+        }
+        match v.len() {
+            0 => Re::Empty,
+            1 => v.pop().unwrap(),
+            _ => Re::Or(v),
+        }
+    }
+
+    /// 交差
+    fn and(r1: Re, r2: Re) -> Re {
+        match (r1, r2) {
+            (Re::Empty, _) | (_, Re::Empty) => Re::Empty,
+            (r1, r2) if r1 == r2 => r1,
+            (r1, r2) => Re::And(Box::new(r1), Box::new(r2)),
+        }
+    }
+
+    /// 補集合
+    fn not(r: Re) -> Re {
+        match r {
+            Re::Not(r) => *r,
+            r => Re::Not(Box::new(r)),
+        }
+    }
+
+    /// 0 回以上の繰り返し
+    fn star(r: Re) -> Re {
+        match r {
+            Re::Empty | Re::Eps => Re::Eps,
+            r @ Re::Star(_) => r,
+            r => Re::Star(Box::new(r)),
+        }
+    }
+
+    /// line の pos 文字目の位置で、空文字列にマッチするかを判定する
+    fn nullable(&self, line: &[char], pos: usize) -> bool {
+        match self {
+            Re::Empty | Re::Char(_) | Re::Class(_) => false,
+            Re::Eps | Re::Star(_) => true,
+            Re::Assert(a) => assert_holds(*a, line, pos),
+            Re::Seq(rs) => rs.iter().all(|r| r.nullable(line, pos)),
+            Re::Or(rs) => rs.iter().any(|r| r.nullable(line, pos)),
+            Re::And(r1, r2) => r1.nullable(line, pos) && r2.nullable(line, pos),
+            Re::Not(r) => !r.nullable(line, pos),
+        }
+    }
+
+    /// line の pos 文字目の文字で微分する
+    fn derive(&self, line: &[char], pos: usize) -> Re {
+        let c = line[pos];
+        match self {
+            Re::Empty | Re::Eps | Re::Assert(_) => Re::Empty,
+            Re::Char(x) if *x == c => Re::Eps,
+            Re::Class(class) if class.contains(c) => Re::Eps,
+            Re::Char(_) | Re::Class(_) => Re::Empty,
+            Re::Seq(rs) => {
+                // ∂c(r1 r2) = ∂c(r1) r2 | ∂c(r2) (r1 が空文字列にマッチする場合)
+                let mut alts = Vec::new();
+                for (i, r) in rs.iter().enumerate() {
+                    let mut rest = vec![r.derive(line, pos)];
+                    rest.extend(rs[i + 1..].iter().cloned());
+                    alts.push(Re::seq(rest));
+                    if !r.nullable(line, pos) {
+                        break;
+                    }
+                }
+                Re::or(alts)
+            }
+            Re::Or(rs) => Re::or(rs.iter().map(|r| r.derive(line, pos)).collect()),
+            Re::And(r1, r2) => Re::and(r1.derive(line, pos), r2.derive(line, pos)),
+            Re::Not(r) => Re::not(r.derive(line, pos)),
+            Re::Star(r) => Re::seq(vec![r.derive(line, pos), self.clone()]),
+        }
+    }
+}
+
+impl From<&AST> for Re {
+    fn from(ast: &AST) -> Self {
+        match ast {
+            AST::Char(c) => Re::Char(*c),
+            AST::Class(class) => Re::Class(class.clone()),
+            AST::Assert(a) => Re::Assert(*a),
+            AST::Plus(e) => {
+                let r = Re::from(&**e);
+                Re::seq(vec![r.clone(), Re::star(r)])
+            }
+            AST::Star(e) => Re::star(Re::from(&**e)),
+            AST::Question(e) => Re::or(vec![Re::from(&**e), Re::Eps]),
+            AST::Or(e1, e2) => Re::or(vec![Re::from(&**e1), Re::from(&**e2)]),
+            AST::Seq(es) => Re::seq(es.iter().map(Re::from).collect()),
+            AST::Capture(_, e) => Re::from(&**e),
+            AST::And(e1, e2) => Re::and(Re::from(&**e1), Re::from(&**e2)),
+            AST::Not(e) => Re::not(Re::from(&**e)),
+        }
+    }
+}
+
+/// 微分によって、 line の先頭から始まるいずれかの部分に ast がマッチするかを判定する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::{derivative, parser};
+///
+/// let ast = parser::parse_extended("\\b(ab)+&~(abab)").unwrap();
+/// let line = |s: &str| s.chars().collect::<Vec<char>>();
+/// assert!(derivative::is_match(&ast, &line("ab")));
+/// assert!(derivative::is_match(&ast, &line("ababab")));
+/// assert!(!derivative::is_match(&ast, &line("ba")));
+/// ```
+pub fn is_match(ast: &AST, line: &[char]) -> bool {
+    let mut re = Re::from(ast);
+    for pos in 0..line.len() {
+        if re.nullable(line, pos) {
+            return true;
+        }
+        re = re.derive(line, pos);
+        if re == Re::Empty {
+            return false;
+        }
+    }
+    re.nullable(line, line.len())
+}
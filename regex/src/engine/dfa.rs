@@ -0,0 +1,343 @@
+//! 交差 `&` と補集合 `~` を含む正規表現のための DFA 。
+//!
+//! AST から ε 遷移付きの NFA を構成し、部分集合構成法で DFA に変換する。
+//! 交差は 2 つの DFA の直積、補集合は受理状態を反転した DFA として構成し、
+//! その DFA を NFA の断片として埋め込むことで、他の演算子と組み合わせられるようにする。
+//!
+//! 文字の種類は無数にあるため、パターン中に現れる文字と「それ以外の文字」を入力記号とする。
+//! \b などのアサーションは直前の文字に依存するため、 DFA では扱わない。
+//! 文字クラスも入力記号に分割できないため扱わない。
+//!
+//! 部分集合構成法と直積は状態数が指数的に増えうるため、 [MAX_STATES] を超えた時点で構成を打ち切る。
+use super::parser::AST;
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 構成する DFA の状態数の上限
+pub const MAX_STATES: usize = 10_000;
+
+/// DFA の構成時のエラーを表す型
+#[derive(Debug)]
+pub enum DfaError {
+    UnsupportedAssertion, // アサーションは DFA で扱えない
+    UnsupportedClass,     // 文字クラスは DFA で扱えない
+    TooManyStates,        // 状態数が MAX_STATES を超える
+}
+
+impl Display for DfaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DfaError: {self:?}")
+    }
+}
+
+impl Error for DfaError {}
+
+/// 入力記号の集合。
+///
+/// パターン中に現れる文字にそれぞれ記号を割り当て、それ以外の文字は 1 つの記号にまとめる。
+#[derive(Debug, Clone)]
+struct Alphabet {
+    chars: Vec<char>, // パターン中に現れる文字 (昇順)
+}
+
+impl Alphabet {
+    fn new(ast: &AST) -> Result<Self, DfaError> {
+        let mut chars = BTreeSet::new();
+        let mut stack = vec![ast];
+        while let Some(ast) = stack.pop() {
+            match ast {
+                AST::Char(c) => {
+                    chars.insert(*c);
+                }
+                AST::Assert(_) => return Err(DfaError::UnsupportedAssertion),
+                AST::Class(_) => return Err(DfaError::UnsupportedClass),
+                AST::Plus(e)
+                | AST::Star(e)
+                | AST::Question(e)
+                | AST::Capture(_, e)
+                | AST::Not(e) => stack.push(e),
+                AST::Or(e1, e2) | AST::And(e1, e2) => {
+                    stack.push(e1);
+                    stack.push(e2);
+                }
+                AST::Seq(es) => stack.extend(es),
+            }
+        }
+        Ok(Self {
+            chars: chars.into_iter().collect(),
+        })
+    }
+
+    /// 記号の数。
+    fn len(&self) -> usize {
+        self.chars.len() + 1
+    }
+
+    /// 文字に対応する記号。パターン中に現れない文字は最後の記号となる。
+    fn symbol(&self, c: char) -> usize {
+        self.chars.binary_search(&c).unwrap_or(self.chars.len())
+    }
+}
+
+/// ε 遷移付きの NFA 。
+#[derive(Debug, Default)]
+struct Nfa {
+    eps: Vec<Vec<usize>>,            // ε 遷移先
+    trans: Vec<Vec<(usize, usize)>>, // (記号, 遷移先)
+}
+
+impl Nfa {
+    fn add_state(&mut self) -> usize {
+        self.eps.push(Vec::new());
+        self.trans.push(Vec::new());
+        self.eps.len() - 1
+    }
+
+    /// AST に対応する断片を生成し、 (開始状態, 受理状態) を返す。
+    ///
+    /// 交差と補集合の DFA の状態数が上限を超える場合は Err を返す。
+    fn build(&mut self, ast: &AST, alphabet: &Alphabet) -> Result<(usize, usize), DfaError> {
+        let s = self.add_state();
+        let t = self.add_state();
+        match ast {
+            AST::Char(c) => self.trans[s].push((alphabet.symbol(*c), t)),
+            AST::Assert(_) | AST::Class(_) => {
+                unreachable!("assertions and classes are rejected by Alphabet::new")
+            }
+            AST::Seq(es) => {
+                let mut cur = s;
+                for e in es {
+                    let (a, b) = self.build(e, alphabet)?;
+                    self.eps[cur].push(a);
+                    cur = b;
+                }
+                self.eps[cur].push(t);
+            }
+            AST::Capture(_, e) => {
+                // キャプチャは位置を記録するだけなので、 DFA では括弧の中身のみを扱う
+                let (a, b) = self.build(e, alphabet)?;
+                self.eps[s].push(a);
+                self.eps[b].push(t);
+            }
+            AST::Or(e1, e2) => {
+                for e in [e1, e2] {
+                    let (a, b) = self.build(e, alphabet)?;
+                    self.eps[s].push(a);
+                    self.eps[b].push(t);
+                }
+            }
+            AST::Star(e) | AST::Plus(e) | AST::Question(e) => {
+                let (a, b) = self.build(e, alphabet)?;
+                self.eps[s].push(a);
+                self.eps[b].push(t);
+                if !matches!(ast, AST::Plus(_)) {
+                    self.eps[s].push(t); // 0 回
+                }
+                if !matches!(ast, AST::Question(_)) {
+                    self.eps[b].push(a); // 繰り返し
+                }
+            }
+            AST::And(e1, e2) => {
+                let dfa = Table::new(e1, alphabet)?.product(&Table::new(e2, alphabet)?)?;
+                self.embed(&dfa, s, t);
+            }
+            AST::Not(e) => {
+                let dfa = Table::new(e, alphabet)?.complement();
+                self.embed(&dfa, s, t);
+            }
+        }
+        Ok((s, t))
+    }
+
+    /// DFA を開始状態 s から受理状態 t までの断片として埋め込む。
+    fn embed(&mut self, dfa: &Table, s: usize, t: usize) {
+        let offset = self.eps.len();
+        for _ in 0..dfa.trans.len() {
+            self.add_state();
+        }
+        for (q, row) in dfa.trans.iter().enumerate() {
+            for (sym, to) in row.iter().enumerate() {
+                self.trans[offset + q].push((sym, offset + to));
+            }
+            if dfa.accept[q] {
+                self.eps[offset + q].push(t);
+            }
+        }
+        self.eps[s].push(offset);
+    }
+
+    /// 状態集合を ε 遷移で到達できる状態まで広げる。
+    fn closure(&self, states: &mut BTreeSet<usize>) {
+        let mut stack = states.iter().copied().collect::<Vec<_>>();
+        while let Some(q) = stack.pop() {
+            for &to in &self.eps[q] {
+                if states.insert(to) {
+                    stack.push(to);
+                }
+            }
+        }
+    }
+}
+
+/// 完全な DFA の遷移表。状態 0 が開始状態。
+#[derive(Debug, Clone)]
+struct Table {
+    trans: Vec<Vec<usize>>, // 状態と記号から遷移先へのマップ
+    accept: Vec<bool>,      // 受理状態か
+}
+
+impl Table {
+    /// AST から部分集合構成法で DFA を構成。状態数が上限を超える場合は Err を返す。
+    fn new(ast: &AST, alphabet: &Alphabet) -> Result<Self, DfaError> {
+        let mut nfa = Nfa::default();
+        let (start, accept) = nfa.build(ast, alphabet)?;
+
+        let mut init = BTreeSet::from([start]);
+        nfa.closure(&mut init);
+
+        let mut table = Table {
+            trans: Vec::new(),
+            accept: Vec::new(),
+        };
+        let mut ids = HashMap::new();
+        let mut queue = VecDeque::new();
+        table.add_state(init.contains(&accept))?;
+        ids.insert(init.clone(), 0);
+        queue.push_back(init);
+
+        while let Some(states) = queue.pop_front() {
+            let from = ids[&states];
+            for sym in 0..alphabet.len() {
+                let mut next = states
+                    .iter()
+                    .flat_map(|q| nfa.trans[*q].iter())
+                    .filter(|(s, _)| *s == sym)
+                    .map(|(_, to)| *to)
+                    .collect::<BTreeSet<_>>();
+                nfa.closure(&mut next);
+
+                let to = match ids.get(&next) {
+                    Some(to) => *to,
+                    None => {
+                        let to = table.add_state(next.contains(&accept))?;
+                        ids.insert(next.clone(), to);
+                        queue.push_back(next);
+                        to
+                    }
+                };
+                table.trans[from].push(to);
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// 状態を追加する。状態数が上限を超える場合は Err を返す。
+    fn add_state(&mut self, accept: bool) -> Result<usize, DfaError> {
+        if self.trans.len() >= MAX_STATES {
+            return Err(DfaError::TooManyStates);
+        }
+        self.trans.push(Vec::new());
+        self.accept.push(accept);
+        Ok(self.trans.len() - 1)
+    }
+
+    /// 直積を構成し、両方が受理する場合に受理する DFA を返す。状態数が上限を超える場合は Err を返す。
+    fn product(&self, other: &Table) -> Result<Table, DfaError> {
+        let mut table = Table {
+            trans: Vec::new(),
+            accept: Vec::new(),
+        };
+        let mut ids = HashMap::new();
+        let mut queue = VecDeque::new();
+        table.add_state(self.accept[0] && other.accept[0])?;
+        ids.insert((0, 0), 0);
+        queue.push_back((0, 0));
+
+        while let Some((p, q)) = queue.pop_front() {
+            let from = ids[&(p, q)];
+            for (&p2, &q2) in self.trans[p].iter().zip(other.trans[q].iter()) {
+                let to = match ids.get(&(p2, q2)) {
+                    Some(to) => *to,
+                    None => {
+                        let to = table.add_state(self.accept[p2] && other.accept[q2])?;
+                        ids.insert((p2, q2), to);
+                        queue.push_back((p2, q2));
+                        to
+                    }
+                };
+                table.trans[from].push(to);
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// 受理状態を反転した DFA を返す。
+    fn complement(mut self) -> Table {
+        for a in self.accept.iter_mut() {
+            *a = !*a;
+        }
+        self
+    }
+}
+
+/// 交差と補集合を含む正規表現をマッチングするための DFA 。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::{dfa::Dfa, parser::parse_extended};
+/// // a を含み、かつ b を含まない文字列
+/// let dfa = Dfa::new(&parse_extended("(a|b|c)*a(a|b|c)*&~((a|b|c)*b(a|b|c)*)").unwrap()).unwrap();
+/// assert!(dfa.is_match(&['c', 'a', 'c']));
+/// assert!(!dfa.is_match(&['b', 'a']));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Dfa {
+    table: Table,
+    alphabet: Alphabet,
+}
+
+impl Dfa {
+    /// AST から DFA を構成する。
+    ///
+    /// AST がアサーションか文字クラスを含む場合と、状態数が [MAX_STATES] を超える場合は Err を返す。
+    pub fn new(ast: &AST) -> Result<Self, DfaError> {
+        let alphabet = Alphabet::new(ast)?;
+        Ok(Self {
+            table: Table::new(ast, &alphabet)?,
+            alphabet,
+        })
+    }
+
+    /// DFA の状態数。
+    pub fn len(&self) -> usize {
+        self.table.trans.len()
+    }
+
+    /// 状態がない場合に true を返す。開始状態が必ずあるため、常に false となる。
+    pub fn is_empty(&self) -> bool {
+        self.table.trans.is_empty()
+    }
+
+    /// 入力文字列の先頭からマッチングを行う。
+    ///
+    /// 命令列による評価と同様に、文字列の先頭部分がマッチすれば true を返す。
+    pub fn is_match(&self, line: &[char]) -> bool {
+        let mut q = 0;
+        if self.table.accept[q] {
+            return true;
+        }
+        for c in line {
+            q = self.table.trans[q][self.alphabet.symbol(*c)];
+            if self.table.accept[q] {
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -0,0 +1,157 @@
+//! エラーと、正規表現中の位置を表す型。
+//!
+//! パース、コード生成、 VM による評価のそれぞれのエラーと、
+//! パースエラーや命令の元になった正規表現中の範囲を表す [Span] を定義する。
+use super::parser::MAX_DEPTH;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    ops::Range,
+};
+
+/// 正規表現中の範囲。位置は文字単位で、 start を含み end を含まない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// 範囲の文字数。
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// 範囲が空の場合に true を返す。
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+/// パースエラーを表現するための型。
+#[derive(Debug)]
+pub enum ParseError {
+    InvalidEscape(usize, char),  // 誤ったエスケープシーケンス
+    InvalidRightParen(usize),    // 開き括弧なし
+    NoPrev(usize),               // +, |, *, ?, & の前に式がない
+    NoNext(usize),               // ~ の後に式がない
+    NoRightParen,                // 閉じ括弧なし
+    InvalidGroup(usize),         // ( の直後の ? に続く記法が不正
+    InvalidClass(usize),         // [ に対応する ] がない、または範囲が不正
+    UnknownClass(usize, String), // 未知の POSIX の文字クラス名
+    TooDeep(usize),              // AST の深さが MAX_DEPTH を超える
+    Empty,                       // 空のパターン
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidEscape(pos, c) => {
+                write!(f, "ParseError: invalid escape: pos = {pos}, char = '{c}'")
+            }
+            ParseError::InvalidRightParen(pos) => {
+                write!(f, "ParseError: invalid right parenthesis: {pos}")
+            }
+            ParseError::NoPrev(pos) => write!(f, "ParseError: no previous expression: pos = {pos}"),
+            ParseError::NoNext(pos) => write!(f, "ParseError: no next expression: pos = {pos}"),
+            ParseError::NoRightParen => write!(f, "ParseError: no right parenthesis"),
+            ParseError::InvalidGroup(pos) => {
+                write!(f, "ParseError: invalid group syntax: pos = {pos}")
+            }
+            ParseError::InvalidClass(pos) => {
+                write!(f, "ParseError: invalid character class: pos = {pos}")
+            }
+            ParseError::UnknownClass(pos, name) => {
+                write!(
+                    f,
+                    "ParseError: unknown character class name: pos = {pos}, name = {name}"
+                )
+            }
+            ParseError::TooDeep(pos) => {
+                write!(
+                    f,
+                    "ParseError: too deeply nested: pos = {pos}, max depth = {MAX_DEPTH}"
+                )
+            }
+            ParseError::Empty => write!(f, "ParseError: empty expression"),
+        }
+    }
+}
+
+impl Error for ParseError {} // エラー用に、 Error トレイトを実装
+
+impl ParseError {
+    /// エラーを検出した位置の 1 文字分の範囲。位置を持たないエラーの場合は None を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::{engine::parser::parse, Span};
+    /// let e = parse("ab)c").unwrap_err();
+    /// assert_eq!(e.span(), Some(Span::new(2, 3)));
+    /// ```
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ParseError::InvalidEscape(pos, _)
+            | ParseError::InvalidRightParen(pos)
+            | ParseError::NoPrev(pos)
+            | ParseError::NoNext(pos)
+            | ParseError::InvalidGroup(pos)
+            | ParseError::InvalidClass(pos)
+            | ParseError::UnknownClass(pos, _)
+            | ParseError::TooDeep(pos) => Some(Span::new(*pos, *pos + 1)),
+            ParseError::NoRightParen | ParseError::Empty => None,
+        }
+    }
+}
+
+/// コード生成エラーを表す型
+#[derive(Debug)]
+pub enum CodeGenError {
+    PCoverFlow,
+    FailStar,
+    FailOr,
+    FailQuestion,
+    RequiresDfa, // 交差や補集合は命令列にコンパイルできない
+}
+
+impl Display for CodeGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CodeGenError: {self:?}")
+    }
+}
+
+impl Error for CodeGenError {}
+
+#[derive(Debug)]
+pub enum EvalError {
+    PCOverFlow,
+    SPOverFlow,
+    InvalidPC,
+    InvalidContext,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EvalError: {self:?}")
+    }
+}
+
+impl Error for EvalError {}
@@ -0,0 +1,304 @@
+//! 素朴な参照実装との比較による適合性の検査。
+//!
+//! AST を直接辿り、マッチし得るすべての終端位置とキャプチャを優先順位の順に列挙する
+//! バックトラックの実装を参照実装 (テストオラクル) とする。参照実装は効率を考慮せず、
+//! [spec](super::spec) に定めた規則をそのまま書き下したものとする。
+//!
+//! ランダムに生成した正規表現と文字列について、深さ優先探索, 幅優先探索, メモ化した深さ優先探索,
+//! DFA, 微分, [Regex::captures] の結果が参照実装と一致することを確かめる。
+//! 生成する正規表現は文字クラス, アサーション (`\b`, `\B`, `$`), キャプチャグループ, 非キャプチャグループ,
+//! 交差 `&`, 補集合 `~` を含む。
+//!
+//! `oracle` フィーチャを有効にした場合のみコンパイルする。
+use super::{
+    codegen, derivative, dfa,
+    parser::{self, Assertion, AST},
+    selfcheck::{gen_line, Rng},
+    vm, Regex,
+};
+use crate::helper::DynError;
+
+/// 生成する正規表現の AST の深さの上限
+const MAX_GEN_DEPTH: usize = 4;
+
+/// 生成する文字クラス
+const CLASSES: &[&str] = &["[ab]", "[^a]", "[a-b ]", "[[:space:]]"];
+
+/// キャプチャグループの範囲 (文字単位)。添字はグループ番号
+type Caps = Vec<Option<(usize, usize)>>;
+
+/// 単語を構成する文字か
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// 参照実装。 ast が line の pos 文字目から始まる部分にマッチし得る (終端位置, キャプチャ) を、
+/// 優先順位の高い順に返す。
+///
+/// 以降のマッチングは終端位置のみに依存するため、同じ終端位置は最も優先順位の高いものだけを残す。
+/// 文字を消費しない繰り返しは、それ以上繰り返さない。
+fn matches(ast: &AST, line: &[char], pos: usize, caps: &Caps) -> Vec<(usize, Caps)> {
+    let one_char = |pred: &dyn Fn(char) -> bool| match line.get(pos) {
+        Some(c) if pred(*c) => vec![(pos + 1, caps.clone())],
+        _ => vec![],
+    };
+
+    let mut out = match ast {
+        AST::Char(c) => one_char(&|x| x == *c),
+        AST::Class(class) => one_char(&|x| class.contains(x)),
+        AST::Assert(a) => {
+            let prev = pos > 0 && is_word_char(line[pos - 1]);
+            let cur = line.get(pos).is_some_and(|c| is_word_char(*c));
+            let holds = match a {
+                Assertion::WordBoundary => prev != cur,
+                Assertion::NotWordBoundary => prev == cur,
+                Assertion::End => pos == line.len(),
+                Assertion::EndCrlf => line[pos..] == ['\r'] || pos == line.len(),
+            };
+            if holds {
+                vec![(pos, caps.clone())]
+            } else {
+                vec![]
+            }
+        }
+        AST::Question(e) => {
+            let mut v = matches(e, line, pos, caps);
+            v.push((pos, caps.clone()));
+            v
+        }
+        AST::Star(e) => repeat(e, line, pos, caps),
+        AST::Plus(e) => matches(e, line, pos, caps)
+            .into_iter()
+            .flat_map(|(m, c)| {
+                if m > pos {
+                    repeat(e, line, m, &c)
+                } else {
+                    vec![(m, c)]
+                }
+            })
+            .collect(),
+        AST::Or(e1, e2) => {
+            let mut v = matches(e1, line, pos, caps);
+            v.extend(matches(e2, line, pos, caps));
+            v
+        }
+        AST::Seq(es) => es.iter().fold(vec![(pos, caps.clone())], |states, e| {
+            states
+                .iter()
+                .flat_map(|(p, c)| matches(e, line, *p, c))
+                .collect()
+        }),
+        AST::Capture(i, e) => matches(e, line, pos, caps)
+            .into_iter()
+            .map(|(m, mut c)| {
+                c[*i] = Some((pos, m));
+                (m, c)
+            })
+            .collect(),
+        AST::And(e1, e2) => {
+            let rhs = ends(e2, line, pos);
+            matches(e1, line, pos, caps)
+                .into_iter()
+                .filter(|(m, _)| rhs.contains(m))
+                .collect()
+        }
+        AST::Not(e) => {
+            let excluded = ends(e, line, pos);
+            (pos..=line.len())
+                .filter(|m| !excluded.contains(m))
+                .map(|m| (m, caps.clone()))
+                .collect()
+        }
+    };
+
+    // 同じ終端位置は最初のものだけを残す
+    let mut seen = Vec::new();
+    out.retain(|(m, _)| {
+        let first = !seen.contains(m);
+        seen.push(*m);
+        first
+    });
+    out
+}
+
+/// e の 0 回以上の繰り返し。繰り返すほうを優先する
+fn repeat(e: &AST, line: &[char], pos: usize, caps: &Caps) -> Vec<(usize, Caps)> {
+    let mut v: Vec<_> = matches(e, line, pos, caps)
+        .into_iter()
+        .filter(|(m, _)| *m > pos)
+        .flat_map(|(m, c)| repeat(e, line, m, &c))
+        .collect();
+    v.push((pos, caps.clone()));
+    v
+}
+
+/// ast が pos 文字目からマッチし得る終端位置
+fn ends(ast: &AST, line: &[char], pos: usize) -> Vec<usize> {
+    let caps = vec![None; max_group(ast) + 1];
+    matches(ast, line, pos, &caps)
+        .into_iter()
+        .map(|(m, _)| m)
+        .collect()
+}
+
+/// ast に含まれるキャプチャグループの最大の番号。グループがない場合は 0
+fn max_group(ast: &AST) -> usize {
+    match ast {
+        AST::Char(_) | AST::Class(_) | AST::Assert(_) => 0,
+        AST::Plus(e) | AST::Star(e) | AST::Question(e) | AST::Not(e) => max_group(e),
+        AST::Capture(i, e) => (*i).max(max_group(e)),
+        AST::Or(e1, e2) | AST::And(e1, e2) => max_group(e1).max(max_group(e2)),
+        AST::Seq(es) => es.iter().map(max_group).max().unwrap_or(0),
+    }
+}
+
+/// e を囲む括弧。キャプチャグループか非キャプチャグループをランダムに選ぶ
+fn group(rng: &mut Rng, e: &str) -> String {
+    if rng.below(2) == 0 {
+        format!("({e})")
+    } else {
+        format!("(?:{e})")
+    }
+}
+
+/// ランダムな正規表現を生成し、 (正規表現, 空文字列にマッチするか) を返す。
+///
+/// use_dfa が true の場合は交差と補集合を含め、 DFA で扱えない文字クラスとアサーションは含めない。
+/// 評価器が無限ループに陥らないよう、空文字列にマッチする式には * や + を適用しない。
+fn gen_expr(rng: &mut Rng, depth: usize, use_dfa: bool) -> (String, bool) {
+    if depth >= MAX_GEN_DEPTH {
+        return (rng.char().to_string(), false);
+    }
+
+    let sub = |rng: &mut Rng| gen_expr(rng, depth + 1, use_dfa);
+    match rng.below(9) {
+        0 => (rng.char().to_string(), false),
+        1 => {
+            let (e1, n1) = sub(rng);
+            let (e2, n2) = sub(rng);
+            (format!("{e1}{e2}"), n1 && n2)
+        }
+        2 => {
+            let (e1, n1) = sub(rng);
+            let (e2, n2) = sub(rng);
+            (group(rng, &format!("{e1}|{e2}")), n1 || n2)
+        }
+        3 => {
+            let (e, _) = sub(rng);
+            (format!("{}?", group(rng, &e)), true)
+        }
+        4 | 5 => {
+            let (e, n) = sub(rng);
+            let op = match (n, rng.below(2)) {
+                (true, _) => '?',
+                (false, 0) => '*',
+                (false, _) => '+',
+            };
+            (format!("{}{op}", group(rng, &e)), n || op != '+')
+        }
+        6 => {
+            let (e, n) = sub(rng);
+            (group(rng, &e), n)
+        }
+        7 if use_dfa => {
+            let (e1, n1) = sub(rng);
+            let (e2, n2) = sub(rng);
+            (group(rng, &format!("{e1}&{e2}")), n1 && n2)
+        }
+        8 if use_dfa => {
+            let (e, n) = sub(rng);
+            (format!("~{}", group(rng, &e)), !n)
+        }
+        7 => (CLASSES[rng.below(CLASSES.len())].to_string(), false),
+        _ => {
+            let a = ["\\b", "\\B", "$"][rng.below(3)];
+            (a.to_string(), true)
+        }
+    }
+}
+
+/// expr と line について、各実装の結果を参照実装と照合する
+fn check(expr: &str, line: &[char]) -> Result<(), DynError> {
+    let ast = parser::parse_extended(expr)?;
+    let caps = vec![None; max_group(&ast) + 1];
+    let expected = |start| matches(&ast, line, start, &caps);
+
+    // DFA と微分は先頭から始まるいずれかの部分にマッチするかを返す
+    let want = !expected(0).is_empty();
+    let got = derivative::is_match(&ast, line);
+    if got != want {
+        return Err(format!("derivatives = {got}, oracle = {want}").into());
+    }
+    if let Ok(dfa) = dfa::Dfa::new(&ast) {
+        let got = dfa.is_match(line);
+        if got != want {
+            return Err(format!("dfa = {got}, oracle = {want}").into());
+        }
+    }
+    if ast.requires_dfa() {
+        return Ok(());
+    }
+
+    // 評価器は start 文字目から始まる、最も優先順位の高いマッチの終端位置を返す
+    let code = codegen::get_code(&ast)?;
+    for start in 0..=line.len() {
+        let want = expected(start).first().map(|(m, _)| *m);
+        let results = [
+            ("depth first", vm::find_at(&code, line, start, true)?),
+            ("width first", vm::find_at(&code, line, start, false)?),
+            ("memo", vm::find_memo_at(&code, line, start)?),
+        ];
+        for (name, got) in results {
+            if got != want {
+                return Err(format!("start = {start}, {name} = {got:?}, oracle = {want:?}").into());
+            }
+        }
+    }
+
+    // captures は最も左から始まるマッチのキャプチャを返す
+    let text = line.iter().collect::<String>();
+    let got = Regex::new(expr)?.captures(&text)?.map(|c| {
+        (0..c.len())
+            .map(|i| c.span(i).map(|r| (r.start, r.end)))
+            .collect::<Caps>()
+    });
+    let want = (0..=line.len()).find_map(|start| {
+        let (end, mut caps) = expected(start).into_iter().next()?;
+        caps[0] = Some((start, end));
+        Some(caps)
+    });
+    if got != want {
+        return Err(format!("captures = {got:?}, oracle = {want:?}").into());
+    }
+
+    Ok(())
+}
+
+/// seed から生成した cases 個の正規表現と文字列で、各実装の結果を参照実装と照合する。
+///
+/// # 利用例
+///
+/// ```
+/// regex::engine::oracle::oracle_check(0, 100).unwrap();
+/// ```
+///
+/// # 返り値
+///
+/// すべての結果が一致した場合は Ok(()) を返す。
+/// 結果が一致しなかった場合や、評価中にエラーが発生した場合は、
+/// 正規表現と文字列を含むエラーメッセージを Err として返す。
+pub fn oracle_check(seed: u64, cases: usize) -> Result<(), DynError> {
+    let mut rng = Rng::new(seed);
+
+    for i in 0..cases {
+        // 4 つに 1 つは交差と補集合を含む式とする
+        let (expr, _) = gen_expr(&mut rng, 0, i % 4 == 3);
+        let line = gen_line(&mut rng);
+        check(&expr, &line).map_err(|e| {
+            let line = line.iter().collect::<String>();
+            format!("oracle check failed: expr = {expr}, line = {line:?}, {e}")
+        })?;
+    }
+
+    Ok(())
+}
@@ -1,59 +1,93 @@
 //! 正規表現の式をパースし、抽象構文木に変換。
+//!
+//! パースは明示的なスタックを用いて繰り返しで行うため、括弧が深くネストしてもスタックはあふれない。
+//! ただし、生成した AST はコード生成や破棄の際に再帰的に辿られるため、
+//! AST の深さが [MAX_DEPTH] を超える場合は [ParseError::TooDeep] を返す。
+//!
+//! 実験的な機能として、 [parse_extended] では交差 `&` と補集合 `~` の演算子を扱う。
+//! 結合の強さは `~` (前置), `+`, `*`, `?` (後置), 連接, `&`, `|` の順となる。
+//! これらの演算子を含む式は命令列にコンパイルできず、 DFA によりマッチングを行う。
+//! [parse] などでは `&` と `~` は通常の文字とする。
+use super::class::{CharClass, ClassItem, PosixClass};
+use super::error::Span;
 use std::{
     error::Error,
     fmt::{self, Display},
-    mem::take,
+    iter::{Enumerate, Peekable},
+    mem::{replace, take},
+    str::Chars,
 };
 
-/// 抽象構文木を表現するための型。
-#[derive(Debug)]
-pub enum AST {
-    Char(char),
-    Plus(Box<AST>),
-    Star(Box<AST>),
-    Question(Box<AST>),
-    Or(Box<AST>, Box<AST>),
-    Seq(Vec<AST>),
-}
+pub use super::ast::{Assertion, AST};
+pub use super::error::ParseError;
 
-/// パースエラーを表現するための型。
-#[derive(Debug)]
-pub enum ParseError {
-    InvalidEscape(usize, char), // 誤ったエスケープシーケンス
-    InvalidRightParen(usize),   // 開き括弧なし
-    NoPrev(usize),              // +, |, *, ? の前に式がない
-    NoRightParen,               // 閉じ括弧なし
-    Empty,                      // 空のパターン
+/// AST の深さの上限。
+///
+/// 括弧のネスト、 +, *, ? の連続、 | の個数がそれぞれ AST の深さとなる。
+pub const MAX_DEPTH: usize = 1000;
+
+/// 特殊文字のエスケープと、 \b, \B のアサーション。
+fn parse_escape(pos: usize, c: char) -> Result<AST, ParseError> {
+    match c {
+        '\\' | '(' | ')' | '|' | '+' | '*' | '?' | '&' | '~' | '[' | '$' => Ok(AST::Char(c)),
+        'b' => Ok(AST::Assert(Assertion::WordBoundary)),
+        'B' => Ok(AST::Assert(Assertion::NotWordBoundary)),
+        _ => Err(ParseError::InvalidEscape(pos, c)),
+    }
 }
 
-impl Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ParseError::InvalidEscape(pos, c) => {
-                write!(f, "ParseError: invalid escape: pos = {pos}, char = '{c}'")
+/// [ から ] までの文字クラスを AST に変換。
+///
+/// pos は [ の位置で、 chars は [ の次の文字から始まる。
+/// POSIX の記法に従い、 ] は先頭に置くと通常の文字となり、 - は先頭か末尾に置くと通常の文字となる。
+/// [:alpha:] のような POSIX の文字クラス名を要素に含めることができる。
+fn parse_class(pos: usize, chars: &mut Peekable<Enumerate<Chars>>) -> Result<AST, ParseError> {
+    let negated = chars.next_if(|(_, c)| *c == '^').is_some();
+    let mut items = Vec::new();
+    let mut first = true;
+
+    loop {
+        let (i, c) = chars.next().ok_or(ParseError::InvalidClass(pos))?;
+        match c {
+            ']' if !first => break,
+            '[' if chars.next_if(|(_, c)| *c == ':').is_some() => {
+                // [:name:] 形式の POSIX の文字クラス
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, ':')) if chars.next_if(|(_, c)| *c == ']').is_some() => break,
+                        Some((_, c)) => name.push(c),
+                        None => return Err(ParseError::InvalidClass(pos)),
+                    }
+                }
+                let class =
+                    PosixClass::from_name(&name).ok_or(ParseError::UnknownClass(i, name))?;
+                items.push(ClassItem::Posix(class));
             }
-            ParseError::InvalidRightParen(pos) => {
-                write!(f, "ParseError: invalid right parenthesis: {pos}")
+            _ => {
+                // a-z 形式の範囲。 - の後が ] の場合は - を通常の文字とする
+                let is_range = matches!(chars.peek(), Some((_, '-')))
+                    && chars.clone().nth(1).is_some_and(|(_, c)| c != ']');
+                if is_range {
+                    chars.next();
+                    let (_, to) = chars.next().ok_or(ParseError::InvalidClass(pos))?;
+                    if to < c {
+                        return Err(ParseError::InvalidClass(i));
+                    }
+                    items.push(ClassItem::Range(c, to));
+                } else {
+                    items.push(ClassItem::Char(c));
+                }
             }
-            ParseError::NoPrev(pos) => write!(f, "ParseError: no previous expression: pos = {pos}"),
-            ParseError::NoRightParen => write!(f, "ParseError: no right parenthesis"),
-            ParseError::Empty => write!(f, "ParseError: empty expression"),
         }
+        first = false;
     }
-}
-
-impl Error for ParseError {} // エラー用に、 Error トレイトを実装
 
-/// 特殊文字のエスケープ。
-fn parse_escape(pos: usize, c: char) -> Result<AST, ParseError> {
-    match c {
-        '\\' | '(' | ')' | '|' | '+' | '*' | '?' => Ok(AST::Char(c)),
-        _ => Err(ParseError::InvalidEscape(pos, c)),
-    }
+    Ok(AST::Class(CharClass { negated, items }))
 }
 
 /// parse_plus_star_question 関数で利用するための列挙型。
-enum PSQ {
+enum Psq {
     Plus,
     Star,
     Question,
@@ -66,14 +100,17 @@ enum PSQ {
 /// 例: *ab, abc|+ などはエラー。
 fn parse_plus_star_question(
     seq: &mut Vec<AST>,
-    ast_type: PSQ,
+    ast_type: Psq,
     pos: usize,
 ) -> Result<(), ParseError> {
     if let Some(prev) = seq.pop() {
+        if depth(&prev) >= MAX_DEPTH {
+            return Err(ParseError::TooDeep(pos));
+        }
         let ast = match ast_type {
-            PSQ::Plus => AST::Plus(Box::new(prev)),
-            PSQ::Star => AST::Star(Box::new(prev)),
-            PSQ::Question => AST::Question(Box::new(prev)),
+            Psq::Plus => AST::Plus(Box::new(prev)),
+            Psq::Star => AST::Star(Box::new(prev)),
+            Psq::Question => AST::Question(Box::new(prev)),
         };
         seq.push(ast);
         Ok(())
@@ -82,26 +119,131 @@ fn parse_plus_star_question(
     }
 }
 
-/// Or で結合された複数の式を AST に変換。
+/// AST の深さを返す。
+///
+/// 深い AST でもスタックがあふれないよう、明示的なスタックを用いて辿る。
+fn depth(ast: &AST) -> usize {
+    let mut max = 0;
+    let mut stack = vec![(ast, 1)];
+    while let Some((ast, d)) = stack.pop() {
+        max = max.max(d);
+        match ast {
+            AST::Char(_) | AST::Class(_) | AST::Assert(_) => (),
+            AST::Plus(e) | AST::Star(e) | AST::Question(e) | AST::Capture(_, e) | AST::Not(e) => {
+                stack.push((e, d + 1))
+            }
+            AST::Or(e1, e2) | AST::And(e1, e2) => {
+                stack.push((e1, d + 1));
+                stack.push((e2, d + 1));
+            }
+            AST::Seq(es) => stack.extend(es.iter().map(|e| (e, d + 1))),
+        }
+    }
+    max
+}
+
+/// Or または And で結合された複数の式を AST に変換。
 ///
 /// 例えば、 abc|def|ghi は、 AST::Or("abc", AST::Or("def", "ghi")) という AST となる。
-fn fold_or(mut seq_or: Vec<AST>) -> Option<AST> {
-    if seq_or.len() > 1 {
-        // seq_or の要素が複数ある場合は、 Or で式を結合。
-        let mut ast = seq_or.pop().unwrap();
-        seq_or.reverse();
-        for s in seq_or {
-            ast = AST::Or(Box::new(s), Box::new(ast));
+/// op には AST::Or か AST::And を指定する。
+/// 生成した AST の深さが MAX_DEPTH を超える場合はエラー。
+fn fold_op(
+    mut seq: Vec<AST>,
+    op: fn(Box<AST>, Box<AST>) -> AST,
+    pos: usize,
+) -> Result<Option<AST>, ParseError> {
+    // 連鎖が長すぎる場合は、 AST を生成する前にエラーとする。
+    if seq.len() > MAX_DEPTH {
+        return Err(ParseError::TooDeep(pos));
+    }
+
+    let ast = if seq.len() > 1 {
+        // seq の要素が複数ある場合は、 op で式を結合。
+        let mut ast = seq.pop().unwrap();
+        seq.reverse();
+        for s in seq {
+            ast = op(Box::new(s), Box::new(ast));
         }
         Some(ast)
     } else {
-        // seq_or の要素がひとつのみの場合は、 Or ではなく最初の値を返す。
-        seq_or.pop()
+        // seq の要素がひとつのみの場合は、 op ではなく最初の値を返す。
+        seq.pop()
+    };
+
+    match ast {
+        Some(ast) if depth(&ast) > MAX_DEPTH => Err(ParseError::TooDeep(pos)),
+        ast => Ok(ast),
+    }
+}
+
+/// 現在の Seq を & のコンテキストに追加し、 & で結合した式を Or のコンテキストに追加。
+///
+/// | や ) の直前、および式の終わりで呼び出す。
+fn close_and(
+    seq: Vec<AST>,
+    seq_and: &mut Vec<AST>,
+    seq_or: &mut Vec<AST>,
+    pos: usize,
+) -> Result<(), ParseError> {
+    if !seq.is_empty() {
+        seq_and.push(AST::Seq(seq));
+    } else if !seq_and.is_empty() {
+        // "a&|b" のように、 & の後の式が空の場合はエラー。
+        return Err(ParseError::NoPrev(pos));
+    }
+
+    if let Some(ast) = fold_op(take(seq_and), AST::And, pos)? {
+        seq_or.push(ast);
+    }
+    Ok(())
+}
+
+/// 式を Seq に追加。 ~ が前置されている場合は補集合にする。
+fn push_atom(seq: &mut Vec<AST>, ast: AST, not: &mut bool) {
+    if take(not) {
+        seq.push(AST::Not(Box::new(ast)));
+    } else {
+        seq.push(ast);
     }
 }
 
-/// 正規表現を抽象構文木に変換。
+/// 正規表現を抽象構文木に変換。 `&` と `~` は通常の文字とする。
 pub fn parse(expr: &str) -> Result<AST, ParseError> {
+    parse_with_spans(expr).map(|(ast, _)| ast)
+}
+
+/// 交差 `&` と補集合 `~` を演算子として扱い、正規表現を抽象構文木に変換 (実験的)。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::parser::{parse, parse_extended};
+/// assert!(parse_extended("a&b").unwrap().requires_dfa());
+/// assert!(!parse("a&b").unwrap().requires_dfa());
+/// ```
+pub fn parse_extended(expr: &str) -> Result<AST, ParseError> {
+    parse_spans(expr, true).map(|(ast, _)| ast)
+}
+
+/// 正規表現を抽象構文木に変換し、文字、エスケープシーケンス、文字クラスの各アトムの範囲を
+/// 正規表現中に現れる順に返す。
+///
+/// コード生成はアトムをこの順に 1 つずつ命令に変換するため、命令と正規表現中の範囲の対応付けに用いる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::{engine::parser::parse_with_spans, Span};
+/// let (_, spans) = parse_with_spans("a[bc]+\\b").unwrap();
+/// assert_eq!(spans, vec![Span::new(0, 1), Span::new(1, 5), Span::new(6, 8)]);
+/// ```
+pub fn parse_with_spans(expr: &str) -> Result<(AST, Vec<Span>), ParseError> {
+    parse_spans(expr, false)
+}
+
+/// extended が true の場合は `&` と `~` を演算子、 false の場合は通常の文字として
+/// [parse_with_spans] と同様にパースする。
+fn parse_spans(expr: &str, extended: bool) -> Result<(AST, Vec<Span>), ParseError> {
     // 内部状態を表現するための型。
     enum ParseState {
         Char,
@@ -109,59 +251,115 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
     }
 
     let mut seq = Vec::new(); // 現在の Seq のコンテキスト
+    let mut seq_and = Vec::new(); // 現在の And のコンテキスト
     let mut seq_or = Vec::new(); // 現在の Or のコンテキスト
+    let mut not = false; // 次の式に ~ が前置されているか
+    let mut capture = None; // 現在の括弧のグループ番号。非キャプチャグループの場合は None
+    let mut groups = 0; // キャプチャグループの数
     let mut stack = Vec::new(); // コンテキストのスタック
     let mut state = ParseState::Char; // 現在の状態
+    let mut atoms = Vec::new(); // アトムの範囲
+    let len = expr.chars().count();
 
-    for (i, c) in expr.chars().enumerate() {
+    let mut chars = expr.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
         match &state {
             ParseState::Char => match c {
-                '+' => parse_plus_star_question(&mut seq, PSQ::Plus, i)?,
-                '*' => parse_plus_star_question(&mut seq, PSQ::Star, i)?,
-                '?' => parse_plus_star_question(&mut seq, PSQ::Question, i)?,
+                '+' | '*' | '?' if not => return Err(ParseError::NoNext(i)),
+                '+' => parse_plus_star_question(&mut seq, Psq::Plus, i)?,
+                '*' => parse_plus_star_question(&mut seq, Psq::Star, i)?,
+                '?' => parse_plus_star_question(&mut seq, Psq::Question, i)?,
                 '(' => {
+                    // "(?:" で始まる場合は非キャプチャグループ。
+                    // それ以外は開き括弧の順にグループ番号を割り当てる。
+                    let group = if chars.next_if(|(_, c)| *c == '?').is_some() {
+                        if chars.next_if(|(_, c)| *c == ':').is_none() {
+                            return Err(ParseError::InvalidGroup(i + 1));
+                        }
+                        None
+                    } else {
+                        groups += 1;
+                        Some(groups)
+                    };
+
                     // 現在のコンテキストをスタックに保存し、現在のコンテキストを空の状態にする。
                     let prev = take(&mut seq);
+                    let prev_and = take(&mut seq_and);
                     let prev_or = take(&mut seq_or);
-                    stack.push((prev, prev_or));
+                    let prev_not = take(&mut not);
+                    let prev_capture = replace(&mut capture, group);
+                    stack.push((prev, prev_and, prev_or, prev_not, prev_capture));
                 }
                 ')' => {
                     // 現在のコンテキストをスタックからポップ。
-                    if let Some((mut prev, prev_or)) = stack.pop() {
-                        // "()" のように、式が空の場合は push しない。
-                        if !seq.is_empty() {
-                            seq_or.push(AST::Seq(seq));
+                    if let Some((mut prev, prev_and, prev_or, prev_not, prev_capture)) = stack.pop()
+                    {
+                        if not {
+                            // "(a~)" のように、 ~ の後に式がない場合はエラー。
+                            return Err(ParseError::NoNext(i));
                         }
 
-                        // Or を生成。
-                        if let Some(ast) = fold_or(seq_or) {
-                            prev.push(ast);
-                        }
+                        // "()" のように、式が空の場合は push しない。
+                        close_and(take(&mut seq), &mut seq_and, &mut seq_or, i)?;
 
                         // 以前のコンテキストを、現在のコンテキストにする。
+                        not = prev_not;
+
+                        // Or を生成し、キャプチャグループの場合は Capture で囲む。
+                        if let Some(ast) = fold_op(seq_or, AST::Or, i)? {
+                            let ast = match replace(&mut capture, prev_capture) {
+                                Some(n) => AST::Capture(n, Box::new(ast)),
+                                None => ast,
+                            };
+                            push_atom(&mut prev, ast, &mut not);
+                        } else {
+                            capture = prev_capture;
+                        }
+
                         seq = prev;
+                        seq_and = prev_and;
                         seq_or = prev_or;
                     } else {
                         // "abc)" のように、開き括弧がないのに閉じ括弧がある場合はエラー。
                         return Err(ParseError::InvalidRightParen(i));
                     }
                 }
-                '|' => {
+                '|' | '&' if c == '|' || extended => {
                     if seq.is_empty() {
                         // "||", "(|abc)" などと、式が空の場合はエラー。
                         return Err(ParseError::NoPrev(i));
+                    } else if not {
+                        // "a~|b" のように、 ~ の後に式がない場合はエラー。
+                        return Err(ParseError::NoNext(i));
+                    } else if c == '|' {
+                        close_and(take(&mut seq), &mut seq_and, &mut seq_or, i)?;
                     } else {
                         let prev = take(&mut seq);
-                        seq_or.push(AST::Seq(prev));
+                        seq_and.push(AST::Seq(prev));
                     }
                 }
+                '[' => {
+                    let ast = parse_class(i, &mut chars)?;
+                    let end = chars.peek().map_or(len, |(j, _)| *j);
+                    atoms.push(Span::new(i, end));
+                    push_atom(&mut seq, ast, &mut not);
+                }
+                '~' if extended => not = !not,
                 '\\' => state = ParseState::Escape,
-                _ => seq.push(AST::Char(c)),
+                '$' => {
+                    atoms.push(Span::new(i, i + 1));
+                    push_atom(&mut seq, AST::Assert(Assertion::End), &mut not);
+                }
+                _ => {
+                    atoms.push(Span::new(i, i + 1));
+                    push_atom(&mut seq, AST::Char(c), &mut not);
+                }
             },
             ParseState::Escape => {
                 // エスケープシーケンスの処理。
                 let ast = parse_escape(i, c)?;
-                seq.push(ast);
+                atoms.push(Span::new(i - 1, i + 1)); // \ から
+                push_atom(&mut seq, ast, &mut not);
                 state = ParseState::Char;
             }
         }
@@ -172,15 +370,82 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
         return Err(ParseError::NoRightParen);
     }
 
-    // "()" のように、式が空の場合は push しない。
-    if !seq.is_empty() {
-        seq_or.push(AST::Seq(seq));
+    // "a~" のように、 ~ の後に式がない場合はエラー。
+    if not {
+        return Err(ParseError::NoNext(len));
     }
 
+    // "()" のように、式が空の場合は push しない。
+    close_and(seq, &mut seq_and, &mut seq_or, len)?;
+
     // Or を生成し、成功した場合はそれを返す。
-    if let Some(ast) = fold_or(seq_or) {
-        Ok(ast)
+    if let Some(ast) = fold_op(seq_or, AST::Or, len)? {
+        Ok((ast, atoms))
     } else {
         Err(ParseError::Empty)
     }
 }
+
+/// パースに失敗した正規表現の診断情報。
+///
+/// 正規表現全体のエラーと、パースできる最長の先頭部分およびその AST を持つ。
+/// シェルのヒストリ検索や REPL などで、入力途中のパターンに部分的な結果を表示するために用いる。
+#[derive(Debug)]
+pub struct PartialParse {
+    pub error: ParseError, // 正規表現全体のパースエラー
+    pub prefix: String,    // パースできる最長の先頭部分。ない場合は空文字列
+    pub ast: Option<AST>,  // prefix の AST 。 prefix が空の場合は None
+}
+
+impl Display for PartialParse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (valid prefix: {:?})", self.error, self.prefix)
+    }
+}
+
+impl Error for PartialParse {}
+
+/// 正規表現を抽象構文木に変換し、失敗した場合はパースできる最長の先頭部分を求める。
+///
+/// 先頭部分は長いものから順にパースを試みる。エラーの位置より後ろを含む先頭部分は
+/// 同じ位置で必ず失敗するため、エラーの位置より短いものから試す。
+/// 最悪の場合はパターンの長さの 2 乗に比例する時間がかかるため、対話的に入力される短いパターンに用いる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::parser::parse_partial;
+/// let e = parse_partial("ab(c|d").unwrap_err();
+/// assert_eq!(e.prefix, "ab");
+/// assert!(e.ast.is_some());
+///
+/// assert!(parse_partial("ab(c|d)").is_ok());
+/// ```
+pub fn parse_partial(expr: &str) -> Result<AST, PartialParse> {
+    let error = match parse(expr) {
+        Ok(ast) => return Ok(ast),
+        Err(e) => e,
+    };
+
+    let chars: Vec<char> = expr.chars().collect();
+    let longest = error
+        .span()
+        .map_or(chars.len(), |s| s.start)
+        .min(chars.len().saturating_sub(1));
+    for len in (1..=longest).rev() {
+        let prefix: String = chars[..len].iter().collect();
+        if let Ok(ast) = parse(&prefix) {
+            return Err(PartialParse {
+                error,
+                prefix,
+                ast: Some(ast),
+            });
+        }
+    }
+
+    Err(PartialParse {
+        error,
+        prefix: String::new(),
+        ast: None,
+    })
+}
@@ -0,0 +1,224 @@
+//! AST と命令列を人が読みやすい形式に整形。
+//!
+//! 命令列は Graphviz の DOT 形式に変換して、オートマトンとして図示することもできる。
+use super::{parser::AST, Instruction};
+use std::collections::BTreeMap;
+
+/// AST を罫線を用いた木の形式で文字列に変換。
+///
+/// 1 行に 1 ノードを出力し、子ノードは罫線で親ノードとつなぐ。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::{parser::parse, pretty::ast_tree};
+/// let ast = parse("a|b+").unwrap();
+/// assert_eq!(
+///     ast_tree(&ast),
+///     "Or
+/// ├── Seq
+/// │   └── Char 'a'
+/// └── Seq
+///     └── Plus
+///         └── Char 'b'
+/// "
+/// );
+/// ```
+pub fn ast_tree(ast: &AST) -> String {
+    let mut out = String::new();
+    write_node(ast, "", &mut out);
+    out
+}
+
+/// ノードのラベルと子ノードを返す。
+fn node(ast: &AST) -> (String, Vec<&AST>) {
+    match ast {
+        AST::Char(c) => (format!("Char {c:?}"), vec![]),
+        AST::Class(c) => (format!("Class {c}"), vec![]),
+        AST::Assert(a) => (format!("Assert {a}"), vec![]),
+        AST::Plus(e) => ("Plus".to_string(), vec![e]),
+        AST::Star(e) => ("Star".to_string(), vec![e]),
+        AST::Question(e) => ("Question".to_string(), vec![e]),
+        AST::Or(e1, e2) => ("Or".to_string(), vec![e1, e2]),
+        AST::Seq(es) => ("Seq".to_string(), es.iter().collect()),
+        AST::Capture(n, e) => (format!("Capture {n}"), vec![e]),
+        AST::And(e1, e2) => ("And".to_string(), vec![e1, e2]),
+        AST::Not(e) => ("Not".to_string(), vec![e]),
+    }
+}
+
+/// ノードのラベルを出力し、子ノードを再帰的に出力。
+///
+/// prefix は子ノードの行頭に付ける罫線。
+fn write_node(ast: &AST, prefix: &str, out: &mut String) {
+    let (label, children) = node(ast);
+    out.push_str(&label);
+    out.push('\n');
+
+    let n = children.len();
+    for (i, child) in children.into_iter().enumerate() {
+        let last = i + 1 == n;
+        out.push_str(prefix);
+        out.push_str(if last { "└── " } else { "├── " });
+        let next = format!("{prefix}{}", if last { "    " } else { "│   " });
+        write_node(child, &next, out);
+    }
+}
+
+/// 命令列を、ジャンプ先にラベルを付けた形式で文字列に変換。
+///
+/// ジャンプ先のアドレスには L1, L2, ... のラベルをアドレス順に付け、
+/// jump と split の引数はラベルで表示する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::{codegen::get_code, parser::parse, pretty::code_listing};
+/// let code = get_code(&parse("a|b").unwrap()).unwrap();
+/// assert_eq!(
+///     code_listing(&code),
+///     "0000:     split L1, L2
+/// 0001: L1: char a
+/// 0002:     jump L3
+/// 0003: L2: char b
+/// 0004: L3: match
+/// "
+/// );
+/// ```
+pub fn code_listing(code: &[Instruction]) -> String {
+    // ジャンプ先のアドレスとラベルの対応
+    let mut labels = BTreeMap::new();
+    for inst in code {
+        match inst {
+            Instruction::Jump(addr) => {
+                labels.insert(*addr, String::new());
+            }
+            Instruction::Split(addr1, addr2) => {
+                labels.insert(*addr1, String::new());
+                labels.insert(*addr2, String::new());
+            }
+            _ => (),
+        }
+    }
+    for (i, label) in labels.values_mut().enumerate() {
+        *label = format!("L{}", i + 1);
+    }
+
+    // ラベルの幅を揃えるための最大長。ラベルがない場合はラベル欄を出力しない
+    let width = labels.values().map(|l| l.len() + 2).max().unwrap_or(0);
+
+    let mut out = String::new();
+    for (n, inst) in code.iter().enumerate() {
+        let label = labels.get(&n).map_or(String::new(), |l| format!("{l}: "));
+        let inst = match inst {
+            Instruction::Jump(addr) => format!("jump {}", labels[addr]),
+            Instruction::Split(addr1, addr2) => {
+                format!("split {}, {}", labels[addr1], labels[addr2])
+            }
+            _ => inst.to_string(),
+        };
+        out.push_str(&format!("{n:>04}: {label:<width$}{inst}\n"));
+    }
+
+    out
+}
+
+/// DOT の文字列中で特別な意味を持つ文字をエスケープ。
+fn dot_escape(c: char) -> String {
+    match c {
+        '"' | '\\' => format!("\\{c}"),
+        _ => c.to_string(),
+    }
+}
+
+/// 命令列を、 Graphviz の DOT 形式のグラフに変換。
+///
+/// 各命令のアドレスをノードとし、 char 命令は文字をラベルとする辺、
+/// jump と split 命令は文字を消費しない破線の辺 (ε 遷移) で表す。
+/// assert 命令はアサーションをラベルとする破線の辺で表す。
+/// match 命令のノードは二重丸で表す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::engine::{codegen::get_code, parser::parse, pretty::code_to_dot};
+/// let code = get_code(&parse("a?").unwrap()).unwrap();
+/// assert_eq!(
+///     code_to_dot(&code),
+///     r#"digraph regex {
+///     rankdir=LR;
+///     node [shape=circle];
+///     start [shape=point];
+///     start -> 0;
+///     0 -> 1 [label="ε", style=dashed];
+///     0 -> 2 [label="ε", style=dashed];
+///     1 -> 2 [label="a"];
+///     2 [shape=doublecircle];
+/// }
+/// "#
+/// );
+/// ```
+pub fn code_to_dot(code: &[Instruction]) -> String {
+    let mut out = String::new();
+    out.push_str("digraph regex {\n");
+    out.push_str("    rankdir=LR;\n");
+    out.push_str("    node [shape=circle];\n");
+    out.push_str("    start [shape=point];\n");
+    out.push_str("    start -> 0;\n");
+
+    for (n, inst) in code.iter().enumerate() {
+        match inst {
+            Instruction::Char(c) => {
+                out.push_str(&format!(
+                    "    {n} -> {} [label=\"{}\"];\n",
+                    n + 1,
+                    dot_escape(*c)
+                ));
+            }
+            Instruction::FoldChar(c) => {
+                // 大文字小文字を区別しない文字は /i を付けて表す
+                out.push_str(&format!(
+                    "    {n} -> {} [label=\"{}/i\"];\n",
+                    n + 1,
+                    dot_escape(*c)
+                ));
+            }
+            Instruction::Class(c) | Instruction::FoldClass(c) => {
+                let mut label = c.to_string().chars().map(dot_escape).collect::<String>();
+                if matches!(inst, Instruction::FoldClass(_)) {
+                    label.push_str("/i");
+                }
+                out.push_str(&format!("    {n} -> {} [label=\"{label}\"];\n", n + 1));
+            }
+            Instruction::Assert(a) => {
+                // アサーションは文字を消費しない破線の辺で表す
+                let label = a.to_string().chars().map(dot_escape).collect::<String>();
+                out.push_str(&format!(
+                    "    {n} -> {} [label=\"{label}\", style=dashed];\n",
+                    n + 1
+                ));
+            }
+            Instruction::Save(slot) => {
+                out.push_str(&format!(
+                    "    {n} -> {} [label=\"save {slot}\", style=dashed];\n",
+                    n + 1
+                ));
+            }
+            Instruction::Match => out.push_str(&format!("    {n} [shape=doublecircle];\n")),
+            Instruction::Jump(addr) => {
+                out.push_str(&format!("    {n} -> {addr} [label=\"ε\", style=dashed];\n"));
+            }
+            Instruction::Split(addr1, addr2) => {
+                out.push_str(&format!(
+                    "    {n} -> {addr1} [label=\"ε\", style=dashed];\n"
+                ));
+                out.push_str(&format!(
+                    "    {n} -> {addr2} [label=\"ε\", style=dashed];\n"
+                ));
+            }
+        }
+    }
+
+    out.push_str("}\n");
+    out
+}
@@ -0,0 +1,131 @@
+//! 深さ優先探索と幅優先探索の評価器が同じ結果を返すかを、ランダムな入力で検査。
+//!
+//! 小さな正規表現と文字列をランダムに生成し、すべての開始位置について
+//! 両方の評価器のマッチ結果 (マッチの終端位置) が一致することを確かめる。
+use super::{codegen, parser, vm};
+use crate::helper::DynError;
+
+/// 生成する正規表現とマッチング対象の文字列に使う文字
+///
+/// \b と \B を検査するため、単語を構成しない空白も含める。
+pub(super) const ALPHABET: &[char] = &['a', 'b', ' '];
+
+/// 生成する正規表現の AST の深さの上限
+const MAX_GEN_DEPTH: usize = 4;
+
+/// 生成するマッチング対象の文字列の長さの上限
+const MAX_LINE_LEN: usize = 8;
+
+/// 検査用の疑似乱数生成器 (xorshift64*)
+///
+/// 同じ seed からは同じ列を生成するので、不一致が見つかった場合に再現できる。
+pub(super) struct Rng(u64);
+
+impl Rng {
+    pub(super) fn new(seed: u64) -> Self {
+        // 状態が 0 だと 0 しか生成しないため、 0 以外にする
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// 0 以上 n 未満の値を返す
+    pub(super) fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    pub(super) fn char(&mut self) -> char {
+        ALPHABET[self.below(ALPHABET.len())]
+    }
+}
+
+/// ランダムな正規表現を生成し、 (正規表現, 空文字列にマッチするか) を返す。
+///
+/// 空文字列にマッチする式に * や + を適用すると、評価器が無限ループに陥るため生成しない。
+fn gen_expr(rng: &mut Rng, depth: usize) -> (String, bool) {
+    if depth >= MAX_GEN_DEPTH {
+        return (rng.char().to_string(), false);
+    }
+
+    match rng.below(7) {
+        0 => (rng.char().to_string(), false),
+        1 => {
+            // 連接
+            let (e1, n1) = gen_expr(rng, depth + 1);
+            let (e2, n2) = gen_expr(rng, depth + 1);
+            (format!("{e1}{e2}"), n1 && n2)
+        }
+        2 => {
+            // 選択
+            let (e1, n1) = gen_expr(rng, depth + 1);
+            let (e2, n2) = gen_expr(rng, depth + 1);
+            (format!("({e1}|{e2})"), n1 || n2)
+        }
+        3 => {
+            let (e, _) = gen_expr(rng, depth + 1);
+            (format!("({e})?"), true)
+        }
+        4 => match gen_expr(rng, depth + 1) {
+            (e, false) => (format!("({e})*"), true),
+            (e, true) => (format!("({e})?"), true),
+        },
+        5 => match gen_expr(rng, depth + 1) {
+            (e, false) => (format!("({e})+"), false),
+            (e, true) => (format!("({e})?"), true),
+        },
+        _ => {
+            // アサーションは文字を消費しないため、空文字列にマッチする式として扱う
+            let a = if rng.below(2) == 0 { "\\b" } else { "\\B" };
+            (a.to_string(), true)
+        }
+    }
+}
+
+/// ランダムなマッチング対象の文字列を生成。
+pub(super) fn gen_line(rng: &mut Rng) -> Vec<char> {
+    let len = rng.below(MAX_LINE_LEN + 1);
+    (0..len).map(|_| rng.char()).collect()
+}
+
+/// seed から生成した cases 個の正規表現と文字列で、深さ優先探索と幅優先探索の結果を比較する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex;
+/// regex::self_check(0, 100).unwrap();
+/// ```
+///
+/// # 返り値
+///
+/// すべての結果が一致した場合は Ok(()) を返す。
+/// 結果が一致しなかった場合や、評価中にエラーが発生した場合は、
+/// 正規表現と文字列を含むエラーメッセージを Err として返す。
+pub fn self_check(seed: u64, cases: usize) -> Result<(), DynError> {
+    let mut rng = Rng::new(seed);
+
+    for _ in 0..cases {
+        let (expr, _) = gen_expr(&mut rng, 0);
+        let line = gen_line(&mut rng);
+        let code = codegen::get_code(&parser::parse(&expr)?)?;
+
+        for start in 0..=line.len() {
+            let depth = vm::find_at(&code, &line, start, true)?;
+            let width = vm::find_at(&code, &line, start, false)?;
+            if depth != width {
+                let line = line.iter().collect::<String>();
+                return Err(format!(
+                    "self check failed: expr = {expr}, line = {line}, start = {start}, depth first = {depth:?}, width first = {width:?}"
+                )
+                .into());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! エンジンの観測可能な振る舞いの仕様。
+//!
+//! キャプチャグループの番号付け、複数のマッチ候補からの選択、長さ 0 のマッチの扱いなど、
+//! 利用者から見える振る舞いを定数として定める。 [CASES] はこれらの規則を確認する例の表で、
+//! テストは表のすべての例を公開 API で実行して結果を照合する。
+//! 評価器や DFA などの実装を変更する場合も、ここに定めた振る舞いを変えてはならない。
+//!
+//! マッチの位置は、入力文字列におけるバイト単位の範囲で表す。
+
+/// マッチ全体を表すグループ番号。
+pub const WHOLE_MATCH_GROUP: usize = 0;
+
+/// 最初のキャプチャグループの番号。
+///
+/// 以降は開き括弧の出現順に 1 ずつ増える。 `(?:...)` は番号を持たない。
+///
+/// ```
+/// use regex::{engine::spec::FIRST_CAPTURE_GROUP, Regex};
+/// let caps = Regex::new("((a)(?:b)(c))").unwrap().captures("abc").unwrap().unwrap();
+/// assert_eq!(caps.get(FIRST_CAPTURE_GROUP), Some("abc"));
+/// assert_eq!(caps.get(FIRST_CAPTURE_GROUP + 1), Some("a"));
+/// assert_eq!(caps.get(FIRST_CAPTURE_GROUP + 2), Some("c"));
+/// ```
+pub const FIRST_CAPTURE_GROUP: usize = 1;
+
+/// 同じ位置から複数のマッチが可能な場合に、どれを選ぶか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preference {
+    /// 優先順位の最も高いマッチ。 `|` は左の選択肢を、量指定子は繰り返すほうを優先する (Perl 方式)
+    LeftmostFirst,
+    /// 最も長いマッチ (POSIX 方式)
+    LeftmostLongest,
+}
+
+/// マッチの選択方法。
+///
+/// 開始位置が最も左のマッチを選び、同じ開始位置では [Preference::LeftmostFirst] に従う。
+///
+/// ```
+/// use regex::{engine::spec::{Preference, PREFERENCE}, MatchOptions};
+/// assert_eq!(PREFERENCE, Preference::LeftmostFirst);
+/// assert_eq!(regex::find_all("a|ab", "ab", MatchOptions::default()).unwrap(), vec![0..1]);
+/// assert_eq!(regex::find_all("a*", "aaa", MatchOptions::default()).unwrap(), vec![0..3]);
+/// ```
+pub const PREFERENCE: Preference = Preference::LeftmostFirst;
+
+/// 量指定子で繰り返したキャプチャグループが、どの繰り返しの部分文字列を持つか。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RepeatedCapture {
+    /// 最初の繰り返し
+    First,
+    /// 最後の繰り返し
+    Last,
+}
+
+/// 繰り返したキャプチャグループは最後の繰り返しにマッチした部分文字列を持つ。
+///
+/// ```
+/// use regex::{engine::spec::{RepeatedCapture, REPEATED_CAPTURE}, Regex};
+/// assert_eq!(REPEATED_CAPTURE, RepeatedCapture::Last);
+/// let caps = Regex::new("(a|b)+").unwrap().captures("ab").unwrap().unwrap();
+/// assert_eq!(caps.get(1), Some("b"));
+/// ```
+pub const REPEATED_CAPTURE: RepeatedCapture = RepeatedCapture::Last;
+
+/// 長さ 0 のマッチの扱い。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyMatch {
+    /// マッチとして返す
+    Report,
+    /// 返さずに 1 文字進めて探索を続ける
+    SkipAndAdvance,
+}
+
+/// [find_all](crate::find_all) と [find](crate::find) は長さ 0 のマッチを返さず、 1 文字進めて探索を続ける。
+///
+/// 長さ 1 以上のマッチの後はその終了位置から探索を続けるため、マッチは重ならず、開始位置の昇順に並ぶ。
+///
+/// ```
+/// use regex::{engine::spec::{EmptyMatch, FIND_ALL_EMPTY_MATCH}, MatchOptions};
+/// assert_eq!(FIND_ALL_EMPTY_MATCH, EmptyMatch::SkipAndAdvance);
+/// assert_eq!(regex::find_all("a*", "baa", MatchOptions::default()).unwrap(), vec![1..3]);
+/// assert_eq!(regex::find_all("aa", "aaaaa", MatchOptions::default()).unwrap(), vec![0..2, 2..4]);
+/// ```
+pub const FIND_ALL_EMPTY_MATCH: EmptyMatch = EmptyMatch::SkipAndAdvance;
+
+/// [Regex::captures](crate::Regex::captures) は長さ 0 のマッチも返す。
+///
+/// ```
+/// use regex::{engine::spec::{EmptyMatch, CAPTURES_EMPTY_MATCH}, Regex};
+/// assert_eq!(CAPTURES_EMPTY_MATCH, EmptyMatch::Report);
+/// let caps = Regex::new("a*").unwrap().captures("baa").unwrap().unwrap();
+/// assert_eq!(caps.span(0), Some(0..0));
+/// ```
+pub const CAPTURES_EMPTY_MATCH: EmptyMatch = EmptyMatch::Report;
+
+/// 規則を確認する例。
+#[derive(Debug)]
+pub struct Case {
+    pub rule: &'static str, // 確認する規則
+    pub expr: &'static str,
+    pub text: &'static str,
+    pub find_all: &'static [(usize, usize)], // find_all の結果の範囲
+    pub captures: Option<&'static [Option<&'static str>]>, // captures の各グループ。マッチしない場合は None
+}
+
+/// 規則を確認する例の表。
+pub const CASES: &[Case] = &[
+    Case {
+        rule: "グループ番号は開き括弧の出現順",
+        expr: "((a)(b))",
+        text: "ab",
+        find_all: &[(0, 2)],
+        captures: Some(&[Some("ab"), Some("ab"), Some("a"), Some("b")]),
+    },
+    Case {
+        rule: "非キャプチャグループは番号を持たない",
+        expr: "(?:a)(b)",
+        text: "ab",
+        find_all: &[(0, 2)],
+        captures: Some(&[Some("ab"), Some("b")]),
+    },
+    Case {
+        rule: "マッチに関与しなかったグループは None",
+        expr: "(a)|(b)",
+        text: "b",
+        find_all: &[(0, 1)],
+        captures: Some(&[Some("b"), None, Some("b")]),
+    },
+    Case {
+        rule: "| は左の選択肢を優先",
+        expr: "a|ab",
+        text: "ab",
+        find_all: &[(0, 1)],
+        captures: Some(&[Some("a")]),
+    },
+    Case {
+        rule: "量指定子は繰り返すほうを優先",
+        expr: "(a?)(a?)",
+        text: "a",
+        find_all: &[(0, 1)],
+        captures: Some(&[Some("a"), Some("a"), Some("")]),
+    },
+    Case {
+        rule: "開始位置が最も左のマッチを優先",
+        expr: "bc|abcd",
+        text: "abcd",
+        find_all: &[(0, 4)],
+        captures: Some(&[Some("abcd")]),
+    },
+    Case {
+        rule: "繰り返したグループは最後の繰り返しを持つ",
+        expr: "(a|b)+",
+        text: "ab",
+        find_all: &[(0, 2)],
+        captures: Some(&[Some("ab"), Some("b")]),
+    },
+    Case {
+        rule: "find_all は長さ 0 のマッチを返さない",
+        expr: "a*",
+        text: "baa",
+        find_all: &[(1, 3)],
+        captures: Some(&[Some("")]),
+    },
+    Case {
+        rule: "find_all のマッチは重ならない",
+        expr: "aba",
+        text: "ababa",
+        find_all: &[(0, 3)],
+        captures: Some(&[Some("aba")]),
+    },
+    Case {
+        rule: "位置はバイト単位",
+        expr: "é+",
+        text: "aééb",
+        find_all: &[(1, 5)],
+        captures: Some(&[Some("éé")]),
+    },
+    Case {
+        rule: "マッチしない",
+        expr: "x",
+        text: "abc",
+        find_all: &[],
+        captures: None,
+    },
+];
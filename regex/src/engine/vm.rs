@@ -0,0 +1,475 @@
+use super::{casefold::fold_case, parser::Assertion, Instruction};
+use crate::helper::safe_add;
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt::{self, Display},
+    ops::AddAssign,
+};
+
+pub use super::error::EvalError;
+
+/// トレースモードで記録する、 VM の 1 ステップ分の状態。
+///
+/// スレッドは split 命令で分岐した実行経路のことで、最初のスレッドの ID は 0 となる。
+/// split 命令の 1 つ目の分岐先は同じスレッドで実行を続け、
+/// 2 つ目の分岐先は新たな ID のスレッドで実行する。
+#[derive(Debug)]
+pub struct Step<'a> {
+    pub thread: usize,         // スレッド ID
+    pub pc: usize,             // プログラムカウンタ
+    pub inst: &'a Instruction, // 実行する命令
+    pub sp: usize,             // 入力文字列の位置
+}
+
+impl Display for Step<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "thread {}: {:>04}: {} (sp = {})",
+            self.thread, self.pc, self.inst, self.sp
+        )
+    }
+}
+
+/// マッチングにかかった手間の統計。
+///
+/// バックトラックの回数が入力文字列の長さに対して急激に増える場合、
+/// 正規表現が病的 (指数的な時間がかかるもの) であることを示す。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatchStats {
+    pub steps: usize,      // 実行した命令の数
+    pub spawns: usize,     // split 命令で生成したスレッドの数
+    pub backtracks: usize, // 失敗した分岐から別の分岐に戻った回数
+}
+
+impl AddAssign for MatchStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.steps += rhs.steps;
+        self.spawns += rhs.spawns;
+        self.backtracks += rhs.backtracks;
+    }
+}
+
+/// 評価中のスレッドの情報。
+struct Threads<'a, 'b> {
+    next_id: usize,                      // 次に割り当てるスレッド ID
+    trace: &'a mut dyn FnMut(&Step<'b>), // 各ステップで呼び出すコールバック
+    stats: MatchStats,                   // マッチングにかかった手間
+
+    // メモ化する場合に、実行済みの (プログラムカウンタ, 入力文字列の位置) を記録する集合
+    memo: Option<HashSet<(usize, usize)>>,
+}
+
+impl<'a, 'b> Threads<'a, 'b> {
+    fn new(trace: &'a mut dyn FnMut(&Step<'b>)) -> Self {
+        Self {
+            next_id: 1,
+            trace,
+            memo: None,
+            stats: MatchStats::default(),
+        }
+    }
+
+    /// 新たなスレッド ID を割り当てる。
+    fn spawn(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.stats.spawns += 1;
+        id
+    }
+}
+
+/// 文字を消費する命令が文字 c にマッチするかを判定する関数。
+fn char_matches(inst: &Instruction, c: char) -> bool {
+    match inst {
+        Instruction::Char(i) => *i == c,
+        Instruction::FoldChar(i) => *i == fold_case(c),
+        Instruction::Class(class) => class.contains(c),
+        Instruction::FoldClass(class) => class.contains_fold(c),
+        _ => false,
+    }
+}
+
+/// 単語を構成する文字かを判定する関数。
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// 入力文字列の位置 sp でアサーションが成り立つかを判定する関数。
+///
+/// 単語の境界は、直前の文字と sp の位置の文字のうち、一方のみが単語を構成する文字である位置。
+/// 文字列の先頭の前と末尾の後には、単語を構成しない文字があるものとして扱う。
+/// `$` は文字列の末尾で、 crlf を指定した場合は末尾の \r の直前でも成り立つ。
+pub(super) fn assert_holds(a: Assertion, line: &[char], sp: usize) -> bool {
+    let prev = sp
+        .checked_sub(1)
+        .and_then(|i| line.get(i))
+        .is_some_and(|c| is_word_char(*c));
+    let cur = line.get(sp).is_some_and(|c| is_word_char(*c));
+    match a {
+        Assertion::WordBoundary => prev != cur,
+        Assertion::NotWordBoundary => prev == cur,
+        Assertion::End => sp == line.len(),
+        Assertion::EndCrlf => sp == line.len() || (sp + 1 == line.len() && line[sp] == '\r'),
+    }
+}
+
+/// 深さ優先探索で再帰的にマッチングを行う関数。
+///
+/// マッチした場合はマッチの終端位置を返す。
+///
+/// threads.memo が Some の場合はメモ化を行い、一度実行した (pc, sp) の組からは再び探索しない。
+/// 同じ (pc, sp) から先の探索結果は常に同じなので、結果を変えずに
+/// 探索の回数を命令列と入力文字列の長さの積に抑えられる。
+/// 文字を消費しない繰り返しによる無限ループも起きない。
+fn eval_depth<'b>(
+    inst: &'b [Instruction],
+    line: &[char],
+    mut pc: usize,
+    mut sp: usize,
+    thread: usize,
+    threads: &mut Threads<'_, 'b>,
+) -> Result<Option<usize>, EvalError> {
+    loop {
+        if let Some(memo) = &mut threads.memo {
+            if !memo.insert((pc, sp)) {
+                return Ok(None); // 探索済み
+            }
+        }
+
+        let next = if let Some(i) = inst.get(pc) {
+            i
+        } else {
+            return Err(EvalError::InvalidPC);
+        };
+        threads.stats.steps += 1;
+        (threads.trace)(&Step {
+            thread,
+            pc,
+            inst: next,
+            sp,
+        });
+
+        match next {
+            Instruction::Char(_)
+            | Instruction::FoldChar(_)
+            | Instruction::Class(_)
+            | Instruction::FoldClass(_) => {
+                if let Some(sp_c) = line.get(sp) {
+                    if char_matches(next, *sp_c) {
+                        safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                        safe_add(&mut sp, &1, || EvalError::SPOverFlow)?;
+                    } else {
+                        return Ok(None);
+                    }
+                } else {
+                    return Ok(None);
+                }
+            }
+            Instruction::Assert(a) => {
+                if assert_holds(*a, line, sp) {
+                    safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                } else {
+                    return Ok(None);
+                }
+            }
+            Instruction::Save(_) => {
+                // 位置の記録はキャプチャを求める場合のみ行う
+                safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+            }
+            Instruction::Match => {
+                return Ok(Some(sp));
+            }
+            Instruction::Jump(addr) => {
+                pc = *addr;
+            }
+            Instruction::Split(addr1, addr2) => {
+                let new_thread = threads.spawn();
+                if let Some(end) = eval_depth(inst, line, *addr1, sp, thread, threads)? {
+                    return Ok(Some(end));
+                } else {
+                    threads.stats.backtracks += 1;
+                    return eval_depth(inst, line, *addr2, sp, new_thread, threads);
+                }
+            }
+        }
+    }
+}
+
+fn pop_ctx(
+    pc: &mut usize,
+    sp: &mut usize,
+    thread: &mut usize,
+    ctx: &mut VecDeque<(usize, usize, usize)>,
+    stats: &mut MatchStats,
+) -> Result<(), EvalError> {
+    if let Some((p, s, t)) = ctx.pop_back() {
+        stats.backtracks += 1;
+        *pc = p;
+        *sp = s;
+        *thread = t;
+        Ok(())
+    } else {
+        Err(EvalError::InvalidContext)
+    }
+}
+
+/// 幅優先探索で再帰的にマッチングを行う関数。
+///
+/// マッチした場合はマッチの終端位置を返す。
+fn eval_width<'b>(
+    inst: &'b [Instruction],
+    line: &[char],
+    start: usize,
+    threads: &mut Threads<'_, 'b>,
+) -> Result<Option<usize>, EvalError> {
+    let mut ctx = VecDeque::new();
+    let mut pc = 0;
+    let mut sp = start;
+    let mut thread = 0;
+
+    loop {
+        let next = if let Some(i) = inst.get(pc) {
+            i
+        } else {
+            return Err(EvalError::InvalidPC);
+        };
+        threads.stats.steps += 1;
+        (threads.trace)(&Step {
+            thread,
+            pc,
+            inst: next,
+            sp,
+        });
+
+        match next {
+            Instruction::Char(_)
+            | Instruction::FoldChar(_)
+            | Instruction::Class(_)
+            | Instruction::FoldClass(_) => {
+                if let Some(sp_c) = line.get(sp) {
+                    if char_matches(next, *sp_c) {
+                        safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                        safe_add(&mut sp, &1, || EvalError::SPOverFlow)?;
+                    } else {
+                        if ctx.is_empty() {
+                            return Ok(None);
+                        } else {
+                            pop_ctx(&mut pc, &mut sp, &mut thread, &mut ctx, &mut threads.stats)?;
+                        }
+                    }
+                } else {
+                    if ctx.is_empty() {
+                        return Ok(None);
+                    } else {
+                        pop_ctx(&mut pc, &mut sp, &mut thread, &mut ctx, &mut threads.stats)?;
+                    }
+                }
+            }
+            Instruction::Assert(a) => {
+                if assert_holds(*a, line, sp) {
+                    safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                } else if ctx.is_empty() {
+                    return Ok(None);
+                } else {
+                    pop_ctx(&mut pc, &mut sp, &mut thread, &mut ctx, &mut threads.stats)?;
+                }
+            }
+            Instruction::Save(_) => {
+                // 位置の記録はキャプチャを求める場合のみ行う
+                safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+            }
+            Instruction::Match => {
+                return Ok(Some(sp));
+            }
+            Instruction::Jump(addr) => {
+                pc = *addr;
+            }
+            Instruction::Split(addr1, addr2) => {
+                pc = *addr1;
+                ctx.push_back((*addr2, sp, threads.spawn()));
+                continue;
+            }
+        }
+    }
+}
+
+/// 命令列の評価を行う関数。
+///
+/// inst が命令列となり、その命令列を用いて入力文字列 line がマッチするかどうかを判定する。
+/// is_depth が true の場合に深さ優先探索を、 false の場合に幅優先探索を行う。
+///
+/// 実行時にエラーが発生した場合は Err を返す。
+/// マッチ成功時は Ok(true)、マッチ失敗時は Ok(false) を返す。
+pub fn eval(inst: &[Instruction], line: &[char], is_depth: bool) -> Result<bool, EvalError> {
+    Ok(find(inst, line, is_depth)?.is_some())
+}
+
+/// 命令列の評価を行い、マッチした位置を返す関数。
+///
+/// eval と同様にマッチングを行い、マッチ成功時は line の先頭からマッチの終端までの
+/// 文字数を Ok(Some(end)) として返す。マッチ失敗時は Ok(None) を返す。
+pub fn find(
+    inst: &[Instruction],
+    line: &[char],
+    is_depth: bool,
+) -> Result<Option<usize>, EvalError> {
+    find_at(inst, line, 0, is_depth)
+}
+
+/// line の start 文字目からマッチングを行い、マッチした位置を返す関数。
+///
+/// \b などのアサーションは start より前の文字も参照するため、
+/// line[start..] を find に渡した場合とは結果が異なることがある。
+/// マッチ成功時は line の先頭からマッチの終端までの文字数を Ok(Some(end)) として返す。
+pub fn find_at(
+    inst: &[Instruction],
+    line: &[char],
+    start: usize,
+    is_depth: bool,
+) -> Result<Option<usize>, EvalError> {
+    run(
+        inst,
+        line,
+        start,
+        is_depth,
+        &mut |_| (),
+        &mut MatchStats::default(),
+    )
+}
+
+/// find_at と同様にマッチングを行い、かかった手間を stats に加算する関数。
+///
+/// 複数の開始位置や複数の行にわたって呼び出すことで、統計を集計できる。
+pub fn find_at_stats(
+    inst: &[Instruction],
+    line: &[char],
+    start: usize,
+    is_depth: bool,
+    stats: &mut MatchStats,
+) -> Result<Option<usize>, EvalError> {
+    run(inst, line, start, is_depth, &mut |_| (), stats)
+}
+
+/// メモ化した深さ優先探索で、 line の start 文字目からマッチングを行う関数。
+///
+/// 結果は深さ優先探索の find_at と同じだが、バックトラックが指数的に増えるパターンでも
+/// 命令列と入力文字列の長さの積に比例する時間で終了する。
+pub fn find_memo_at(
+    inst: &[Instruction],
+    line: &[char],
+    start: usize,
+) -> Result<Option<usize>, EvalError> {
+    let mut trace = |_: &Step| ();
+    let mut threads = Threads::new(&mut trace);
+    threads.memo = Some(HashSet::new());
+    eval_depth(inst, line, 0, start, 0, &mut threads)
+}
+
+/// save 命令で記録した位置。記録されなかったスロットは None 。
+pub type Slots = Vec<Option<usize>>;
+
+/// バックトラック時に行う処理。
+enum Backtrack {
+    Branch(usize, usize),          // (pc, sp) から探索を再開
+    Restore(usize, Option<usize>), // スロットの値を元に戻す
+}
+
+/// line の start 文字目からマッチングを行い、マッチの終端位置とキャプチャのスロットを返す関数。
+///
+/// save 命令で記録した位置をスロットに保持しながら、メモ化した深さ優先探索を行う。
+/// 深さ優先探索と同じく Split の 1 つ目の分岐を優先するため、キャプチャは
+/// 最初に見つかったマッチでの位置となる。
+/// スロットは slots 個となる。
+/// 長い入力文字列でもスタックがあふれないよう、再帰ではなく明示的なスタックで探索する。
+pub fn captures_at(
+    inst: &[Instruction],
+    line: &[char],
+    start: usize,
+    slots: usize,
+) -> Result<Option<(usize, Slots)>, EvalError> {
+    let mut saved = vec![None; slots];
+    let mut memo = HashSet::new();
+    let mut stack = vec![Backtrack::Branch(0, start)];
+
+    while let Some(job) = stack.pop() {
+        let (mut pc, mut sp) = match job {
+            Backtrack::Branch(pc, sp) => (pc, sp),
+            Backtrack::Restore(slot, val) => {
+                saved[slot] = val;
+                continue;
+            }
+        };
+
+        // 探索済みの (pc, sp) に到達するか、マッチに失敗するまで進める
+        while memo.insert((pc, sp)) {
+            let next = inst.get(pc).ok_or(EvalError::InvalidPC)?;
+            match next {
+                Instruction::Char(_)
+                | Instruction::FoldChar(_)
+                | Instruction::Class(_)
+                | Instruction::FoldClass(_) => match line.get(sp) {
+                    Some(c) if char_matches(next, *c) => {
+                        safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                        safe_add(&mut sp, &1, || EvalError::SPOverFlow)?;
+                    }
+                    _ => break,
+                },
+                Instruction::Assert(a) => {
+                    if !assert_holds(*a, line, sp) {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                }
+                Instruction::Save(slot) => {
+                    if let Some(val) = saved.get_mut(*slot) {
+                        stack.push(Backtrack::Restore(*slot, val.replace(sp)));
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                }
+                Instruction::Match => return Ok(Some((sp, saved))),
+                Instruction::Jump(addr) => pc = *addr,
+                Instruction::Split(addr1, addr2) => {
+                    stack.push(Backtrack::Branch(*addr2, sp));
+                    pc = *addr1;
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// トレースモードで命令列の評価を行う関数。
+///
+/// find と同様にマッチングを行い、命令を 1 つ実行するごとに、
+/// その時点の VM の状態を引数として trace を呼び出す。
+pub fn find_traced<'b>(
+    inst: &'b [Instruction],
+    line: &[char],
+    is_depth: bool,
+    trace: &mut dyn FnMut(&Step<'b>),
+) -> Result<Option<usize>, EvalError> {
+    run(inst, line, 0, is_depth, trace, &mut MatchStats::default())
+}
+
+/// line の start 文字目から、深さ優先探索か幅優先探索で評価を行う。
+///
+/// 評価にかかった手間は、結果にかかわらず stats に加算する。
+fn run<'b>(
+    inst: &'b [Instruction],
+    line: &[char],
+    start: usize,
+    is_depth: bool,
+    trace: &mut dyn FnMut(&Step<'b>),
+    stats: &mut MatchStats,
+) -> Result<Option<usize>, EvalError> {
+    let mut threads = Threads::new(trace);
+    let result = if is_depth {
+        eval_depth(inst, line, 0, start, 0, &mut threads)
+    } else {
+        eval_width(inst, line, start, &mut threads)
+    };
+    *stats += threads.stats;
+    result
+}
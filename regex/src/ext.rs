@@ -0,0 +1,30 @@
+//! &str に正規表現のマッチングを追加する拡張トレイト。
+//!
+//! 小さなスクリプトや演習で、 [Regex](crate::Regex) や [MatchStrategy](crate::MatchStrategy) を
+//! 用意せずに手軽にマッチングするためのもの。
+//!
+//! `ext` フィーチャを有効にした場合のみコンパイルする。
+use crate::helper::DynError;
+
+/// 文字列に正規表現のマッチングを追加する。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::StrRegexExt;
+/// # fn main() -> Result<(), regex::helper::DynError> {
+/// assert!("abcbc".regex_match("a(bc)+")?);
+/// assert!(!"ac".regex_match("a(bc)+")?);
+/// # Ok(())
+/// # }
+/// ```
+pub trait StrRegexExt {
+    /// [matched](crate::matched) と同様に、文字列の先頭から expr にマッチする場合に Ok(true) を返す。
+    fn regex_match(&self, expr: &str) -> Result<bool, DynError>;
+}
+
+impl StrRegexExt for str {
+    fn regex_match(&self, expr: &str) -> Result<bool, DynError> {
+        crate::matched(expr, self)
+    }
+}
@@ -6,10 +6,28 @@
 //! use regex;
 //! let expr = "a(bc)+|c(def)*"; // 正規表現と文字列をマッチング
 //! let line = "cdefdefdef"; // マッチング対象の文字列
-//! regex::do_matching(expr, line, true); // 深さ優先探索でマッチング
+//! regex::do_matching(expr, line, regex::MatchStrategy::Dfs); // 深さ優先探索でマッチング
 //! regex::print(expr); // 正規表現の AST と命令列を表示
 //! ```
 pub mod engine;
+#[cfg(feature = "ext")]
+pub mod ext;
 pub mod helper;
 
-pub use engine::{do_matching, print};
+pub use engine::{
+    do_matching, do_matching_extended, do_matching_traced, find, find_all, match_reader,
+    match_reader_with, matched, print, self_check, to_dot, Captures, Instruction, MatchLines,
+    MatchOptions, MatchStats, MatchStrategy, Regex, Span,
+};
+
+pub use engine::compile::compile_with_spans;
+pub use engine::error::{CodeGenError, EvalError, ParseError};
+
+#[allow(deprecated)]
+pub use engine::do_matching_bool;
+
+#[cfg(feature = "oracle")]
+pub use engine::oracle_check;
+
+#[cfg(feature = "ext")]
+pub use ext::StrRegexExt;
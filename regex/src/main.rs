@@ -1,63 +1,258 @@
-mod engine;
-mod helper;
-
-use helper::DynError;
+use regex::{helper::DynError, MatchOptions, Regex};
 use std::{
     env,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{self, BufReader, IsTerminal},
+    num::NonZeroUsize,
+    thread,
 };
 
-/// ファイルをオープンし、行ごとにマッチングを行う。
-///
-/// マッチングはそれぞれの行頭から 1 文字ずつずらして行い、
-/// いずれかにマッチした場合に、その行がマッチしたものとみなす。
+/// マッチ部分の強調表示に用いるエスケープシーケンス (太字の赤)
+const COLOR_MATCH: &str = "\x1b[1;31m";
+/// 強調表示を元に戻すエスケープシーケンス
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// コマンドラインで指定されたオプション。
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Config {
+    ignore_case: bool,  // -i: 大文字小文字を区別しない
+    invert: bool,       // -v: マッチしなかった行を表示
+    line_number: bool,  // -n: 行番号を表示
+    count: bool,        // -c: マッチした行数のみを表示
+    stats: bool,        // --stats: ファイルごとにマッチングの統計を表示
+    crlf: bool,         // --crlf: 行末の \r\n を改行として扱う
+    null_data: bool,    // -z: 入力と出力の行を NUL で区切る
+    color: bool,        // マッチ部分を色付きで表示 (標準出力が端末の場合)
+    expr: String,       // 正規表現
+    files: Vec<String>, // 対象ファイル
+}
+
+impl Config {
+    fn match_options(&self) -> MatchOptions {
+        MatchOptions {
+            ignore_case: self.ignore_case,
+            invert: self.invert,
+            crlf: self.crlf,
+            null_data: self.null_data,
+        }
+    }
+
+    /// マッチした行の終端。 -z の場合は NUL
+    fn line_end(&self) -> char {
+        if self.null_data {
+            '\0'
+        } else {
+            '\n'
+        }
+    }
+}
+
+/// コマンドライン引数をパース。 args にはプログラム名を含めない。
 ///
-/// 例えば、 abcd という文字列があった場合、以下の順にマッチが行われ、
-/// このいずれかにマッチした場合、与えられた正規表現にマッチする行と判定する。
+/// オプションは正規表現より前に指定し、 -in のようにまとめて指定することもできる。
+/// `--` 以降はオプションとして扱わない。
+fn parse_args(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
+    let mut rest = args.iter();
+
+    for arg in rest.by_ref() {
+        match arg.as_str() {
+            "--" => break,
+            "--stats" => {
+                config.stats = true;
+                continue;
+            }
+            "--crlf" => {
+                config.crlf = true;
+                continue;
+            }
+            _ => (),
+        }
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for f in flags.chars() {
+                    match f {
+                        'i' => config.ignore_case = true,
+                        'v' => config.invert = true,
+                        'n' => config.line_number = true,
+                        'c' => config.count = true,
+                        'z' => config.null_data = true,
+                        _ => return Err(format!("unknown option: -{f}")),
+                    }
+                }
+            }
+            _ => {
+                config.expr = arg.clone();
+                break;
+            }
+        }
+    }
+
+    if config.expr.is_empty() {
+        match rest.next() {
+            Some(expr) => config.expr = expr.clone(),
+            None => return Err("no regex specified".to_string()),
+        }
+    }
+
+    config.files = rest.cloned().collect();
+    if config.files.is_empty() {
+        return Err("no file specified".to_string());
+    }
+
+    Ok(config)
+}
+
+/// 行中の re のマッチ部分を色付きにした文字列を返す。
+fn highlight(re: &Regex, line: &str) -> Result<String, DynError> {
+    let mut result = String::new();
+    let mut last = 0;
+    for range in re.find_all(line)? {
+        result.push_str(&line[last..range.start]);
+        result.push_str(COLOR_MATCH);
+        result.push_str(&line[range.clone()]);
+        result.push_str(COLOR_RESET);
+        last = range.end;
+    }
+    result.push_str(&line[last..]);
+    Ok(result)
+}
+
+/// ファイルをオープンし、行ごとにマッチングを行う。
 ///
-/// - abcd
-/// - bcd
-/// - cd
-/// - d
-fn match_file(expr: &str, file: &str) -> Result<(), DynError> {
+/// マッチした行を `ファイル名:行` 、 -n 指定時は `ファイル名:行番号:行` の形式の文字列にして返す。
+/// -c 指定時は `ファイル名:マッチした行数` のみを返す。
+/// --stats 指定時は、最後にファイル全体のマッチングの統計を
+/// `ファイル名: steps=実行した命令数 spawns=スレッド数 backtracks=バックトラック数` の形式で加える。
+/// 各文字列は終端を含む。 -z 指定時はマッチした行を NUL で、行数と統計は改行で終える。
+/// 標準出力が端末の場合は、マッチ部分を色付きにする。
+/// 行のマッチング方法は [regex::match_reader] を参照。
+fn match_file(config: &Config, file: &str) -> Result<Vec<String>, DynError> {
     let f = File::open(file)?;
     let reader = BufReader::new(f);
 
-    for (i, line) in reader.lines().enumerate() {
-        let line = line?;
-        let i = i + 1;
-        for (j, _) in line.char_indices() {
-            if engine::do_matching(expr, &line[j..], true)? {
-                println!("{file}:{i}:{line}");
-                break;
+    let mut lines = regex::match_reader_with(&config.expr, reader, config.match_options())?;
+    if config.stats {
+        lines = lines.with_stats();
+    }
+
+    // 反転時はマッチ部分がないので強調表示しない
+    // 強調表示に用いるコードは、行ごとではなくファイルごとに 1 回だけ生成する
+    let color = if config.color && !config.invert {
+        Some(Regex::with_options(&config.expr, &config.match_options())?)
+    } else {
+        None
+    };
+
+    let mut result = Vec::new();
+    let mut count = 0;
+    for m in lines.by_ref() {
+        let (i, mut line) = m?;
+        count += 1;
+        if config.count {
+            continue;
+        }
+
+        if let Some(re) = &color {
+            line = highlight(re, &line)?;
+        }
+
+        let end = config.line_end();
+        if config.line_number {
+            result.push(format!("{file}:{i}:{line}{end}"));
+        } else {
+            result.push(format!("{file}:{line}{end}"));
+        }
+    }
+
+    if config.count {
+        result.push(format!("{file}:{count}\n"));
+    }
+
+    if let Some(stats) = lines.stats() {
+        result.push(format!(
+            "{file}: steps={} spawns={} backtracks={}\n",
+            stats.steps, stats.spawns, stats.backtracks
+        ));
+    }
+
+    Ok(result)
+}
+
+/// 複数のファイルを並列にマッチングし、結果をファイルの指定順に表示。
+///
+/// 同時に起動するワーカースレッド数は CPU の並列度までとする。
+/// 読み込みに失敗したファイルがあっても残りのファイルの処理は続け、
+/// 最後にエラーを返す。
+fn match_files(config: &Config) -> Result<(), DynError> {
+    let workers = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let mut failed = false;
+
+    for chunk in config.files.chunks(workers) {
+        let results = thread::scope(|s| {
+            let handles = chunk
+                .iter()
+                .map(|file| s.spawn(move || match_file(config, file)))
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("worker thread panicked"))
+                .collect::<Vec<_>>()
+        });
+
+        for (file, result) in chunk.iter().zip(results) {
+            match result {
+                Ok(lines) => lines.iter().for_each(|line| print!("{line}")),
+                Err(e) => {
+                    eprintln!("{file}: {e}");
+                    failed = true;
+                }
             }
         }
     }
 
-    Ok(())
+    if failed {
+        Err("failed to match some files".into())
+    } else {
+        Ok(())
+    }
 }
 
 fn main() -> Result<(), DynError> {
     let args: Vec<String> = env::args().collect();
-    if args.len() <= 2 {
-        println!("Usage: {} <regex> <file>", args[0]);
-        return Err("invalid arguments".into());
-    } else {
-        engine::print(&args[1])?;
-        println!();
+    let mut config = match parse_args(&args[1..]) {
+        Ok(config) => config,
+        Err(e) => {
+            println!(
+                "Usage: {} [-i] [-v] [-n] [-c] [-z] [--stats] [--crlf] <regex> <file>...",
+                args[0]
+            );
+            return Err(e.into());
+        }
+    };
 
-        match_file(&args[1], &args[2])?;
-    }
+    config.color = io::stdout().is_terminal();
+
+    regex::print(&config.expr)?;
+    println!();
+
+    match_files(&config)?;
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        engine::do_matching,
+    use crate::{highlight, match_file, parse_args, Config};
+    use regex::{
+        compile_with_spans, do_matching, do_matching_extended, do_matching_traced,
+        engine::dfa::DfaError,
+        find_all,
         helper::{safe_add, SafeAdd},
+        match_reader, matched, MatchOptions, MatchStats,
+        MatchStrategy::{self, *},
+        ParseError, Regex, Span,
     };
 
     #[test]
@@ -78,20 +273,726 @@ mod tests {
     #[test]
     fn test_matching() {
         // パースエラー
-        assert!(do_matching("+b", "bbb", true).is_err());
-        assert!(do_matching("*b", "bbb", true).is_err());
-        assert!(do_matching("|b", "bbb", true).is_err());
-        assert!(do_matching("?b", "bbb", true).is_err());
+        assert!(do_matching("+b", "bbb", Dfs).is_err());
+        assert!(do_matching("*b", "bbb", Dfs).is_err());
+        assert!(do_matching("|b", "bbb", Dfs).is_err());
+        assert!(do_matching("?b", "bbb", Dfs).is_err());
+        assert!(do_matching("(?a)", "a", Dfs).is_err());
+        assert!(do_matching("(?:a", "a", Dfs).is_err());
 
         // パース成功、マッチ成功
-        assert!(do_matching("abc|def", "def", true).unwrap());
-        assert!(do_matching("(abc)*", "abcabc", true).unwrap());
-        assert!(do_matching("(ab|cd)+", "abcdcd", true).unwrap());
-        assert!(do_matching("abc?", "ab", true).unwrap());
+        assert!(do_matching("abc|def", "def", Dfs).unwrap());
+        assert!(do_matching("(abc)*", "abcabc", Dfs).unwrap());
+        assert!(do_matching("(ab|cd)+", "abcdcd", Dfs).unwrap());
+        assert!(do_matching("abc?", "ab", Dfs).unwrap());
+        assert!(do_matching("(?:ab|cd)+", "abcdcd", Dfs).unwrap());
+        assert!(do_matching("a(?:b(?:c|d))*", "abcbd", Bfs).unwrap());
 
         // パース成功、マッチ失敗
-        assert!(!do_matching("abc|def", "efa", true).unwrap());
-        assert!(!do_matching("(ab|cd)+", "", true).unwrap());
-        assert!(!do_matching("abc?", "acb", true).unwrap());
+        assert!(!do_matching("abc|def", "efa", Dfs).unwrap());
+        assert!(!do_matching("(ab|cd)+", "", Dfs).unwrap());
+        assert!(!do_matching("abc?", "acb", Dfs).unwrap());
+        assert!(!do_matching("(?:ab)+", "", Dfs).unwrap());
+    }
+
+    #[test]
+    fn test_and_not() {
+        // パースエラー
+        assert!(do_matching_extended("a&", "a", Dfs).is_err());
+        assert!(do_matching_extended("&a", "a", Dfs).is_err());
+        assert!(do_matching_extended("a&|b", "a", Dfs).is_err());
+        assert!(do_matching_extended("~", "a", Dfs).is_err());
+        assert!(do_matching_extended("a~|b", "a", Dfs).is_err());
+        assert!(do_matching_extended("~*a", "a", Dfs).is_err());
+        assert!(do_matching_extended("(a~)", "a", Dfs).is_err());
+
+        // 交差
+        assert!(do_matching_extended("a+&aa", "aaa", Dfs).unwrap());
+        assert!(!do_matching_extended("a+&b", "ab", Dfs).unwrap());
+        assert!(do_matching_extended("(a|b)*&(b|c)*", "b", Bfs).unwrap());
+
+        // 補集合。空文字列にマッチするため、先頭からのマッチは常に成功する
+        assert!(do_matching_extended("~a", "a", Dfs).unwrap());
+        assert!(do_matching_extended("~(a*)b", "cb", Dfs).unwrap());
+        assert!(!do_matching_extended("~(a*)b", "aab", Dfs).unwrap());
+
+        // a を含み、 b を含まない
+        let expr = "(a|b|c)*a(a|b|c)*&~((a|b|c)*b(a|b|c)*)";
+        assert!(do_matching_extended(expr, "cca", Dfs).unwrap());
+        assert!(!do_matching_extended(expr, "cbca", Dfs).unwrap());
+        assert!(!do_matching_extended(expr, "ccc", Dfs).unwrap());
+
+        // aa を含まない a と b の並び。 $ により行全体でマッチさせる
+        let no_aa = "((a|b)*&~((a|b)*aa(a|b)*))$";
+        for s in [Hybrid, Derivatives] {
+            assert!(do_matching_extended(no_aa, "abab", s).unwrap(), "{s:?}");
+            assert!(!do_matching_extended(no_aa, "aab", s).unwrap(), "{s:?}");
+            assert!(!do_matching_extended(no_aa, "abaa", s).unwrap(), "{s:?}");
+        }
+
+        // 優先順位: 連接は & より、 & は | より強い
+        assert!(do_matching_extended("ab&a|c", "c", Dfs).unwrap());
+        assert!(!do_matching_extended("ab&a|c", "ab", Dfs).unwrap());
+
+        // エスケープした場合は通常の文字
+        assert!(do_matching_extended("a\\&b\\~", "a&b~", Dfs).unwrap());
+
+        // do_matching などでは通常の文字
+        for s in [Dfs, Bfs, Memo, Dfa, Hybrid, Derivatives] {
+            assert!(do_matching("a&b", "a&b", s).unwrap(), "{s:?}");
+            assert!(!do_matching("a&b", "a", s).unwrap(), "{s:?}");
+            assert!(do_matching("~a", "~a", s).unwrap(), "{s:?}");
+            assert!(!do_matching("~a", "b", s).unwrap(), "{s:?}");
+        }
+        assert!(matched("R&D", "R&D").unwrap());
+        let re = Regex::new("R&D|~").unwrap();
+        assert!(re.is_match("xR&Dx").unwrap());
+        assert!(!re.is_match("RD").unwrap());
+        assert_eq!(re.find_all("a~R&D").unwrap(), vec![1..2, 2..5]);
+        let mut lines = match_reader("a&b", "a&b\nab\n".as_bytes()).unwrap();
+        assert_eq!(lines.next().unwrap().unwrap(), (1, "a&b".to_string()));
+        assert!(lines.next().is_none());
+
+        // DFA の状態数が上限を超える場合はエラー
+        let expr = format!("(a|b)*a{}&(a|b)*", "(a|b)".repeat(18));
+        for s in [Dfs, Hybrid] {
+            let e = do_matching_extended(&expr, "a", s).unwrap_err();
+            assert!(
+                matches!(e.downcast_ref(), Some(DfaError::TooManyStates)),
+                "{s:?}"
+            );
+        }
+
+        // 交差や補集合を含まなければ、 Hybrid はメモ化した深さ優先探索でマッチングする
+        let expr = format!("(a|b)*a{}", "(a|b)".repeat(18));
+        let line = format!("a{}", "b".repeat(18));
+        assert!(do_matching_extended(&expr, &line, Hybrid).unwrap());
+
+        // DFA で扱えないアサーションを含む場合、 Hybrid は微分でマッチングする
+        let expr = "(\\b(ab)+&~(abab))$";
+        assert!(do_matching_extended(expr, "ababab", Hybrid).unwrap());
+        assert!(!do_matching_extended(expr, "abab", Hybrid).unwrap());
+    }
+
+    #[test]
+    fn test_word_boundary() {
+        let re = Regex::new("\\bcat\\b").unwrap();
+        assert!(re.is_match("cat").unwrap());
+        assert!(re.is_match("a cat.").unwrap());
+        assert!(!re.is_match("concat").unwrap());
+        assert!(!re.is_match("cats").unwrap());
+
+        let re = Regex::new("\\Bcat").unwrap();
+        assert!(re.is_match("concat").unwrap());
+        assert!(!re.is_match("a cat").unwrap());
+
+        // 開始位置より前の文字も参照する
+        assert_eq!(
+            find_all("\\bab", "ab xab ab", MatchOptions::default()).unwrap(),
+            vec![0..2, 7..9]
+        );
+
+        // 深さ優先探索と幅優先探索で同じ結果
+        for s in [Dfs, Bfs, Memo, Hybrid, Derivatives] {
+            assert!(do_matching("a\\b", "a b", s).unwrap());
+            assert!(!do_matching("a\\b", "ab", s).unwrap());
+            assert!(do_matching("a\\Bb", "ab", s).unwrap());
+        }
+
+        // DFA ではアサーションを扱えない
+        assert!(do_matching_extended("\\ba&a", "a", Dfs).is_err());
+    }
+
+    #[test]
+    fn test_char_class() {
+        // パースエラー
+        assert!(do_matching("[abc", "a", Dfs).is_err());
+        assert!(do_matching("[z-a]", "a", Dfs).is_err());
+        assert!(do_matching("[[:foo:]]", "a", Dfs).is_err());
+        assert!(do_matching("[[:alpha:]", "a", Dfs).is_err());
+
+        assert!(do_matching("[abc]+d", "cabd", Dfs).unwrap());
+        assert!(do_matching("[a-c]x", "bx", Bfs).unwrap());
+        assert!(!do_matching("[^a-c]x", "bx", Dfs).unwrap());
+        assert!(do_matching("[]a]", "]", Dfs).unwrap());
+        assert!(do_matching("[a-]", "-", Dfs).unwrap());
+        assert!(do_matching("\\[a]", "[a]", Dfs).unwrap());
+
+        // POSIX の文字クラス
+        let re = Regex::new("[[:alpha:]_][[:alnum:]_]*=[[:digit:]]+").unwrap();
+        assert!(re.is_match("export FOO_1=42").unwrap());
+        assert!(re.is_match("名前=1").unwrap());
+        assert!(!re.is_match("1=1").unwrap());
+        assert!(Regex::new("[[:space:]][[:punct:]]")
+            .unwrap()
+            .is_match("a ;")
+            .unwrap());
+        assert!(!Regex::new("[^[:xdigit:]]")
+            .unwrap()
+            .is_match("c0ffee")
+            .unwrap());
+
+        // 大文字小文字を区別しない
+        let opts = MatchOptions {
+            ignore_case: true,
+            ..Default::default()
+        };
+        assert_eq!(find_all("[a-c]+", "xABcx", opts).unwrap(), vec![1..4]);
+        assert_eq!(find_all("[[:upper:]]", "a1", opts).unwrap(), vec![0..1]);
+    }
+
+    #[test]
+    fn test_match_strategy() {
+        let all = [Dfs, Bfs, Memo, Dfa, Hybrid, Derivatives];
+        let cases = [
+            ("abc|(de|cd)+", "decddede", true),
+            ("(ab|cd)+", "", false),
+            ("a?a?a?aaa", "aaa", true),
+            ("a?a?a?aaa", "aa", false),
+            ("(a|b)*c", "ababc", true),
+        ];
+        for (expr, line, expected) in cases {
+            for s in all {
+                assert_eq!(
+                    do_matching(expr, line, s).unwrap(),
+                    expected,
+                    "{expr} {s:?}"
+                );
+            }
+        }
+
+        // メモ化した探索は文字を消費しない繰り返しでも停止する
+        assert!(do_matching("(a*)*b", "aab", Memo).unwrap());
+        assert!(!do_matching("(a*)*b", "aac", Memo).unwrap());
+
+        // アサーションや文字クラスは DFA で扱えない
+        assert!(do_matching("\\ba", "a", Dfa).is_err());
+        assert!(do_matching("[ab]", "a", Dfa).is_err());
+        assert!(do_matching("[ab]", "a", Memo).unwrap());
+
+        // Hybrid は DFA を構成できない場合にメモ化した探索を用いる
+        assert!(do_matching("\\ba", "a", Hybrid).unwrap());
+        assert!(do_matching("[ab]", "a", Hybrid).unwrap());
+
+        // 微分はアサーション、文字クラス、交差、補集合のいずれも扱える
+        assert!(do_matching("a\\b", "a b", Derivatives).unwrap());
+        assert!(!do_matching("a\\b", "ab", Derivatives).unwrap());
+        assert!(do_matching_extended("\\ba&a", "a", Derivatives).unwrap());
+        assert!(do_matching("[^a]b", "cb", Derivatives).unwrap());
+        assert!(!do_matching("[^a]b", "ab", Derivatives).unwrap());
+        for s in [Dfa, Derivatives] {
+            assert!(do_matching_extended("~(a*)b", "bb", s).unwrap());
+            assert!(!do_matching_extended("~(a*)b", "ab", s).unwrap());
+            assert!(do_matching_extended("((a|b)*&~((a|b)*bb(a|b)*))c", "abac", s).unwrap());
+            assert!(!do_matching_extended("((a|b)*&~((a|b)*bb(a|b)*))c", "abbc", s).unwrap());
+        }
+        assert!(do_matching("(a*)*b", "aab", Derivatives).unwrap());
+
+        // 従来の bool による指定
+        #[allow(deprecated)]
+        {
+            assert!(regex::do_matching_bool("a?a?aa", "aa", true).unwrap());
+            assert!(regex::do_matching_bool("a?a?aa", "aa", false).unwrap());
+        }
+        assert_eq!(MatchStrategy::from(true), Dfs);
+        assert_eq!(MatchStrategy::from(false), Bfs);
+    }
+
+    #[test]
+    fn test_captures() {
+        let re = Regex::new("(a|ab)(c|bcd)(d*)").unwrap();
+        let caps = re.captures("xabcd").unwrap().unwrap();
+        assert_eq!(caps.len(), 4);
+        assert_eq!(
+            caps.iter().collect::<Vec<_>>(),
+            vec![Some("abcd"), Some("a"), Some("bcd"), Some("")]
+        );
+
+        // 位置はバイト単位
+        let re = Regex::new("(あ+)(い)?う").unwrap();
+        let caps = re.captures("んああう").unwrap().unwrap();
+        assert_eq!(&caps[1], "ああ");
+        assert_eq!(caps.span(1), Some(3..9));
+        assert_eq!(caps.get(2), None);
+        assert_eq!(caps.get(3), None);
+
+        // 繰り返したグループは最後にマッチした部分
+        let re = Regex::new("((a|b)c)+").unwrap();
+        let caps = re.captures("acbc").unwrap().unwrap();
+        assert_eq!(&caps[1], "bc");
+        assert_eq!(&caps[2], "b");
+
+        // 非キャプチャグループは番号を持たない
+        let re = Regex::new("(?:a)(b)").unwrap();
+        assert_eq!(re.captures_len(), 2);
+        assert_eq!(&re.captures("ab").unwrap().unwrap()[1], "b");
+
+        // 長さ 0 のマッチも返す
+        let re = Regex::new("(a*)").unwrap();
+        assert_eq!(re.captures("").unwrap().unwrap().get(1), Some(""));
+        assert!(Regex::new("x(y)")
+            .unwrap()
+            .captures("xz")
+            .unwrap()
+            .is_none());
+
+        // キャプチャは他の方式のマッチング結果に影響しない
+        for s in [Dfs, Bfs, Memo, Dfa, Hybrid, Derivatives] {
+            assert!(do_matching("(a(b)?)+c", "ababac", s).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // 深くネストしてもスタックはあふれず、エラーとなる
+        let n = 100_000;
+        let nested = format!("{}a{}", "(".repeat(n), ")".repeat(n));
+        assert!(do_matching(&nested, "a", Dfs).is_err());
+        let unclosed = "(".repeat(n);
+        assert!(do_matching(&unclosed, "a", Dfs).is_err());
+        let stars = format!("a{}", "*".repeat(n));
+        assert!(do_matching(&stars, "a", Dfs).is_err());
+        let ors = vec!["a"; n].join("|");
+        assert!(do_matching(&ors, "a", Dfs).is_err());
+
+        // 上限より浅い場合はマッチングできる
+        let n = 100;
+        let nested = format!("{}a{}", "(".repeat(n), ")".repeat(n));
+        assert!(do_matching(&nested, "a", Dfs).unwrap());
+        let ors = vec!["a"; n].join("|");
+        assert!(do_matching(&ors, "a", Bfs).unwrap());
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        use regex::engine::parser::parse_partial;
+        let prefix = |expr| parse_partial(expr).unwrap_err().prefix;
+
+        // 入力途中のパターン
+        assert_eq!(prefix("abc|(de"), "abc|");
+        assert_eq!(prefix("a[bc"), "a");
+        assert_eq!(prefix("ab*c|*"), "ab*c|");
+        assert_eq!(prefix("ab[[:foo:]]"), "ab");
+        assert_eq!(prefix("a(?x)b"), "a");
+        // 閉じ括弧が多い場合は直前までが有効
+        assert_eq!(prefix("(ab))c"), "(ab)");
+        // 有効な先頭部分がない場合
+        let e = parse_partial("*a").unwrap_err();
+        assert_eq!(e.prefix, "");
+        assert!(e.ast.is_none());
+        assert!(parse_partial("").is_err());
+
+        // 先頭部分の AST でマッチングできる
+        let e = parse_partial("ab+(c").unwrap_err();
+        let code = regex::engine::codegen::get_code(&e.ast.unwrap()).unwrap();
+        assert!(regex::engine::vm::find(&code, &['a', 'b', 'b'], true)
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_spec() {
+        use regex::{engine::spec::CASES, find_all, MatchOptions, Regex};
+
+        for case in CASES {
+            let msg = format!("{}: {} / {}", case.rule, case.expr, case.text);
+            let all = find_all(case.expr, case.text, MatchOptions::default()).unwrap();
+            let expected: Vec<_> = case.find_all.iter().map(|&(s, e)| s..e).collect();
+            assert_eq!(all, expected, "{msg}");
+
+            let re = Regex::new(case.expr).unwrap();
+            let caps = re.captures(case.text).unwrap().map(|caps| {
+                (0..re.captures_len())
+                    .map(|i| caps.get(i))
+                    .collect::<Vec<_>>()
+            });
+            assert_eq!(caps.as_deref(), case.captures, "{msg}");
+        }
+    }
+
+    #[test]
+    fn test_self_check() {
+        // 深さ優先探索と幅優先探索の結果が一致する
+        for seed in 0..10 {
+            regex::self_check(seed, 1000).unwrap();
+        }
+    }
+
+    #[cfg(feature = "oracle")]
+    #[test]
+    fn test_oracle_check() {
+        // 各実装の結果が参照実装と一致する
+        for seed in 0..10 {
+            regex::oracle_check(seed, 1000).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_matching_traced() {
+        let trace = |is_depth| {
+            let mut steps = Vec::new();
+            let m = do_matching_traced("a(b|c)*d", "abcd", is_depth, |s| {
+                steps.push((s.thread, s.pc, s.sp))
+            })
+            .unwrap();
+            (m, steps)
+        };
+
+        let (m, steps) = trace(true);
+        assert!(m);
+        assert_eq!(steps.first(), Some(&(0, 0, 0)));
+        assert_eq!(steps.last().map(|s| s.2), Some(4));
+        // 失敗した分岐は別のスレッドで実行される
+        assert!(steps.iter().any(|(t, _, _)| *t > 0));
+
+        // 幅優先探索でも同じ順序で命令を実行する
+        assert_eq!(trace(false), (m, steps));
+
+        // パースエラーの場合は Err
+        assert!(do_matching_traced("*", "", true, |_| ()).is_err());
+    }
+
+    #[test]
+    fn test_match_reader() {
+        let input = "abc\nxyz\nxabcx\n\ncdef".as_bytes();
+        let lines = match_reader("abc|c(def)*", input)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                (1, "abc".to_string()),
+                (3, "xabcx".to_string()),
+                (5, "cdef".to_string()),
+            ]
+        );
+
+        // パースエラーはイテレータ生成時に返る
+        assert!(match_reader("*b", "bbb".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            parse_args(&args(&["-in", "-c", "ab", "a.txt", "b.txt"])),
+            Ok(Config {
+                ignore_case: true,
+                invert: false,
+                line_number: true,
+                count: true,
+                stats: false,
+                crlf: false,
+                null_data: false,
+                color: false,
+                expr: "ab".to_string(),
+                files: vec!["a.txt".to_string(), "b.txt".to_string()],
+            })
+        );
+        assert_eq!(
+            parse_args(&args(&["-v", "--", "-a", "a.txt"])),
+            Ok(Config {
+                invert: true,
+                expr: "-a".to_string(),
+                files: vec!["a.txt".to_string()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_args(&args(&["--stats", "-c", "ab", "a.txt"])),
+            Ok(Config {
+                count: true,
+                stats: true,
+                expr: "ab".to_string(),
+                files: vec!["a.txt".to_string()],
+                ..Default::default()
+            })
+        );
+        assert!(parse_args(&args(&["--stat", "ab", "a.txt"])).is_err());
+        assert!(parse_args(&args(&["-x", "ab", "a.txt"])).is_err());
+        assert!(parse_args(&args(&["-i", "ab"])).is_err());
+        assert!(parse_args(&args(&["-i"])).is_err());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let re = Regex::new("b+").unwrap();
+        assert_eq!(
+            highlight(&re, "abbcbd").unwrap(),
+            "a\x1b[1;31mbb\x1b[0mc\x1b[1;31mb\x1b[0md"
+        );
+        assert_eq!(highlight(&re, "xyz").unwrap(), "xyz");
+
+        let re = Regex::new("あい").unwrap();
+        assert_eq!(
+            highlight(&re, "うあいう").unwrap(),
+            "う\x1b[1;31mあい\x1b[0mう"
+        );
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let opts = MatchOptions {
+            ignore_case: true,
+            ..Default::default()
+        };
+        let find = |expr, line| find_all(expr, line, opts).unwrap();
+
+        // ギリシャ文字: 語末のシグマ ς も Σ, σ と同一視する
+        assert_eq!(find("ΟΔΥΣΣΕΥΣ", "οδυσσευς"), vec![0..16]);
+        assert_eq!(find("σ+", "ΣσςΣ"), vec![0..8]);
+        assert_eq!(find("θ", "ϑΘθ"), vec![0..2, 2..4, 4..6]);
+        assert_eq!(find("μ", "µ"), vec![0..2]);
+
+        // 文字クラスも文字と同じく正規化して比較する
+        assert_eq!(find("[σ]", "ς"), vec![0..2]);
+        assert_eq!(find("[ς]+", "Σσ"), vec![0..4]);
+        assert_eq!(find("[s]", "ſ"), vec![0..2]);
+        assert_eq!(find("[a-z]", "ſ\u{212A}"), vec![0..2, 2..5]); // ケルビン記号 K
+        assert_eq!(find("[^s]", "ſSx"), vec![3..4]);
+        assert_eq!(find("[α-ω]+", "ΑΩς"), vec![0..6]);
+
+        // キリル文字
+        assert_eq!(find("москва", "МОСКВА"), vec![0..12]);
+        assert_eq!(find("(Ж|Щ)+", "xжщЖy"), vec![1..7]);
+        assert_eq!(find("в", "ᲀ"), vec![0..3]);
+
+        // 結果のバイト位置は元の文字列のものを返す
+        let line = "İstanbul KAPPA ϰ";
+        let m = find("kappa", line);
+        assert_eq!(&line[m[0].clone()], "KAPPA");
+        assert_eq!(find("κ", line).len(), 1);
+
+        // 大文字小文字を区別する場合はマッチしない
+        assert!(find_all("σ", "Σς", MatchOptions::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_save_program() {
+        use regex::Regex;
+
+        let path = std::env::temp_dir().join(format!("regex-{}.json", std::process::id()));
+        let re = Regex::new("a(bc|d)*").unwrap();
+        re.save_program(&path).unwrap();
+
+        let loaded = Regex::load_program(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, re);
+        assert!(loaded.is_match("xabcd").unwrap());
+        assert!(!loaded.is_match("bcd").unwrap());
+
+        assert!(Regex::load_program("no/such/file").is_err());
+    }
+
+    #[test]
+    fn test_match_file() {
+        // 内容が既知のファイル
+        let path = std::env::temp_dir().join(format!("regex-match-{}.txt", std::process::id()));
+        std::fs::write(&path, "use std::io;\npub mod engine;\nfn main() {}\n").unwrap();
+        let file = path.to_str().unwrap();
+        let config = |opts: &[&str]| {
+            let mut args = opts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            args.push("PUB MOD ENGINE".to_string());
+            args.push(file.to_string());
+            parse_args(&args).unwrap()
+        };
+
+        // 大文字小文字を区別する場合はマッチしない
+        assert_eq!(match_file(&config(&[]), file).unwrap().len(), 0);
+
+        let lines = match_file(&config(&["-i"]), file).unwrap();
+        assert_eq!(lines, vec![format!("{file}:pub mod engine;\n")]);
+
+        let lines = match_file(&config(&["-in"]), file).unwrap();
+        assert_eq!(lines, vec![format!("{file}:2:pub mod engine;\n")]);
+
+        let lines = match_file(&config(&["-ic"]), file).unwrap();
+        assert_eq!(lines, vec![format!("{file}:1\n")]);
+
+        // 反転すると、マッチした 1 行以外の行数になる
+        let lines = match_file(&config(&["-icv"]), file).unwrap();
+        assert_eq!(lines, vec![format!("{file}:2\n")]);
+
+        // --stats 指定時は最後に統計を加える
+        let lines = match_file(&config(&["-ic", "--stats"]), file).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], format!("{file}:1\n"));
+        assert!(lines[1].starts_with(&format!("{file}: steps=")));
+
+        // 端末に出力する場合はマッチ部分を色付きにする
+        let mut color = config(&["-i"]);
+        color.color = true;
+        let lines = match_file(&color, file).unwrap();
+        assert_eq!(
+            lines,
+            vec![format!("{file}:\x1b[1;31mpub mod engine\x1b[0m;\n")]
+        );
+
+        assert!(match_file(&config(&[]), "no/such/file").is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_match_stats() {
+        // a?^n a^n は深さ優先探索でバックトラックが指数的に増える
+        let stats = |n: usize| {
+            let re = Regex::new(&format!("{}{}", "a?".repeat(n), "a".repeat(n))).unwrap();
+            let mut stats = MatchStats::default();
+            assert!(re.is_match_with_stats(&"a".repeat(n), &mut stats).unwrap());
+            stats
+        };
+        assert_eq!(stats(1).backtracks, 1);
+        assert_eq!(stats(3).backtracks, 7);
+        assert_eq!(stats(6).backtracks, 63);
+        assert!(stats(6).steps > stats(3).steps * 4);
+        assert_eq!(stats(6).spawns, stats(6).backtracks);
+
+        // 統計は加算される
+        let re = Regex::new("ab").unwrap();
+        let mut stats = MatchStats::default();
+        assert!(!re.is_match_with_stats("xy", &mut stats).unwrap());
+        assert_eq!(stats.steps, 2);
+        assert!(re.is_match_with_stats("ab", &mut stats).unwrap());
+        assert_eq!(stats.steps, 5);
+        assert_eq!(stats.backtracks, 0);
+    }
+
+    #[test]
+    fn test_span() {
+        // パースエラーの位置
+        let err = regex::engine::parser::parse("a(b").unwrap_err();
+        assert!(matches!(err, ParseError::NoRightParen));
+        assert_eq!(err.span(), None);
+        let err = regex::engine::parser::parse("ab)").unwrap_err();
+        assert_eq!(err.span(), Some(Span::new(2, 3)));
+        assert_eq!(std::ops::Range::from(Span::new(2, 3)), 2..3);
+
+        // 命令と正規表現中の範囲の対応
+        let opts = MatchOptions::default();
+        let (code, spans) = compile_with_spans("a(\\+|[xy])*\\b", &opts).unwrap();
+        let leaf = |s: &str| {
+            code.iter()
+                .zip(&spans)
+                .find(|(inst, _)| inst.to_string().starts_with(s))
+                .and_then(|(_, span)| *span)
+        };
+        assert_eq!(code.len(), spans.len());
+        assert_eq!(leaf("char a"), Some(Span::new(0, 1)));
+        assert_eq!(leaf("char +"), Some(Span::new(2, 4)));
+        assert_eq!(leaf("class [xy]"), Some(Span::new(5, 9)));
+        assert_eq!(leaf("assert \\b"), Some(Span::new(11, 13)));
+        assert_eq!(leaf("split"), None);
+        assert_eq!(leaf("match"), None);
+    }
+
+    #[test]
+    fn test_matched() {
+        // do_matching と異なり、マッチング方式を指定せずにどの構文も扱える
+        assert!(matched("abc|(de|cd)+", "decddede").unwrap());
+        assert!(matched("[[:digit:]]+\\b", "123 abc").unwrap());
+        // & と ~ は通常の文字
+        assert!(matched("a&b|~", "a&b").unwrap());
+        assert!(!matched("a&b", "a").unwrap());
+        assert!(!matched("b", "ab").unwrap());
+        assert!(matched("a(b", "ab").is_err());
+    }
+
+    #[test]
+    fn test_end_assertion() {
+        // $ は文字列の末尾にマッチし、 \$ は $ という文字にマッチする
+        let re = Regex::new("bc$").unwrap();
+        assert!(re.is_match("abc").unwrap());
+        assert!(!re.is_match("abcd").unwrap());
+        assert!(!re.is_match("abc\r").unwrap());
+        assert!(Regex::new("a\\$").unwrap().is_match("xa$").unwrap());
+        assert!(!Regex::new("a\\$").unwrap().is_match("xa").unwrap());
+        assert_eq!(
+            find_all("a$", "aaa", MatchOptions::default()).unwrap(),
+            vec![2..3]
+        );
+        for s in [Dfs, Bfs, Memo, Hybrid, Derivatives] {
+            assert!(do_matching("(ab)*$", "abab", s).unwrap(), "{s:?}");
+            assert!(!do_matching("(ab)*$", "ababa", s).unwrap(), "{s:?}");
+        }
+
+        // crlf の場合は末尾の \r の直前にもマッチする
+        let opts = MatchOptions {
+            crlf: true,
+            ..Default::default()
+        };
+        let re = Regex::with_options("bc$", &opts).unwrap();
+        assert!(re.is_match("abc\r").unwrap());
+        assert!(re.is_match("abc").unwrap());
+        assert!(!re.is_match("abc\r\r").unwrap());
+        assert!(!re.is_match("abcd\r").unwrap());
+        assert_eq!(find_all("c$", "abc\r", opts).unwrap(), vec![2..3]);
+    }
+
+    #[test]
+    fn test_match_file_crlf() {
+        // Windows の改行 \r\n のファイル
+        let path = std::env::temp_dir().join(format!("regex-crlf-{}.txt", std::process::id()));
+        std::fs::write(&path, "foo\r\nbar\r\nfoobar\r\n").unwrap();
+        let file = path.to_str().unwrap();
+        let config = |opts: &[&str]| {
+            let mut args = opts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            args.push("o$".to_string());
+            args.push(file.to_string());
+            parse_args(&args).unwrap()
+        };
+
+        // \r を行の一部とするため、 $ は \r の前にマッチしない
+        let lines = match_file(&config(&[]), file).unwrap();
+        assert!(lines.is_empty());
+
+        // --crlf の場合は \r を取り除いて表示する
+        let lines = match_file(&config(&["--crlf"]), file).unwrap();
+        assert_eq!(lines, vec![format!("{file}:foo\n")]);
+        let lines = match_file(&config(&["--crlf", "-v"]), file).unwrap();
+        assert_eq!(
+            lines,
+            vec![format!("{file}:bar\n"), format!("{file}:foobar\n")]
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_match_file_null_data() {
+        // find -print0 の出力のように、 NUL で区切ったファイル名の並び
+        let path = std::env::temp_dir().join(format!("regex-null-{}.txt", std::process::id()));
+        std::fs::write(&path, "./a.rs\0./new\nline.rs\0./b.txt\0").unwrap();
+        let file = path.to_str().unwrap();
+        let config = |opts: &[&str]| {
+            let mut args = opts.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+            args.push("rs$".to_string());
+            args.push(file.to_string());
+            parse_args(&args).unwrap()
+        };
+
+        // 改行で区切る場合は、 NUL を含む行となりマッチしない
+        assert!(match_file(&config(&[]), file).unwrap().is_empty());
+
+        // -z の場合は NUL で区切り、マッチした行も NUL で終える
+        let lines = match_file(&config(&["-zn"]), file).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                format!("{file}:1:./a.rs\0"),
+                format!("{file}:2:./new\nline.rs\0")
+            ]
+        );
+
+        // 行数は改行で終える
+        let lines = match_file(&config(&["-zc"]), file).unwrap();
+        assert_eq!(lines, vec![format!("{file}:2\n")]);
+
+        std::fs::remove_file(&path).unwrap();
     }
 }
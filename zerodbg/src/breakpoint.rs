@@ -0,0 +1,248 @@
+//! ブレークポイントの管理
+//!
+//! 子プロセスのメモリに書き込んだ 0xcc (int 3) と元の値の対応を一元的に管理する。
+//! 各ブレークポイントは以下のいずれかの状態をとる。
+//!
+//! - 未挿入 : アドレスのみ登録している。プロセスの実行前や、停止して元の値に戻した後
+//! - 挿入済み : メモリを 0xcc に書き換えている
+//!
+//! ブレークポイントで停止した場合や、ブレークポイントのアドレスからステップ実行する場合は、
+//! 一時的に元の値に戻して 1 ステップ実行した後に再挿入する。再挿入待ちのアドレスは pending に記録する。
+//!
+//! ptrace によるメモリの読み書きは 8 バイト単位のため、近接したブレークポイント同士で
+//! 互いの 0xcc を上書きしないよう、元の値は 1 バイトのみ保持し、書き込む直前に現在の値を読み出す。
+use crate::trace::ptrace;
+use nix::unistd::Pid;
+use std::{collections::BTreeMap, ffi::c_void};
+
+/// int 3 の機械語
+const INT3: u8 = 0xcc;
+
+/// 子プロセスのメモリ。 8 バイト単位で読み書きする
+pub trait Memory {
+    fn read_word(&mut self, addr: usize) -> nix::Result<i64>;
+    fn write_word(&mut self, addr: usize, val: i64) -> nix::Result<()>;
+}
+
+impl Memory for Pid {
+    fn read_word(&mut self, addr: usize) -> nix::Result<i64> {
+        ptrace::read(*self, addr as *mut c_void)
+    }
+
+    fn write_word(&mut self, addr: usize, val: i64) -> nix::Result<()> {
+        ptrace::write(*self, addr as *mut c_void, val)
+    }
+}
+
+/// ブレークポイント
+#[derive(Debug, Default)]
+struct Breakpoint {
+    orig: Option<u8>, // 挿入済みの場合は書き換える前の値
+    hits: usize,      // 停止した回数
+}
+
+/// ブレークポイントの一覧と、ステップ実行による再挿入待ちの状態
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    bps: BTreeMap<usize, Breakpoint>,
+    pending: Option<usize>, // 元の値に戻しており、次のステップ実行後に再挿入するアドレス
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// ブレークポイントを登録する。メモリには反映しない
+    /// 登録済みの場合は false を返す
+    pub fn add(&mut self, addr: usize) -> bool {
+        if self.bps.contains_key(&addr) {
+            return false;
+        }
+        self.bps.insert(addr, Breakpoint::default());
+        true
+    }
+
+    /// 登録済みのアドレスの一覧
+    pub fn addrs(&self) -> Vec<usize> {
+        self.bps.keys().copied().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bps.is_empty()
+    }
+
+    /// すべてのブレークポイントで停止した回数の合計
+    pub fn total_hits(&self) -> usize {
+        self.bps.values().map(|bp| bp.hits).sum()
+    }
+
+    /// addr のブレークポイントをメモリに挿入し、書き換える前後の 8 バイトを返す
+    /// 挿入済みの場合は何もせず None を返す
+    pub fn insert(
+        &mut self,
+        mem: &mut impl Memory,
+        addr: usize,
+    ) -> nix::Result<Option<(i64, i64)>> {
+        let Some(bp) = self.bps.get_mut(&addr) else {
+            return Ok(None);
+        };
+        if bp.orig.is_some() {
+            return Ok(None);
+        }
+
+        let val = mem.read_word(addr)?;
+        let val_int3 = (val & !0xff) | INT3 as i64;
+        mem.write_word(addr, val_int3)?;
+        bp.orig = Some(val as u8);
+        Ok(Some((val, val_int3)))
+    }
+
+    /// addr のブレークポイントをメモリから取り除き、元の値に戻す
+    /// 登録は残るため、 insert で再挿入できる
+    pub fn remove(&mut self, mem: &mut impl Memory, addr: usize) -> nix::Result<()> {
+        let Some(bp) = self.bps.get_mut(&addr) else {
+            return Ok(());
+        };
+        let Some(orig) = bp.orig else {
+            return Ok(());
+        };
+
+        let val = mem.read_word(addr)?;
+        mem.write_word(addr, (val & !0xff) | orig as i64)?;
+        bp.orig = None;
+        Ok(())
+    }
+
+    /// int 3 により pc の直後で停止した際に呼び出す
+    /// pc が挿入済みのブレークポイントであれば元の値に戻して再挿入待ちとし、 true を返す
+    pub fn on_trap(&mut self, mem: &mut impl Memory, pc: usize) -> nix::Result<bool> {
+        match self.bps.get(&pc) {
+            Some(bp) if bp.orig.is_some() => (),
+            _ => return Ok(false),
+        }
+
+        self.remove(mem, pc)?;
+        self.bps.get_mut(&pc).unwrap().hits += 1;
+        self.pending = Some(pc);
+        Ok(true)
+    }
+
+    /// pc から 1 ステップ実行する前に呼び出す
+    /// pc がブレークポイントであれば元の値に戻して再挿入待ちとし、 true を返す
+    pub fn begin_step(&mut self, mem: &mut impl Memory, pc: usize) -> nix::Result<bool> {
+        if !self.bps.contains_key(&pc) {
+            return Ok(false);
+        }
+
+        self.remove(mem, pc)?;
+        self.pending = Some(pc);
+        Ok(true)
+    }
+
+    /// 1 ステップ実行した後に呼び出し、再挿入待ちのブレークポイントを挿入する
+    pub fn end_step(&mut self, mem: &mut impl Memory) -> nix::Result<Option<(i64, i64)>> {
+        match self.pending.take() {
+            Some(addr) => self.insert(mem, addr),
+            None => Ok(None),
+        }
+    }
+
+    /// 再挿入待ちの状態を破棄し、すべてのブレークポイントを元の値に戻す
+    /// デタッチや終了の前に呼び出し、子プロセスに 0xcc が残らないようにする
+    /// 失敗したアドレスがあっても残りは戻し、最初のエラーを返す
+    pub fn restore_all(&mut self, mem: &mut impl Memory) -> nix::Result<()> {
+        self.pending = None;
+
+        let mut ret = Ok(());
+        for addr in self.addrs() {
+            if let Err(e) = self.remove(mem, addr) {
+                // 戻せなかったものは挿入済みのまま残る
+                if ret.is_ok() {
+                    ret = Err(e);
+                }
+            }
+        }
+        ret
+    }
+
+    /// 子プロセスが終了した場合に呼び出し、すべてを未挿入とする
+    pub fn reset(&mut self) {
+        self.pending = None;
+        for bp in self.bps.values_mut() {
+            bp.orig = None;
+        }
+    }
+}
+#[cfg(test)]
+mod breakpoints {
+    use super::*;
+
+    /// テスト用のメモリ。 0 番地からのバイト列
+    struct FakeMem(Vec<u8>);
+
+    impl Memory for FakeMem {
+        fn read_word(&mut self, addr: usize) -> nix::Result<i64> {
+            let b = self.0.get(addr..addr + 8).ok_or(nix::Error::EIO)?;
+            Ok(i64::from_le_bytes(b.try_into().unwrap()))
+        }
+
+        fn write_word(&mut self, addr: usize, val: i64) -> nix::Result<()> {
+            let b = self.0.get_mut(addr..addr + 8).ok_or(nix::Error::EIO)?;
+            b.copy_from_slice(&val.to_le_bytes());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test() {
+        let orig: Vec<u8> = (0..32).collect();
+        let mut mem = FakeMem(orig.clone());
+        let mut bps = Breakpoints::new();
+
+        // 同じ 8 バイトに収まる近接したブレークポイント
+        assert!(bps.add(3));
+        assert!(bps.add(1));
+        assert!(bps.add(4));
+        assert!(!bps.add(3));
+        assert_eq!(bps.addrs(), vec![1, 3, 4]);
+
+        for addr in bps.addrs() {
+            bps.insert(&mut mem, addr).unwrap();
+        }
+        assert_eq!(bps.insert(&mut mem, 1), Ok(None)); // 挿入済み
+        assert_eq!(&mem.0[..6], &[0, INT3, 2, INT3, INT3, 5]);
+
+        // 3 番地で停止して 1 ステップ実行。他のブレークポイントは残る
+        assert_eq!(bps.on_trap(&mut mem, 3), Ok(true));
+        assert_eq!(&mem.0[..6], &[0, INT3, 2, 3, INT3, 5]);
+        assert_eq!(bps.on_trap(&mut mem, 2), Ok(false));
+        bps.end_step(&mut mem).unwrap();
+        assert_eq!(&mem.0[..6], &[0, INT3, 2, INT3, INT3, 5]);
+        assert_eq!(bps.total_hits(), 1);
+
+        // 停止した後、再挿入せずにすべて元に戻す
+        assert_eq!(bps.on_trap(&mut mem, 4), Ok(true));
+        bps.restore_all(&mut mem).unwrap();
+        assert_eq!(mem.0, orig);
+        assert_eq!(bps.end_step(&mut mem), Ok(None)); // 再挿入待ちは破棄されている
+        assert_eq!(mem.0, orig);
+
+        // ブレークポイントのアドレスからのステップ実行
+        bps.insert(&mut mem, 1).unwrap();
+        assert_eq!(bps.begin_step(&mut mem, 1), Ok(true));
+        assert_eq!(mem.0, orig);
+        assert_eq!(bps.begin_step(&mut mem, 2), Ok(false));
+        bps.end_step(&mut mem).unwrap();
+        assert_eq!(mem.0[1], INT3);
+
+        // 挿入に失敗したものは未挿入のままとなり、元に戻す対象にならない
+        bps.add(30);
+        bps.reset();
+        mem.0 = orig.clone();
+        bps.insert(&mut mem, 1).unwrap();
+        assert!(bps.insert(&mut mem, 30).is_err());
+        assert_eq!(bps.restore_all(&mut mem), Ok(()));
+        assert_eq!(mem.0, orig);
+    }
+}
@@ -0,0 +1,226 @@
+//! コマンドの登録表
+//!
+//! 各コマンドの名前, 別名, 省略できる最小の文字数, 状態ごとの処理関数, 書式, 説明, 使用例を
+//! dbg::COMMANDS にまとめて登録し、コマンドの実行, help の表示, 入力補完のすべてで用いる。
+//! 新しいコマンドは COMMANDS に 1 つ追加するだけでよい。
+//!
+//! 入力されたコマンド名は以下の順に解決する。
+//!
+//! 1. 名前または別名と完全に一致するもの
+//! 2. 名前の先頭 min_abbrev 文字以上と一致するもの。複数ある場合は曖昧としてエラー
+use crate::{
+    dbg::{NotRunning, Running, State, ZDbg, COMMANDS},
+    helper::DynError,
+};
+use rustyline::{
+    completion::Completer, highlight::Highlighter, hint::Hinter, validate::Validator, Context,
+    Helper,
+};
+
+/// コマンドの処理関数。引数の cmd[0] はコマンド名
+pub type Handler<T> = fn(ZDbg<T>, &[&str]) -> Result<State, DynError>;
+
+/// 登録するコマンド
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub min_abbrev: usize,     // 名前を省略できる最小の文字数
+    pub usage: &'static str,   // 書式。引数は <> で、省略できるものは [] で囲む
+    pub summary: &'static str, // 1 行の説明
+    pub examples: &'static [(&'static str, &'static str)], // 使用例と説明
+    pub running: Handler<Running>, // 実行中の処理
+    pub not_running: Handler<NotRunning>, // 実行していない場合の処理
+}
+
+impl Command {
+    /// name が名前または別名と一致するか
+    fn is_named(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+/// 入力されたコマンド名を解決
+pub fn resolve(input: &str) -> Result<&'static Command, String> {
+    if let Some(c) = COMMANDS.iter().find(|c| c.is_named(input)) {
+        return Ok(c);
+    }
+
+    let prefixed: Vec<&Command> = COMMANDS
+        .iter()
+        .filter(|c| c.name.starts_with(input))
+        .collect();
+    let found: Vec<&Command> = prefixed
+        .iter()
+        .copied()
+        .filter(|c| input.len() >= c.min_abbrev)
+        .collect();
+    match (found.as_slice(), prefixed.as_slice()) {
+        ([c], _) => Ok(c),
+        (_, []) => Err(format!("不明なコマンドです : {input}")),
+        (_, [c]) => Err(format!("省略が短すぎます : {input} ({})", c.name)),
+        _ => {
+            let names: Vec<&str> = prefixed.iter().map(|c| c.name).collect();
+            Err(format!(
+                "コマンドが曖昧です : {input} (候補 : {})",
+                names.join(", ")
+            ))
+        }
+    }
+}
+#[cfg(test)]
+mod resolve {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let name = |input| resolve(input).map(|c| c.name);
+        assert_eq!(name("b"), Ok("break"));
+        assert_eq!(name("bre"), Ok("break"));
+        assert_eq!(name("cont"), Ok("continue"));
+        assert_eq!(name("regs"), Ok("registers"));
+        assert_eq!(name("q"), Ok("exit"));
+        assert!(name("br").is_err()); // 短すぎる
+        assert!(name("de").is_err()); // detach と define
+        assert!(name("breaks").is_err());
+        assert!(name("xyz").is_err());
+
+        // すべての名前, 別名, 最短の省略形が一意に解決できること
+        for c in COMMANDS {
+            assert_eq!(name(c.name), Ok(c.name));
+            assert_eq!(name(&c.name[..c.min_abbrev]), Ok(c.name));
+            for a in c.aliases {
+                assert_eq!(name(a), Ok(c.name));
+            }
+        }
+    }
+}
+
+/// マクロ名として使えない名前か
+/// end はマクロの定義を終了するため予約する
+pub fn is_reserved(name: &str) -> bool {
+    name == "end" || COMMANDS.iter().any(|c| c.is_named(name))
+}
+
+/// help コマンドを実行
+/// コマンド名が指定された場合はそのコマンドの詳細を、指定されていない場合は一覧を表示
+pub fn do_help(cmd: &[&str]) {
+    match cmd.get(1) {
+        None => print!("{}", help_text()),
+        Some(name) => match resolve(name) {
+            Ok(c) => print!("{}", command_help(c)),
+            Err(e) => eprintln!("<<{e}>>"),
+        },
+    }
+}
+
+/// コマンド一覧の文字列を生成
+pub fn help_text() -> String {
+    let mut s =
+        "コマンド一覧 (括弧内は別名。コマンド名は他と区別できる長さまで省略可能)\n".to_string();
+    let width = COMMANDS.iter().map(|c| c.usage.len()).max().unwrap_or(0);
+    for c in COMMANDS {
+        s.push_str(&format!("{:<width$} : {}", c.usage, c.summary));
+        if !c.aliases.is_empty() {
+            s.push_str(&format!(" ({})", c.aliases.join(", ")));
+        }
+        s.push('\n');
+    }
+    s.push_str("help <command> で各コマンドの使用例を表示\n");
+    s
+}
+
+/// コマンド c の書式, 別名, 省略形, 使用例の文字列を生成
+pub fn command_help(c: &Command) -> String {
+    let mut s = format!("{}\n  {}\n", c.usage, c.summary);
+    if !c.aliases.is_empty() {
+        s.push_str(&format!("別名 : {}\n", c.aliases.join(", ")));
+    }
+    if c.min_abbrev < c.name.len() {
+        s.push_str(&format!("省略形 : {}\n", &c.name[..c.min_abbrev]));
+    }
+    if !c.examples.is_empty() {
+        s.push_str("使用例 :\n");
+        let width = c.examples.iter().map(|(e, _)| e.len()).max().unwrap_or(0);
+        for (example, desc) in c.examples {
+            s.push_str(&format!("  {example:<width$} : {desc}\n"));
+        }
+    }
+    s
+}
+#[cfg(test)]
+mod command_help {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let c = resolve("b").unwrap();
+        assert_eq!(
+            command_help(c),
+            "break <addr>\n  ブレークポイントを追加\n別名 : b\n省略形 : bre\n使用例 :\n  break 0x8000 : ブレークポイントを 0x8000 番地に追加\n"
+        );
+        assert!(command_help(resolve("watchmem").unwrap())
+            .contains("  watchmem off       : メモリの監視を解除\n"));
+
+        // 一覧にはすべてのコマンドが 1 行ずつ含まれること
+        let text = help_text();
+        assert_eq!(text.lines().count(), COMMANDS.len() + 2);
+        for c in COMMANDS {
+            assert!(text.contains(c.usage));
+            assert!(!c.examples.is_empty());
+        }
+    }
+}
+
+/// prefix から始まるコマンド名の一覧
+fn complete(prefix: &str) -> Vec<String> {
+    COMMANDS
+        .iter()
+        .map(|c| c.name)
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| name.to_string())
+        .collect()
+}
+#[cfg(test)]
+mod complete {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(complete("in"), vec!["interrupt", "info"]);
+        assert_eq!(complete("wat"), vec!["watchmem"]);
+        assert!(complete("x").is_empty());
+        assert_eq!(complete("").len(), COMMANDS.len());
+    }
+}
+
+/// rustyline のヘルパ。行頭のコマンド名を補完する
+pub struct CmdHelper;
+
+impl Completer for CmdHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let head = &line[..pos];
+        let start = head.len() - head.trim_start().len();
+        if head[start..].contains(' ') {
+            // 引数は補完しない
+            return Ok((pos, vec![]));
+        }
+        Ok((start, complete(&head[start..])))
+    }
+}
+
+impl Hinter for CmdHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CmdHelper {}
+
+impl Validator for CmdHelper {}
+
+impl Helper for CmdHelper {}
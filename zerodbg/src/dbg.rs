@@ -1,21 +1,100 @@
-use crate::helper::DynError;
+use crate::{
+    breakpoint::Breakpoints,
+    command::{self, Command},
+    helper::DynError,
+    interrupt::Interrupter,
+    reaper,
+    symbol::{format_addr, Symbols},
+    trace::{ptrace, waitpid},
+    watch::{hexdump, WatchMem},
+};
 use nix::{
     libc::user_regs_struct,
     sys::{
         personality::{self, Persona},
-        ptrace,
-        wait::{waitpid, WaitStatus},
+        signal::Signal,
+        wait::WaitStatus,
     },
     unistd::{execvp, fork, ForkResult, Pid},
 };
-use std::ffi::{c_void, CString};
+use std::{
+    ffi::{c_void, CString},
+    fmt::{self, Display},
+    time::Duration,
+};
 
 /// デバッガ内の情報
 pub struct DbgInfo {
     pid: Pid,
-    brk_addr: Option<*mut c_void>, // ブレークポイントのアドレス
-    brk_val: i64,                  // ブレークポイントを設定したメモリの元の値
-    filename: String,              // 実行ファイル名
+    bps: Breakpoints,            // ブレークポイント
+    filename: String,            // 実行ファイル名
+    watch: Option<WatchMem>,     // watchmem で監視するメモリ範囲
+    symbols: Option<Symbols>,    // 実行ファイルのシンボル
+    last_run: Option<LastRun>,   // 前回の実行の終了状態
+    interrupt: Option<Duration>, // continue で再開してから SIGSTOP で一時停止するまでの時間
+    stop: Option<StopKind>,      // 直前に停止した理由
+}
+
+/// 子プロセスが停止した理由
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopKind {
+    Trap,           // ブレークポイントやステップ実行による SIGTRAP
+    Interrupted,    // interrupt による SIGSTOP
+    Signal(Signal), // その他のシグナル
+}
+
+impl StopKind {
+    /// 停止した場合の WaitStatus から変換。停止していない場合は None
+    fn from_status(status: WaitStatus) -> Option<Self> {
+        match status {
+            WaitStatus::Stopped(_, Signal::SIGTRAP) => Some(StopKind::Trap),
+            WaitStatus::Stopped(_, Signal::SIGSTOP) => Some(StopKind::Interrupted),
+            WaitStatus::Stopped(_, sig) => Some(StopKind::Signal(sig)),
+            _ => None,
+        }
+    }
+}
+
+/// 前回の実行の終了状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LastRun {
+    Exited(i32),            // 終了コード
+    Signaled(Signal, bool), // 終了させたシグナルと、コアダンプしたか
+    Detached,               // デタッチした
+}
+
+impl LastRun {
+    /// 終了した場合の WaitStatus から変換。終了していない場合は None
+    fn from_status(status: WaitStatus) -> Option<Self> {
+        match status {
+            WaitStatus::Exited(_, code) => Some(LastRun::Exited(code)),
+            WaitStatus::Signaled(_, sig, core) => Some(LastRun::Signaled(sig, core)),
+            _ => None,
+        }
+    }
+
+    /// プロンプトに表示する短い形式
+    fn summary(&self) -> String {
+        match self {
+            LastRun::Exited(code) => format!("exit {code}"),
+            LastRun::Signaled(sig, true) => format!("{sig} core"),
+            LastRun::Signaled(sig, false) => sig.to_string(),
+            LastRun::Detached => "detached".to_string(),
+        }
+    }
+}
+
+impl Display for LastRun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LastRun::Exited(code) => write!(f, "終了コード {code} で終了しました"),
+            LastRun::Signaled(sig, true) => {
+                write!(f, "シグナル {sig} で終了しました (コアダンプあり)")
+            }
+            LastRun::Signaled(sig, false) => write!(f, "シグナル {sig} で終了しました"),
+            LastRun::Detached => write!(f, "デタッチしました"),
+        }
+    }
 }
 
 /// デバッガ
@@ -38,31 +117,121 @@ pub enum State {
     Exit,
 }
 
+impl State {
+    /// プロンプト。実行していない場合は前回の実行の終了状態を表示
+    pub fn prompt(&self) -> String {
+        match self {
+            State::NotRunning(d) => match d.info.last_run {
+                Some(last) => format!("zdbg ({}) > ", last.summary()),
+                None => "zdbg > ".to_string(),
+            },
+            _ => "zdbg > ".to_string(),
+        }
+    }
+}
+
 /// Running と NotRunning で共通の実装
 impl<T> ZDbg<T> {
-    /// 共通のコマンドを実行
-    fn do_cmd_common(&self, cmd: &[&str]) {
-        match cmd[0] {
-            "help" | "h" => do_help(),
-            _ => (),
-        }
+    /// アドレスを表示用の文字列に変換
+    /// シンボルを読み込み済みの場合は `0x4011ab <main+0x2b>` の形式となる
+    fn fmt_addr(&self, addr: usize) -> String {
+        format_addr(addr, self.info.symbols.as_ref())
     }
 
-    /// ブレークポイントのアドレスを設定する関数
+    /// ブレークポイントのアドレスを登録する関数
     /// 子プロセスのメモリ上には反映しない
-    /// アドレス設定に成功した場合は true を返す
-    fn set_break_addr(&mut self, cmd: &[&str]) -> bool {
-        if self.info.brk_addr.is_some() {
+    /// 登録に成功した場合はアドレスを返す
+    fn set_break_addr(&mut self, cmd: &[&str]) -> Option<usize> {
+        let addr = get_break_addr(cmd)? as usize;
+        if self.info.bps.add(addr) {
+            Some(addr)
+        } else {
             println!(
-                "ブレークポイントは設定済みです : Addr = {:?}>>",
-                self.info.brk_addr.unwrap()
+                "<<ブレークポイントは設定済みです : Addr = {}>>",
+                self.fmt_addr(addr)
             );
-            false
-        } else if let Some(addr) = get_break_addr(cmd) {
-            self.info.brk_addr = Some(addr); // ブレークポイントのアドレスを設定
-            true
-        } else {
-            false
+            None
+        }
+    }
+
+    /// 監視するメモリ範囲を設定する関数
+    /// watchmem off の場合は監視を解除
+    /// 範囲の設定に成功した場合は true を返す
+    fn set_watch(&mut self, cmd: &[&str]) -> bool {
+        if cmd.get(1) == Some(&"off") {
+            self.info.watch = None;
+            println!("<<メモリの監視を解除しました>>");
+            return false;
+        }
+
+        if cmd.len() < 3 {
+            eprintln!("<<アドレスと長さを指定してください\n 例 : watchmem 0x8000 32>>");
+            return false;
+        }
+
+        let addr = match cmd[1].strip_prefix("0x") {
+            Some(s) => usize::from_str_radix(s, 16),
+            None => {
+                eprintln!("<<アドレスは 16 進数でのみ指定可能です\n 例 : watchmem 0x8000 32>>");
+                return false;
+            }
+        };
+        let (addr, len) = match (addr, cmd[2].parse::<usize>()) {
+            (Ok(addr), Ok(len)) if len > 0 => (addr, len),
+            _ => {
+                eprintln!("<<アドレスまたは長さが不正です\n 例 : watchmem 0x8000 32>>");
+                return false;
+            }
+        };
+
+        self.info.watch = Some(WatchMem {
+            addr,
+            len,
+            prev: None,
+        });
+        true
+    }
+
+    /// interrupt を実行。 continue で再開してから一時停止するまでの時間を設定
+    /// interrupt off の場合は解除
+    fn set_interrupt(&mut self, cmd: &[&str]) {
+        match cmd.get(1).map(|s| (*s, s.parse::<u64>())) {
+            Some(("off", _)) => {
+                self.info.interrupt = None;
+                println!("<<一時停止を解除しました>>");
+            }
+            Some((_, Ok(ms))) if ms > 0 => {
+                self.info.interrupt = Some(Duration::from_millis(ms));
+                println!("<<continue から {ms} ms 経っても停止しない場合は一時停止します>>");
+            }
+            _ => eprintln!("<<時間をミリ秒で指定してください\n 例 : interrupt 500>>"),
+        }
+    }
+
+    /// info を実行。 info last-run で前回の実行の終了状態を表示
+    fn do_info(&self, cmd: &[&str]) {
+        match cmd.get(1) {
+            Some(&"last-run") => match self.info.last_run {
+                Some(last) => println!("<<前回の実行 : {last}>>"),
+                None => println!("<<まだ実行していません>>"),
+            },
+            _ => eprintln!("<<項目を指定してください\n 例 : info last-run>>"),
+        }
+    }
+
+    /// 子プロセスの終了状態を記録し、 NotRunning 状態に遷移
+    fn finish(mut self, last: LastRun) -> ZDbg<NotRunning> {
+        match last {
+            LastRun::Detached => (),
+            _ => println!("<<子プロセスが{last}>>"),
+        }
+        reaper::clear();
+        self.info.bps.reset();
+        self.info.stop = None;
+        self.info.last_run = Some(last);
+        ZDbg::<NotRunning> {
+            info: self.info,
+            _state: NotRunning,
         }
     }
 }
@@ -73,37 +242,37 @@ impl ZDbg<NotRunning> {
         Self {
             info: Box::new(DbgInfo {
                 pid: Pid::from_raw(0),
-                brk_addr: None,
-                brk_val: 0,
+                bps: Breakpoints::new(),
                 filename,
+                watch: None,
+                symbols: None,
+                last_run: None,
+                interrupt: None,
+                stop: None,
             }),
             _state: NotRunning,
         }
     }
 
-    pub fn do_cmd(mut self, cmd: &[&str]) -> Result<State, DynError> {
+    /// 登録表からコマンドを解決して実行
+    pub fn do_cmd(self, cmd: &[&str]) -> Result<State, DynError> {
         if cmd.is_empty() {
             return Ok(State::NotRunning(self));
         }
 
-        match cmd[0] {
-            "run" | "r" => return self.do_run(cmd),
-            "break" | "b" => {
-                self.do_break(cmd);
-            }
-            "exit" | "q" => return Ok(State::Exit),
-            "continue" | "c" | "stepi" | "s" | "registers" | "regs" => {
-                eprintln!("<<ターゲットを実行していません。 run で実行してください>>");
+        match command::resolve(cmd[0]) {
+            Ok(c) => (c.not_running)(self, cmd),
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                Ok(State::NotRunning(self))
             }
-            _ => self.do_cmd_common(cmd),
         }
-
-        Ok(State::NotRunning(self))
     }
 
-    /// ブレークポイントを設定
-    fn do_break(&mut self, cmd: &[&str]) -> bool {
-        self.set_break_addr(cmd)
+    /// 実行中にのみ使えるコマンドの処理
+    fn need_run(self, _cmd: &[&str]) -> Result<State, DynError> {
+        eprintln!("<<ターゲットを実行していません。 run で実行してください>>");
+        Ok(State::NotRunning(self))
     }
 
     /// 子プロセスを生成し、成功した場合は Running 状態に遷移
@@ -120,23 +289,34 @@ impl ZDbg<NotRunning> {
                 personality::set(p | Persona::ADDR_NO_RANDOMIZE).unwrap();
                 // 自身がデバッガによるトレース対象であることを指定
                 // traceme を指定した後は exec すると即座にプロセスが停止するようになる
-                ptrace::traceme().unwrap();
+                nix::sys::ptrace::traceme().unwrap();
 
                 // 子プロセスを実行
-                execvp(&CString::new(self.info.filename.as_str()).unwrap(), &args).unwrap();
-                unreachable!();
+                match execvp(&CString::new(self.info.filename.as_str()).unwrap(), &args) {
+                    Err(e) => panic!("execvp failed: {e}"),
+                    Ok(_) => unreachable!(),
+                }
             }
             ForkResult::Parent { child } => match waitpid(child, None)? {
                 // 子プロセスで traceme しているので子プロセスは停止もしくは終了するはず
                 WaitStatus::Stopped(..) => {
                     println!("<<子プロセスの実行に成功しました : PID = {child}>>");
                     self.info.pid = child;
+                    self.info.stop = Some(StopKind::Trap);
+                    reaper::set(child);
+                    // デバッガが後始末できずに終了した場合も、カーネルが子プロセスを kill する
+                    ptrace::setoptions(child, ptrace::Options::PTRACE_O_EXITKILL)?;
+                    // 実行ファイルのロードアドレスは実行後にしか分からないのでこの時点で読み込む
+                    match Symbols::load(child) {
+                        Ok(symbols) => self.info.symbols = Some(symbols),
+                        Err(e) => eprintln!("<<シンボルの読み込みに失敗 : {e}>>"),
+                    }
                     let mut dbg = ZDbg::<Running> {
                         info: self.info,
                         _state: Running,
                     };
                     // ブレークポイントはプロセスの実行中にしか行えないのでこの時点で設定
-                    dbg.set_break()?;
+                    dbg.set_break(true)?;
                     // 子プロセスの実行を再開
                     dbg.do_continue()
                 }
@@ -151,43 +331,39 @@ impl ZDbg<NotRunning> {
 
 /// Running 時に呼び出し可能なメソッド
 impl ZDbg<Running> {
-    pub fn do_cmd(mut self, cmd: &[&str]) -> Result<State, DynError> {
+    /// 登録表からコマンドを解決して実行
+    pub fn do_cmd(self, cmd: &[&str]) -> Result<State, DynError> {
         if cmd.is_empty() {
             return Ok(State::Running(self));
         }
 
-        match cmd[0] {
-            "break" | "b" => self.do_break(cmd)?,
-            "continue" | "c" => return self.do_continue(),
-            "registers" | "regs" => {
-                let regs = ptrace::getregs(self.info.pid)?;
-                print_regs(&regs);
-            }
-            "stepi" | "s" => return self.do_stepi(),
-            "run" | "r" => eprintln!("<<すでに実行中です>>"),
-            "exit" | "q" => {
-                self.do_exit()?;
-                return Ok(State::Exit);
+        match command::resolve(cmd[0]) {
+            Ok(c) => (c.running)(self, cmd),
+            Err(e) => {
+                eprintln!("<<{e}>>");
+                Ok(State::Running(self))
             }
-            _ => self.do_cmd_common(cmd),
         }
-
-        Ok(State::Running(self))
+    }
+    /// レジスタを表示
+    fn do_registers(&self) -> Result<(), DynError> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        print_regs(&regs);
+        println!("PC : {}", self.fmt_addr(regs.rip as usize));
+        Ok(())
+    }
+    /// pc から実行する前に、ブレークポイントを元に戻して 1 ステップ実行する必要があるか
+    /// interrupt で一時停止した場合、 pc のブレークポイントはまだ実行していないため対象外
+    fn at_break(&self, pc: usize) -> bool {
+        self.info.stop != Some(StopKind::Interrupted) && self.info.bps.addrs().contains(&pc)
     }
     /// stepi を実行。機械語レベルで 1 行実行
     fn do_stepi(self) -> Result<State, DynError> {
         let regs = ptrace::getregs(self.info.pid)?;
-        if Some((regs.rip) as *mut c_void) == self.info.brk_addr {
+        if self.at_break(regs.rip as usize) {
             // 次の実行先がブレークポイントのアドレスの場合、
             // 先に、 0xcc(int 3) に書き換えたメモリを元に戻してから実行する必要がある
-
-            ptrace::write(
-                self.info.pid,
-                self.info.brk_addr.unwrap(),
-                self.info.brk_val,
-            )?;
-
-            self.step_and_break()
+            self.step_and_break(true)
         } else {
             ptrace::step(self.info.pid, None)?;
             self.wait_child()
@@ -195,153 +371,534 @@ impl ZDbg<Running> {
     }
     /// ブレークポイントで停止していた場合は
     /// 1 ステップ実行しブレークポイントを再設定
-    fn step_and_break(mut self) -> Result<State, DynError> {
+    /// verbose が false の場合は書き換えるメモリの内容を表示しない
+    fn step_and_break(mut self, verbose: bool) -> Result<State, DynError> {
         let regs = ptrace::getregs(self.info.pid)?;
-        if Some((regs.rip) as *mut c_void) == self.info.brk_addr {
+        if !self.at_break(regs.rip as usize) {
+            return Ok(State::Running(self));
+        }
+        let info = &mut *self.info;
+        if info.bps.begin_step(&mut info.pid, regs.rip as usize)? {
             ptrace::step(self.info.pid, None)?; // 1 ステップ実行
-            match waitpid(self.info.pid, None)? {
-                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                    println!("<<子プロセスが終了しました>>");
-                    return Ok(State::NotRunning(ZDbg::<NotRunning> {
-                        info: self.info,
-                        _state: NotRunning,
-                    }));
-                }
-                _ => (),
+            let status = waitpid(self.info.pid, None)?;
+            if let Some(last) = LastRun::from_status(status) {
+                return Ok(State::NotRunning(self.finish(last)));
             }
-            self.set_break()?; // ブレークポイントを再設定
+            self.info.stop = StopKind::from_status(status);
+
+            // ブレークポイントを再設定
+            let addr = regs.rip as usize;
+            let info = &mut *self.info;
+            let ret = info.bps.end_step(&mut info.pid);
+            self.report_insert(addr, ret, verbose);
         }
 
         Ok(State::Running(self))
     }
-    /// ブレークポイントを実際に設定
+    /// 登録済みのブレークポイントを実際に設定
     /// つまり、該当アドレスのメモリを 0xcc(int 3) に設定
-    fn set_break(&mut self) -> Result<(), DynError> {
-        let addr = if let Some(addr) = self.info.brk_addr {
-            addr
-        } else {
-            return Ok(());
-        };
-
-        // ブレークするアドレスにあるメモリ上の値を取得
-        // メモリの値は i64(8bytes) で返される
-        let val = match ptrace::read(self.info.pid, addr) {
-            Ok(val) => val,
-            Err(e) => {
-                eprintln!("<<ptrace::read に失敗 : {e}, addr = {addr:?}>>");
-                return Ok(());
-            }
-        };
-
+    /// verbose が true の場合は書き換える前後のメモリの内容を表示
+    fn set_break(&mut self, verbose: bool) -> Result<(), DynError> {
+        for addr in self.info.bps.addrs() {
+            self.insert_break(addr, verbose);
+        }
+        Ok(())
+    }
+    /// addr のブレークポイントをメモリに設定
+    fn insert_break(&mut self, addr: usize, verbose: bool) {
+        let info = &mut *self.info;
+        let ret = info.bps.insert(&mut info.pid, addr);
+        self.report_insert(addr, ret, verbose);
+    }
+    /// ブレークポイントの設定結果を表示
+    /// verbose が true の場合は書き換える前後のメモリの内容を表示
+    fn report_insert(&self, addr: usize, ret: nix::Result<Option<(i64, i64)>>, verbose: bool) {
         // メモリ上の値を表示する補助関数
         // read で得られた値と 0xcc で書き換えた値をわかりやすく表示する
-        fn print_val(addr: usize, val: i64) {
-            print!("{addr:x}");
+        fn print_val(addr: &str, val: i64) {
+            print!("{addr}:");
             for n in (0..8).map(|n| ((val >> (n * 8)) & 0xff) as u8) {
                 print!(" {n:x}");
             }
         }
 
-        println!("<<以下のようにメモリを書き換えます>>");
-        print!("<<before: "); // 元の値を表示
-        print_val(addr as usize, val);
-        println!(">>");
-
-        // "int 3" に設定する
-        let val_int3 = (val & !0xff) | 0xcc;
-        print!("<<after : "); // 変更後の値を表示
-        print_val(addr as usize, val_int3);
-        println!(">>");
+        match ret {
+            Ok(Some((val, val_int3))) if verbose => {
+                println!("<<以下のようにメモリを書き換えました>>");
+                print!("<<before: "); // 元の値を表示
+                print_val(&self.fmt_addr(addr), val);
+                println!(">>");
 
-        // "int 3" をメモリに書き込み
-        // as *mut c_void と型変換しているのは、C の ptrace が引数にポインタをとるため
-        match ptrace::write(self.info.pid, addr, val_int3) {
-            Ok(_) => {
-                self.info.brk_addr = Some(addr);
-                self.info.brk_val = val; // 元の値を保持
-            }
-            Err(e) => {
-                eprintln!("<<ptrace::write に失敗 : {e}, addr = {addr:p}>>");
+                print!("<<after : "); // 変更後の値を表示
+                print_val(&self.fmt_addr(addr), val_int3);
+                println!(">>");
             }
+            Ok(_) => (),
+            Err(e) => eprintln!(
+                "<<ブレークポイントの設定に失敗 : {e}, addr = {}>>",
+                self.fmt_addr(addr)
+            ),
         }
-
-        Ok(())
     }
     /// break を実行
     fn do_break(&mut self, cmd: &[&str]) -> Result<(), DynError> {
-        if self.set_break_addr(cmd) {
-            self.set_break()?;
+        if let Some(addr) = self.set_break_addr(cmd) {
+            self.insert_break(addr, true);
         }
         Ok(())
     }
     /// continue を実行
     fn do_continue(self) -> Result<State, DynError> {
         // ブレークポイントで停止していた場合は 1 ステップ実行後に再設定
-        match self.step_and_break()? {
-            State::Running(r) => {
-                // 実行再開
-                ptrace::cont(r.info.pid, None)?;
-                r.wait_child()
+        match self.step_and_break(true)? {
+            State::Running(r) => match r.resume()? {
+                State::Running(mut r) => {
+                    r.print_stop()?;
+                    Ok(State::Running(r))
+                }
+                n => Ok(n),
+            },
+            n => Ok(n),
+        }
+    }
+    /// autocontinue を実行
+    /// ブレークポイントで停止するたびに 1 行のトレースを表示して自動的に実行を再開し、
+    /// n 回停止した時点で止まる
+    fn do_autocontinue(self, cmd: &[&str]) -> Result<State, DynError> {
+        let n = match cmd.get(1).map(|s| s.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => n,
+            _ => {
+                eprintln!("<<回数を指定してください\n 例 : autocontinue 10>>");
+                return Ok(State::Running(self));
+            }
+        };
+        if self.info.bps.is_empty() {
+            eprintln!("<<ブレークポイントが設定されていません>>");
+            return Ok(State::Running(self));
+        }
+
+        let mut dbg = self;
+        for i in 1..=n {
+            let hits = dbg.info.bps.total_hits();
+            let state = match dbg.step_and_break(false)? {
+                State::Running(r) => r.resume()?,
+                other => other,
+            };
+            dbg = match state {
+                State::Running(r) => r,
+                other => return Ok(other), // 子プロセスが終了した
+            };
+
+            if dbg.info.bps.total_hits() == hits {
+                // シグナルなどでブレークポイント以外の場所で停止した場合は自動実行を中断
+                eprintln!("<<ブレークポイント以外で停止したため中断します>>");
+                break;
+            }
+
+            let regs = ptrace::getregs(dbg.info.pid)?;
+            println!(
+                "<<[{i}/{n}] hit {} : PC = {}, RSP = {:#x}, RAX = {:#x}>>",
+                dbg.info.bps.total_hits(),
+                dbg.fmt_addr(regs.rip as usize),
+                regs.rsp,
+                regs.rax
+            );
+        }
+
+        dbg.print_stop()?;
+        Ok(State::Running(dbg))
+    }
+    /// 実行を再開し、停止するまで wait
+    /// interrupt で時間を設定している場合は、その時間が経っても停止しなければ SIGSTOP で一時停止する
+    /// 直前の停止がシグナルによる場合、そのシグナルは子プロセスに渡さない
+    fn resume(self) -> Result<State, DynError> {
+        let pid = self.info.pid;
+        ptrace::cont(pid, None)?;
+        let Some(after) = self.info.interrupt else {
+            return self.wait_stop();
+        };
+
+        let timer = Interrupter::start(pid, after);
+        let state = self.wait_stop()?;
+        let sent = timer.cancel();
+        match state {
+            // 別の理由で停止した直後に SIGSTOP を送っていた場合は、保留中の SIGSTOP を回収する
+            // 回収しないと次に再開した直後に一時停止してしまう
+            State::Running(r) if sent && r.info.stop != Some(StopKind::Interrupted) => {
+                ptrace::cont(pid, None)?;
+                let status = waitpid(pid, None)?;
+                match LastRun::from_status(status) {
+                    Some(last) => Ok(State::NotRunning(r.finish(last))),
+                    None => Ok(State::Running(r)),
+                }
             }
             n => Ok(n),
         }
     }
-    /// 子プロセスを wait 。子プロセスが終了した場合は NotRunning 状態に遷移
+    /// 監視しているメモリを読み出して表示
+    /// 前回の内容から変化したバイトは色付きで表示する
+    fn dump_watch(&mut self) -> Result<(), DynError> {
+        let watch = match self.info.watch.as_mut() {
+            Some(watch) => watch,
+            None => return Ok(()),
+        };
+
+        // メモリは 8 バイト単位で読み出されるので、必要な長さまで読み出して切り詰める
+        let mut cur = Vec::with_capacity(watch.len + 8);
+        while cur.len() < watch.len {
+            let addr = watch.addr + cur.len();
+            match ptrace::read(self.info.pid, addr as *mut c_void) {
+                Ok(val) => cur.extend_from_slice(&val.to_le_bytes()),
+                Err(e) => {
+                    let addr = format_addr(addr, self.info.symbols.as_ref());
+                    eprintln!("<<ptrace::read に失敗 : {e}, addr = {addr}>>");
+                    return Ok(());
+                }
+            }
+        }
+        cur.truncate(watch.len);
+
+        println!(
+            "<<watchmem {} {}>>",
+            format_addr(watch.addr, self.info.symbols.as_ref()),
+            watch.len
+        );
+        print!("{}", hexdump(watch.addr, &cur, watch.prev.as_deref()));
+        watch.prev = Some(cur);
+
+        Ok(())
+    }
+    /// 子プロセスを wait し、停止した場合は停止位置を表示
     fn wait_child(self) -> Result<State, DynError> {
-        match waitpid(self.info.pid, None)? {
-            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
-                println!("<<子プロセスが終了しました>>");
-                let not_run = ZDbg::<NotRunning> {
-                    info: self.info,
-                    _state: NotRunning,
-                };
-                Ok(State::NotRunning(not_run))
+        match self.wait_stop()? {
+            State::Running(mut r) => {
+                r.print_stop()?;
+                Ok(State::Running(r))
             }
-            WaitStatus::Stopped(..) => {
+            n => Ok(n),
+        }
+    }
+    /// 停止位置と監視しているメモリを表示
+    fn print_stop(&mut self) -> Result<(), DynError> {
+        let regs = ptrace::getregs(self.info.pid)?;
+        let pc = self.fmt_addr(regs.rip as usize);
+        match self.info.stop {
+            Some(StopKind::Interrupted) => println!("<<子プロセスを一時停止しました : PC = {pc}>>"),
+            Some(StopKind::Signal(sig)) => {
+                println!("<<子プロセスが {sig} で停止しました : PC = {pc}>>")
+            }
+            _ => println!("<<子プロセスが停止しました : PC = {pc}>>"),
+        }
+        self.dump_watch()
+    }
+    /// 子プロセスを wait 。子プロセスが終了した場合は NotRunning 状態に遷移
+    /// ブレークポイントで停止した場合は停止回数を数える
+    /// SIGSTOP などシグナルで停止した場合の PC は int 3 の直後ではないため、ブレークポイントとして扱わない
+    fn wait_stop(mut self) -> Result<State, DynError> {
+        let status = waitpid(self.info.pid, None)?;
+        if let Some(last) = LastRun::from_status(status) {
+            return Ok(State::NotRunning(self.finish(last)));
+        }
+        self.info.stop = StopKind::from_status(status);
+        match status {
+            WaitStatus::Stopped(_, Signal::SIGTRAP) => {
                 let mut regs = ptrace::getregs(self.info.pid)?;
-                if Some((regs.rip - 1) as *mut c_void) == self.info.brk_addr {
-                    // 書き換えたメモリを元の値に戻す
-                    ptrace::write(
-                        self.info.pid,
-                        self.info.brk_addr.unwrap(),
-                        self.info.brk_val,
-                    )?;
-
+                // ブレークポイントであれば書き換えたメモリを元の値に戻し、停止回数を数える
+                let info = &mut *self.info;
+                if info
+                    .bps
+                    .on_trap(&mut info.pid, regs.rip.wrapping_sub(1) as usize)?
+                {
                     // ブレークポイントで停止したアドレスから 1 つ戻す
                     regs.rip -= 1;
                     ptrace::setregs(self.info.pid, regs)?;
                 }
-                println!("<<子プロセスが停止しました : PC = {:#x}>>", regs.rip);
 
                 Ok(State::Running(self))
             }
+            WaitStatus::Stopped(..) => Ok(State::Running(self)),
             _ => Err("waitpid の返り値が不正です".into()),
         }
     }
+    /// detach を実行。ブレークポイントをすべて元に戻してから子プロセスを切り離す
+    /// 元に戻せなかった場合は 0xcc が残ってしまうため、切り離さない
+    fn do_detach(mut self) -> Result<State, DynError> {
+        let info = &mut *self.info;
+        if let Err(e) = info.bps.restore_all(&mut info.pid) {
+            eprintln!("<<ブレークポイントを元に戻せないためデタッチしません : {e}>>");
+            return Ok(State::Running(self));
+        }
+
+        ptrace::detach(self.info.pid, None)?;
+        println!(
+            "<<子プロセスからデタッチしました : PID = {}>>",
+            self.info.pid
+        );
+        Ok(State::NotRunning(self.finish(LastRun::Detached)))
+    }
     /// exit を実行。実行中のプロセスは kill
-    fn do_exit(self) -> Result<(), DynError> {
+    /// kill する前にブレークポイントをすべて元に戻しておく
+    fn do_exit(mut self) -> Result<State, DynError> {
+        let info = &mut *self.info;
+        if let Err(e) = info.bps.restore_all(&mut info.pid) {
+            eprintln!("<<ブレークポイントを元に戻せませんでした : {e}>>");
+        }
+
         loop {
             ptrace::kill(self.info.pid)?;
             match waitpid(self.info.pid, None)? {
-                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => return Ok(()),
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    reaper::clear();
+                    return Ok(State::Exit);
+                }
                 _ => (),
             }
         }
     }
 }
 
-/// ヘルプを表示
-fn do_help() {
-    println!(
-        r#"コマンド一覧 (括弧内は省略記法)
-break 0x8000  : ブレークポイントを 0x8000 番地に設定 (b 0x8000)
-run           : プログラムを実行 (r)
-continue      : プログラムを再開 (c)
-stepi         : 機械語レベルで 1 ステップ実行 (s)
-registers     : レジスタを表示 (regs)
-exit          : 終了 (q)
-help          : このヘルプを表示 (h)"#
-    );
+/// コマンドの登録表
+/// 実行中にのみ使えるコマンドは、実行していない場合に need_run を呼び出す
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "break",
+        aliases: &["b"],
+        min_abbrev: 3,
+        usage: "break <addr>",
+        summary: "ブレークポイントを追加",
+        examples: &[("break 0x8000", "ブレークポイントを 0x8000 番地に追加")],
+        running: |mut d, cmd| {
+            d.do_break(cmd)?;
+            Ok(State::Running(d))
+        },
+        not_running: |mut d, cmd| {
+            d.set_break_addr(cmd);
+            Ok(State::NotRunning(d))
+        },
+    },
+    Command {
+        name: "run",
+        aliases: &["r"],
+        min_abbrev: 2,
+        usage: "run",
+        summary: "プログラムを実行",
+        examples: &[("run", "プログラムを実行")],
+        running: |d, _| {
+            eprintln!("<<すでに実行中です>>");
+            Ok(State::Running(d))
+        },
+        not_running: ZDbg::<NotRunning>::do_run,
+    },
+    Command {
+        name: "continue",
+        aliases: &["c"],
+        min_abbrev: 4,
+        usage: "continue",
+        summary: "プログラムを再開",
+        examples: &[("continue", "プログラムを再開")],
+        running: |d, _| d.do_continue(),
+        not_running: ZDbg::<NotRunning>::need_run,
+    },
+    Command {
+        name: "autocontinue",
+        aliases: &["ac"],
+        min_abbrev: 4,
+        usage: "autocontinue <n>",
+        summary: "ブレークポイントで指定した回数停止するまで自動的に再開",
+        examples: &[(
+            "autocontinue 10",
+            "ブレークポイントで 10 回停止するまで自動的に再開",
+        )],
+        running: ZDbg::<Running>::do_autocontinue,
+        not_running: ZDbg::<NotRunning>::need_run,
+    },
+    Command {
+        name: "stepi",
+        aliases: &["s"],
+        min_abbrev: 4,
+        usage: "stepi",
+        summary: "機械語レベルで 1 ステップ実行",
+        examples: &[("stepi", "機械語レベルで 1 ステップ実行")],
+        running: |d, _| d.do_stepi(),
+        not_running: ZDbg::<NotRunning>::need_run,
+    },
+    Command {
+        name: "interrupt",
+        aliases: &[],
+        min_abbrev: 3,
+        usage: "interrupt <ms>|off",
+        summary: "continue で再開してから指定した時間が経っても停止しない場合に一時停止",
+        examples: &[
+            (
+                "interrupt 500",
+                "continue から 500 ms 経っても停止しない場合に SIGSTOP で一時停止",
+            ),
+            ("interrupt off", "一時停止を解除"),
+        ],
+        running: |mut d, cmd| {
+            d.set_interrupt(cmd);
+            Ok(State::Running(d))
+        },
+        not_running: |mut d, cmd| {
+            d.set_interrupt(cmd);
+            Ok(State::NotRunning(d))
+        },
+    },
+    Command {
+        name: "registers",
+        aliases: &["regs"],
+        min_abbrev: 3,
+        usage: "registers",
+        summary: "レジスタを表示",
+        examples: &[("registers", "レジスタを表示")],
+        running: |d, _| {
+            d.do_registers()?;
+            Ok(State::Running(d))
+        },
+        not_running: ZDbg::<NotRunning>::need_run,
+    },
+    Command {
+        name: "watchmem",
+        aliases: &[],
+        min_abbrev: 5,
+        usage: "watchmem <addr> <len>|off",
+        summary: "停止するたびにメモリを表示",
+        examples: &[
+            (
+                "watchmem 0x8000 32",
+                "0x8000 番地から 32 バイトを停止するたびに表示",
+            ),
+            ("watchmem off", "メモリの監視を解除"),
+        ],
+        running: |mut d, cmd| {
+            if d.set_watch(cmd) {
+                d.dump_watch()?;
+            }
+            Ok(State::Running(d))
+        },
+        not_running: |mut d, cmd| {
+            d.set_watch(cmd);
+            Ok(State::NotRunning(d))
+        },
+    },
+    Command {
+        name: "detach",
+        aliases: &[],
+        min_abbrev: 3,
+        usage: "detach",
+        summary: "プログラムを切り離して実行を続けさせる",
+        examples: &[(
+            "detach",
+            "ブレークポイントを元に戻し、プログラムを切り離して実行を続けさせる",
+        )],
+        running: |d, _| d.do_detach(),
+        not_running: ZDbg::<NotRunning>::need_run,
+    },
+    Command {
+        name: "exit",
+        aliases: &["q"],
+        min_abbrev: 2,
+        usage: "exit",
+        summary: "プログラムを kill して終了",
+        examples: &[(
+            "exit",
+            "ブレークポイントを元に戻し、プログラムを kill して終了",
+        )],
+        running: |d, _| d.do_exit(),
+        not_running: |_, _| Ok(State::Exit),
+    },
+    Command {
+        name: "info",
+        aliases: &[],
+        min_abbrev: 3,
+        usage: "info last-run",
+        summary: "前回の実行の終了状態を表示",
+        examples: &[("info last-run", "前回の実行の終了状態を表示")],
+        running: |d, cmd| {
+            d.do_info(cmd);
+            Ok(State::Running(d))
+        },
+        not_running: |d, cmd| {
+            d.do_info(cmd);
+            Ok(State::NotRunning(d))
+        },
+    },
+    #[cfg(feature = "trace")]
+    Command {
+        name: "debug-dump",
+        aliases: &[],
+        min_abbrev: 3,
+        usage: "debug-dump [<n>]",
+        summary: "ptrace と waitpid の呼び出しの記録を表示",
+        examples: &[
+            ("debug-dump", "記録をすべて古い順に表示"),
+            ("debug-dump 20", "最新の 20 件を表示"),
+        ],
+        running: |d, cmd| {
+            do_debug_dump(cmd);
+            Ok(State::Running(d))
+        },
+        not_running: |d, cmd| {
+            do_debug_dump(cmd);
+            Ok(State::NotRunning(d))
+        },
+    },
+    // マクロの定義は main の do_line で処理するため、ここには到達しない
+    Command {
+        name: "define",
+        aliases: &[],
+        min_abbrev: 3,
+        usage: "define [<name>]",
+        summary: "マクロを定義",
+        examples: &[
+            (
+                "define name",
+                "end までに入力したコマンドをマクロ name として定義",
+            ),
+            ("define", "定義済みのマクロを表示"),
+        ],
+        running: |d, _| Ok(State::Running(d)),
+        not_running: |d, _| Ok(State::NotRunning(d)),
+    },
+    Command {
+        name: "help",
+        aliases: &["h"],
+        min_abbrev: 3,
+        usage: "help [<command>]",
+        summary: "ヘルプを表示",
+        examples: &[
+            ("help", "コマンドの一覧を表示"),
+            ("help break", "break の使い方と使用例を表示"),
+        ],
+        running: |d, cmd| {
+            command::do_help(cmd);
+            Ok(State::Running(d))
+        },
+        not_running: |d, cmd| {
+            command::do_help(cmd);
+            Ok(State::NotRunning(d))
+        },
+    },
+];
+
+/// debug-dump を実行。 ptrace と waitpid の呼び出しの記録を古い順に表示
+#[cfg(feature = "trace")]
+fn do_debug_dump(cmd: &[&str]) {
+    let n = match cmd.get(1).map(|s| s.parse::<usize>()) {
+        None => usize::MAX,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("<<件数を指定してください\n 例 : debug-dump 20>>");
+            return;
+        }
+    };
+    let lines = crate::trace::log::dump(n);
+    if lines.is_empty() {
+        println!("<<記録はありません>>");
+    }
+    for line in lines {
+        println!("{line}");
+    }
 }
 
 /// コマンドからブレークポイントを計算
@@ -396,3 +953,54 @@ R14: {:#016x}, R15: {:#016x}"#,
         regs.r15
     );
 }
+#[cfg(test)]
+mod last_run {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let pid = Pid::from_raw(1);
+        let exited = LastRun::from_status(WaitStatus::Exited(pid, 3)).unwrap();
+        assert_eq!(exited.summary(), "exit 3");
+        assert_eq!(exited.to_string(), "終了コード 3 で終了しました");
+
+        let signaled =
+            LastRun::from_status(WaitStatus::Signaled(pid, Signal::SIGSEGV, true)).unwrap();
+        assert_eq!(signaled.summary(), "SIGSEGV core");
+        assert_eq!(
+            signaled.to_string(),
+            "シグナル SIGSEGV で終了しました (コアダンプあり)"
+        );
+        assert_eq!(
+            LastRun::Signaled(Signal::SIGKILL, false).summary(),
+            "SIGKILL"
+        );
+
+        assert_eq!(
+            LastRun::from_status(WaitStatus::Stopped(pid, Signal::SIGTRAP)),
+            None
+        );
+    }
+}
+#[cfg(test)]
+mod stop_kind {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let pid = Pid::from_raw(1);
+        assert_eq!(
+            StopKind::from_status(WaitStatus::Stopped(pid, Signal::SIGTRAP)),
+            Some(StopKind::Trap)
+        );
+        assert_eq!(
+            StopKind::from_status(WaitStatus::Stopped(pid, Signal::SIGSTOP)),
+            Some(StopKind::Interrupted)
+        );
+        assert_eq!(
+            StopKind::from_status(WaitStatus::Stopped(pid, Signal::SIGSEGV)),
+            Some(StopKind::Signal(Signal::SIGSEGV))
+        );
+        assert_eq!(StopKind::from_status(WaitStatus::Exited(pid, 0)), None);
+    }
+}
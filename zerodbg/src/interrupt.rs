@@ -0,0 +1,71 @@
+//! 実行中の子プロセスを一時停止するタイマー
+//!
+//! continue で再開した子プロセスが指定した時間内に停止しない場合に、別スレッドから
+//! kill で SIGSTOP を送る。子プロセスが先に停止した場合は [Interrupter::cancel] で取り消す。
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// 一定時間後に SIGSTOP を送るタイマー
+pub struct Interrupter {
+    tx: Sender<()>,
+    handle: JoinHandle<bool>, // SIGSTOP を送った場合は true
+}
+
+impl Interrupter {
+    /// after が経過した時点で pid に SIGSTOP を送るタイマーを開始
+    pub fn start(pid: Pid, after: Duration) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || match rx.recv_timeout(after) {
+            Err(RecvTimeoutError::Timeout) => kill(pid, Signal::SIGSTOP).is_ok(),
+            _ => false,
+        });
+        Self { tx, handle }
+    }
+
+    /// タイマーを取り消す。既に SIGSTOP を送っていた場合は true を返す
+    pub fn cancel(self) -> bool {
+        let _ = self.tx.send(());
+        self.handle.join().unwrap_or(false)
+    }
+}
+#[cfg(test)]
+mod interrupter {
+    use super::*;
+    use nix::{
+        libc,
+        sys::wait::{waitpid, WaitPidFlag, WaitStatus},
+        unistd::{fork, ForkResult},
+    };
+
+    #[test]
+    fn test() {
+        let child = match unsafe { fork() }.unwrap() {
+            ForkResult::Child => loop {
+                unsafe { libc::pause() };
+            },
+            ForkResult::Parent { child } => child,
+        };
+
+        // 時間が経過すると SIGSTOP で停止する
+        let timer = Interrupter::start(child, Duration::from_millis(10));
+        assert_eq!(
+            waitpid(child, Some(WaitPidFlag::WUNTRACED)),
+            Ok(WaitStatus::Stopped(child, Signal::SIGSTOP))
+        );
+        assert!(timer.cancel());
+
+        // 取り消した場合は送らない
+        let timer = Interrupter::start(child, Duration::from_secs(60));
+        assert!(!timer.cancel());
+
+        kill(child, Signal::SIGKILL).unwrap();
+        waitpid(child, None).unwrap();
+    }
+}
@@ -0,0 +1,105 @@
+//! define コマンドで定義するユーザ定義マクロ
+//!
+//! ```text
+//! zdbg > define show
+//! > regs
+//! > stepi
+//! > end
+//! zdbg > show
+//! ```
+//!
+//! のように定義すると、 show と入力するだけで regs と stepi を順に実行する。
+//! マクロの中から別のマクロを呼び出すこともできるが、
+//! 無限に再帰しないよう呼び出しの深さは MAX_DEPTH までに制限する。
+use crate::command;
+use std::collections::BTreeMap;
+
+/// マクロ呼び出しの深さの上限
+pub const MAX_DEPTH: usize = 16;
+
+/// 定義済みのマクロと、定義中のマクロ
+#[derive(Debug, Default)]
+pub struct Macros {
+    defs: BTreeMap<String, Vec<String>>,
+    recording: Option<(String, Vec<String>)>, // 定義中のマクロ名と本体
+}
+
+impl Macros {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// マクロを定義中の場合は true
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// define コマンドを実行
+    /// 名前が指定されていない場合は定義済みのマクロを一覧表示
+    pub fn define(&mut self, cmd: &[&str]) -> Result<(), String> {
+        match cmd {
+            [_] => {
+                for (name, body) in &self.defs {
+                    println!("{name} : {}", body.join("; "));
+                }
+                Ok(())
+            }
+            [_, name] if command::is_reserved(name) => {
+                Err(format!("{name} は組み込みコマンドのため定義できません"))
+            }
+            [_, name] => {
+                self.recording = Some((name.to_string(), Vec::new()));
+                Ok(())
+            }
+            _ => Err("マクロ名を 1 つ指定してください\n 例 : define mymacro".to_string()),
+        }
+    }
+
+    /// 定義中のマクロに 1 行追加
+    /// end が入力された場合は定義を終了し、マクロ名を返す
+    pub fn record(&mut self, line: &str) -> Option<String> {
+        let line = line.trim();
+        if line == "end" {
+            let (name, body) = self.recording.take()?;
+            self.defs.insert(name.clone(), body);
+            return Some(name);
+        }
+
+        if let Some((_, body)) = self.recording.as_mut() {
+            if !line.is_empty() {
+                body.push(line.to_string());
+            }
+        }
+        None
+    }
+
+    /// マクロの本体を取得
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.defs.get(name).map(|body| body.as_slice())
+    }
+}
+#[cfg(test)]
+mod record {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let mut m = Macros::new();
+        assert!(m.define(&["define", "show"]).is_ok());
+        assert!(m.is_recording());
+        assert_eq!(m.record("regs"), None);
+        assert_eq!(m.record("  "), None);
+        assert_eq!(m.record(" stepi "), None);
+        assert_eq!(m.record("end"), Some("show".to_string()));
+        assert!(!m.is_recording());
+        assert_eq!(
+            m.get("show"),
+            Some(&["regs".to_string(), "stepi".to_string()][..])
+        );
+        assert_eq!(m.get("other"), None);
+
+        assert!(m.define(&["define", "regs"]).is_err());
+        assert!(m.define(&["define", "a", "b"]).is_err());
+        assert!(!m.is_recording());
+    }
+}
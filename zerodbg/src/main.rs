@@ -1,13 +1,30 @@
+mod breakpoint;
+mod command;
 mod dbg;
 mod helper;
+mod interrupt;
+mod macros;
+mod reaper;
+mod symbol;
+mod trace;
+mod watch;
 
+use command::CmdHelper;
 use dbg::{State, ZDbg};
 use helper::DynError;
-use rustyline::{error::ReadlineError, DefaultEditor};
+use macros::{Macros, MAX_DEPTH};
+use rustyline::{error::ReadlineError, history::DefaultHistory, Editor};
 use std::env;
 
+/// 起動時のオプション。 (オプション, 説明)
+const OPTIONS: &[(&str, &str)] = &[("-h, --help", "このヘルプを表示して終了")];
+
 fn main() -> Result<(), DynError> {
     let args: Vec<String> = env::args().collect();
+    if matches!(args.get(1).map(|s| s.as_str()), Some("-h" | "--help")) {
+        print!("{}", usage(&args[0]));
+        return Ok(());
+    }
     if args.len() < 2 {
         let msg = format!("引数が必要です\n 例 : {} 実行ファイル [引数*]", args[0]);
         return Err(msg.into());
@@ -16,23 +33,48 @@ fn main() -> Result<(), DynError> {
     run_dbg(&args[1])?;
     Ok(())
 }
+
+/// --help で表示する文字列を生成
+/// 起動後に使えるコマンドの一覧も合わせて表示する
+fn usage(prog: &str) -> String {
+    let mut s = format!("使い方 : {prog} [オプション] 実行ファイル\n\nオプション :\n");
+    for (opt, desc) in OPTIONS {
+        s.push_str(&format!("  {opt:<12} : {desc}\n"));
+    }
+    s.push('\n');
+    s.push_str(&command::help_text());
+    s
+}
+
 fn run_dbg(filename: &str) -> Result<(), DynError> {
+    // パニックやエラーで終了した場合も子プロセスを残さない
+    reaper::install_panic_hook();
+    let _guard = reaper::Guard;
+
     let debugger = ZDbg::new(filename.to_string());
     let mut state = State::NotRunning(debugger);
-    let mut rl = DefaultEditor::new()?;
+    let mut macros = Macros::new();
+    let mut rl = Editor::<CmdHelper, DefaultHistory>::new()?;
+    rl.set_helper(Some(CmdHelper));
 
     loop {
-        match rl.readline("zdbg > ") {
+        // マクロの定義中はプロンプトを変える
+        let prompt = if macros.is_recording() {
+            "> ".to_string()
+        } else {
+            state.prompt()
+        };
+        match rl.readline(&prompt) {
             Ok(line) => {
-                let trimed = line.trim(); // 行頭と行末の空白文字を削除
-                let cmd: Vec<&str> = trimed.split(' ').filter(|c| !c.is_empty()).collect(); // 空白文字を削除
-                state = match state {
-                    State::Running(r) => r.do_cmd(&cmd)?,
-                    State::NotRunning(r) => r.do_cmd(&cmd)?,
-                    _ => break,
-                };
-                if let State::Exit = state {
-                    break;
+                if macros.is_recording() {
+                    if let Some(name) = macros.record(&line) {
+                        println!("<<マクロ {name} を定義しました>>");
+                    }
+                } else {
+                    (state, _) = do_line(state, &line, &mut macros, 0)?;
+                    if let State::Exit = state {
+                        break;
+                    }
                 }
                 rl.add_history_entry(line)?;
             }
@@ -49,3 +91,52 @@ fn run_dbg(filename: &str) -> Result<(), DynError> {
 
     Ok(())
 }
+
+/// 1 行分のコマンドを実行
+/// マクロが呼び出された場合は本体の各行を順に実行する
+/// depth はマクロ呼び出しの深さで、深すぎる場合やデバッガが終了した場合は
+/// 残りのマクロを実行しないように false を返す
+fn do_line(
+    state: State,
+    line: &str,
+    macros: &mut Macros,
+    depth: usize,
+) -> Result<(State, bool), DynError> {
+    let trimed = line.trim(); // 行頭と行末の空白文字を削除
+    let cmd: Vec<&str> = trimed.split(' ').filter(|c| !c.is_empty()).collect(); // 空白文字を削除
+
+    match cmd.first() {
+        Some(name) if matches!(command::resolve(name), Ok(c) if c.name == "define") => {
+            if let Err(e) = macros.define(&cmd) {
+                eprintln!("<<{e}>>");
+            }
+            return Ok((state, true));
+        }
+        Some(name) if macros.get(name).is_some() => {
+            if depth >= MAX_DEPTH {
+                eprintln!("<<マクロの呼び出しが深すぎます : {name}>>");
+                return Ok((state, false));
+            }
+
+            let body = macros.get(name).unwrap().to_vec();
+            let mut state = state;
+            for line in body {
+                let cont;
+                (state, cont) = do_line(state, &line, macros, depth + 1)?;
+                if !cont {
+                    return Ok((state, false));
+                }
+            }
+            return Ok((state, true));
+        }
+        _ => (),
+    }
+
+    let state = match state {
+        State::Running(r) => r.do_cmd(&cmd)?,
+        State::NotRunning(r) => r.do_cmd(&cmd)?,
+        State::Exit => State::Exit,
+    };
+    let cont = !matches!(state, State::Exit);
+    Ok((state, cont))
+}
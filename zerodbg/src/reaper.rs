@@ -0,0 +1,92 @@
+//! デバッガが異常終了した場合に子プロセスを残さないための後始末
+//!
+//! 実行中の子プロセスの PID を記録しておき、パニックした場合はパニックフックで、
+//! エラーで main から抜けた場合は [Guard] の drop で kill して回収する。
+//! デバッガが SIGKILL などで後始末できずに終了した場合は、 PTRACE_O_EXITKILL によりカーネルが kill する。
+use nix::{
+    errno::Errno,
+    sys::{
+        signal::{kill, Signal},
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::Pid,
+};
+use std::{
+    panic,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+/// 実行中の子プロセスの PID。実行していない場合は 0
+static TRACEE: AtomicI32 = AtomicI32::new(0);
+
+/// 子プロセスの実行を開始した場合に呼び出す
+pub fn set(pid: Pid) {
+    TRACEE.store(pid.as_raw(), Ordering::SeqCst);
+}
+
+/// 子プロセスが終了した場合やデタッチした場合に呼び出す
+pub fn clear() {
+    TRACEE.store(0, Ordering::SeqCst);
+}
+
+/// 実行中の子プロセスがあれば kill して回収する
+pub fn kill_tracee() {
+    let pid = TRACEE.swap(0, Ordering::SeqCst);
+    if pid <= 0 {
+        return;
+    }
+    let pid = Pid::from_raw(pid);
+    let _ = kill(pid, Signal::SIGKILL);
+    loop {
+        match waitpid(pid, None) {
+            Ok(WaitStatus::Exited(..) | WaitStatus::Signaled(..)) | Err(Errno::ECHILD) => return,
+            Ok(_) | Err(Errno::EINTR) => (),
+            Err(_) => return,
+        }
+    }
+}
+
+/// パニックした場合に子プロセスを kill するフックを設定する。元のフックも呼び出す
+pub fn install_panic_hook() {
+    let prev = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        kill_tracee();
+        prev(info);
+    }));
+}
+
+/// drop された場合に子プロセスを kill する
+pub struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        kill_tracee();
+    }
+}
+#[cfg(test)]
+mod kill_tracee {
+    use super::*;
+    use nix::{
+        libc,
+        unistd::{fork, ForkResult},
+    };
+
+    #[test]
+    fn test() {
+        let child = match unsafe { fork() }.unwrap() {
+            ForkResult::Child => loop {
+                unsafe { libc::pause() };
+            },
+            ForkResult::Parent { child } => child,
+        };
+
+        set(child);
+        drop(Guard);
+        // 回収済みのため、子プロセスはもう存在しない
+        assert_eq!(TRACEE.load(Ordering::SeqCst), 0);
+        assert_eq!(waitpid(child, None), Err(Errno::ECHILD));
+
+        // 実行していない場合は何もしない
+        kill_tracee();
+    }
+}
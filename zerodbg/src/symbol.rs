@@ -0,0 +1,249 @@
+//! ELF のシンボルテーブルの読み込みと、アドレスのシンボル相対表示
+//!
+//! 実行ファイルの .symtab セクションから関数と変数のシンボルを読み込み、
+//! アドレスを `0x4011ab <main+0x2b>` の形式で表示する。
+//! PIE の場合は /proc/PID/maps から実行ファイルがロードされたアドレスを求めて加算する。
+use crate::helper::DynError;
+use nix::unistd::Pid;
+use std::fs;
+
+const SHT_SYMTAB: u32 = 2; // シンボルテーブルのセクションタイプ
+const ET_DYN: u16 = 3; // PIE などの共有オブジェクトのファイルタイプ
+const STT_OBJECT: u8 = 1; // 変数のシンボルタイプ
+const STT_FUNC: u8 = 2; // 関数のシンボルタイプ
+
+/// シンボル
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Symbol {
+    addr: usize, // ロード後のアドレス
+    size: usize, // サイズ。 0 の場合は不明
+    name: String,
+}
+
+/// アドレス順に並べたシンボルの一覧
+#[derive(Debug, Default)]
+pub struct Symbols {
+    syms: Vec<Symbol>,
+}
+
+impl Symbols {
+    /// 停止中のプロセス pid の実行ファイルからシンボルを読み込む
+    pub fn load(pid: Pid) -> Result<Self, DynError> {
+        let exe = fs::read_link(format!("/proc/{pid}/exe"))?;
+        let elf = fs::read(&exe)?;
+
+        let base = if read_u16(&elf, 16)? == ET_DYN {
+            let maps = fs::read_to_string(format!("/proc/{pid}/maps"))?;
+            load_base(&maps, &exe.to_string_lossy())
+                .ok_or("実行ファイルのロードアドレスが見つかりません")?
+        } else {
+            0
+        };
+
+        Self::parse(&elf, base)
+    }
+
+    /// ELF64 (リトルエンディアン) のバイト列からシンボルを読み込む
+    /// base はロードアドレスで、各シンボルのアドレスに加算する
+    fn parse(elf: &[u8], base: usize) -> Result<Self, DynError> {
+        if elf.get(0..4) != Some(b"\x7fELF") || elf.get(4..6) != Some(&[2, 1]) {
+            return Err("64 ビットのリトルエンディアンの ELF ではありません".into());
+        }
+
+        let shoff = read_u64(elf, 0x28)? as usize;
+        let shentsize = read_u16(elf, 0x3a)? as usize;
+        let shnum = read_u16(elf, 0x3c)? as usize;
+        let section = |i: usize| shoff + i * shentsize;
+
+        let mut syms = Vec::new();
+        for i in 0..shnum {
+            let sh = section(i);
+            if read_u32(elf, sh + 4)? != SHT_SYMTAB {
+                continue;
+            }
+            let offset = read_u64(elf, sh + 0x18)? as usize;
+            let size = read_u64(elf, sh + 0x20)? as usize;
+            let entsize = (read_u64(elf, sh + 0x38)? as usize).max(1);
+
+            // シンボル名は sh_link が指す文字列テーブルにある
+            let strtab = section(read_u32(elf, sh + 0x28)? as usize);
+            let stroff = read_u64(elf, strtab + 0x18)? as usize;
+
+            for sym in (offset..offset + size).step_by(entsize) {
+                let info = *elf.get(sym + 4).ok_or("シンボルテーブルが不正です")?;
+                let value = read_u64(elf, sym + 8)? as usize;
+                if !matches!(info & 0xf, STT_FUNC | STT_OBJECT) || value == 0 {
+                    continue;
+                }
+                let name = read_str(elf, stroff + read_u32(elf, sym)? as usize)?;
+                syms.push(Symbol {
+                    addr: base + value,
+                    size: read_u64(elf, sym + 16)? as usize,
+                    name: demangle(name),
+                });
+            }
+        }
+
+        syms.sort_by_key(|s| s.addr);
+        Ok(Self { syms })
+    }
+
+    /// addr を含むシンボルの名前と、シンボルの先頭からのオフセットを返す
+    fn lookup(&self, addr: usize) -> Option<(&str, usize)> {
+        let i = self
+            .syms
+            .partition_point(|s| s.addr <= addr)
+            .checked_sub(1)?;
+        let sym = &self.syms[i];
+        let offset = addr - sym.addr;
+        if sym.size == 0 || offset < sym.size {
+            Some((&sym.name, offset))
+        } else {
+            None
+        }
+    }
+}
+
+/// アドレスを表示用の文字列に変換
+/// シンボルが読み込まれていて、アドレスを含むシンボルがある場合は `0x4011ab <main+0x2b>` となる
+pub fn format_addr(addr: usize, symbols: Option<&Symbols>) -> String {
+    match symbols.and_then(|s| s.lookup(addr)) {
+        Some((name, 0)) => format!("{addr:#x} <{name}>"),
+        Some((name, offset)) => format!("{addr:#x} <{name}+{offset:#x}>"),
+        None => format!("{addr:#x}"),
+    }
+}
+
+/// /proc/PID/maps の内容から、 path がファイルオフセット 0 でマップされたアドレスを返す
+fn load_base(maps: &str, path: &str) -> Option<usize> {
+    maps.lines().find_map(|line| {
+        // 書式 : 開始-終了 権限 オフセット デバイス inode パス
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        match fields.as_slice() {
+            [range, _, "00000000", _, _, p] if *p == path => {
+                usize::from_str_radix(range.split('-').next()?, 16).ok()
+            }
+            _ => None,
+        }
+    })
+}
+
+/// Rust の旧形式のマングリング (_ZN...E) を元に戻す
+/// 末尾のハッシュ値は除去し、それ以外の名前はそのまま返す
+fn demangle(name: &str) -> String {
+    let Some(mut rest) = name.strip_prefix("_ZN") else {
+        return name.to_string();
+    };
+
+    let mut parts = Vec::new();
+    while let Some(len_end) = rest.find(|c: char| !c.is_ascii_digit()) {
+        let Ok(len) = rest[..len_end].parse::<usize>() else {
+            break;
+        };
+        let Some(part) = rest.get(len_end..len_end + len) else {
+            return name.to_string();
+        };
+        parts.push(part);
+        rest = &rest[len_end + len..];
+    }
+    if rest != "E" || parts.is_empty() {
+        return name.to_string();
+    }
+
+    // h に続く 16 桁の 16 進数はハッシュ値
+    if let Some(last) = parts.last() {
+        if last.len() == 17
+            && last.starts_with('h')
+            && last[1..].chars().all(|c| c.is_ascii_hexdigit())
+        {
+            parts.pop();
+        }
+    }
+    parts.join("::").replace("..", "::")
+}
+
+fn read_bytes<const N: usize>(elf: &[u8], at: usize) -> Result<[u8; N], DynError> {
+    elf.get(at..at + N)
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| "ELF ファイルが不正です".into())
+}
+
+fn read_u16(elf: &[u8], at: usize) -> Result<u16, DynError> {
+    Ok(u16::from_le_bytes(read_bytes(elf, at)?))
+}
+
+fn read_u32(elf: &[u8], at: usize) -> Result<u32, DynError> {
+    Ok(u32::from_le_bytes(read_bytes(elf, at)?))
+}
+
+fn read_u64(elf: &[u8], at: usize) -> Result<u64, DynError> {
+    Ok(u64::from_le_bytes(read_bytes(elf, at)?))
+}
+
+/// at から始まる NUL 終端の文字列を読み込む
+fn read_str(elf: &[u8], at: usize) -> Result<&str, DynError> {
+    let bytes = elf.get(at..).ok_or("ELF ファイルが不正です")?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Ok(std::str::from_utf8(&bytes[..end])?)
+}
+#[cfg(test)]
+mod format_addr {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let symbols = Symbols {
+            syms: vec![
+                Symbol {
+                    addr: 0x401180,
+                    size: 0x40,
+                    name: "main".to_string(),
+                },
+                Symbol {
+                    addr: 0x401200,
+                    size: 0,
+                    name: "_start".to_string(),
+                },
+            ],
+        };
+        assert_eq!(
+            format_addr(0x4011ab, Some(&symbols)),
+            "0x4011ab <main+0x2b>"
+        );
+        assert_eq!(format_addr(0x401180, Some(&symbols)), "0x401180 <main>");
+        assert_eq!(format_addr(0x4011c0, Some(&symbols)), "0x4011c0");
+        assert_eq!(format_addr(0x401000, Some(&symbols)), "0x401000");
+        assert_eq!(
+            format_addr(0x401208, Some(&symbols)),
+            "0x401208 <_start+0x8>"
+        );
+        assert_eq!(format_addr(0x4011ab, None), "0x4011ab");
+
+        assert_eq!(
+            demangle("_ZN10dbg_target4main17hd8490fb2dae43305E"),
+            "dbg_target::main"
+        );
+        assert_eq!(demangle("main"), "main");
+
+        let maps = "555555554000-555555569000 r--p 00000000 08:01 42 /bin/t\n\
+                    555555569000-5555555d4000 r-xp 00015000 08:01 42 /bin/t\n";
+        assert_eq!(load_base(maps, "/bin/t"), Some(0x555555554000));
+        assert_eq!(load_base(maps, "/bin/u"), None);
+    }
+}
+
+#[cfg(test)]
+mod load {
+    use super::*;
+    use nix::unistd::getpid;
+
+    #[test]
+    fn test() {
+        // テスト自身の実行ファイルからシンボルを読み込み、関数のアドレスを引く
+        let symbols = Symbols::load(getpid()).unwrap();
+        let addr = test as fn() as usize;
+        let (name, offset) = symbols.lookup(addr).unwrap();
+        assert!(name.ends_with("load::test"), "{name}");
+        assert_eq!(offset, 0);
+    }
+}
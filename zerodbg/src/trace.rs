@@ -0,0 +1,255 @@
+//! ptrace の呼び出しの記録
+//!
+//! デバッガ本体は nix の ptrace と waitpid の代わりに、同じシグネチャを持つこのモジュールの関数を呼び出す。
+//! `trace` フィーチャを有効にした場合は、呼び出しごとに引数と結果をリングバッファに記録し、
+//! `debug-dump` コマンドで時系列に表示する。無効の場合は nix の関数をそのまま呼び出す。
+//!
+//! ```text
+//! cargo run -p zerodbg --features trace -- 実行ファイル
+//! ```
+#[cfg(feature = "trace")]
+use nix::libc::user_regs_struct;
+use nix::{
+    sys::wait::{self, WaitPidFlag, WaitStatus},
+    unistd::Pid,
+};
+
+/// 記録する際の結果の表示
+#[cfg(feature = "trace")]
+trait Summary {
+    fn summary(&self) -> String;
+}
+
+#[cfg(feature = "trace")]
+impl Summary for () {
+    fn summary(&self) -> String {
+        "()".to_string()
+    }
+}
+
+#[cfg(feature = "trace")]
+impl Summary for i64 {
+    fn summary(&self) -> String {
+        format!("{self:#018x}")
+    }
+}
+
+#[cfg(feature = "trace")]
+impl Summary for user_regs_struct {
+    fn summary(&self) -> String {
+        format!("rip = {:#x}, rsp = {:#x}", self.rip, self.rsp)
+    }
+}
+
+#[cfg(feature = "trace")]
+impl Summary for WaitStatus {
+    fn summary(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+/// f を呼び出し、 call とその結果を記録する。 call は記録する場合のみ評価する
+#[cfg(feature = "trace")]
+fn traced<T: Summary>(
+    call: impl FnOnce() -> String,
+    f: impl FnOnce() -> nix::Result<T>,
+) -> nix::Result<T> {
+    let ret = f();
+    let result = match &ret {
+        Ok(val) => val.summary(),
+        Err(e) => format!("Err({e})"),
+    };
+    log::record(call(), result);
+    ret
+}
+
+#[cfg(not(feature = "trace"))]
+fn traced<T>(_call: impl FnOnce() -> String, f: impl FnOnce() -> nix::Result<T>) -> nix::Result<T> {
+    f()
+}
+
+/// waitpid を呼び出して記録
+pub fn waitpid(pid: Pid, options: Option<WaitPidFlag>) -> nix::Result<WaitStatus> {
+    traced(
+        || format!("waitpid({pid}, {options:?})"),
+        || wait::waitpid(pid, options),
+    )
+}
+
+/// nix::sys::ptrace と同じ名前の、記録する版の関数
+pub mod ptrace {
+    use super::traced;
+    use nix::{
+        libc::user_regs_struct,
+        sys::{ptrace, signal::Signal},
+        unistd::Pid,
+    };
+    use std::ffi::c_void;
+
+    pub use nix::sys::ptrace::Options;
+
+    pub fn setoptions(pid: Pid, options: Options) -> nix::Result<()> {
+        traced(
+            || format!("setoptions({pid}, {options:?})"),
+            || ptrace::setoptions(pid, options),
+        )
+    }
+
+    pub fn getregs(pid: Pid) -> nix::Result<user_regs_struct> {
+        traced(|| format!("getregs({pid})"), || ptrace::getregs(pid))
+    }
+
+    pub fn setregs(pid: Pid, regs: user_regs_struct) -> nix::Result<()> {
+        traced(
+            || format!("setregs({pid}, rip = {:#x})", regs.rip),
+            || ptrace::setregs(pid, regs),
+        )
+    }
+
+    pub fn read(pid: Pid, addr: *mut c_void) -> nix::Result<i64> {
+        traced(
+            || format!("read({pid}, {addr:?})"),
+            || ptrace::read(pid, addr),
+        )
+    }
+
+    pub fn write(pid: Pid, addr: *mut c_void, data: i64) -> nix::Result<()> {
+        traced(
+            || format!("write({pid}, {addr:?}, {data:#018x})"),
+            || ptrace::write(pid, addr, data),
+        )
+    }
+
+    pub fn step(pid: Pid, sig: Option<Signal>) -> nix::Result<()> {
+        traced(
+            || format!("step({pid}, {sig:?})"),
+            || ptrace::step(pid, sig),
+        )
+    }
+
+    pub fn cont(pid: Pid, sig: Option<Signal>) -> nix::Result<()> {
+        traced(
+            || format!("cont({pid}, {sig:?})"),
+            || ptrace::cont(pid, sig),
+        )
+    }
+
+    pub fn detach(pid: Pid, sig: Option<Signal>) -> nix::Result<()> {
+        traced(
+            || format!("detach({pid}, {sig:?})"),
+            || ptrace::detach(pid, sig),
+        )
+    }
+
+    pub fn kill(pid: Pid) -> nix::Result<()> {
+        traced(|| format!("kill({pid})"), || ptrace::kill(pid))
+    }
+}
+
+/// 記録のリングバッファ
+#[cfg(feature = "trace")]
+pub mod log {
+    use std::{
+        collections::VecDeque,
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    /// 保持する記録の数。超えた場合は古いものから捨てる
+    const CAPACITY: usize = 1024;
+
+    /// 1 回の呼び出しの記録
+    #[derive(Debug, Clone)]
+    struct Entry {
+        seq: usize,        // 通し番号。捨てた記録も数える
+        elapsed: Duration, // 最初の記録からの経過時間
+        call: String,      // 関数名と引数
+        result: String,    // 結果
+    }
+
+    /// 記録の一覧
+    #[derive(Debug)]
+    struct Ring {
+        entries: VecDeque<Entry>,
+        next: usize,            // 次の通し番号
+        start: Option<Instant>, // 最初の記録の時刻
+        capacity: usize,
+    }
+
+    impl Ring {
+        const fn new(capacity: usize) -> Self {
+            Self {
+                entries: VecDeque::new(),
+                next: 0,
+                start: None,
+                capacity,
+            }
+        }
+
+        fn push(&mut self, call: String, result: String) {
+            let start = *self.start.get_or_insert_with(Instant::now);
+            if self.entries.len() == self.capacity {
+                self.entries.pop_front();
+            }
+            self.entries.push_back(Entry {
+                seq: self.next,
+                elapsed: start.elapsed(),
+                call,
+                result,
+            });
+            self.next += 1;
+        }
+
+        /// 最新の n 件を古い順に 1 行ずつ表す
+        fn dump(&self, n: usize) -> Vec<String> {
+            let skip = self.entries.len().saturating_sub(n);
+            self.entries
+                .iter()
+                .skip(skip)
+                .map(|e| {
+                    format!(
+                        "#{:<5} {:>10.3}ms  {} -> {}",
+                        e.seq,
+                        e.elapsed.as_secs_f64() * 1000.0,
+                        e.call,
+                        e.result
+                    )
+                })
+                .collect()
+        }
+    }
+    static LOG: Mutex<Ring> = Mutex::new(Ring::new(CAPACITY));
+
+    /// 呼び出しを記録する
+    pub(super) fn record(call: String, result: String) {
+        LOG.lock().unwrap().push(call, result);
+    }
+
+    /// 最新の n 件の記録を古い順に返す
+    pub fn dump(n: usize) -> Vec<String> {
+        LOG.lock().unwrap().dump(n)
+    }
+    #[cfg(test)]
+    mod ring {
+        use super::*;
+
+        #[test]
+        fn test() {
+            let mut ring = Ring::new(2);
+            assert!(ring.dump(10).is_empty());
+
+            ring.push("getregs(1)".to_string(), "rip = 0x10".to_string());
+            ring.push("step(1, None)".to_string(), "()".to_string());
+            ring.push("waitpid(1, None)".to_string(), "Exited(1, 0)".to_string());
+            let lines = ring.dump(10);
+            assert_eq!(lines.len(), 2);
+            assert!(lines[0].starts_with("#1 "));
+            assert!(lines[0].ends_with("step(1, None) -> ()"));
+            assert!(lines[1].ends_with("waitpid(1, None) -> Exited(1, 0)"));
+
+            let lines = ring.dump(1);
+            assert_eq!(lines.len(), 1);
+            assert!(lines[0].starts_with("#2 "));
+        }
+    }
+}
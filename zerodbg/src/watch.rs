@@ -0,0 +1,60 @@
+//! watchmem コマンドによるメモリの監視
+//!
+//! 子プロセスが停止するたびに指定範囲のメモリを読み出し、
+//! 前回停止時の内容と比べて変化したバイトを色付きで表示する。
+
+/// 変化したバイトの強調表示に用いるエスケープシーケンス (太字の赤)
+const COLOR_CHANGED: &str = "\x1b[1;31m";
+/// 強調表示を元に戻すエスケープシーケンス
+const COLOR_RESET: &str = "\x1b[0m";
+
+/// 1 行に表示するバイト数
+const BYTES_PER_LINE: usize = 16;
+
+/// 監視するメモリ範囲と、前回停止時の内容
+pub struct WatchMem {
+    pub addr: usize,
+    pub len: usize,
+    pub prev: Option<Vec<u8>>,
+}
+
+/// addr 番地から始まるメモリの内容 cur を 16 進ダンプした文字列を返す
+/// prev に前回の内容を指定すると、値が変化したバイトを色付きで表示する
+pub fn hexdump(addr: usize, cur: &[u8], prev: Option<&[u8]>) -> String {
+    let mut out = String::new();
+    for (i, line) in cur.chunks(BYTES_PER_LINE).enumerate() {
+        let offset = i * BYTES_PER_LINE;
+        out.push_str(&format!("{:016x}:", addr + offset));
+        for (j, b) in line.iter().enumerate() {
+            let changed = prev.and_then(|p| p.get(offset + j)).is_some_and(|p| p != b);
+            if changed {
+                out.push_str(&format!(" {COLOR_CHANGED}{b:02x}{COLOR_RESET}"));
+            } else {
+                out.push_str(&format!(" {b:02x}"));
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+#[cfg(test)]
+mod hexdump {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let cur = (0..18).collect::<Vec<u8>>();
+        assert_eq!(
+            hexdump(0x1000, &cur, None),
+            "0000000000001000: 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f\n\
+             0000000000001010: 10 11\n"
+        );
+
+        let prev = [0xff, 0x01];
+        assert_eq!(
+            hexdump(0x8, &cur[..3], Some(&prev)),
+            "0000000000000008: \x1b[1;31m00\x1b[0m 01 02\n"
+        );
+        assert_eq!(hexdump(0x8, &[], None), "");
+    }
+}
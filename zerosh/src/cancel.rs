@@ -0,0 +1,97 @@
+//! 組み込みコマンドの協調的な中断
+//!
+//! 組み込みコマンドは worker スレッドで実行するため、組み込みコマンドがブロックしている間は
+//! self-pipe に書き込まれたシグナルが処理されない。そこでシグナルハンドラは
+//! SIGINT を受信すると [CancelToken] を中断状態にし、ブロックする組み込みコマンドは
+//! 待機中に定期的に中断状態を確認して、中断された場合は [Cancelled] を返して処理を打ち切る。
+//!
+//! worker はジョブを実行するたびに中断状態を確認し、中断されていれば同じ行の残りのジョブを実行しない。
+//! 中断状態は入力された行の実行を開始する直前と中断を処理した時点で解除するため、
+//! それ以前に受信した SIGINT で中断されることはない。
+use nix::libc;
+use std::{
+    os::fd::RawFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// 中断状態を確認する間隔 (ミリ秒)
+const POLL_INTERVAL_MS: i32 = 100;
+
+/// 組み込みコマンドが中断されたことを表す
+#[derive(Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+/// シグナルハンドラと worker スレッドで共有する中断状態
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 中断状態にする
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// 中断状態を解除する
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    /// 中断状態であれば Err を返す
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.0.load(Ordering::SeqCst) {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// fd が読み込み可能になるまで待つ
+    /// 待機中に中断状態になった場合は Err を返す
+    pub fn wait_readable(&self, fd: RawFd) -> Result<(), Cancelled> {
+        loop {
+            self.check()?;
+            let mut pfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // EINTR などで失敗した場合も中断状態を確認してから待ち直す
+            if unsafe { libc::poll(&mut pfd, 1, POLL_INTERVAL_MS) } > 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod wait_readable {
+    use super::*;
+    use nix::unistd::{pipe, write};
+    use std::{os::fd::AsRawFd, thread, time::Duration};
+
+    #[test]
+    fn test() {
+        let (r, w) = pipe().unwrap();
+        let token = CancelToken::new();
+
+        // 別スレッドから中断する
+        let t = token.clone();
+        let h = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            t.cancel();
+        });
+        assert_eq!(token.wait_readable(r.as_raw_fd()), Err(Cancelled));
+        h.join().unwrap();
+
+        // 解除すると読み込み可能になるまで待つ
+        token.reset();
+        write(&w, b"y").unwrap();
+        assert_eq!(token.wait_readable(r.as_raw_fd()), Ok(()));
+    }
+}
@@ -0,0 +1,306 @@
+//! Tab キーによる補完
+//!
+//! 補完する単語がコマンド名の位置にある場合は、組み込みコマンド, 定義済みの関数,
+//! PATH にある実行可能ファイルの名前を補完する。それ以外の位置の単語と、 `/` を含む単語は
+//! ファイルのパスとして補完する。
+//!
+//! 関数は worker スレッドで定義されるため、 worker スレッドが定義した関数名を
+//! 共有する補完の状態に登録し、 main スレッドの行エディタのヘルパから参照する。
+use crate::search::SearchHelper;
+use rustyline::{
+    completion::{Completer, Pair},
+    highlight::Highlighter,
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// 組み込みコマンドの名前
+pub const BUILTINS: &[&str] = &[
+    "bg", "bindkey", "cd", "echo", "exec", "exit", "export", "false", "fg", "jobs", "local", "pwd",
+    "read", "set", "source", "times", "trap", "true", "type", "umask", "wait",
+];
+
+/// 直後の単語がコマンド名となる予約語
+const COMMAND_WORDS: &[&str] = &["if", "then", "elif", "else", "do", "while", "{", "time"];
+
+/// 単語を区切る制御記号
+const OPERATORS: &str = "&|()<>;";
+
+/// 補完の状態
+#[derive(Debug, Default)]
+pub struct CompletionState {
+    functions: BTreeSet<String>, // 定義済みの関数名
+}
+
+impl CompletionState {
+    /// 関数 name が定義されたことを登録する
+    pub fn add_function(&mut self, name: &str) {
+        self.functions.insert(name.to_string());
+    }
+}
+
+/// main スレッドと worker スレッドで共有する補完の状態
+pub type SharedCompletion = Arc<Mutex<CompletionState>>;
+
+/// rustyline のヘルパ。 Tab キーで補完し、ヒストリ検索中はマッチした行をヒントとして表示する
+pub struct ShellHelper {
+    search: SearchHelper,
+    completion: SharedCompletion,
+}
+
+impl ShellHelper {
+    pub fn new(search: SearchHelper, completion: SharedCompletion) -> Self {
+        Self { search, completion }
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, word) = current_word(line, pos);
+        let candidates = if is_command_position(&line[..start]) && !word.contains('/') {
+            let completion = self.completion.lock().unwrap();
+            let path_env = env::var("PATH").unwrap_or_default();
+            complete_command(&word, &completion.functions, &path_env)
+        } else {
+            complete_path(&word)
+        };
+        let pairs = candidates
+            .into_iter()
+            .map(|c| {
+                // 候補の一覧にはパスの最後の要素のみ表示する
+                let name = c.trim_end_matches('/').rfind('/').map_or(0, |i| i + 1);
+                Pair {
+                    display: c[name..].to_string(),
+                    replacement: escape(&c),
+                }
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
+        self.search.hint(line, pos, ctx)
+    }
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// カーソル位置 pos で補完する単語の開始位置と単語を返す
+/// バックスラッシュで退避した空白と制御記号は単語の一部とし、退避を解除して返す
+fn current_word(line: &str, pos: usize) -> (usize, String) {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in line[..pos].char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c.is_whitespace() || OPERATORS.contains(c) {
+            start = i + c.len_utf8();
+        }
+    }
+    (start, unescape(&line[start..pos]))
+}
+
+/// 単語の前までの入力 before から、単語がコマンド名の位置にあるかを判定する
+/// 直前の単語が予約語の場合は、その予約語がコマンド名の位置にあればコマンド名の位置とする
+fn is_command_position(before: &str) -> bool {
+    let before = before.trim_end();
+    match before.chars().last() {
+        None => true,
+        Some(c) if "&|(;".contains(c) => true,
+        Some(_) => {
+            let start = before
+                .char_indices()
+                .rfind(|&(_, c)| c.is_whitespace() || OPERATORS.contains(c))
+                .map_or(0, |(i, c)| i + c.len_utf8());
+            COMMAND_WORDS.contains(&&before[start..]) && is_command_position(&before[..start])
+        }
+    }
+}
+#[cfg(test)]
+mod current_word {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(current_word("", 0), (0, "".to_string()));
+        assert_eq!(current_word("ls sr", 5), (3, "sr".to_string()));
+        assert_eq!(current_word("ls sr", 2), (0, "ls".to_string()));
+        assert_eq!(current_word("cat a|gr", 8), (6, "gr".to_string()));
+        assert_eq!(current_word("cat my\\ fi", 10), (4, "my fi".to_string()));
+        assert_eq!(current_word("ls ", 3), (3, "".to_string()));
+
+        assert!(is_command_position(""));
+        assert!(is_command_position("cat a | "));
+        assert!(is_command_position("true && "));
+        assert!(is_command_position("if "));
+        assert!(is_command_position("for i in a; do "));
+        assert!(!is_command_position("ls "));
+        assert!(!is_command_position("for "));
+        assert!(!is_command_position("echo if "));
+    }
+}
+
+/// コマンド名 prefix の候補を返す
+/// 組み込みコマンド, 関数 functions, PATH を path_env とする実行可能ファイルの名前を重複なく昇順に返す
+fn complete_command(prefix: &str, functions: &BTreeSet<String>, path_env: &str) -> Vec<String> {
+    let mut names: BTreeSet<String> = BUILTINS
+        .iter()
+        .map(|s| s.to_string())
+        .chain(functions.iter().cloned())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+
+    for dir in path_env.split(':').filter(|d| !d.is_empty()) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(prefix) && is_executable(&entry.path()) {
+                names.insert(name);
+            }
+        }
+    }
+    names.into_iter().collect()
+}
+
+/// path が実行可能な通常のファイルか
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+/// パス prefix の候補を昇順に返す
+/// ディレクトリには `/` を付ける。 `.` で始まるファイルは prefix の最後の要素が `.` で始まる場合のみ返す
+fn complete_path(prefix: &str) -> Vec<String> {
+    let (dir, file) = match prefix.rfind('/') {
+        Some(i) => (&prefix[..=i], &prefix[i + 1..]),
+        None => ("", prefix),
+    };
+    // ~/ で始まる場合はホームディレクトリから探す
+    let search_dir = match (dir.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ if dir.is_empty() => ".".into(),
+        _ => dir.into(),
+    };
+    let Ok(entries) = fs::read_dir(search_dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(file) || (name.starts_with('.') && !file.starts_with('.')) {
+                return None;
+            }
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+            Some(format!("{dir}{name}{}", if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+#[cfg(test)]
+mod complete_path {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let base = env::temp_dir().join(format!("zerosh_complete_{}", std::process::id()));
+        let bin = base.join("bin");
+        fs::create_dir_all(base.join("src dir")).unwrap();
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(base.join("src.rs"), "").unwrap();
+        fs::write(base.join(".hidden"), "").unwrap();
+        fs::write(bin.join("zcmd"), "").unwrap();
+        fs::write(bin.join("zdata"), "").unwrap();
+        fs::set_permissions(bin.join("zcmd"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        let dir = format!("{}/", base.display());
+        assert_eq!(
+            complete_path(&format!("{dir}sr")),
+            vec![format!("{dir}src dir/"), format!("{dir}src.rs")]
+        );
+        assert_eq!(complete_path(&dir).len(), 3);
+        assert_eq!(
+            complete_path(&format!("{dir}.h")),
+            vec![format!("{dir}.hidden")]
+        );
+        assert!(complete_path(&format!("{dir}nothing/")).is_empty());
+
+        let functions = BTreeSet::from(["zfunc".to_string(), "other".to_string()]);
+        let path_env = format!("{}:/nonexistent", bin.display());
+        assert_eq!(
+            complete_command("z", &functions, &path_env),
+            vec!["zcmd", "zfunc"]
+        );
+        assert_eq!(
+            complete_command("ex", &functions, &path_env),
+            vec!["exec", "exit", "export"]
+        );
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
+
+/// 空白, クォート, 制御記号などをバックスラッシュで退避する
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        if c.is_whitespace() || OPERATORS.contains(c) || "\\'\"$*?[]`".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// バックスラッシュによる退避を解除する
+fn unescape(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+#[cfg(test)]
+mod escape {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(escape("a b&c.rs"), "a\\ b\\&c.rs");
+        assert_eq!(escape("$x"), "\\$x");
+        assert_eq!(unescape("a\\ b\\&c"), "a b&c");
+        assert_eq!(unescape(&escape("it's (1)")), "it's (1)");
+    }
+}
@@ -0,0 +1,646 @@
+//! 環境変数の展開とクォートの除去
+//!
+//! コマンドの引数とリダイレクト先のファイル名に含まれる `$NAME` と `${NAME}` を、
+//! 実行時の環境変数の値に置き換える。未定義の変数は空文字列となる。
+//! `$` の後ろが変数名でない場合や `${` が閉じていない場合は、そのまま残す。
+//!
+//! vars モジュールの配列は以下の形式で参照する。配列に `$NAME` を用いると最初の要素となり、
+//! 環境変数に `${NAME[0]}` や `${NAME[@]}` を用いると要素が 1 つの配列として扱う。
+//!
+//! - `${NAME[i]}` : i 番目 (0 始まり) の要素。範囲外の場合は空文字列
+//! - `${NAME[@]}`, `${NAME[*]}` : すべての要素を IFS の最初の文字で連結した文字列
+//! - `${#NAME[@]}`, `${#NAME[*]}` : 要素数
+//! - `${#NAME}` : 値の文字数
+//!
+//! 関数の中では位置パラメータを以下の形式で参照する。位置パラメータは名前が
+//! [vars::POSITIONAL] の配列として array から得る。
+//!
+//! - `$1` から `$9`, `${n}` : n 番目 (1 始まり) の引数
+//! - `$@`, `$*` : すべての引数。 `"$@"` は引数ごとに別の引数、 `"$*"` は連結した 1 つの引数となる
+//! - `$#` : 引数の数
+//!
+//! `$?` と `${?}` は直前に実行したジョブの終了コードとなる。
+//!
+//! クォートは以下の規則で取り除く。
+//!
+//! - `'...'` : 内側はすべてそのままの文字列
+//! - `"..."` : 内側の変数は展開する。 `\` は `$`, `"`, `\`, `` ` `` の前でのみエスケープとなる
+//! - `\c` : クォートの外側では c をそのままの文字とする
+//!
+//! 引数の場合は、クォートの外側で展開した値を IFS (未設定の場合は空白、タブ、改行) の文字で分割し、
+//! 空になった引数は取り除く。ただし `""` のようにクォートを含む引数は空でも残す。
+//! `"${NAME[@]}"` は要素ごとに別の引数となり、 `"${NAME[*]}"` は IFS の最初の文字 (未設定の場合は空白、
+//! 空の場合は区切りなし) で連結した 1 つの引数となる。
+//! IFS は [vars::ifs] で参照するため、配列の場合は最初の要素となる。
+//! 分割した後の各引数は、 glob モジュールでファイル名に展開する。クォートされた `*` と `?` は展開しない。
+//!
+//! `` `cmd` `` はコマンド置換とし、 cmd を /bin/sh で実行した標準出力の末尾の改行を取り除いた値に置き換える。
+//! クォートの外側の場合は、変数と同様に IFS で分割する。
+//!
+//! 外部コマンドの引数は、パースした時点で [parse_word] により [Word] の部分の列に分解しておき、
+//! 実行する直前に [expand_words] で文字列にする。
+use crate::model::{Segment, Word};
+use crate::{glob, vars};
+use std::{
+    env,
+    process::{Command, Stdio},
+};
+
+/// 環境変数名として使える名前か
+pub fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// word の環境変数と配列を展開し、クォートを取り除く。分割とファイル名の展開は行わない
+pub fn expand_vars(word: &str) -> String {
+    expand_with(
+        &parse_word(word),
+        |name| env::var(name).ok(),
+        vars::array,
+        None,
+    )
+    .iter()
+    .map(|field| field.iter().map(|(c, _)| c).collect::<String>())
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+/// コマンドの引数の環境変数を展開し、 IFS で分割した後にファイル名を展開する
+pub fn expand_args(args: &[String]) -> Vec<String> {
+    let words: Vec<Word> = args.iter().map(|arg| parse_word(arg)).collect();
+    expand_words(&words)
+}
+
+/// 単語の並びを [expand_args] と同様に展開して文字列にする
+pub fn expand_words(words: &[Word]) -> Vec<String> {
+    let ifs = vars::ifs();
+    words
+        .iter()
+        .flat_map(|word| expand_with(word, |name| env::var(name).ok(), vars::array, Some(&ifs)))
+        .flat_map(|field| glob::expand_glob(&to_pattern(&field)))
+        .collect()
+}
+
+/// read で読み込んだ行を IFS で n 個以下の値に分割する。 n 個に満たない場合は空文字列で補う
+pub fn split_ifs(line: &str, n: usize) -> Vec<String> {
+    let ifs = vars::ifs();
+    split_fields(line, &ifs, n)
+}
+
+/// line を ifs の文字で n 個以下に分割する。最後の値は残りのすべてとする
+///
+/// ifs のうち空白文字は行頭と行末では取り除き、連続した場合は 1 つの区切りとする。
+/// 空白以外の文字は 1 つごとに区切りとなり、前後の空白とともに 1 つの区切りとする。
+fn split_fields(line: &str, ifs: &str, n: usize) -> Vec<String> {
+    let is_space = |c: char| ifs.contains(c) && c.is_ascii_whitespace();
+    let mut fields = Vec::new();
+    let mut rest = line.trim_matches(is_space);
+    while fields.len() + 1 < n && !rest.is_empty() {
+        let Some(i) = rest.find(|c| ifs.contains(c)) else {
+            break;
+        };
+        fields.push(rest[..i].to_string());
+        rest = rest[i..].trim_start_matches(is_space);
+        if let Some(c) = rest
+            .chars()
+            .next()
+            .filter(|c| ifs.contains(*c) && !is_space(*c))
+        {
+            rest = rest[c.len_utf8()..].trim_start_matches(is_space);
+        }
+    }
+    if !rest.is_empty() {
+        fields.push(rest.to_string());
+    }
+    fields.resize(n.max(fields.len()), String::new());
+    fields
+}
+#[cfg(test)]
+mod split_fields {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let split = |line, ifs, n| split_fields(line, ifs, n);
+        assert_eq!(
+            split("  a  b   c d ", vars::DEFAULT_IFS, 2),
+            ["a", "b   c d"]
+        );
+        assert_eq!(split("a b", vars::DEFAULT_IFS, 3), ["a", "b", ""]);
+        assert_eq!(split(" a b ", vars::DEFAULT_IFS, 1), ["a b"]);
+        assert_eq!(split("", vars::DEFAULT_IFS, 1), [""]);
+        assert_eq!(split("a:b::c", ":", 4), ["a", "b", "", "c"]);
+        assert_eq!(split("a : b", " :", 3), ["a", "b", ""]);
+        assert_eq!(split(" a b ", "", 2), [" a b ", ""]);
+    }
+}
+
+/// 展開した引数。文字と、その文字をワイルドカードとしないかの組の列
+/// クォートの外側で展開した値と Glob の部分の文字のみ false となる
+type Field = Vec<(char, bool)>;
+
+/// 引数を glob モジュールのパターンに変換する
+/// ワイルドカードとしない `*` と `?` 、およびすべての `\` をエスケープする
+fn to_pattern(field: &Field) -> String {
+    let mut pat = String::new();
+    for &(c, quoted) in field {
+        if c == '\\' || (quoted && (c == '*' || c == '?')) {
+            pat.push('\\');
+        }
+        pat.push(c);
+    }
+    pat
+}
+#[cfg(test)]
+mod to_pattern {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let field = vec![('*', false), ('*', true), ('?', true), ('\\', false)];
+        assert_eq!(to_pattern(&field), "*\\*\\?\\\\");
+    }
+}
+
+/// 展開途中の引数の列
+#[derive(Default)]
+struct Fields {
+    fields: Vec<Field>,
+    cur: Field,
+    keep: bool, // cur が空でも引数として残すか
+}
+
+impl Fields {
+    fn push_str(&mut self, s: &str, quoted: bool) {
+        self.cur.extend(s.chars().map(|c| (c, quoted)));
+    }
+
+    /// クォートの外側で展開した値 s を ifs の文字で分割しながら追加する
+    fn push_split(&mut self, s: &str, ifs: Option<&str>) {
+        for c in s.chars() {
+            if ifs.is_some_and(|ifs| ifs.contains(c)) {
+                self.end_field();
+            } else {
+                self.cur.push((c, false));
+            }
+        }
+    }
+
+    /// 現在の引数を終える
+    fn end_field(&mut self) {
+        if !self.cur.is_empty() || self.keep {
+            self.fields.push(std::mem::take(&mut self.cur));
+        }
+        self.keep = false;
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        self.end_field();
+        self.fields
+    }
+}
+
+/// `${` と `}` の間に書く変数の参照
+#[derive(Debug, PartialEq, Eq)]
+enum Param<'a> {
+    Var(&'a str),          // NAME
+    Index(&'a str, usize), // NAME[i]
+    All(&'a str),          // NAME[@]
+    Star(&'a str),         // NAME[*]
+    Len(&'a str),          // #NAME
+    Count(&'a str),        // #NAME[@], #NAME[*]
+    Arg(usize),            // 1..9, n (位置パラメータ)
+    Status,                // ? (直前の終了コード)
+}
+
+/// `${` と `}` の間を解析する。不正な形式の場合は None を返す
+fn parse_param(s: &str) -> Option<Param<'_>> {
+    match s {
+        "@" => return Some(Param::All(vars::POSITIONAL)),
+        "*" => return Some(Param::Star(vars::POSITIONAL)),
+        "#" => return Some(Param::Count(vars::POSITIONAL)),
+        "?" => return Some(Param::Status),
+        _ if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => {
+            return s.parse().ok().filter(|n| *n > 0).map(Param::Arg);
+        }
+        _ => {}
+    }
+    let (is_len, s) = match s.strip_prefix('#') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (name, index) = match s.strip_suffix(']').and_then(|s| s.split_once('[')) {
+        Some((name, index)) => (name, Some(index)),
+        None => (s, None),
+    };
+    if !is_var_name(name) {
+        return None;
+    }
+
+    match (is_len, index) {
+        (false, None) => Some(Param::Var(name)),
+        (true, None) => Some(Param::Len(name)),
+        (false, Some("@")) => Some(Param::All(name)),
+        (false, Some("*")) => Some(Param::Star(name)),
+        (true, Some("@" | "*")) => Some(Param::Count(name)),
+        (false, Some(i)) if !i.is_empty() && i.bytes().all(|b| b.is_ascii_digit()) => {
+            Some(Param::Index(name, i.parse().ok()?))
+        }
+        _ => None,
+    }
+}
+#[cfg(test)]
+mod parse_param {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(parse_param("A_1"), Some(Param::Var("A_1")));
+        assert_eq!(parse_param("arr[10]"), Some(Param::Index("arr", 10)));
+        assert_eq!(parse_param("arr[@]"), Some(Param::All("arr")));
+        assert_eq!(parse_param("arr[*]"), Some(Param::Star("arr")));
+        assert_eq!(parse_param("#arr[@]"), Some(Param::Count("arr")));
+        assert_eq!(parse_param("#arr"), Some(Param::Len("arr")));
+        assert_eq!(parse_param("arr[]"), None);
+        assert_eq!(parse_param("arr[-1]"), None);
+        assert_eq!(parse_param("arr[+1]"), None);
+        assert_eq!(parse_param("#arr[0]"), None);
+        assert_eq!(parse_param("1arr"), None);
+        assert_eq!(parse_param(""), None);
+        assert_eq!(parse_param("12"), Some(Param::Arg(12)));
+        assert_eq!(parse_param("0"), None);
+        assert_eq!(parse_param("@"), Some(Param::All(vars::POSITIONAL)));
+        assert_eq!(parse_param("*"), Some(Param::Star(vars::POSITIONAL)));
+        assert_eq!(parse_param("#"), Some(Param::Count(vars::POSITIONAL)));
+        assert_eq!(parse_param("?"), Some(Param::Status));
+    }
+}
+
+/// 変数の参照 param の値を返す
+/// scalar は環境変数の値を、 array は配列の要素を返す関数。 sep はすべての要素を連結する区切り
+fn param_value<S, A>(param: &Param, scalar: &S, array: &A, sep: &str) -> String
+where
+    S: Fn(&str) -> Option<String>,
+    A: Fn(&str) -> Option<Vec<String>>,
+{
+    match *param {
+        Param::Var(name) => scalar(name)
+            .or_else(|| array(name)?.into_iter().next())
+            .unwrap_or_default(),
+        Param::Index(name, i) => match array(name) {
+            Some(values) => values.get(i).cloned().unwrap_or_default(),
+            None if i == 0 => scalar(name).unwrap_or_default(),
+            None => String::new(),
+        },
+        Param::All(name) | Param::Star(name) => array(name)
+            .map(|values| values.join(sep))
+            .or_else(|| scalar(name))
+            .unwrap_or_default(),
+        Param::Len(name) => param_value(&Param::Var(name), scalar, array, sep)
+            .chars()
+            .count()
+            .to_string(),
+        Param::Count(name) => match array(name) {
+            Some(values) => values.len().to_string(),
+            None if scalar(name).is_some() => "1".to_string(),
+            None => "0".to_string(),
+        },
+        Param::Arg(n) => array(vars::POSITIONAL)
+            .and_then(|args| args.get(n - 1).cloned())
+            .unwrap_or_default(),
+        Param::Status => vars::status().to_string(),
+    }
+}
+
+/// `$` の直後の after から変数の参照を読み取り、参照と残りの文字列を返す
+/// 変数の参照でなければ None を返す
+fn parse_ref(after: &str) -> Option<(Param<'_>, &str)> {
+    if let Some(braced) = after.strip_prefix('{') {
+        let end = braced.find('}')?;
+        return Some((parse_param(&braced[..end])?, &braced[end + 1..]));
+    }
+    match after.chars().next()? {
+        c @ '1'..='9' => return Some((Param::Arg(c as usize - '0' as usize), &after[1..])),
+        '@' => return Some((Param::All(vars::POSITIONAL), &after[1..])),
+        '*' => return Some((Param::Star(vars::POSITIONAL), &after[1..])),
+        '#' => return Some((Param::Count(vars::POSITIONAL), &after[1..])),
+        '?' => return Some((Param::Status, &after[1..])),
+        _ => {}
+    }
+    let len = after
+        .char_indices()
+        .find(|(i, c)| !(*c == '_' || c.is_ascii_alphanumeric()) || (*i == 0 && c.is_ascii_digit()))
+        .map_or(after.len(), |(i, _)| i);
+    (len > 0).then(|| (Param::Var(&after[..len]), &after[len..]))
+}
+
+/// raw を単語の部分の列に分解する。クォートと `\` によるエスケープは取り除く
+///
+/// 閉じていないシングルクォートは末尾までとし、閉じていないバッククォートはそのままの文字とする。
+pub fn parse_word(raw: &str) -> Word {
+    let mut segments = Vec::new();
+    let mut lit = String::new(); // 区切っていない文字列
+    let mut in_dq = false; // ダブルクォートの内側か
+    let mut dq_start = 0; // ダブルクォートの開始時点の部分の数
+
+    // 文字列の部分を区切る。 quoted はクォートの内側か
+    let flush = |lit: &mut String, segments: &mut Vec<Segment>, quoted: bool| {
+        if !lit.is_empty() {
+            segments.push(Segment::Literal(std::mem::take(lit), quoted));
+        }
+    };
+
+    let mut rest = raw;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        rest = after;
+        match c {
+            '\'' if !in_dq => {
+                flush(&mut lit, &mut segments, false);
+                let (quoted, next) = after.split_once('\'').unwrap_or((after, ""));
+                segments.push(Segment::Literal(quoted.to_string(), true));
+                rest = next;
+            }
+            '"' => {
+                flush(&mut lit, &mut segments, in_dq);
+                if !in_dq {
+                    dq_start = segments.len();
+                } else if segments.len() == dq_start {
+                    // "" も空の引数として残す
+                    segments.push(Segment::Literal(String::new(), true));
+                }
+                in_dq = !in_dq;
+            }
+            '\\' => match after.chars().next() {
+                Some(e) if !in_dq || "$\"\\`".contains(e) => {
+                    lit.push(e);
+                    rest = &after[e.len_utf8()..];
+                }
+                _ => lit.push(c),
+            },
+            '$' => match parse_ref(after) {
+                Some((_, next)) => {
+                    flush(&mut lit, &mut segments, in_dq);
+                    let param = &after[..after.len() - next.len()];
+                    segments.push(Segment::Var(param.to_string(), in_dq));
+                    rest = next;
+                }
+                // 変数の参照でなければ $ をそのまま残す
+                None => lit.push(c),
+            },
+            '`' => match after.split_once('`') {
+                Some((cmd, next)) => {
+                    flush(&mut lit, &mut segments, in_dq);
+                    segments.push(Segment::CmdSubst(cmd.to_string(), in_dq));
+                    rest = next;
+                }
+                None => lit.push(c),
+            },
+            '*' | '?' if !in_dq => {
+                flush(&mut lit, &mut segments, false);
+                match segments.last_mut() {
+                    Some(Segment::Glob(glob)) => glob.push(c),
+                    _ => segments.push(Segment::Glob(c.to_string())),
+                }
+            }
+            _ => lit.push(c),
+        }
+    }
+    flush(&mut lit, &mut segments, in_dq);
+
+    Word {
+        raw: raw.to_string(),
+        segments,
+    }
+}
+#[cfg(test)]
+mod parse_word {
+    use super::*;
+
+    #[test]
+    fn test() {
+        use Segment::*;
+        let lit = |s: &str, q| Literal(s.to_string(), q);
+        let segs = |raw| parse_word(raw).segments;
+        assert_eq!(segs("ls"), vec![lit("ls", false)]);
+        assert_eq!(segs(""), vec![]);
+        assert_eq!(
+            segs("a\\ b'c d'\"e\""),
+            vec![lit("a b", false), lit("c d", true), lit("e", true)]
+        );
+        assert_eq!(segs("''"), vec![lit("", true)]);
+        assert_eq!(segs("\"\""), vec![lit("", true)]);
+        assert_eq!(
+            segs("$HOME/${arr[1]}\"$?x\""),
+            vec![
+                Var("HOME".to_string(), false),
+                lit("/", false),
+                Var("{arr[1]}".to_string(), false),
+                Var("?".to_string(), true),
+                lit("x", true)
+            ]
+        );
+        assert_eq!(segs("$-\\$HOME"), vec![lit("$-$HOME", false)]);
+        assert_eq!(
+            segs("*.r?\\*\"*\""),
+            vec![
+                Glob("*".to_string()),
+                lit(".r", false),
+                Glob("?".to_string()),
+                lit("*", false),
+                lit("*", true)
+            ]
+        );
+        assert_eq!(segs("**"), vec![Glob("**".to_string())]);
+        assert_eq!(
+            segs("v`uname -s`\"`date`\""),
+            vec![
+                lit("v", false),
+                CmdSubst("uname -s".to_string(), false),
+                CmdSubst("date".to_string(), true)
+            ]
+        );
+        assert_eq!(segs("a`b"), vec![lit("a`b", false)]);
+        assert_eq!(parse_word("'a b'").raw, "'a b'");
+    }
+}
+
+/// コマンド置換。 cmd を /bin/sh で実行し、標準出力の末尾の改行を取り除いて返す
+/// 標準エラー出力はシェルのものを引き継ぐ。実行できない場合は空文字列とする
+fn command_output(cmd: &str) -> String {
+    let output = Command::new(SUBST_SH)
+        .arg("-c")
+        .arg(cmd)
+        .stderr(Stdio::inherit())
+        .output();
+    match output {
+        Ok(out) => String::from_utf8_lossy(&out.stdout)
+            .trim_end_matches('\n')
+            .to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// コマンド置換を実行するシェル
+const SUBST_SH: &str = "/bin/sh";
+
+/// word の変数の参照とコマンド置換を展開し、引数の列にする
+/// 変数の値は scalar と array で得る。 ifs が Some の場合はクォートの外側で展開した値を分割する
+/// すべての要素は ifs の最初の文字で連結し、 ifs が None の場合は空白で連結する
+fn expand_with<S, A>(word: &Word, scalar: S, array: A, ifs: Option<&str>) -> Vec<Field>
+where
+    S: Fn(&str) -> Option<String>,
+    A: Fn(&str) -> Option<Vec<String>>,
+{
+    let sep = ifs.map_or(" ", |ifs| {
+        &ifs[..ifs.chars().next().map_or(0, char::len_utf8)]
+    });
+    let mut out = Fields::default();
+    for seg in &word.segments {
+        match seg {
+            Segment::Literal(s, quoted) => {
+                out.push_str(s, true);
+                out.keep |= quoted;
+            }
+            Segment::Glob(s) => out.cur.extend(s.chars().map(|c| (c, false))),
+            Segment::Var(param, in_dq) => {
+                out.keep |= in_dq;
+                match parse_ref(param) {
+                    Some((Param::All(name), _)) if *in_dq => {
+                        // 要素ごとに別の引数とする
+                        let values =
+                            array(name).unwrap_or_else(|| scalar(name).into_iter().collect());
+                        for (i, v) in values.iter().enumerate() {
+                            if i > 0 {
+                                out.end_field();
+                                out.keep = true;
+                            }
+                            out.push_str(v, true);
+                        }
+                    }
+                    Some((param, _)) => {
+                        let value = param_value(&param, &scalar, &array, sep);
+                        if *in_dq {
+                            out.push_str(&value, true);
+                        } else {
+                            out.push_split(&value, ifs);
+                        }
+                    }
+                    None => out.push_str(&format!("${param}"), true),
+                }
+            }
+            Segment::CmdSubst(cmd, in_dq) => {
+                let value = command_output(cmd);
+                if *in_dq {
+                    out.keep = true;
+                    out.push_str(&value, true);
+                } else {
+                    out.push_split(&value, ifs);
+                }
+            }
+        }
+    }
+    out.finish()
+}
+#[cfg(test)]
+mod expand_with {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let scalar = |name: &str| match name {
+            "HOME" => Some("/home/a".to_string()),
+            "X_1" => Some("x".to_string()),
+            _ => None,
+        };
+        let array = |name: &str| match name {
+            "arr" => Some(vec!["a".to_string(), "b c".to_string(), "".to_string()]),
+            vars::POSITIONAL => Some(vec!["p1".to_string(), "p 2".to_string()]),
+            _ => None,
+        };
+        let fields = |word, ifs| -> Vec<String> {
+            expand_with(&parse_word(word), scalar, array, ifs)
+                .iter()
+                .map(|field| field.iter().map(|(c, _)| c).collect())
+                .collect()
+        };
+        let ex = |word| fields(word, None).join(" ");
+        assert_eq!(ex("$HOME/bin"), "/home/a/bin");
+        assert_eq!(ex("${X_1}y$X_1"), "xyx");
+        assert_eq!(ex("a$NONE-b"), "a-b");
+        assert_eq!(ex("${NONE}"), "");
+        assert_eq!(ex("$"), "$");
+        assert_eq!(ex("a$-$0"), "a$-$0");
+        assert_eq!(ex("${HOME"), "${HOME");
+        assert_eq!(ex("${}x"), "${}x");
+        assert_eq!(ex("${a-b}$X_1"), "${a-b}x");
+
+        // 直前の終了コード
+        vars::set_status(2);
+        assert_eq!(ex("$?x${?}"), "2x2");
+        assert_eq!(ex("'$?'"), "$?");
+        vars::set_status(0);
+        assert_eq!(ex("no vars"), "no vars");
+
+        // 配列
+        assert_eq!(ex("${arr[1]}"), "b c");
+        assert_eq!(ex("${arr[3]}"), "");
+        assert_eq!(ex("$arr/${arr}"), "a/a");
+        assert_eq!(ex("${arr[@]}."), "a b c .");
+        assert_eq!(ex("${#arr[*]}"), "3");
+        assert_eq!(ex("${#arr}"), "1");
+        assert_eq!(ex("$arr[1]"), "a[1]");
+        // 環境変数は要素が 1 つの配列として扱う
+        assert_eq!(
+            ex("${HOME[0]}|${HOME[1]}|${#HOME[@]}|${#NONE[@]}"),
+            "/home/a||1|0"
+        );
+        assert_eq!(ex("${#HOME}"), "7");
+
+        // クォート
+        assert_eq!(ex("'$HOME'"), "$HOME");
+        assert_eq!(ex("\"$HOME\"/'a b'"), "/home/a/a b");
+        assert_eq!(ex("a\\ b\\$HOME\\\\"), "a b$HOME\\");
+        assert_eq!(ex("\"a\\b\\$X_1\\\"\""), "a\\b$X_1\"");
+        assert_eq!(ex("\"'$X_1'\""), "'x'");
+        assert_eq!(ex("'a\\'"), "a\\");
+
+        // 分割
+        let ifs = Some(vars::DEFAULT_IFS);
+        assert_eq!(fields("a b", ifs), vec!["a b"]);
+        assert_eq!(fields("${arr[1]}", ifs), vec!["b", "c"]);
+        assert_eq!(fields("x${arr[1]}y", ifs), vec!["xb", "cy"]);
+        assert_eq!(fields("\"${arr[1]}\"", ifs), vec!["b c"]);
+        assert_eq!(fields("${arr[@]}", ifs), vec!["a", "b", "c"]);
+        assert_eq!(fields("\"x${arr[@]}y\"", ifs), vec!["xa", "b c", "y"]);
+        assert_eq!(fields("\"$NONE\"", ifs), vec![""]);
+        assert_eq!(fields("''", ifs), vec![""]);
+        assert!(fields("$NONE", ifs).is_empty());
+        assert!(fields("${arr[2]}", ifs).is_empty());
+        assert_eq!(fields("a\\ b", ifs), vec!["a b"]);
+        assert_eq!(fields("${arr[1]}", Some(":")), vec!["b c"]);
+        assert_eq!(fields("${arr[1]}", Some("")), vec!["b c"]);
+
+        // 位置パラメータ
+        assert_eq!(ex("$1-$2-$3-${1}0-$10"), "p1-p 2--p10-p10");
+        assert_eq!(ex("$#:${#}"), "2:2");
+        assert_eq!(fields("$@", ifs), vec!["p1", "p", "2"]);
+        assert_eq!(fields("\"$@\"", ifs), vec!["p1", "p 2"]);
+        assert_eq!(fields("\"$*\"", ifs), vec!["p1 p 2"]);
+        assert_eq!(fields("\"${*}\"", Some(":")), vec!["p1:p 2"]);
+        assert_eq!(fields("\"$*\"", Some("")), vec!["p1p 2"]);
+        assert_eq!(fields("$*", ifs), vec!["p1", "p", "2"]);
+        assert_eq!(fields("\"${arr[*]}\"", Some(":\t")), vec!["a:b c:"]);
+        assert_eq!(fields("${arr[@]}", Some(":")), vec!["a", "b c"]);
+        assert_eq!(fields("\"$2\"", ifs), vec!["p 2"]);
+
+        // コマンド置換
+        assert_eq!(fields("`echo a b`", ifs), vec!["a", "b"]);
+        assert_eq!(fields("\"`echo a b`\"", ifs), vec!["a b"]);
+        assert_eq!(ex("x`printf '1\\n\\n'`y"), "x1y");
+        assert!(fields("`true`", ifs).is_empty());
+        assert_eq!(fields("\"`true`\"", ifs), vec![""]);
+    }
+}
@@ -0,0 +1,209 @@
+//! ファイル名の展開 (グロブ)
+//!
+//! 引数に含まれる `*` (0 文字以上の任意の文字列) と `?` (任意の 1 文字) を、
+//! マッチするファイル名に置き換える。 `dir/*.txt` のように `/` を含む場合は
+//! `/` で区切った部分ごとにディレクトリを辿る。 `*` と `?` は `/` にはマッチしない。
+//! `.` で始まるファイル名は、パターンの部分も `.` で始まる場合にのみマッチする。
+//!
+//! `\` の直後の文字は、 `*` と `?` も含めて通常の文字として扱う。
+//! expand モジュールはクォートされた文字をこの形式でエスケープして渡す。
+//!
+//! 展開結果は辞書順に並べる。マッチするファイルがない場合は元の引数をエスケープを外して残す。
+use std::{fs, path::Path};
+
+/// パターンを文字と、その文字がワイルドカードかの組に分解する
+fn tokens(pat: &str) -> Vec<(char, bool)> {
+    let mut toks = Vec::new();
+    let mut chars = pat.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => toks.push((chars.next().unwrap_or('\\'), false)),
+            '*' | '?' => toks.push((c, true)),
+            _ => toks.push((c, false)),
+        }
+    }
+    toks
+}
+
+/// エスケープされていない `*` または `?` を含むか
+pub fn has_wildcard(word: &str) -> bool {
+    tokens(word).iter().any(|(_, wild)| *wild)
+}
+
+/// `\` によるエスケープを外す
+fn unescape(word: &str) -> String {
+    tokens(word).into_iter().map(|(c, _)| c).collect()
+}
+#[cfg(test)]
+mod unescape {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(unescape("a\\*b\\\\c"), "a*b\\c");
+        assert_eq!(unescape("a\\"), "a\\");
+        assert!(!has_wildcard("a\\*\\?"));
+        assert!(has_wildcard("\\\\*"));
+    }
+}
+
+/// word をカレントディレクトリからのファイル名に展開する
+pub fn expand_glob(word: &str) -> Vec<String> {
+    glob_in(Path::new("."), word)
+}
+
+/// word を base からのファイル名に展開する
+fn glob_in(base: &Path, word: &str) -> Vec<String> {
+    if !has_wildcard(word) {
+        return vec![unescape(word)];
+    }
+
+    let (mut paths, rest) = match word.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], word),
+    };
+    for part in rest.split('/') {
+        paths = paths
+            .iter()
+            .flat_map(|dir| {
+                if !has_wildcard(part) {
+                    return vec![join(dir, &unescape(part))];
+                }
+                read_names(&base.join(dir))
+                    .into_iter()
+                    .filter(|name| !name.starts_with('.') || part.starts_with('.'))
+                    .filter(|name| wildcard_match(part, name))
+                    .map(|name| join(dir, &name))
+                    .collect()
+            })
+            .collect();
+    }
+
+    // ワイルドカードを含まない部分は存在を確認していないため、ここで取り除く
+    paths.retain(|p| base.join(p).symlink_metadata().is_ok());
+    if paths.is_empty() {
+        return vec![unescape(word)];
+    }
+    paths.sort();
+    paths
+}
+#[cfg(test)]
+mod glob_in {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let base = std::env::temp_dir().join(format!("zerosh_glob_{}", std::process::id()));
+        for dir in ["src", "doc", "src/sub"] {
+            fs::create_dir_all(base.join(dir)).unwrap();
+        }
+        for file in [
+            "a.rs",
+            "b.rs",
+            "c.txt",
+            ".hidden.rs",
+            "src/x.rs",
+            "doc/y.rs",
+            "src/sub/z.rs",
+        ] {
+            fs::write(base.join(file), "").unwrap();
+        }
+
+        assert_eq!(glob_in(&base, "*.rs"), vec!["a.rs", "b.rs"]);
+        assert_eq!(glob_in(&base, "?.txt"), vec!["c.txt"]);
+        assert_eq!(glob_in(&base, ".*.rs"), vec![".hidden.rs"]);
+        assert_eq!(glob_in(&base, "*/*.rs"), vec!["doc/y.rs", "src/x.rs"]);
+        assert_eq!(glob_in(&base, "src/*/z.rs"), vec!["src/sub/z.rs"]);
+        assert_eq!(glob_in(&base, "*/x.rs"), vec!["src/x.rs"]);
+        assert_eq!(glob_in(&base, "*.none"), vec!["*.none"]);
+        assert_eq!(glob_in(&base, "plain"), vec!["plain"]);
+        assert_eq!(glob_in(&base, "\\*.rs"), vec!["*.rs"]);
+        assert_eq!(glob_in(&base, "[ab]\\?*.rs"), vec!["[ab]?*.rs"]);
+        assert_eq!(glob_in(&base, "\\s\\rc/*.rs"), vec!["src/x.rs"]);
+
+        let abs = format!("{}/*.txt", base.display());
+        assert_eq!(
+            glob_in(Path::new("."), &abs),
+            vec![format!("{}/c.txt", base.display())]
+        );
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
+
+/// ディレクトリ内のファイル名の一覧。読み込めない場合は空
+fn read_names(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .collect()
+}
+
+/// dir と name を `/` で連結する
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if dir.ends_with('/') {
+        format!("{dir}{name}")
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+/// name が `*` と `?` を含むパターン pat にマッチするか
+fn wildcard_match(pat: &str, name: &str) -> bool {
+    let pat = tokens(pat);
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut star = None; // 直前の `*` の位置と、その `*` にマッチさせ始めた name の位置
+
+    while n < name.len() {
+        match pat.get(p) {
+            Some(('*', true)) => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(('?', true)) => {
+                p += 1;
+                n += 1;
+            }
+            Some((c, _)) if *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // `*` にマッチさせる文字を 1 つ増やしてやり直す
+                Some((sp, sn)) => {
+                    star = Some((sp, sn + 1));
+                    p = sp + 1;
+                    n = sn + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pat[p..].iter().all(|t| *t == ('*', true))
+}
+#[cfg(test)]
+mod wildcard_match {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert!(wildcard_match("*.rs", "main.rs"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(wildcard_match("?b?", "abc"));
+        assert!(wildcard_match("**a", "ba"));
+        assert!(!wildcard_match("*.rs", "main.rsx"));
+        assert!(!wildcard_match("?", ""));
+        assert!(!wildcard_match("a?", "a"));
+        assert!(wildcard_match("日*", "日本語"));
+        assert!(wildcard_match("a\\*", "a*"));
+        assert!(!wildcard_match("a\\*", "ab"));
+        assert!(wildcard_match("\\?*", "?x"));
+        assert!(!wildcard_match("\\?", "x"));
+    }
+}
@@ -0,0 +1,141 @@
+//! コマンドのパスのキャッシュ
+//!
+//! 外部コマンドは fork する前に worker スレッドで PATH から検索し、見つからない場合は
+//! 子プロセスを生成せずにエラーとする。検索したパスはコマンド名ごとに保持し、
+//! 同じコマンドを再び実行する場合は PATH を検索しない。
+//!
+//! PATH が変更された場合は保持したパスをすべて破棄し、保持したパスのファイルがなくなった場合は検索し直す。
+//! 子プロセスは fork した時点の複製を持つため、検索したパスをそのまま exec に用いる。
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+/// コマンド名から検索したパスへのマップ
+#[derive(Debug, Default, Clone)]
+pub struct CommandHash {
+    path_env: String, // 検索に用いた PATH
+    paths: HashMap<String, PathBuf>,
+}
+
+impl CommandHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// name のパスを返す。見つからない場合は None
+    /// name が / を含む場合は検索せずにそのまま返す
+    pub fn lookup(&mut self, name: &str) -> Option<PathBuf> {
+        self.lookup_in(name, &env::var("PATH").unwrap_or_default())
+    }
+
+    /// PATH を path_env として name のパスを返す
+    fn lookup_in(&mut self, name: &str, path_env: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            return Some(PathBuf::from(name));
+        }
+        if self.path_env != path_env {
+            self.paths.clear();
+            self.path_env = path_env.to_string();
+        }
+        if let Some(path) = self.paths.get(name).filter(|p| p.is_file()) {
+            return Some(path.clone());
+        }
+
+        match find_command(name, path_env) {
+            Some(path) => {
+                self.paths.insert(name.to_string(), path.clone());
+                Some(path)
+            }
+            None => {
+                self.paths.remove(name);
+                None
+            }
+        }
+    }
+
+    /// lookup で検索済みのパスを返す。 fork した子プロセスで用いる
+    pub fn get(&self, name: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            Some(PathBuf::from(name))
+        } else {
+            self.paths.get(name).cloned()
+        }
+    }
+}
+#[cfg(test)]
+mod lookup_in {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test() {
+        let base = env::temp_dir().join(format!("zerosh_hash_{}", std::process::id()));
+        let (bin1, bin2) = (base.join("bin1"), base.join("bin2"));
+        fs::create_dir_all(&bin1).unwrap();
+        fs::create_dir_all(&bin2).unwrap();
+        fs::write(bin1.join("cmd"), "").unwrap();
+        fs::write(bin2.join("cmd"), "").unwrap();
+        let path_env = format!("{}:{}", bin1.display(), bin2.display());
+
+        let mut hash = CommandHash::new();
+        assert_eq!(hash.get("cmd"), None);
+        assert_eq!(hash.lookup_in("cmd", &path_env), Some(bin1.join("cmd")));
+        assert_eq!(hash.get("cmd"), Some(bin1.join("cmd")));
+        assert_eq!(hash.lookup_in("none", &path_env), None);
+        assert_eq!(hash.lookup_in("./a.sh", &path_env), Some("./a.sh".into()));
+
+        // ファイルがなくなった場合は検索し直す
+        fs::remove_file(bin1.join("cmd")).unwrap();
+        assert_eq!(hash.lookup_in("cmd", &path_env), Some(bin2.join("cmd")));
+        fs::remove_file(bin2.join("cmd")).unwrap();
+        assert_eq!(hash.lookup_in("cmd", &path_env), None);
+        assert_eq!(hash.get("cmd"), None);
+
+        // PATH が変更された場合は破棄する
+        fs::write(bin2.join("cmd"), "").unwrap();
+        assert_eq!(hash.lookup_in("cmd", &path_env), Some(bin2.join("cmd")));
+        assert_eq!(hash.lookup_in("none", &bin1.display().to_string()), None);
+        assert_eq!(hash.get("cmd"), None);
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
+
+/// execvp と同様に、 PATH を path_env としてコマンドを検索し、パスを返す
+/// name が / を含む場合はそのまま、含まない場合は path_env のディレクトリから検索する
+fn find_command(name: &str, path_env: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+    path_env
+        .split(':')
+        .map(|dir| {
+            if dir.is_empty() {
+                Path::new(".")
+            } else {
+                Path::new(dir)
+            }
+            .join(name)
+        })
+        .find(|p| p.is_file())
+}
+#[cfg(test)]
+mod find_command {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let path_env = env::var("PATH").unwrap_or_default();
+        assert_eq!(
+            find_command("./a.sh", &path_env),
+            Some(PathBuf::from("./a.sh"))
+        );
+        assert_eq!(
+            find_command("sh", &path_env).map(|p| p.is_file()),
+            Some(true)
+        );
+        assert_eq!(find_command("zerosh-no-such-command", &path_env), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! ヒアドキュメント
+//!
+//! `<<TAG` を含む行が入力されると、 main スレッドは続く行を TAG だけの行まで読み込み、
+//! 元の行の後に改行で区切って連結し、 worker スレッドに送る。
+//! worker スレッドは先頭の行をパースした後、残りの行をヒアドキュメントの本文として
+//! 出現順にリダイレクトへ設定する。
+//!
+//! 本文の環境変数は展開せず、そのままパイプで子プロセスの標準入力に渡す。
+use crate::model::{Compound, ExternalCmd, Job, Pipeline, Redirection};
+use crate::parser;
+
+/// line に含まれるヒアドキュメントの終端の文字列を出現順に返す
+/// line をパースできない場合は空とする
+pub fn terminators(line: &str) -> Vec<String> {
+    let Ok(mut jobs) = parser::parse(line) else {
+        return Vec::new();
+    };
+    heredocs(&mut jobs)
+        .into_iter()
+        .map(|(tag, _)| tag.to_string())
+        .collect()
+}
+
+/// line のヒアドキュメントの本文を read で 1 行ずつ読み込み、終端の行を含めて line の後に連結する
+///
+/// 終端の行を読み込む前に read が None を返した場合は、その終端の文字列を Err で返す。
+pub fn read_bodies(line: &str, mut read: impl FnMut() -> Option<String>) -> Result<String, String> {
+    let mut text = line.to_string();
+    for tag in terminators(line) {
+        loop {
+            let Some(l) = read() else {
+                return Err(tag);
+            };
+            text.push('\n');
+            text.push_str(&l);
+            if l == tag {
+                break;
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// パースした jobs のヒアドキュメントに、終端の行を含む本文 bodies を出現順に設定する
+pub fn fill(jobs: &mut [Job], bodies: &str) {
+    let mut lines = bodies.lines();
+    for (tag, body) in heredocs(jobs) {
+        for l in lines.by_ref() {
+            if l == tag {
+                break;
+            }
+            body.push_str(l);
+            body.push('\n');
+        }
+    }
+}
+
+/// jobs のヒアドキュメントの (終端の文字列, 本文) を出現順に返す
+fn heredocs(list: &mut [Job]) -> Vec<(&str, &mut String)> {
+    fn job<'a>(j: &'a mut Job, out: &mut Vec<(&'a str, &'a mut String)>) {
+        match j {
+            Job::BuiltIn { .. } => (),
+            Job::External { cmds, .. } => pipeline(cmds, out),
+            Job::List { first, rest, .. } => {
+                job(first, out);
+                rest.iter_mut().for_each(|(_, j)| job(j, out));
+            }
+            Job::Compound { cmd, .. } => match cmd {
+                Compound::If { cond, then, els } => {
+                    jobs(cond, out);
+                    jobs(then, out);
+                    if let Some(els) = els {
+                        jobs(els, out);
+                    }
+                }
+                Compound::For { body, .. } => jobs(body, out),
+                Compound::While { cond, body } => {
+                    jobs(cond, out);
+                    jobs(body, out);
+                }
+            },
+            Job::Function { body, .. } => jobs(body, out),
+        }
+    }
+    fn jobs<'a>(js: &'a mut [Job], out: &mut Vec<(&'a str, &'a mut String)>) {
+        js.iter_mut().for_each(|j| job(j, out));
+    }
+    fn pipeline<'a>(p: &'a mut Pipeline, out: &mut Vec<(&'a str, &'a mut String)>) {
+        match p {
+            Pipeline::Src(c) => cmd(c, out),
+            Pipeline::Out(p, c) | Pipeline::Both(p, c) => {
+                pipeline(p, out);
+                cmd(c, out);
+            }
+        }
+    }
+    fn cmd<'a>(c: &'a mut ExternalCmd, out: &mut Vec<(&'a str, &'a mut String)>) {
+        for red in c.redirects.iter_mut() {
+            if let Redirection::HereDoc(tag, body) = red {
+                out.push((tag.as_str(), body));
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    jobs(list, &mut out);
+    out
+}
+#[cfg(test)]
+mod read_bodies {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            terminators("cat <<EOF | grep a <<'END'"),
+            vec!["EOF", "END"]
+        );
+        assert_eq!(
+            terminators("if true; then cat <<A; fi; f() { cat <<B; }"),
+            vec!["A", "B"]
+        );
+        assert!(terminators("echo a < b").is_empty());
+        assert!(terminators("cat <<").is_empty());
+
+        let lines = |ls: &[&str]| {
+            let mut it = ls
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .into_iter();
+            move || it.next()
+        };
+        let text = read_bodies("cat <<A && cat <<B", lines(&["a1", "a2", "A", "B", "x"]));
+        assert_eq!(text, Ok("cat <<A && cat <<B\na1\na2\nA\nB".to_string()));
+        assert_eq!(read_bodies("ls", lines(&["x"])), Ok("ls".to_string()));
+        assert_eq!(
+            read_bodies("cat <<EOF", lines(&["a", "EOF "])),
+            Err("EOF".to_string())
+        );
+
+        let (line, bodies) = text.as_ref().unwrap().split_once('\n').unwrap();
+        let mut jobs = parser::parse(line).unwrap();
+        fill(&mut jobs, bodies);
+        let bodies: Vec<String> = heredocs(&mut jobs)
+            .into_iter()
+            .map(|(_, body)| body.clone())
+            .collect();
+        assert_eq!(bodies, vec!["a1\na2\n", ""]);
+    }
+}
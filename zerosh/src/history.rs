@@ -0,0 +1,132 @@
+//! ヒストリ展開
+//!
+//! 入力された行を worker スレッドに送る前に、 `!` で始まるイベント指示子をヒストリの行で置き換える。
+//!
+//! - `!!` : 直前の行
+//! - `!n` : n 番目の行 (最も古い行が 1)
+//! - `!-n` : n 個前の行
+//! - `!prefix` : prefix で始まる最も新しい行
+//!
+//! シングルクォートの内側と、バックスラッシュの直後の `!` は展開しない。
+//! `!` の直後が空白, `=`, `(` または行末の場合も展開しない。
+use rustyline::history::{History, SearchDirection};
+
+/// イベント指示子の終わりとなる文字
+const DELIMITERS: &str = "&|()<>;'\"";
+
+/// history の行を古い順に返す
+pub fn entries(history: &dyn History) -> Vec<String> {
+    (0..history.len())
+        .filter_map(|i| history.get(i, SearchDirection::Forward).ok().flatten())
+        .map(|r| r.entry.into_owned())
+        .collect()
+}
+
+/// line のイベント指示子を、古い順に並んだヒストリ entries の行で置き換える
+///
+/// 展開した場合は Ok(Some(展開後の行)) 、イベント指示子を含まない場合は Ok(None) を返す。
+/// 該当する行がない場合は、そのイベント指示子を Err で返す。
+pub fn expand(line: &str, entries: &[String]) -> Result<Option<String>, String> {
+    let mut out = String::new();
+    let mut expanded = false;
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('\''), _) => (),
+            (_, '\\') => {
+                out.push(c);
+                if let Some((_, next)) = chars.next() {
+                    out.push(next);
+                }
+                continue;
+            }
+            (_, '!') => {
+                let rest = &line[i + 1..];
+                if let Some((len, found)) = event(rest, quote.is_some(), entries)? {
+                    out.push_str(found);
+                    expanded = true;
+                    // イベント指示子の残りを読み飛ばす
+                    while chars.next_if(|&(j, _)| j <= i + len).is_some() {}
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        out.push(c);
+    }
+    Ok(expanded.then_some(out))
+}
+
+/// `!` の直後の文字列 rest にあるイベント指示子が指す行を、指示子の長さとともに返す
+/// 展開しない場合は None 、該当する行がない場合は Err
+/// in_quote はダブルクォートの内側か
+fn event<'a>(
+    rest: &str,
+    in_quote: bool,
+    entries: &'a [String],
+) -> Result<Option<(usize, &'a str)>, String> {
+    let end = rest
+        .find(|c: char| c.is_whitespace() || DELIMITERS.contains(c) || (c == '!' && !in_quote))
+        .unwrap_or(rest.len());
+
+    let (len, found) = if rest.starts_with('!') {
+        (1, entries.last())
+    } else if let Some(spec) = rest.strip_prefix('-').filter(|_| end > 1) {
+        let n = spec[..end - 1].parse::<usize>().ok().filter(|&n| n > 0);
+        (
+            end,
+            n.and_then(|n| entries.len().checked_sub(n))
+                .map(|i| &entries[i]),
+        )
+    } else if end == 0 || rest.starts_with('=') {
+        return Ok(None);
+    } else if let Ok(n) = rest[..end].parse::<usize>() {
+        (end, n.checked_sub(1).and_then(|i| entries.get(i)))
+    } else {
+        let prefix = &rest[..end];
+        (end, entries.iter().rev().find(|e| e.starts_with(prefix)))
+    };
+
+    match found {
+        Some(found) => Ok(Some((len, found))),
+        None => Err(format!("!{}", &rest[..len])),
+    }
+}
+#[cfg(test)]
+mod expand {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let entries: Vec<String> = ["ls -l", "grep foo bar.txt", "echo hello", "git status"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let exp = |line| expand(line, &entries);
+
+        assert_eq!(exp("!!"), Ok(Some("git status".to_string())));
+        assert_eq!(exp("!! | less"), Ok(Some("git status | less".to_string())));
+        assert_eq!(exp("!2"), Ok(Some("grep foo bar.txt".to_string())));
+        assert_eq!(exp("!-2;!1"), Ok(Some("echo hello;ls -l".to_string())));
+        assert_eq!(exp("!gr"), Ok(Some("grep foo bar.txt".to_string())));
+        assert_eq!(exp("sudo !g"), Ok(Some("sudo git status".to_string())));
+        assert_eq!(
+            exp("echo \"!e\""),
+            Ok(Some("echo \"echo hello\"".to_string()))
+        );
+
+        // 展開しない
+        assert_eq!(exp("ls -l"), Ok(None));
+        assert_eq!(exp("echo '!!' \\!! ! !="), Ok(None));
+        assert_eq!(exp("echo hi!"), Ok(None));
+
+        // 該当する行がない
+        assert_eq!(exp("!zz"), Err("!zz".to_string()));
+        assert_eq!(exp("!5"), Err("!5".to_string()));
+        assert_eq!(exp("!-9 x"), Err("!-9".to_string()));
+        assert_eq!(expand("!!", &[]), Err("!!".to_string()));
+    }
+}
@@ -0,0 +1,132 @@
+//! bindkey 組み込みコマンドで設定するキーバインド
+//!
+//! キーの記法は以下の通り。
+//!
+//! - `\C-x` : Ctrl + x
+//! - `\M-x` : Alt(Meta) + x
+//! - `\e`   : Esc
+//! - `\\`   : バックスラッシュ
+//! - その他の文字はその文字自身
+//!
+//! `\M-\C-x` のように修飾を重ねることができ、 `\C-xt` のように複数のキーを並べると
+//! キーシーケンスとなる。
+use rustyline::{
+    Cmd, ConditionalEventHandler, Event, EventContext, KeyCode, KeyEvent, Modifiers, RepeatCount,
+};
+use std::sync::{Arc, Mutex};
+
+/// キーの記法をパースしてキーシーケンスに変換
+/// 記法が不正な場合は None を返す
+pub fn parse_keyseq(s: &str) -> Option<Vec<KeyEvent>> {
+    let mut keys = Vec::new();
+    let mut mods = Modifiers::NONE; // 次のキーに適用する修飾キー
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        let key = if c == '\\' {
+            match chars.next()? {
+                m @ ('C' | 'M') => {
+                    if chars.next()? != '-' {
+                        return None;
+                    }
+                    mods |= if m == 'C' {
+                        Modifiers::CTRL
+                    } else {
+                        Modifiers::ALT
+                    };
+                    continue;
+                }
+                'e' => KeyEvent(KeyCode::Esc, mods),
+                '\\' => KeyEvent::new('\\', mods),
+                _ => return None,
+            }
+        } else {
+            KeyEvent::new(c, mods)
+        };
+        keys.push(key);
+        mods = Modifiers::NONE;
+    }
+
+    // 修飾キーのみで終わっている場合や空の場合は不正
+    if !mods.is_empty() || keys.is_empty() {
+        None
+    } else {
+        Some(keys)
+    }
+}
+#[cfg(test)]
+mod parse_keyseq {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(parse_keyseq("\\C-t"), Some(vec![KeyEvent::ctrl('t')]));
+        assert_eq!(parse_keyseq("\\M-x"), Some(vec![KeyEvent::alt('x')]));
+        assert_eq!(
+            parse_keyseq("\\M-\\C-x"),
+            Some(vec![KeyEvent::new('x', Modifiers::CTRL | Modifiers::ALT)])
+        );
+        assert_eq!(
+            parse_keyseq("\\C-xt"),
+            Some(vec![
+                KeyEvent::ctrl('x'),
+                KeyEvent::new('t', Modifiers::NONE)
+            ])
+        );
+        assert_eq!(
+            parse_keyseq("\\e\\\\"),
+            Some(vec![
+                KeyEvent(KeyCode::Esc, Modifiers::NONE),
+                KeyEvent::new('\\', Modifiers::NONE)
+            ])
+        );
+        assert_eq!(parse_keyseq(""), None);
+        assert_eq!(parse_keyseq("\\C-"), None);
+        assert_eq!(parse_keyseq("\\Cx"), None);
+        assert_eq!(parse_keyseq("\\q"), None);
+    }
+}
+
+/// キーバインドにより実行が要求されたコマンド
+#[derive(Debug)]
+pub struct BoundCmd {
+    pub cmd: String,  // 実行するコマンド
+    pub line: String, // キーが押された時点の入力行
+    pub pos: usize,   // キーが押された時点のカーソル位置
+}
+
+/// キーバインドで要求されたコマンドを main スレッドへ受け渡すための場所
+pub type PendingCmd = Arc<Mutex<Option<BoundCmd>>>;
+
+/// rustyline に登録するキーハンドラ
+///
+/// キーが押されると、実行するコマンドと入力中の行を pending に保存し、
+/// readline を中断させる。 main スレッドは中断後に pending を確認してコマンドを実行し、
+/// 入力中だった行を復元して読み込みを再開する。
+pub struct KeyBindHandler {
+    cmd: String,
+    pending: PendingCmd,
+}
+
+impl KeyBindHandler {
+    pub fn new(cmd: String, pending: PendingCmd) -> Self {
+        Self { cmd, pending }
+    }
+}
+
+impl ConditionalEventHandler for KeyBindHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        *self.pending.lock().unwrap() = Some(BoundCmd {
+            cmd: self.cmd.clone(),
+            line: ctx.line().to_string(),
+            pos: ctx.pos(),
+        });
+        Some(Cmd::Interrupt)
+    }
+}
@@ -0,0 +1,34 @@
+//! # ジョブ制御を行うシェル zerosh
+//!
+//! [Shell::run] はシェルが終了した場合に終了コードを返し、プロセスは終了しない。
+//!
+//! ```no_run
+//! let sh = zerosh::Shell::new(".zerosh_history", None, None);
+//! let exit_val = sh.run().unwrap();
+//! std::process::exit(exit_val);
+//! ```
+pub use log;
+pub use parser_combinator;
+
+mod cancel;
+mod complete;
+mod expand;
+mod glob;
+mod hash;
+mod helper;
+mod heredoc;
+mod history;
+mod keybind;
+mod local;
+mod model;
+mod parser;
+mod prompt;
+mod search;
+mod shell;
+mod sigpipe;
+mod status;
+mod term;
+mod vars;
+
+pub use helper::DynError;
+pub use shell::Shell;
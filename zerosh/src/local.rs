@@ -0,0 +1,238 @@
+//! ディレクトリごとのローカル設定ファイル (.zerosh.local)
+//!
+//! cd で移動したディレクトリに .zerosh.local がある場合、その内容を実行する。
+//! ファイルの各行は以下のいずれか。
+//!
+//! - `NAME=value` : 環境変数を設定する。ディレクトリから出ると元の値に戻す
+//! - `#` で始まる行と空行 : 無視する
+//! - その他 : シェルのコマンドとして実行する
+//!
+//! 任意のコマンドを実行できるため、初めて読み込むファイルや内容が変更されたファイルは
+//! 実行前に信頼するかを問い合わせ、信頼したファイルの SHA-256 のハッシュ値を記録しておく。
+use crate::expand::is_var_name;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// ローカル設定ファイルの名前
+pub const LOCAL_FILE: &str = ".zerosh.local";
+
+/// 信頼したローカル設定ファイルを記録するファイルの名前。ホームディレクトリに置く
+const TRUST_FILE: &str = ".zerosh_trusted";
+
+/// ローカル設定ファイルの内容
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct LocalRc {
+    pub vars: Vec<(String, String)>, // 設定する環境変数
+    pub cmds: Vec<String>,           // 実行するコマンド
+}
+
+/// ローカル設定ファイルの内容をパース
+pub fn parse_local(content: &str) -> LocalRc {
+    let mut rc = LocalRc::default();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((name, value)) if is_var_name(name) => {
+                rc.vars.push((name.to_string(), value.to_string()));
+            }
+            _ => rc.cmds.push(line.to_string()),
+        }
+    }
+    rc
+}
+#[cfg(test)]
+mod parse_local {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let rc = parse_local("# comment\nRUST_LOG=debug\n\n  ls -l | wc\nA_1=x=y\n1A=z\n");
+        assert_eq!(
+            rc,
+            LocalRc {
+                vars: vec![
+                    ("RUST_LOG".to_string(), "debug".to_string()),
+                    ("A_1".to_string(), "x=y".to_string())
+                ],
+                cmds: vec!["ls -l | wc".to_string(), "1A=z".to_string()],
+            }
+        );
+        assert_eq!(parse_local(""), LocalRc::default());
+    }
+}
+
+/// SHA-256 のハッシュ値
+pub type Hash = [u8; 32];
+
+/// ファイルの内容の変更を検出するためのハッシュ値 (SHA-256)
+///
+/// 信頼したディレクトリのファイルを git pull などで書き換えられた場合にも問い合わせるよう、
+/// 同じハッシュ値となる内容を作れない暗号学的ハッシュ関数を用いる。
+pub fn hash(content: &[u8]) -> Hash {
+    Sha256::digest(content).into()
+}
+#[cfg(test)]
+mod hash {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            format_hash(&hash(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            format_hash(&hash(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_ne!(hash(b"FOO=1\n"), hash(b"FOO=2\n"));
+    }
+}
+
+/// ハッシュ値を 16 進数の文字列にする
+fn format_hash(h: &Hash) -> String {
+    h.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// 64 桁の 16 進数の文字列をハッシュ値にする。不正な形式の場合は None
+fn parse_hash(s: &str) -> Option<Hash> {
+    if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let mut h = [0; 32];
+    for (i, b) in h.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(h)
+}
+
+/// 信頼したローカル設定ファイルのパスとハッシュ値の一覧
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    file: Option<PathBuf>, // 記録するファイル。ホームディレクトリがない場合は記録しない
+    entries: BTreeMap<PathBuf, Hash>,
+}
+
+impl TrustStore {
+    /// ホームディレクトリの記録ファイルから読み込む
+    pub fn load() -> Self {
+        let file = dirs::home_dir().map(|h| h.join(TRUST_FILE));
+        let entries = file
+            .as_ref()
+            .and_then(|f| fs::read_to_string(f).ok())
+            .map(|s| parse_trust(&s))
+            .unwrap_or_default();
+        Self { file, entries }
+    }
+
+    /// path のファイルを内容のハッシュ値 h で信頼済みか
+    pub fn is_trusted(&self, path: &Path, h: Hash) -> bool {
+        self.entries.get(path) == Some(&h)
+    }
+
+    /// path のファイルを内容のハッシュ値 h で信頼し、記録ファイルに保存
+    pub fn trust(&mut self, path: &Path, h: Hash) -> io::Result<()> {
+        self.entries.insert(path.to_path_buf(), h);
+        match &self.file {
+            Some(f) => fs::write(f, format_trust(&self.entries)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// 記録ファイルの内容をパース。各行は `ハッシュ値 (64 桁の 16 進数) パス` の形式
+/// 以前の 64 ビットのハッシュ値の行は読み飛ばすため、そのファイルは改めて問い合わせる
+fn parse_trust(s: &str) -> BTreeMap<PathBuf, Hash> {
+    s.lines()
+        .filter_map(|line| {
+            let (h, path) = line.split_once(' ')?;
+            Some((PathBuf::from(path), parse_hash(h)?))
+        })
+        .collect()
+}
+
+/// 記録ファイルの内容を生成
+fn format_trust(entries: &BTreeMap<PathBuf, Hash>) -> String {
+    entries
+        .iter()
+        .map(|(path, h)| format!("{} {}\n", format_hash(h), path.display()))
+        .collect()
+}
+#[cfg(test)]
+mod parse_trust {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let line = format!("{}ff /tmp/a b/.zerosh.local\n", "0".repeat(62));
+        let entries = parse_trust(&format!(
+            "{line}broken\n00000000000000ff /tmp/old/.zerosh.local\n{}ff /tmp/c\n",
+            "+".repeat(62)
+        ));
+        let mut h = [0; 32];
+        h[31] = 0xff;
+        assert_eq!(
+            entries,
+            BTreeMap::from([(PathBuf::from("/tmp/a b/.zerosh.local"), h)])
+        );
+        assert_eq!(format_trust(&entries), line);
+    }
+}
+
+/// 適用中のローカル設定
+#[derive(Debug)]
+pub struct ActiveLocal {
+    pub dir: PathBuf,                       // ローカル設定ファイルのあるディレクトリ
+    saved: Vec<(String, Option<OsString>)>, // 設定前の環境変数の値
+}
+
+impl ActiveLocal {
+    /// dir のローカル設定の環境変数を設定し、設定前の値を保存する
+    pub fn apply(dir: PathBuf, rc: &LocalRc) -> Self {
+        let mut saved = Vec::new();
+        for (name, value) in &rc.vars {
+            saved.push((name.clone(), env::var_os(name)));
+            env::set_var(name, value);
+        }
+        Self { dir, saved }
+    }
+
+    /// 環境変数を設定前の値に戻す
+    pub fn restore(self) {
+        // 同じ変数を複数回設定した場合に最初の値に戻るよう、逆順に戻す
+        for (name, value) in self.saved.into_iter().rev() {
+            match value {
+                Some(v) => env::set_var(name, v),
+                None => env::remove_var(name),
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod active_local {
+    use super::*;
+
+    #[test]
+    fn test() {
+        env::set_var("ZEROSH_LOCAL_TEST_A", "orig");
+        env::remove_var("ZEROSH_LOCAL_TEST_B");
+
+        let rc =
+            parse_local("ZEROSH_LOCAL_TEST_A=1\nZEROSH_LOCAL_TEST_B=2\nZEROSH_LOCAL_TEST_A=3\n");
+        let active = ActiveLocal::apply(PathBuf::from("/tmp"), &rc);
+        assert_eq!(env::var("ZEROSH_LOCAL_TEST_A").unwrap(), "3");
+        assert_eq!(env::var("ZEROSH_LOCAL_TEST_B").unwrap(), "2");
+
+        active.restore();
+        assert_eq!(env::var("ZEROSH_LOCAL_TEST_A").unwrap(), "orig");
+        assert!(env::var_os("ZEROSH_LOCAL_TEST_B").is_none());
+    }
+}
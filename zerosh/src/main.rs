@@ -1,14 +1,8 @@
-pub use log;
-pub use parser_combinator;
-
-mod helper;
-mod model;
-mod parser;
-mod shell;
-
-use helper::DynError;
+use std::path::PathBuf;
+use zerosh::{DynError, Shell};
 
 const HISTORY_FILE: &str = ".zerosh_history";
+const RC_FILE: &str = ".zeroshrc";
 
 fn main() -> Result<(), DynError> {
     env_logger::init();
@@ -20,8 +14,21 @@ fn main() -> Result<(), DynError> {
         logfile = h.to_str().unwrap_or(HISTORY_FILE);
     }
 
-    let sh = shell::Shell::new(logfile);
-    sh.run()?;
+    // 起動時の設定ファイルはホームディレクトリにある場合のみ読み込む
+    let rcfile = dirs::home_dir().map(|h| h.join(RC_FILE));
+
+    // --control PATH を指定した場合は、端末の代わりに PATH の制御用ソケットからコマンドを読み込む
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let control = match args.as_slice() {
+        [] => None,
+        [opt, path] if opt == "--control" => Some(PathBuf::from(path)),
+        _ => {
+            eprintln!("usage: zerosh [--control PATH]");
+            std::process::exit(2);
+        }
+    };
 
-    Ok(())
+    let sh = Shell::new(logfile, rcfile, control);
+    let exit_val = sh.run()?;
+    std::process::exit(exit_val);
 }
@@ -3,16 +3,57 @@ use std::fmt;
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum BuiltInCmd {
     Exit(Option<i32>),
-    Jobs,
+    Jobs(bool, bool), // (-l, -v) 。 -l は各段のプロセス、 -v はジョブを起動した時点の環境も表示
+    JobsTree(Option<i32>), // jobs --tree 。 None の場合はすべてのジョブ
     Fg(i32),
+    Bg(i32),
+    Wait(Option<i32>), // None の場合はすべてのジョブを待つ
     Cd(Option<String>),
+    Export(Vec<String>),               // NAME=VALUE の並び。空の場合は一覧表示
+    Local(Vec<String>),                // NAME=VALUE または NAME の並び
+    Array(String, Vec<String>),        // NAME=(a b c) 。 (配列名, 要素)
+    BindKey(Option<(String, String)>), // None の場合は一覧表示
+    Set(Option<(bool, String)>),       // (有効にするか, オプション名)。 None の場合は一覧表示
+    Echo(Vec<String>),                 // 先頭の -n, -e, -E はオプション
+    Pwd,
+    True,
+    False,
+    Type(Vec<String>), // 種類を表示するコマンド名
+    Trap(Vec<String>), // コマンドとシグナル名の並び。 -l は一覧表示
+    Times,
+    Read(Vec<String>),   // 先頭の -p PROMPT と -r はオプション。続く変数名の並び
+    Umask(Vec<String>),  // 8 進数のマスク。空の場合は表示
+    Exec(Vec<String>),   // シェルを置き換えるコマンドと引数。空の場合は何もしない
+    Source(Vec<String>), // 現在のシェルで実行するファイル
 }
 
+/// リダイレクト。コマンドに指定された順に適用する
 #[derive(Debug, PartialEq, Clone)]
 pub enum Redirection {
-    StdOut(String), // > file
-    Both(String),   // >& file
-    Append(String), // >> file
+    StdIn(String),           // < file
+    HereDoc(String, String), // << TAG 。 (終端の文字列, 本文)
+    StdOut(String),          // > file
+    Both(String),            // >& file
+    Append(String),          // >> file
+    StdErr(String),          // 2> file
+    ErrAppend(String),       // 2>> file
+    ErrToOut,                // 2>&1
+    OutToErr,                // >&2
+}
+impl fmt::Display for Redirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Redirection::StdIn(file) => write!(f, "< {}", file),
+            Redirection::HereDoc(tag, _) => write!(f, "<< {}", tag),
+            Redirection::StdOut(file) => write!(f, "> {}", file),
+            Redirection::Both(file) => write!(f, ">& {}", file),
+            Redirection::Append(file) => write!(f, ">> {}", file),
+            Redirection::StdErr(file) => write!(f, "2> {}", file),
+            Redirection::ErrAppend(file) => write!(f, "2>> {}", file),
+            Redirection::ErrToOut => write!(f, "2>&1"),
+            Redirection::OutToErr => write!(f, ">&2"),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -27,6 +68,20 @@ pub enum Pipeline {
     Out(Box<Pipeline>, ExternalCmd),
     Both(Box<Pipeline>, ExternalCmd),
 }
+impl Pipeline {
+    /// 各段を左から順に並べ、コマンドと次の段へのパイプの種類を返す。最後の段のパイプは None
+    pub fn stages(&self) -> Vec<(&ExternalCmd, Option<&Pipe>)> {
+        let (prev, cmd, pipe) = match self {
+            Pipeline::Src(cmd) => return vec![(cmd, None)],
+            Pipeline::Out(prev, cmd) => (prev, cmd, &Pipe::StdOut),
+            Pipeline::Both(prev, cmd) => (prev, cmd, &Pipe::Both),
+        };
+        let mut stages = prev.stages();
+        stages.last_mut().unwrap().1 = Some(pipe);
+        stages.push((cmd, None));
+        stages
+    }
+}
 impl fmt::Display for Pipeline {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
@@ -37,17 +92,39 @@ impl fmt::Display for Pipeline {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct ExternalCmd {
-    pub args: Vec<String>,
-    pub redirect: Option<Redirection>,
+/// 外部コマンドの引数の単語
+///
+/// クォートと展開の区切りを部分ごとに保持し、実行する直前に expand モジュールで文字列にする。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Word {
+    pub raw: String,            // 入力された文字列。表示に用いる
+    pub segments: Vec<Segment>, // クォートとエスケープを取り除いた各部分
 }
-impl ExternalCmd {
-    pub fn filename(&self) -> &str {
-        assert_ne!(self.args.len(), 0);
-        &self.args[0]
+impl From<&str> for Word {
+    fn from(raw: &str) -> Self {
+        crate::expand::parse_word(raw)
     }
 }
+impl fmt::Display for Word {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// 単語の部分
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Segment {
+    Literal(String, bool), // そのままの文字列と、クォートの内側か。クォートの内側は空でも引数として残す
+    Var(String, bool),     // `$` に続く変数の参照と、ダブルクォートの内側か
+    CmdSubst(String, bool), // `` `cmd` `` のコマンドと、ダブルクォートの内側か
+    Glob(String),          // クォートされていない `*` と `?` の並び
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExternalCmd {
+    pub args: Vec<Word>,
+    pub redirects: Vec<Redirection>,
+}
 impl fmt::Display for ExternalCmd {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -55,15 +132,73 @@ impl fmt::Display for ExternalCmd {
             "{}",
             self.args[0..]
                 .iter()
-                .map(|s| s.as_str())
-                .collect::<Vec<&str>>()
+                .map(|s| s.to_string())
+                .chain(self.redirects.iter().map(|r| r.to_string()))
+                .collect::<Vec<String>>()
                 .join(" ")
         )
     }
 }
 
+/// 条件付きで次のジョブを実行する論理演算子
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Cond {
+    And, // && : 直前の終了コードが 0 の場合に実行
+    Or,  // || : 直前の終了コードが 0 以外の場合に実行
+}
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Cond::And => write!(f, "&&"),
+            Cond::Or => write!(f, "||"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Job {
-    BuiltIn { cmd: BuiltInCmd, is_bg: bool },
-    External { cmds: Pipeline, is_bg: bool },
+    BuiltIn {
+        cmd: BuiltInCmd,
+        is_bg: bool,
+    },
+    External {
+        cmds: Pipeline,
+        is_bg: bool,
+        timed: bool, // time を前置した場合は真。終了時に実行時間を表示する
+    },
+    /// && と || で連結したジョブ。 first と rest の各ジョブの is_bg は常に false
+    List {
+        first: Box<Job>,
+        rest: Vec<(Cond, Job)>,
+        is_bg: bool,
+    },
+    /// 制御構文
+    Compound {
+        cmd: Compound,
+        is_bg: bool,
+    },
+    /// 関数の定義 name() { body }
+    Function {
+        name: String,
+        body: Vec<Job>,
+    },
+}
+
+/// 制御構文。条件には終了コードを用いる
+#[derive(Debug, PartialEq, Clone)]
+pub enum Compound {
+    /// if cond; then then; else els; fi 。 elif は els の中の If とする
+    If {
+        cond: Vec<Job>,
+        then: Vec<Job>,
+        els: Option<Vec<Job>>,
+    },
+    /// for var in words; do body; done 。 words は実行時に展開する
+    For {
+        var: String,
+        words: Vec<String>,
+        body: Vec<Job>,
+    },
+    /// while cond; do body; done
+    While { cond: Vec<Job>, body: Vec<Job> },
 }
@@ -7,19 +7,72 @@
 //! - [x] exit
 //! - [x] jobs
 //! - [x] fg
+//! - [x] bg
+//! - [x] wait
 //! - [x] cd
+//! - [x] bindkey
+//! - [x] set
+//! - [x] export
+//! - [x] array assignment "NAME=(a b c)"
+//! - [x] local
+//! - [x] echo, pwd, true, false, type
+//! - [x] trap
+//! - [x] times, time prefix "time cmd | cmd"
+//! - [x] read "read -p PROMPT NAME..."
+//! - [x] umask "umask 022", exec "exec cmd args", source "source file"
+//!
+//! # Control structure
+//!
+//! - [x] if "if cmd; then cmd; elif cmd; then cmd; else cmd; fi"
+//! - [x] for "for NAME in a b c; do cmd; done"
+//! - [x] while "while cmd; do cmd; done"
+//! - [x] function definition "name() { cmd; }"
+//!
+//! # Quoting
+//!
+//! - [x] single quote "'a b'", double quote "\"$HOME/a b\"", backslash "a\\ b"
+//! - [x] command substitution "`cmd`"
 //!
 //! # Priority of control code
 //!
 //! - [ ] parenthesis "()","{}","``","$()"
-//! - [x] redirection ">",">>",">&"
+//! - [x] redirection ">",">>",">&","<","2>","2>>","2>&1",">&2"
+//! - [x] here document "<<TAG"
 //! - [x] pipe "|","|&"
-//! - [ ] logic operator "&&","||"
+//! - [x] logic operator "&&","||"
 //! - [x] background "&"
-//! - [ ] semicolon ";"
+//! - [x] semicolon ";"
 //!
+use crate::expand::is_var_name;
 use crate::model::*;
 use parser_combinator::*;
+use std::fmt;
+
+/// built-in command name parser
+///
+/// keyword と異なり、名前の直後が空白か制御記号か入力の終わりでなければ失敗する。
+/// これにより setsid などの外部コマンドを set と誤認しない。
+fn cmd_name<'a>(name: &'static str) -> impl Parser<'a, &'static str> {
+    move |input: &'a str| {
+        let (next_i, name) = keyword(name).parse(input)?;
+        match next_i.chars().next() {
+            Some(c) if !"&|()<>;".contains(c) && !c.is_whitespace() => Err(input),
+            _ => Ok((next_i, name)),
+        }
+    }
+}
+#[cfg(test)]
+mod cmd_name {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(cmd_name("set").parse("set"), Ok(("", "set")));
+        assert_eq!(cmd_name("set").parse("set -o"), Ok((" -o", "set")));
+        assert_eq!(cmd_name("set").parse("set&"), Ok(("&", "set")));
+        assert_eq!(cmd_name("set").parse("setsid"), Err("setsid"));
+    }
+}
 
 /// exit command parser
 fn exit_cmd<'a>() -> impl Parser<'a, Option<i32>> {
@@ -42,11 +95,17 @@ mod exit_cmd {
     }
 }
 /// jobs command parser
-fn jobs_cmd<'a>() -> impl Parser<'a, &'a str> {
+/// -l と -v を指定したかを返す。オプションは任意の順に複数指定できる
+fn jobs_cmd<'a>() -> impl Parser<'a, (bool, bool)> {
     |input| {
         let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = keyword("jobs").parse(next_i)?;
+        let (next_i, opts) = space1()
+            .skip(cmd_name("-l").or_else(cmd_name("-v")))
+            .many0()
+            .parse(next_i)?;
 
-        keyword("jobs").parse(next_i)
+        Ok((next_i, (opts.contains(&"-l"), opts.contains(&"-v"))))
     }
 }
 #[cfg(test)]
@@ -55,9 +114,41 @@ mod jobs_cmd {
 
     #[test]
     fn test() {
-        assert_eq!(jobs_cmd().parse("jobs"), Ok(("", "jobs")));
-        assert_eq!(jobs_cmd().parse("jobs &"), Ok((" &", "jobs")));
-        assert_eq!(jobs_cmd().parse("jobs |"), Ok((" |", "jobs")));
+        assert_eq!(jobs_cmd().parse("jobs"), Ok(("", (false, false))));
+        assert_eq!(jobs_cmd().parse("jobs &"), Ok((" &", (false, false))));
+        assert_eq!(jobs_cmd().parse("jobs |"), Ok((" |", (false, false))));
+        assert_eq!(jobs_cmd().parse("jobs -v"), Ok(("", (false, true))));
+        assert_eq!(jobs_cmd().parse("jobs -v; ls"), Ok(("; ls", (false, true))));
+        assert_eq!(jobs_cmd().parse("jobs -vx"), Ok((" -vx", (false, false))));
+        assert_eq!(jobs_cmd().parse("jobs -l"), Ok(("", (true, false))));
+        assert_eq!(jobs_cmd().parse("jobs -v -l &"), Ok((" &", (true, true))));
+    }
+}
+/// jobs --tree command parser
+/// ジョブ ID は % を前置してもよい
+fn jobs_tree_cmd<'a>() -> impl Parser<'a, Option<i32>> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = keyword("jobs").parse(next_i)?;
+        let (next_i, _) = space1().skip(cmd_name("--tree")).parse(next_i)?;
+
+        opt(space1().skip(opt(char('%'))).skip(int32)).parse(next_i)
+    }
+}
+#[cfg(test)]
+mod jobs_tree_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(jobs_tree_cmd().parse("jobs --tree"), Ok(("", None)));
+        assert_eq!(jobs_tree_cmd().parse("jobs --tree 1"), Ok(("", Some(1))));
+        assert_eq!(
+            jobs_tree_cmd().parse("jobs --tree %1 &"),
+            Ok((" &", Some(1)))
+        );
+        assert_eq!(jobs_tree_cmd().parse("jobs --trees"), Err("--trees"));
+        assert_eq!(jobs_tree_cmd().parse("jobs -l"), Err("-l"));
     }
 }
 /// fg command parser
@@ -81,20 +172,56 @@ mod fg_cmd {
         assert_eq!(fg_cmd().parse("fg |"), Err("|"));
     }
 }
-/// path name parser
-fn path_name<'a>() -> impl Parser<'a, String> {
+/// bg command parser
+fn bg_cmd<'a>() -> impl Parser<'a, i32> {
     |input| {
         let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = cmd_name("bg").parse(next_i)?;
+        let (next_i, _) = space1().parse(next_i)?;
 
-        // TODO: ファイルパス名の構文を調べて実装する
-        any_char
-            .pred(|c| !"&|()<>;".contains(*c) && !c.is_whitespace())
-            .many1()
-            .map(|s| s.into_iter().collect::<String>())
-            .parse(next_i)
+        int32(next_i)
     }
 }
 #[cfg(test)]
+mod bg_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(bg_cmd().parse("bg 1"), Ok(("", 1)));
+        assert_eq!(bg_cmd().parse("bg 2; jobs"), Ok(("; jobs", 2)));
+        assert_eq!(bg_cmd().parse("bg &"), Err("&"));
+        assert_eq!(bg_cmd().parse("bgrep 1"), Err("bgrep 1"));
+    }
+}
+/// wait command parser
+fn wait_cmd<'a>() -> impl Parser<'a, Option<i32>> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = cmd_name("wait").parse(next_i)?;
+
+        opt(space1().skip(int32)).parse(next_i)
+    }
+}
+#[cfg(test)]
+mod wait_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(wait_cmd().parse("wait"), Ok(("", None)));
+        assert_eq!(wait_cmd().parse("wait 1; jobs"), Ok(("; jobs", Some(1))));
+        assert_eq!(wait_cmd().parse("wait &"), Ok((" &", None)));
+        assert_eq!(wait_cmd().parse("waitpid 1"), Err("waitpid 1"));
+    }
+}
+
+/// path name parser
+fn path_name<'a>() -> impl Parser<'a, String> {
+    // TODO: ファイルパス名の構文を調べて実装する
+    symbol()
+}
+#[cfg(test)]
 mod path_name {
     use super::*;
 
@@ -104,6 +231,10 @@ mod path_name {
         assert_eq!(path_name().parse("./a"), Ok(("", "./a".to_string())));
         assert_eq!(path_name().parse("&"), Err("&"));
         assert_eq!(path_name().parse("|"), Err("|"));
+        assert_eq!(
+            path_name().parse("'a b.txt' c"),
+            Ok((" c", "'a b.txt'".to_string()))
+        );
     }
 }
 /// cd command parser
@@ -127,13 +258,286 @@ mod cd_cmd {
         assert_eq!(cd_cmd().parse("cd |"), Ok((" |", None)));
     }
 }
+/// quoted string or symbol parser
+fn quoted_or_symbol<'a>() -> impl Parser<'a, String> {
+    lexeme(single_quoted_string())
+        .or_else(lexeme(double_quoted_string()))
+        .or_else(symbol())
+}
+#[cfg(test)]
+mod quoted_or_symbol {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            quoted_or_symbol().parse(" 'a b'"),
+            Ok(("", "a b".to_string()))
+        );
+        assert_eq!(
+            quoted_or_symbol().parse("\"a | b\" c"),
+            Ok((" c", "a | b".to_string()))
+        );
+        assert_eq!(
+            quoted_or_symbol().parse("ls -l"),
+            Ok((" -l", "ls".to_string()))
+        );
+        assert_eq!(quoted_or_symbol().parse("&"), Err("&"));
+    }
+}
+
+/// bindkey command parser
+fn bindkey_cmd<'a>() -> impl Parser<'a, Option<(String, String)>> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = cmd_name("bindkey").parse(next_i)?;
+
+        opt(quoted_or_symbol().join(quoted_or_symbol())).parse(next_i)
+    }
+}
+#[cfg(test)]
+mod bindkey_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(bindkey_cmd().parse("bindkey"), Ok(("", None)));
+        assert_eq!(
+            bindkey_cmd().parse("bindkey '\\C-t' 'jobs'"),
+            Ok(("", Some(("\\C-t".to_string(), "jobs".to_string()))))
+        );
+        assert_eq!(
+            bindkey_cmd().parse("bindkey '\\C-l' \"ls -l | less\""),
+            Ok(("", Some(("\\C-l".to_string(), "ls -l | less".to_string()))))
+        );
+        assert_eq!(bindkey_cmd().parse("bindkey &"), Ok((" &", None)));
+    }
+}
+
+/// set command parser
+fn set_cmd<'a>() -> impl Parser<'a, Option<(bool, String)>> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = cmd_name("set").parse(next_i)?;
+
+        // -b と +b は notify オプションの短縮形
+        let notify = lexeme(cmd_name("-b").or_else(cmd_name("+b")))
+            .map(|o| (o == "-b", "notify".to_string()));
+        opt(lexeme(keyword("-o").or_else(keyword("+o")))
+            .map(|o| o == "-o")
+            .join(symbol())
+            .or_else(notify))
+        .parse(next_i)
+    }
+}
+#[cfg(test)]
+mod set_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(set_cmd().parse("set"), Ok(("", None)));
+        assert_eq!(set_cmd().parse("setsid ls"), Err("setsid ls"));
+        assert_eq!(
+            set_cmd().parse("set -o title"),
+            Ok(("", Some((true, "title".to_string()))))
+        );
+        assert_eq!(
+            set_cmd().parse("set +o title &"),
+            Ok((" &", Some((false, "title".to_string()))))
+        );
+        assert_eq!(
+            set_cmd().parse("set -b"),
+            Ok(("", Some((true, "notify".to_string()))))
+        );
+        assert_eq!(
+            set_cmd().parse("set +b;"),
+            Ok((";", Some((false, "notify".to_string()))))
+        );
+    }
+}
+
+/// export command parser
+///
+/// 値の環境変数は実行時に展開するため、 NAME=VALUE の形式のまま返す。
+fn export_cmd<'a>() -> impl Parser<'a, Vec<String>> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = cmd_name("export").parse(next_i)?;
+
+        symbol().many0().parse(next_i)
+    }
+}
+#[cfg(test)]
+mod export_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(export_cmd().parse("export"), Ok(("", vec![])));
+        assert_eq!(
+            export_cmd().parse("export A=1 PATH=$PATH:/opt/bin; ls"),
+            Ok((
+                "; ls",
+                vec!["A=1".to_string(), "PATH=$PATH:/opt/bin".to_string()]
+            ))
+        );
+        assert_eq!(export_cmd().parse("exports"), Err("exports"));
+    }
+}
+
+/// local command parser
+///
+/// export と同様に、値の環境変数は実行時に展開するため、そのまま返す。
+fn local_cmd<'a>() -> impl Parser<'a, Vec<String>> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = cmd_name("local").parse(next_i)?;
+
+        symbol().many0().parse(next_i)
+    }
+}
+#[cfg(test)]
+mod local_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(local_cmd().parse("local"), Ok(("", vec![])));
+        assert_eq!(
+            local_cmd().parse("local i x=\"$1\"; ls"),
+            Ok(("; ls", vec!["i".to_string(), "x=\"$1\"".to_string()]))
+        );
+        assert_eq!(local_cmd().parse("locale"), Err("locale"));
+    }
+}
+
+/// variable name parser
+fn var_name<'a>() -> impl Parser<'a, String> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        any_char
+            .pred(|c| *c == '_' || c.is_ascii_alphanumeric())
+            .many1()
+            .map(|cs| cs.into_iter().collect::<String>())
+            .pred(|name| is_var_name(name))
+            .parse(next_i)
+    }
+}
+
+/// array assignment parser
+///
+/// `NAME=(a b c)` の形式。要素の環境変数は実行時に展開するため、そのまま返す。
+fn array_cmd<'a>() -> impl Parser<'a, (String, Vec<String>)> {
+    |input| {
+        let (next_i, name) = var_name().parse(input)?;
+        let (next_i, _) = keyword("=(").parse(next_i)?;
+        let (next_i, values) = symbol().many0().parse(next_i)?;
+        let (next_i, _) = space0().parse(next_i)?;
+        let (next_i, _) = keyword(")").parse(next_i)?;
+
+        Ok((next_i, (name, values)))
+    }
+}
+#[cfg(test)]
+mod array_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            array_cmd().parse("arr=(a $B *.rs ); ls"),
+            Ok((
+                "; ls",
+                (
+                    "arr".to_string(),
+                    vec!["a".to_string(), "$B".to_string(), "*.rs".to_string()]
+                )
+            ))
+        );
+        assert_eq!(
+            array_cmd().parse("e=()"),
+            Ok(("", ("e".to_string(), vec![])))
+        );
+        assert!(array_cmd().parse("arr=(a b").is_err());
+        assert!(array_cmd().parse("1a=(a)").is_err());
+        assert!(array_cmd().parse("arr=a").is_err());
+        assert!(array_cmd().parse("arr = (a)").is_err());
+    }
+}
+
+/// simple built-in command parser
+///
+/// 引数は実行時に展開するため、そのまま返す。
+/// 組み込みコマンドはパイプとリダイレクトに対応しないため、直後に | やリダイレクトが続く場合は失敗し、
+/// 同じ名前の外部コマンドとして実行する。
+fn simple_cmd<'a>(name: &'static str) -> impl Parser<'a, Vec<String>> {
+    move |input| {
+        let (next_i, _) = space0().parse(input)?;
+        let (next_i, _) = cmd_name(name).parse(next_i)?;
+        let (next_i, args) = symbol().many0().parse(next_i)?;
+
+        let rest = next_i.trim_start();
+        if rest.starts_with(['<', '>']) || (rest.starts_with('|') && !rest.starts_with("||")) {
+            return Err(input);
+        }
+        Ok((next_i, args))
+    }
+}
+#[cfg(test)]
+mod simple_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            simple_cmd("echo").parse("echo -n a \"b c\"; ls"),
+            Ok((
+                "; ls",
+                vec!["-n".to_string(), "a".to_string(), "\"b c\"".to_string()]
+            ))
+        );
+        assert_eq!(simple_cmd("pwd").parse("pwd"), Ok(("", vec![])));
+        assert_eq!(
+            simple_cmd("true").parse("true || ls"),
+            Ok((" || ls", vec![]))
+        );
+        assert_eq!(
+            simple_cmd("echo").parse("echo a | cat"),
+            Err("echo a | cat")
+        );
+        assert_eq!(simple_cmd("echo").parse("echo a > f"), Err("echo a > f"));
+        assert_eq!(simple_cmd("echo").parse("echo >f a"), Err("echo >f a"));
+        assert_eq!(simple_cmd("type").parse("types"), Err("types"));
+    }
+}
+
 /// built-in command parser
 fn built_in_cmd<'a>() -> impl Parser<'a, BuiltInCmd> {
     exit_cmd()
         .map(BuiltInCmd::Exit)
-        .or_else(jobs_cmd().map(|_| BuiltInCmd::Jobs))
+        .or_else(jobs_tree_cmd().map(BuiltInCmd::JobsTree))
+        .or_else(jobs_cmd().map(|(long, verbose)| BuiltInCmd::Jobs(long, verbose)))
         .or_else(fg_cmd().map(BuiltInCmd::Fg))
+        .or_else(bg_cmd().map(BuiltInCmd::Bg))
+        .or_else(wait_cmd().map(BuiltInCmd::Wait))
         .or_else(cd_cmd().map(BuiltInCmd::Cd))
+        .or_else(bindkey_cmd().map(BuiltInCmd::BindKey))
+        .or_else(set_cmd().map(BuiltInCmd::Set))
+        .or_else(export_cmd().map(BuiltInCmd::Export))
+        .or_else(local_cmd().map(BuiltInCmd::Local))
+        .or_else(array_cmd().map(|(name, values)| BuiltInCmd::Array(name, values)))
+        .or_else(simple_cmd("echo").map(BuiltInCmd::Echo))
+        .or_else(simple_cmd("pwd").map(|_| BuiltInCmd::Pwd))
+        .or_else(simple_cmd("true").map(|_| BuiltInCmd::True))
+        .or_else(simple_cmd("false").map(|_| BuiltInCmd::False))
+        .or_else(simple_cmd("type").map(BuiltInCmd::Type))
+        .or_else(simple_cmd("trap").map(BuiltInCmd::Trap))
+        .or_else(simple_cmd("times").map(|_| BuiltInCmd::Times))
+        .or_else(simple_cmd("read").map(BuiltInCmd::Read))
+        .or_else(simple_cmd("umask").map(BuiltInCmd::Umask))
+        .or_else(simple_cmd("exec").map(BuiltInCmd::Exec))
+        .or_else(simple_cmd("source").map(BuiltInCmd::Source))
 }
 #[cfg(test)]
 mod built_in_cmd {
@@ -149,8 +553,24 @@ mod built_in_cmd {
             built_in_cmd().parse("exit ;"),
             Ok((" ;", BuiltInCmd::Exit(None)))
         );
-        assert_eq!(built_in_cmd().parse("jobs"), Ok(("", BuiltInCmd::Jobs)));
+        assert_eq!(
+            built_in_cmd().parse("jobs"),
+            Ok(("", BuiltInCmd::Jobs(false, false)))
+        );
+        assert_eq!(
+            built_in_cmd().parse("jobs --tree %0"),
+            Ok(("", BuiltInCmd::JobsTree(Some(0))))
+        );
+        assert_eq!(
+            built_in_cmd().parse("jobs -v"),
+            Ok(("", BuiltInCmd::Jobs(false, true)))
+        );
         assert_eq!(built_in_cmd().parse("fg 1"), Ok(("", BuiltInCmd::Fg(1))));
+        assert_eq!(built_in_cmd().parse("bg 1"), Ok(("", BuiltInCmd::Bg(1))));
+        assert_eq!(
+            built_in_cmd().parse("wait"),
+            Ok(("", BuiltInCmd::Wait(None)))
+        );
         assert_eq!(
             built_in_cmd().parse("cd ~/app"),
             Ok(("", BuiltInCmd::Cd(Some("~/app".to_string()))))
@@ -162,238 +582,905 @@ mod built_in_cmd {
                 BuiltInCmd::Exit(Some(1))
             ))
         );
+        assert_eq!(
+            built_in_cmd().parse("echo -e a\\tb && false"),
+            Ok((
+                " && false",
+                BuiltInCmd::Echo(vec!["-e".to_string(), "a\\tb".to_string()])
+            ))
+        );
+        assert_eq!(built_in_cmd().parse("pwd"), Ok(("", BuiltInCmd::Pwd)));
+        assert_eq!(built_in_cmd().parse("true"), Ok(("", BuiltInCmd::True)));
+        assert_eq!(built_in_cmd().parse("false;"), Ok((";", BuiltInCmd::False)));
+        assert_eq!(
+            built_in_cmd().parse("type cd ls"),
+            Ok((
+                "",
+                BuiltInCmd::Type(vec!["cd".to_string(), "ls".to_string()])
+            ))
+        );
+        assert_eq!(
+            built_in_cmd().parse("trap 'echo bye' INT TERM"),
+            Ok((
+                "",
+                BuiltInCmd::Trap(vec![
+                    "'echo bye'".to_string(),
+                    "INT".to_string(),
+                    "TERM".to_string()
+                ])
+            ))
+        );
+        assert_eq!(built_in_cmd().parse("times"), Ok(("", BuiltInCmd::Times)));
+        assert_eq!(
+            built_in_cmd().parse("read -p 'name? ' first rest"),
+            Ok((
+                "",
+                BuiltInCmd::Read(vec![
+                    "-p".to_string(),
+                    "'name? '".to_string(),
+                    "first".to_string(),
+                    "rest".to_string()
+                ])
+            ))
+        );
+        assert_eq!(
+            built_in_cmd().parse("umask 022"),
+            Ok(("", BuiltInCmd::Umask(vec!["022".to_string()])))
+        );
+        assert_eq!(
+            built_in_cmd().parse("exec ls -l"),
+            Ok((
+                "",
+                BuiltInCmd::Exec(vec!["ls".to_string(), "-l".to_string()])
+            ))
+        );
+        assert_eq!(
+            built_in_cmd().parse("source env.sh; ls"),
+            Ok(("; ls", BuiltInCmd::Source(vec!["env.sh".to_string()])))
+        );
+        assert!(built_in_cmd().parse("echo a | cat").is_err());
+    }
+}
+
+/// symbol parser
+///
+/// クォートとバックスラッシュは実行時の展開で取り除くため、そのまま返す。
+/// クォートの内側とバックスラッシュの直後では、空白と制御記号も単語の一部となる。
+/// クォートが閉じていない場合と、入力がバックスラッシュで終わる場合は失敗する。
+fn symbol<'a>() -> impl Parser<'a, String> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+
+        let mut quote = None;
+        let mut escaped = false;
+        let mut end = next_i.len();
+        for (i, c) in next_i.char_indices() {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some('"') if c == '\\' => escaped = true,
+                Some(_) => {}
+                None if c == '\'' || c == '"' || c == '`' => quote = Some(c),
+                None if c == '\\' => escaped = true,
+                None if "&|()<>;".contains(c) || c.is_whitespace() => {
+                    end = i;
+                    break;
+                }
+                None => {}
+            }
+        }
+        if end == 0 || quote.is_some() || escaped {
+            return Err(next_i);
+        }
+
+        Ok((&next_i[end..], next_i[..end].to_string()))
+    }
+}
+#[cfg(test)]
+mod symbol {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(symbol().parse("ls"), Ok(("", "ls".to_string())));
+        assert_eq!(symbol().parse("ls -laF"), Ok((" -laF", "ls".to_string())));
+        assert_eq!(symbol().parse("&"), Err("&"));
+        assert_eq!(symbol().parse("|"), Err("|"));
+        assert_eq!(
+            symbol().parse("\"foo bar\"|"),
+            Ok(("|", "\"foo bar\"".to_string()))
+        );
+        assert_eq!(
+            symbol().parse("a'b; c'\\ d e"),
+            Ok((" e", "a'b; c'\\ d".to_string()))
+        );
+        assert_eq!(
+            symbol().parse("\"a\\\" 'b\" c"),
+            Ok((" c", "\"a\\\" 'b\"".to_string()))
+        );
+        assert_eq!(symbol().parse("'$HOME'"), Ok(("", "'$HOME'".to_string())));
+        assert_eq!(
+            symbol().parse("a`ls -l; date`b c"),
+            Ok((" c", "a`ls -l; date`b".to_string()))
+        );
+        assert_eq!(symbol().parse("\"\""), Ok(("", "\"\"".to_string())));
+        assert_eq!(symbol().parse("'a b"), Err("'a b"));
+        assert_eq!(symbol().parse("a\\"), Err("a\\"));
+    }
+}
+
+/// redirection parser
+///
+/// `2>&1` と `>&2` はファイルではなくファイルディスクリプタの複製となる。
+/// `<<TAG` はヒアドキュメントとし、 TAG のクォートを取り除いたものを終端の文字列とする。
+/// 本文は空のままで、パースした後に [crate::heredoc::fill] で設定する。
+fn redirect<'a>() -> impl Parser<'a, Redirection> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+
+        // 複製は直後が区切りの場合のみとし、 >&2.log などはファイル名とみなす
+        if let Ok((next_i, tok)) = cmd_name("2>&1")
+            .or_else(cmd_name("1>&2"))
+            .or_else(cmd_name(">&2"))
+            .parse(next_i)
+        {
+            let red = match tok {
+                "2>&1" => Redirection::ErrToOut,
+                _ => Redirection::OutToErr,
+            };
+            return Ok((next_i, red));
+        }
+
+        // 長いものから順に試さないと、短いものにマッチしてしまう
+        let (next_i, tok) = keyword("2>>")
+            .or_else(keyword("2>"))
+            .or_else(keyword("1>"))
+            .or_else(keyword(">&"))
+            .or_else(keyword(">>"))
+            .or_else(keyword(">"))
+            .or_else(keyword("<<"))
+            .or_else(keyword("<"))
+            .parse(next_i)?;
+        let (next_i, _) = space0().parse(next_i)?;
+        let (next_i, file) = path_name().parse(next_i)?;
+
+        let red = match tok {
+            "<" => Redirection::StdIn(file),
+            "<<" => {
+                let tag = file.chars().filter(|c| !"'\"\\".contains(*c)).collect();
+                Redirection::HereDoc(tag, String::new())
+            }
+            ">" | "1>" => Redirection::StdOut(file),
+            ">&" => Redirection::Both(file),
+            ">>" => Redirection::Append(file),
+            "2>" => Redirection::StdErr(file),
+            "2>>" => Redirection::ErrAppend(file),
+            _ => unreachable!(),
+        };
+
+        Ok((next_i, red))
+    }
+}
+#[cfg(test)]
+mod redirect {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            redirect().parse("> a.txt"),
+            Ok(("", Redirection::StdOut("a.txt".to_string())))
+        );
+        assert_eq!(
+            redirect().parse(">& a.txt"),
+            Ok(("", Redirection::Both("a.txt".to_string())))
+        );
+        assert_eq!(
+            redirect().parse("<a.txt > b.txt"),
+            Ok((" > b.txt", Redirection::StdIn("a.txt".to_string())))
+        );
+        assert_eq!(
+            redirect().parse(" 2>err.log"),
+            Ok(("", Redirection::StdErr("err.log".to_string())))
+        );
+        assert_eq!(
+            redirect().parse("2>> err.log"),
+            Ok(("", Redirection::ErrAppend("err.log".to_string())))
+        );
+        assert_eq!(
+            redirect().parse("2>&1 |"),
+            Ok((" |", Redirection::ErrToOut))
+        );
+        assert_eq!(redirect().parse(">&2"), Ok(("", Redirection::OutToErr)));
+        assert_eq!(
+            redirect().parse(">&2.log"),
+            Ok(("", Redirection::Both("2.log".to_string())))
+        );
+        assert_eq!(
+            redirect().parse("<<EOF | cat"),
+            Ok((
+                " | cat",
+                Redirection::HereDoc("EOF".to_string(), String::new())
+            ))
+        );
+        assert_eq!(
+            redirect().parse("<< 'END'"),
+            Ok(("", Redirection::HereDoc("END".to_string(), String::new())))
+        );
+        assert_eq!(redirect().parse("2 > a.txt"), Err("2 > a.txt"));
+        assert!(redirect().parse("> |").is_err());
+    }
+}
+
+/// コマンド名の位置では外部コマンドとみなさない予約語
+const RESERVED_WORDS: &[&str] = &[
+    "if", "then", "elif", "else", "fi", "for", "do", "done", "while", "{", "}",
+];
+
+/// external command parser
+///
+/// リダイレクトは引数の前後や間のどこにでも指定でき、指定された順に適用する。
+fn external_cmd<'a>() -> impl Parser<'a, ExternalCmd> {
+    enum Item {
+        Arg(String),
+        Redirect(Redirection),
+    }
+
+    |input| {
+        let (next_i, items) = redirect()
+            .map(Item::Redirect)
+            .or_else(symbol().map(Item::Arg))
+            .many1()
+            .parse(input)?;
+
+        let mut args: Vec<Word> = Vec::new();
+        let mut redirects = Vec::new();
+        for item in items {
+            match item {
+                Item::Arg(arg) => args.push(arg.as_str().into()),
+                Item::Redirect(red) => redirects.push(red),
+            }
+        }
+        // 予約語はコマンド名とせず、制御構文の区切りとする
+        if args.is_empty() || RESERVED_WORDS.contains(&args[0].raw.as_str()) {
+            return Err(input);
+        }
+
+        Ok((next_i, ExternalCmd { args, redirects }))
+    }
+}
+#[cfg(test)]
+mod external_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            external_cmd().parse("ls -laF"),
+            Ok((
+                "",
+                ExternalCmd {
+                    args: vec!["ls".into(), "-laF".into()],
+                    redirects: vec![],
+                }
+            ))
+        );
+        assert_eq!(
+            external_cmd().parse("ls -laF |"),
+            Ok((
+                " |",
+                ExternalCmd {
+                    args: vec!["ls".into(), "-laF".into()],
+                    redirects: vec![],
+                }
+            ))
+        );
+        assert_eq!(
+            external_cmd().parse("ls -laF > a.log"),
+            Ok((
+                "",
+                ExternalCmd {
+                    args: vec!["ls".into(), "-laF".into()],
+                    redirects: vec![Redirection::StdOut("a.log".to_string())],
+                }
+            ))
+        );
+        assert_eq!(
+            external_cmd().parse("grep \"foo bar\" a\\ b.txt|"),
+            Ok((
+                "|",
+                ExternalCmd {
+                    args: vec!["grep".into(), "\"foo bar\"".into(), "a\\ b.txt".into()],
+                    redirects: vec![],
+                }
+            ))
+        );
+        assert_eq!(external_cmd().parse(" fi"), Err(" fi"));
+        assert_eq!(
+            external_cmd().parse("echo done"),
+            Ok((
+                "",
+                ExternalCmd {
+                    args: vec!["echo".into(), "done".into()],
+                    redirects: vec![],
+                }
+            ))
+        );
+        assert_eq!(
+            external_cmd().parse("sort < in.txt > out.txt"),
+            Ok((
+                "",
+                ExternalCmd {
+                    args: vec!["sort".into()],
+                    redirects: vec![
+                        Redirection::StdIn("in.txt".to_string()),
+                        Redirection::StdOut("out.txt".to_string())
+                    ],
+                }
+            ))
+        );
+        assert_eq!(
+            external_cmd().parse("sort >> out.txt <in.txt |"),
+            Ok((
+                " |",
+                ExternalCmd {
+                    args: vec!["sort".into()],
+                    redirects: vec![
+                        Redirection::Append("out.txt".to_string()),
+                        Redirection::StdIn("in.txt".to_string())
+                    ],
+                }
+            ))
+        );
+        assert_eq!(
+            external_cmd().parse("2>/dev/null make -j 2 > out.log 2>&1;"),
+            Ok((
+                ";",
+                ExternalCmd {
+                    args: vec!["make".into(), "-j".into(), "2".into()],
+                    redirects: vec![
+                        Redirection::StdErr("/dev/null".to_string()),
+                        Redirection::StdOut("out.log".to_string()),
+                        Redirection::ErrToOut
+                    ],
+                }
+            ))
+        );
+        // リダイレクトのみでコマンドがない場合は失敗
+        assert_eq!(external_cmd().parse("> a.log"), Err("> a.log"));
+    }
+}
+
+/// pipe control simbol parser
+///
+/// 論理演算子の "||" はパイプとみなさない。
+fn pipe<'a>() -> impl Parser<'a, Pipe> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        if next_i.starts_with("||") {
+            return Err(next_i);
+        }
+        // '|' は最後にしないとなんでも '|' にマッチしてしまう
+        let (next_i, p) = keyword("|&").or_else(keyword("|")).parse(next_i)?;
+
+        match p {
+            "|" => Ok((next_i, Pipe::StdOut)),
+            "|&" => Ok((next_i, Pipe::Both)),
+            _ => unreachable!(),
+        }
+    }
+}
+#[cfg(test)]
+mod pipe {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(pipe().parse("|"), Ok(("", Pipe::StdOut)));
+        assert_eq!(pipe().parse("|&"), Ok(("", Pipe::Both)));
+        assert_eq!(pipe().parse("|| ls"), Err("|| ls"));
+    }
+}
+
+/// pipeline parser
+fn pipeline<'a>() -> impl Parser<'a, Pipeline> {
+    |input| {
+        let (next_i, cmd) = external_cmd().parse(input)?;
+        let (next_i, cmds) = pipe().join(external_cmd()).many0().parse(next_i)?;
+
+        let mut acc = Pipeline::Src(cmd.clone());
+        for (p, cmd) in cmds {
+            acc = match &p {
+                Pipe::StdOut => Pipeline::Out(Box::new(acc), cmd),
+                Pipe::Both => Pipeline::Both(Box::new(acc), cmd),
+            };
+        }
+        Ok((next_i, acc))
+    }
+}
+#[cfg(test)]
+mod pipeline {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            pipeline().parse("foo | bar"),
+            Ok((
+                "",
+                Pipeline::Out(
+                    Box::new(Pipeline::Src(ExternalCmd {
+                        args: vec!["foo".into()],
+                        redirects: vec![],
+                    })),
+                    ExternalCmd {
+                        args: vec!["bar".into()],
+                        redirects: vec![],
+                    }
+                )
+            ))
+        );
+        assert_eq!(
+            pipeline().parse("foo |& bar"),
+            Ok((
+                "",
+                Pipeline::Both(
+                    Box::new(Pipeline::Src(ExternalCmd {
+                        args: vec!["foo".into()],
+                        redirects: vec![],
+                    })),
+                    ExternalCmd {
+                        args: vec!["bar".into()],
+                        redirects: vec![],
+                    }
+                )
+            ))
+        );
+        assert_eq!(
+            pipeline().parse("foo | bar |& buz"),
+            Ok((
+                "",
+                Pipeline::Both(
+                    Box::new(Pipeline::Out(
+                        Box::new(Pipeline::Src(ExternalCmd {
+                            args: vec!["foo".into()],
+                            redirects: vec![],
+                        })),
+                        ExternalCmd {
+                            args: vec!["bar".into()],
+                            redirects: vec![],
+                        }
+                    )),
+                    ExternalCmd {
+                        args: vec!["buz".into()],
+                        redirects: vec![],
+                    }
+                )
+            ))
+        );
+    }
+}
+
+/// job terminator parser
+///
+/// バックグラウンド実行の "&" なら true 、 ";" なら false を返す。
+/// 論理演算子の "&&" は "&" とみなさない。
+fn job_term<'a>() -> impl Parser<'a, bool> {
+    |input| {
+        let (next_i, _) = space0().parse(input)?;
+        if next_i.starts_with("&&") {
+            return Err(next_i);
+        }
+
+        keyword("&")
+            .or_else(keyword(";"))
+            .map(|t| t == "&")
+            .parse(next_i)
+    }
+}
+#[cfg(test)]
+mod job_term {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(job_term().parse(" & ls"), Ok((" ls", true)));
+        assert_eq!(job_term().parse("; ls"), Ok((" ls", false)));
+        assert_eq!(job_term().parse("&& ls"), Err("&& ls"));
+        assert_eq!(job_term().parse("| ls"), Err("| ls"));
     }
 }
 
-/// symbol parser
-fn symbol<'a>() -> impl Parser<'a, String> {
+/// logical operator parser
+fn cond<'a>() -> impl Parser<'a, Cond> {
     |input| {
         let (next_i, _) = space0().parse(input)?;
+        let (next_i, c) = keyword("&&").or_else(keyword("||")).parse(next_i)?;
 
-        any_char
-            .pred(|c| !"&|()<>;".contains(*c) && !c.is_whitespace())
-            .many1()
-            .map(|cs| cs.into_iter().collect::<String>())
-            .parse(next_i)
+        match c {
+            "&&" => Ok((next_i, Cond::And)),
+            "||" => Ok((next_i, Cond::Or)),
+            _ => unreachable!(),
+        }
     }
 }
 #[cfg(test)]
-mod symbol {
+mod cond {
     use super::*;
 
     #[test]
     fn test() {
-        assert_eq!(symbol().parse("ls"), Ok(("", "ls".to_string())));
-        assert_eq!(symbol().parse("ls -laF"), Ok((" -laF", "ls".to_string())));
-        assert_eq!(symbol().parse("&"), Err("&"));
-        assert_eq!(symbol().parse("|"), Err("|"));
+        assert_eq!(cond().parse(" && ls"), Ok((" ls", Cond::And)));
+        assert_eq!(cond().parse("|| ls"), Ok((" ls", Cond::Or)));
+        assert_eq!(cond().parse("& ls"), Err("& ls"));
+        assert_eq!(cond().parse("| ls"), Err("| ls"));
     }
 }
 
-fn redirect<'a>() -> impl Parser<'a, Redirection> {
-    |input| {
+/// reserved word parser
+fn reserved<'a>(word: &'static str) -> impl Parser<'a, &'static str> {
+    move |input| {
         let (next_i, _) = space0().parse(input)?;
-        let (next_i, tok) = keyword(">&")
-            .or_else(keyword(">>"))
-            .or_else(keyword(">")) // 短いのを最後にしないと全部 '>' にマッチしてしまう
-            .parse(next_i)?;
-        let (next_i, _) = space0().parse(next_i)?;
-        let (next_i, file) = path_name().parse(next_i)?;
+        cmd_name(word).parse(next_i)
+    }
+}
 
-        let red = match tok {
-            ">" => Redirection::StdOut(file),
-            ">&" => Redirection::Both(file),
-            ">>" => Redirection::Append(file),
-            _ => unreachable!(),
-        };
+/// 制御構文の中のジョブの並び
+/// 予約語で始まるジョブはないため、次の予約語の直前で終わる
+fn job_list<'a>() -> impl Parser<'a, Vec<Job>> {
+    job().many1()
+}
 
-        Ok((next_i, red))
+/// if または elif の後の部分の parser
+fn if_rest(input: &str) -> ParseResult<'_, Compound> {
+    let (next_i, cond) = job_list().parse(input)?;
+    let (next_i, _) = reserved("then").parse(next_i)?;
+    let (next_i, then) = job_list().parse(next_i)?;
+
+    let (next_i, els) = if let Ok((next_i, _)) = reserved("elif").parse(next_i) {
+        let (next_i, cmd) = if_rest(next_i)?;
+        return Ok((
+            next_i,
+            Compound::If {
+                cond,
+                then,
+                els: Some(vec![Job::Compound { cmd, is_bg: false }]),
+            },
+        ));
+    } else if let Ok((next_i, _)) = reserved("else").parse(next_i) {
+        let (next_i, els) = job_list().parse(next_i)?;
+        (next_i, Some(els))
+    } else {
+        (next_i, None)
+    };
+    let (next_i, _) = reserved("fi").parse(next_i)?;
+
+    Ok((next_i, Compound::If { cond, then, els }))
+}
+
+/// if command parser
+fn if_cmd<'a>() -> impl Parser<'a, Compound> {
+    |input| {
+        let (next_i, _) = reserved("if").parse(input)?;
+        if_rest(next_i)
     }
 }
 #[cfg(test)]
-mod redirect {
+mod if_cmd {
     use super::*;
 
     #[test]
     fn test() {
+        let ext = |args: &[&str]| Job::External {
+            cmds: Pipeline::Src(ExternalCmd {
+                args: args.iter().map(|s| (*s).into()).collect(),
+                redirects: vec![],
+            }),
+            is_bg: false,
+            timed: false,
+        };
+
         assert_eq!(
-            redirect().parse("> a.txt"),
-            Ok(("", Redirection::StdOut("a.txt".to_string())))
+            if_cmd().parse("if test -f a; then echo fi; fi; ls"),
+            Ok((
+                "; ls",
+                Compound::If {
+                    cond: vec![ext(&["test", "-f", "a"])],
+                    then: vec![Job::BuiltIn {
+                        cmd: BuiltInCmd::Echo(vec!["fi".to_string()]),
+                        is_bg: false
+                    }],
+                    els: None,
+                }
+            ))
         );
         assert_eq!(
-            redirect().parse(">& a.txt"),
-            Ok(("", Redirection::Both("a.txt".to_string())))
+            if_cmd().parse("if a; b; then c; elif d; then e; else f; fi"),
+            Ok((
+                "",
+                Compound::If {
+                    cond: vec![ext(&["a"]), ext(&["b"])],
+                    then: vec![ext(&["c"])],
+                    els: Some(vec![Job::Compound {
+                        cmd: Compound::If {
+                            cond: vec![ext(&["d"])],
+                            then: vec![ext(&["e"])],
+                            els: Some(vec![ext(&["f"])]),
+                        },
+                        is_bg: false,
+                    }]),
+                }
+            ))
         );
+        assert!(if_cmd().parse("if a; then b").is_err());
+        assert!(if_cmd().parse("if a; then; fi").is_err());
+        assert!(if_cmd().parse("if a then b; fi").is_err());
+        assert!(if_cmd().parse("ifconfig").is_err());
     }
 }
 
-/// external command parser
-fn external_cmd<'a>() -> impl Parser<'a, ExternalCmd> {
-    symbol().many1().and_then(|args| {
-        opt(redirect()).map(move |out| ExternalCmd {
-            args: args.clone(),
-            redirect: out,
-        })
-    })
+/// for command parser
+fn for_cmd<'a>() -> impl Parser<'a, Compound> {
+    |input| {
+        let (next_i, _) = reserved("for").parse(input)?;
+        let (next_i, var) = symbol().pred(|v| is_var_name(v)).parse(next_i)?;
+        let (next_i, _) = reserved("in").parse(next_i)?;
+        let (next_i, words) = symbol().many0().parse(next_i)?;
+        let (next_i, _) = lexeme(keyword(";")).parse(next_i)?;
+        let (next_i, _) = reserved("do").parse(next_i)?;
+        let (next_i, body) = job_list().parse(next_i)?;
+        let (next_i, _) = reserved("done").parse(next_i)?;
+
+        Ok((next_i, Compound::For { var, words, body }))
+    }
 }
 #[cfg(test)]
-mod external_cmd {
+mod for_cmd {
     use super::*;
 
     #[test]
     fn test() {
+        let echo = |arg: &str| Job::BuiltIn {
+            cmd: BuiltInCmd::Echo(vec![arg.to_string()]),
+            is_bg: false,
+        };
+
         assert_eq!(
-            external_cmd().parse("ls -laF"),
+            for_cmd().parse("for x in a \"b c\" *.rs; do echo $x; done &"),
+            Ok((
+                " &",
+                Compound::For {
+                    var: "x".to_string(),
+                    words: vec!["a".to_string(), "\"b c\"".to_string(), "*.rs".to_string()],
+                    body: vec![echo("$x")],
+                }
+            ))
+        );
+        assert_eq!(
+            for_cmd().parse("for x in; do echo done; done"),
             Ok((
                 "",
-                ExternalCmd {
-                    args: vec!["ls".to_string(), "-laF".to_string()],
-                    redirect: None,
+                Compound::For {
+                    var: "x".to_string(),
+                    words: vec![],
+                    body: vec![echo("done")],
                 }
             ))
         );
+        assert!(for_cmd().parse("for 1x in a; do echo; done").is_err());
+        assert!(for_cmd().parse("for x in a do echo; done").is_err());
+        assert!(for_cmd().parse("for x; do echo; done").is_err());
+    }
+}
+
+/// while command parser
+fn while_cmd<'a>() -> impl Parser<'a, Compound> {
+    |input| {
+        let (next_i, _) = reserved("while").parse(input)?;
+        let (next_i, cond) = job_list().parse(next_i)?;
+        let (next_i, _) = reserved("do").parse(next_i)?;
+        let (next_i, body) = job_list().parse(next_i)?;
+        let (next_i, _) = reserved("done").parse(next_i)?;
+
+        Ok((next_i, Compound::While { cond, body }))
+    }
+}
+#[cfg(test)]
+mod while_cmd {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let ext = |args: &[&str]| Job::External {
+            cmds: Pipeline::Src(ExternalCmd {
+                args: args.iter().map(|s| (*s).into()).collect(),
+                redirects: vec![],
+            }),
+            is_bg: false,
+            timed: false,
+        };
+
         assert_eq!(
-            external_cmd().parse("ls -laF |"),
+            while_cmd().parse("while test -f a; do sleep 1; cd; done"),
             Ok((
-                " |",
-                ExternalCmd {
-                    args: vec!["ls".to_string(), "-laF".to_string()],
-                    redirect: None,
+                "",
+                Compound::While {
+                    cond: vec![ext(&["test", "-f", "a"])],
+                    body: vec![
+                        ext(&["sleep", "1"]),
+                        Job::BuiltIn {
+                            cmd: BuiltInCmd::Cd(None),
+                            is_bg: false
+                        }
+                    ],
                 }
             ))
         );
+        // 入れ子
         assert_eq!(
-            external_cmd().parse("ls -laF > a.log"),
+            while_cmd().parse("while a; do if b; then c; fi; done"),
             Ok((
                 "",
-                ExternalCmd {
-                    args: vec!["ls".to_string(), "-laF".to_string()],
-                    redirect: Some(Redirection::StdOut("a.log".to_string())),
+                Compound::While {
+                    cond: vec![ext(&["a"])],
+                    body: vec![Job::Compound {
+                        cmd: Compound::If {
+                            cond: vec![ext(&["b"])],
+                            then: vec![ext(&["c"])],
+                            els: None,
+                        },
+                        is_bg: false
+                    }],
                 }
             ))
         );
+        assert!(while_cmd().parse("while a; do b; don").is_err());
     }
 }
 
-/// pipe control simbol parser
-fn pipe<'a>() -> impl Parser<'a, Pipe> {
+/// function definition parser
+///
+/// 本体は `{` と `}` の間のジョブの並び。 `}` の直前には ";" が必要となる。
+fn function_def<'a>() -> impl Parser<'a, (String, Vec<Job>)> {
     |input| {
-        let (next_i, _) = space0().parse(input)?;
-        // '|' は最後にしないとなんでも '|' にマッチしてしまう
-        let (next_i, p) = keyword("|&").or_else(keyword("|")).parse(next_i)?;
+        let (next_i, name) = var_name().parse(input)?;
+        let (next_i, _) = lexeme(keyword("(")).parse(next_i)?;
+        let (next_i, _) = lexeme(keyword(")")).parse(next_i)?;
+        let (next_i, _) = reserved("{").parse(next_i)?;
+        let (next_i, body) = job_list().parse(next_i)?;
+        let (next_i, _) = reserved("}").parse(next_i)?;
 
-        match p {
-            "|" => Ok((next_i, Pipe::StdOut)),
-            "|&" => Ok((next_i, Pipe::Both)),
-            _ => unreachable!(),
-        }
+        Ok((next_i, (name, body)))
     }
 }
 #[cfg(test)]
-mod pipe {
+mod function_def {
     use super::*;
 
     #[test]
     fn test() {
-        assert_eq!(pipe().parse("|"), Ok(("", Pipe::StdOut)));
-        assert_eq!(pipe().parse("|&"), Ok(("", Pipe::Both)));
+        let echo = |arg: &str| Job::BuiltIn {
+            cmd: BuiltInCmd::Echo(vec![arg.to_string()]),
+            is_bg: false,
+        };
+
+        assert_eq!(
+            function_def().parse("greet () { echo }; echo $1; }; greet a"),
+            Ok((
+                "; greet a",
+                ("greet".to_string(), vec![echo("}"), echo("$1")])
+            ))
+        );
+        assert!(function_def().parse("f() { echo a }").is_err());
+        assert!(function_def().parse("f() echo a").is_err());
+        assert!(function_def().parse("1f() { echo a; }").is_err());
     }
 }
 
-/// pipeline parser
-fn pipeline<'a>() -> impl Parser<'a, Pipeline> {
-    |input| {
-        let (next_i, cmd) = external_cmd().parse(input)?;
-        let (next_i, cmds) = pipe().join(external_cmd()).many0().parse(next_i)?;
+/// built-in command, control structure or pipeline parser
+///
+/// && と || で連結する各ジョブとなる。 is_bg は常に false で、 job で設定する。
+fn simple_job<'a>() -> impl Parser<'a, Job> {
+    built_in_cmd()
+        .map(|cmd| Job::BuiltIn { cmd, is_bg: false })
+        .or_else(function_def().map(|(name, body)| Job::Function { name, body }))
+        .or_else(
+            if_cmd()
+                .or_else(for_cmd())
+                .or_else(while_cmd())
+                .map(|cmd| Job::Compound { cmd, is_bg: false }),
+        )
+        .or_else(timed_pipeline().map(|cmds| Job::External {
+            cmds,
+            is_bg: false,
+            timed: true,
+        }))
+        .or_else(pipeline().map(|cmds| Job::External {
+            cmds,
+            is_bg: false,
+            timed: false,
+        }))
+}
 
-        let mut acc = Pipeline::Src(cmd.clone());
-        for (p, cmd) in cmds {
-            acc = match &p {
-                Pipe::StdOut => Pipeline::Out(Box::new(acc), cmd),
-                Pipe::Both => Pipeline::Both(Box::new(acc), cmd),
-            };
-        }
-        Ok((next_i, acc))
-    }
+/// time を前置したパイプラインの parser
+/// パイプラインが続かない場合は time をコマンド名とする
+fn timed_pipeline<'a>() -> impl Parser<'a, Pipeline> {
+    reserved("time").skip(pipeline())
 }
 #[cfg(test)]
-mod pipeline {
+mod timed_pipeline {
     use super::*;
 
     #[test]
     fn test() {
+        let cmd = |args: &[&str]| ExternalCmd {
+            args: args.iter().map(|s| (*s).into()).collect(),
+            redirects: vec![],
+        };
         assert_eq!(
-            pipeline().parse("foo | bar"),
+            timed_pipeline().parse("time sleep 1 | cat && ls"),
             Ok((
-                "",
-                Pipeline::Out(
-                    Box::new(Pipeline::Src(ExternalCmd {
-                        args: vec!["foo".to_string()],
-                        redirect: None,
-                    })),
-                    ExternalCmd {
-                        args: vec!["bar".to_string()],
-                        redirect: None,
-                    }
-                )
+                " && ls",
+                Pipeline::Out(Box::new(Pipeline::Src(cmd(&["sleep", "1"]))), cmd(&["cat"]))
             ))
         );
+        assert!(timed_pipeline().parse("time").is_err());
+        assert!(timed_pipeline().parse("timeout 1").is_err());
+
+        // パイプラインが続かない time は外部コマンド
         assert_eq!(
-            pipeline().parse("foo |& bar"),
+            job().parse("time &"),
             Ok((
                 "",
-                Pipeline::Both(
-                    Box::new(Pipeline::Src(ExternalCmd {
-                        args: vec!["foo".to_string()],
-                        redirect: None,
-                    })),
-                    ExternalCmd {
-                        args: vec!["bar".to_string()],
-                        redirect: None,
-                    }
-                )
+                Job::External {
+                    cmds: Pipeline::Src(cmd(&["time"])),
+                    is_bg: true,
+                    timed: false,
+                }
             ))
         );
         assert_eq!(
-            pipeline().parse("foo | bar |& buz"),
+            job().parse("time ls &"),
             Ok((
                 "",
-                Pipeline::Both(
-                    Box::new(Pipeline::Out(
-                        Box::new(Pipeline::Src(ExternalCmd {
-                            args: vec!["foo".to_string()],
-                            redirect: None,
-                        })),
-                        ExternalCmd {
-                            args: vec!["bar".to_string()],
-                            redirect: None,
-                        }
-                    )),
-                    ExternalCmd {
-                        args: vec!["buz".to_string()],
-                        redirect: None,
-                    }
-                )
+                Job::External {
+                    cmds: Pipeline::Src(cmd(&["ls"])),
+                    is_bg: true,
+                    timed: true,
+                }
             ))
         );
     }
 }
 
 /// job parser
+///
+/// "&" は && と || で連結したジョブ全体に適用する。
 fn job<'a>() -> impl Parser<'a, Job> {
-    built_in_cmd()
-        .and_then(|cmd| {
-            lexeme(opt(literal("&"))).map(move |bg| Job::BuiltIn {
-                cmd: cmd.clone(),
-                is_bg: bg.is_some(),
-            })
-        })
-        .or_else(pipeline().and_then(|cmds| {
-            lexeme(opt(literal("&"))).map(move |bg| Job::External {
-                cmds: cmds.clone(),
-                is_bg: bg.is_some(),
-            })
-        }))
+    |input| {
+        let (next_i, first) = simple_job().parse(input)?;
+        let (next_i, rest) = cond().join(simple_job()).many0().parse(next_i)?;
+        let (next_i, bg) = lexeme(opt(job_term())).parse(next_i)?;
+        let is_bg = bg == Some(true);
+
+        let job = match first {
+            _ if !rest.is_empty() => Job::List {
+                first: Box::new(first),
+                rest,
+                is_bg,
+            },
+            Job::BuiltIn { cmd, .. } => Job::BuiltIn { cmd, is_bg },
+            Job::External { cmds, timed, .. } => Job::External { cmds, is_bg, timed },
+            Job::Compound { cmd, .. } => Job::Compound { cmd, is_bg },
+            // 定義は実行しないため、 "&" は無視する
+            Job::Function { .. } => first,
+            Job::List { .. } => unreachable!(),
+        };
+        Ok((next_i, job))
+    }
 }
 #[cfg(test)]
 mod job {
@@ -408,15 +1495,16 @@ mod job {
                 Job::External {
                     cmds: Pipeline::Out(
                         Box::new(Pipeline::Src(ExternalCmd {
-                            args: vec!["ls".to_string(), "-laF".to_string()],
-                            redirect: None,
+                            args: vec!["ls".into(), "-laF".into()],
+                            redirects: vec![],
                         })),
                         ExternalCmd {
-                            args: vec!["grep".to_string(), "a".to_string()],
-                            redirect: None,
+                            args: vec!["grep".into(), "a".into()],
+                            redirects: vec![],
                         }
                     ),
                     is_bg: false,
+                    timed: false,
                 }
             ))
         );
@@ -445,7 +1533,7 @@ mod job {
             Ok((
                 "",
                 Job::BuiltIn {
-                    cmd: BuiltInCmd::Jobs,
+                    cmd: BuiltInCmd::Jobs(false, false),
                     is_bg: false,
                 }
             ))
@@ -491,15 +1579,16 @@ mod job {
                 Job::External {
                     cmds: Pipeline::Out(
                         Box::new(Pipeline::Src(ExternalCmd {
-                            args: vec!["ls".to_string(), "-laF".to_string()],
-                            redirect: None,
+                            args: vec!["ls".into(), "-laF".into()],
+                            redirects: vec![],
                         })),
                         ExternalCmd {
-                            args: vec!["grep".to_string(), "a".to_string()],
-                            redirect: None,
+                            args: vec!["grep".into(), "a".into()],
+                            redirects: vec![],
                         }
                     ),
                     is_bg: true,
+                    timed: false,
                 }
             ))
         );
@@ -533,15 +1622,16 @@ mod parse_cmd {
                     Job::External {
                         cmds: Pipeline::Out(
                             Box::new(Pipeline::Src(ExternalCmd {
-                                args: vec!["ls".to_string(), "-laF".to_string()],
-                                redirect: None,
+                                args: vec!["ls".into(), "-laF".into()],
+                                redirects: vec![],
                             })),
                             ExternalCmd {
-                                args: vec!["grep".to_string(), "a".to_string()],
-                                redirect: None,
+                                args: vec!["grep".into(), "a".into()],
+                                redirects: vec![],
                             }
                         ),
                         is_bg: true,
+                        timed: false,
                     },
                     Job::BuiltIn {
                         cmd: BuiltInCmd::Cd(Some("~/app".to_string())),
@@ -555,9 +1645,258 @@ mod parse_cmd {
             ))
         );
     }
+
+    /// 各ジョブに付いた "&" はそのジョブだけに適用される
+    #[test]
+    fn job_list() {
+        let ext = |args: &[&str], is_bg| Job::External {
+            cmds: Pipeline::Src(ExternalCmd {
+                args: args.iter().map(|s| (*s).into()).collect(),
+                redirects: vec![],
+            }),
+            is_bg,
+            timed: false,
+        };
+
+        assert_eq!(
+            parse_cmd().parse("sleep 1 & sleep 2 &"),
+            Ok((
+                "",
+                vec![ext(&["sleep", "1"], true), ext(&["sleep", "2"], true)]
+            ))
+        );
+        assert_eq!(
+            parse_cmd().parse("sleep 1 & ls; sleep 2 &"),
+            Ok((
+                "",
+                vec![
+                    ext(&["sleep", "1"], true),
+                    ext(&["ls"], false),
+                    ext(&["sleep", "2"], true)
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_cmd().parse("jobs; ls &"),
+            Ok((
+                "",
+                vec![
+                    Job::BuiltIn {
+                        cmd: BuiltInCmd::Jobs(false, false),
+                        is_bg: false
+                    },
+                    ext(&["ls"], true)
+                ]
+            ))
+        );
+        // "&&" はバックグラウンド実行とみなさない
+        assert_eq!(
+            parse_cmd().parse("sleep 1 && ls &"),
+            Ok((
+                "",
+                vec![Job::List {
+                    first: Box::new(ext(&["sleep", "1"], false)),
+                    rest: vec![(Cond::And, ext(&["ls"], false))],
+                    is_bg: true
+                }]
+            ))
+        );
+    }
+
+    /// && と || で連結したジョブ
+    #[test]
+    fn cond_list() {
+        let ext = |args: &[&str]| Job::External {
+            cmds: Pipeline::Src(ExternalCmd {
+                args: args.iter().map(|s| (*s).into()).collect(),
+                redirects: vec![],
+            }),
+            is_bg: false,
+            timed: false,
+        };
+
+        assert_eq!(
+            parse_cmd().parse("make && make test || echo failed; cd"),
+            Ok((
+                "",
+                vec![
+                    Job::List {
+                        first: Box::new(ext(&["make"])),
+                        rest: vec![
+                            (Cond::And, ext(&["make", "test"])),
+                            (
+                                Cond::Or,
+                                Job::BuiltIn {
+                                    cmd: BuiltInCmd::Echo(vec!["failed".to_string()]),
+                                    is_bg: false
+                                }
+                            )
+                        ],
+                        is_bg: false
+                    },
+                    Job::BuiltIn {
+                        cmd: BuiltInCmd::Cd(None),
+                        is_bg: false
+                    }
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_cmd().parse("cd /tmp||ls | wc"),
+            Ok((
+                "",
+                vec![Job::List {
+                    first: Box::new(Job::BuiltIn {
+                        cmd: BuiltInCmd::Cd(Some("/tmp".to_string())),
+                        is_bg: false
+                    }),
+                    rest: vec![(
+                        Cond::Or,
+                        Job::External {
+                            cmds: Pipeline::Out(
+                                Box::new(Pipeline::Src(ExternalCmd {
+                                    args: vec!["ls".into()],
+                                    redirects: vec![],
+                                })),
+                                ExternalCmd {
+                                    args: vec!["wc".into()],
+                                    redirects: vec![],
+                                }
+                            ),
+                            is_bg: false,
+                            timed: false,
+                        }
+                    )],
+                    is_bg: false
+                }]
+            ))
+        );
+        // 論理演算子の後にジョブがない場合は、パースせずに残す
+        assert_eq!(parse_cmd().parse("ls &&"), Ok(("&&", vec![ext(&["ls"])])));
+    }
+}
+
+/// parse error
+///
+/// パースできなかった位置と、その位置に期待した字句の説明を持つ。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ShellParseError {
+    pub offset: usize,    // 入力の先頭からのバイト位置
+    pub expected: String, // 期待した字句の説明
+}
+
+impl fmt::Display for ShellParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "syntax error at offset {}: expected {}",
+            self.offset, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ShellParseError {}
+
+impl ShellParseError {
+    /// line の下の行に、エラーの位置を ^ で示した 2 行を返す
+    pub fn caret(&self, line: &str) -> String {
+        let col = line.get(..self.offset).map_or(0, |s| s.chars().count());
+        format!("{line}\n{}^", " ".repeat(col))
+    }
+}
+
+/// パースできずに残った入力 rest の先頭に期待した字句の説明
+fn expected(rest: &str) -> String {
+    let word = rest.split_whitespace().next().unwrap_or("");
+    // 長いものから順に比べる
+    let op = [
+        "&&", "||", "|&", "2>>", "2>", ">>", ">&", "<<", "|", ";", "&", ">", "<",
+    ]
+    .into_iter()
+    .find(|op| rest.starts_with(op));
+    match op {
+        Some(op @ ("<<" | "2>>" | "2>" | ">>" | ">&" | ">" | "<")) => {
+            format!("a word after `{op}`")
+        }
+        // 演算子の後にコマンドがない
+        Some(op)
+            if !matches!(op, ";" | "&")
+                && rest[op.len()..]
+                    .trim_start()
+                    .chars()
+                    .next()
+                    .is_none_or(|c| "|&;".contains(c)) =>
+        {
+            format!("a command after `{op}`")
+        }
+        Some(op) => format!("a command before `{op}`"),
+        None if rest.starts_with(['\'', '"']) => format!("a closing `{}`", &rest[..1]),
+        None if word.ends_with('\\') && rest.trim_end() == rest => {
+            "a character after `\\`".to_string()
+        }
+        None => match word {
+            "if" => "`fi`".to_string(),
+            "while" | "for" => "`done`".to_string(),
+            _ if rest.starts_with("()") => "`}` to close the function".to_string(),
+            _ => format!("a command instead of `{word}`"),
+        },
+    }
 }
 
 /// parsing
-pub fn parse<'a>(input: &'a str) -> ParseResult<'a, Vec<Job>> {
-    parse_cmd().parse(input)
+///
+/// 入力の最後までパースできなかった場合は、残った部分の先頭の位置を ShellParseError で返す。
+pub fn parse(input: &str) -> Result<Vec<Job>, ShellParseError> {
+    let rest = match parse_cmd().parse(input) {
+        Ok((rest, jobs)) if rest.trim().is_empty() => return Ok(jobs),
+        Ok((rest, _)) | Err(rest) => rest.trim_start(),
+    };
+    Err(ShellParseError {
+        offset: input.len() - rest.len(),
+        expected: expected(rest),
+    })
+}
+#[cfg(test)]
+mod parse {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(parse("ls; echo a").unwrap().len(), 2);
+        assert_eq!(parse("  ").unwrap(), vec![]);
+
+        let err = |input| parse(input).unwrap_err();
+        let e = err("ls &&");
+        assert_eq!(e.offset, 3);
+        assert_eq!(e.expected, "a command after `&&`");
+        assert_eq!(
+            e.to_string(),
+            "syntax error at offset 3: expected a command after `&&`"
+        );
+        assert_eq!(err("ls | | wc").expected, "a command after `|`");
+        assert_eq!(err("| wc").expected, "a command before `|`");
+        assert_eq!(err("&& ls").offset, 0);
+        assert_eq!(err("ls; ; ls").expected, "a command before `;`");
+        assert_eq!(err("ls > ").expected, "a word after `>`");
+        assert_eq!(err("echo 'abc").expected, "a closing `'`");
+        assert_eq!(err("echo a\\").expected, "a character after `\\`");
+        assert_eq!(err("if true; then ls").expected, "`fi`");
+        assert_eq!(err("while true; do").expected, "`done`");
+        assert_eq!(err("f() { echo").expected, "`}` to close the function");
+
+        let e = err("echo ok; fi");
+        assert_eq!(e.offset, 9);
+        assert_eq!(e.expected, "a command instead of `fi`");
+    }
+
+    #[test]
+    fn test_caret() {
+        let e = parse("echo ok; fi").unwrap_err();
+        assert_eq!(e.caret("echo ok; fi"), "echo ok; fi\n         ^");
+
+        // 列は文字単位で数える
+        let line = "echo あい; fi";
+        let e = parse(line).unwrap_err();
+        assert_eq!(e.caret(line), "echo あい; fi\n         ^");
+    }
 }
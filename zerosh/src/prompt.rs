@@ -0,0 +1,238 @@
+//! プロンプトの書式
+//!
+//! 環境変数 PS1 にプロンプトの書式を指定する。未設定の場合は直前の終了コードに応じた顔を表示する。
+//! 顔は絵文字を表示できない端末では ASCII となり、色を付けない端末では書式の色の指定を取り除く。
+//! ~/.zeroshrc に `export PS1='\u@\h:\w\$ '` のように書いておくと起動時から適用される。
+//!
+//! 書式には以下のエスケープを用いる。それ以外の文字はそのまま表示する。
+//!
+//! - `\u` : ユーザ名
+//! - `\h` : 最初の `.` までのホスト名, `\H` : ホスト名
+//! - `\w` : カレントディレクトリ。ホームディレクトリ以下は `~` で始める, `\W` : その最後の要素
+//! - `\s` : シェルの名前
+//! - `\$` : root の場合は `#` 、それ以外は `$`
+//! - `\?`, `$?` : 直前の終了コード
+//! - `\e` : エスケープ文字 (`\e[32m` などで色を指定する)
+//! - `\n` : 改行, `\\` : `\`
+//! - `\[`, `\]` : 表示されない文字列の範囲。行エディタが幅を計算するため無視する
+use crate::term;
+use nix::libc;
+use std::{
+    env,
+    ffi::CStr,
+    path::{Path, PathBuf},
+};
+
+/// プロンプトの書式を指定する環境変数
+pub const PS1: &str = "PS1";
+
+/// プロンプトに表示する情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub name: String,          // シェルの名前
+    pub user: String,          // ユーザ名
+    pub host: String,          // ホスト名
+    pub cwd: PathBuf,          // カレントディレクトリ
+    pub home: Option<PathBuf>, // ホームディレクトリ
+    pub is_root: bool,         // root か
+    pub exit_val: i32,         // 直前の終了コード
+    pub color: bool,           // 色を付けるか
+}
+
+impl Info {
+    /// 現在のプロセスの情報を取得
+    pub fn current(name: &str, exit_val: i32) -> Self {
+        let uid = unsafe { libc::getuid() };
+        Self {
+            name: name.to_string(),
+            user: env::var("USER").unwrap_or_else(|_| user_name(uid)),
+            host: host_name(),
+            cwd: env::current_dir().unwrap_or_default(),
+            home: dirs::home_dir(),
+            is_root: uid == 0,
+            exit_val,
+            color: term::caps().color,
+        }
+    }
+}
+
+/// uid のユーザ名。取得できない場合は uid を文字列にする
+fn user_name(uid: libc::uid_t) -> String {
+    let pw = unsafe { libc::getpwuid(uid) };
+    if pw.is_null() {
+        return uid.to_string();
+    }
+    unsafe { CStr::from_ptr((*pw).pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// ホスト名。取得できない場合は空文字列
+fn host_name() -> String {
+    let mut buf = [0u8; 256];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if ret != 0 {
+        return String::new();
+    }
+    CStr::from_bytes_until_nul(&buf)
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// PS1 に従ってプロンプトを生成する
+pub fn prompt(name: &str, exit_val: i32) -> String {
+    match env::var(PS1) {
+        Ok(template) => render(&template, &Info::current(name, exit_val)),
+        Err(_) => format!("{name} {} > ", term::caps().face(exit_val)),
+    }
+}
+
+/// 端末のタイトルに表示するプロンプト
+pub fn title(name: &str, exit_val: i32) -> String {
+    to_title(&prompt(name, exit_val))
+}
+
+/// タイトルを設定するエスケープシーケンスを壊さないよう、色の指定を取り除き、
+/// 改行などの制御文字を空白にする。前後の空白は取り除く
+fn to_title(prompt: &str) -> String {
+    let title: String = strip_sgr(prompt)
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    title.trim().to_string()
+}
+#[cfg(test)]
+mod to_title {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(to_title("zerosh 😀 > "), "zerosh 😀 >");
+        assert_eq!(to_title("\x1b[32malice@box\x1b[0m:~\n$ "), "alice@box:~ $");
+        assert_eq!(to_title("a\x07b\x1bc"), "a b c");
+    }
+}
+
+/// ホームディレクトリ以下のパスを ~ で始めて表示する
+fn tilde(cwd: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|h| cwd.strip_prefix(h).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => cwd.display().to_string(),
+    }
+}
+
+/// 書式 template のエスケープを info で置き換える
+pub fn render(template: &str, info: &Info) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '$' if chars.peek() == Some(&'?') => {
+                chars.next();
+                out.push_str(&info.exit_val.to_string());
+            }
+            '\\' => match chars.next() {
+                Some('u') => out.push_str(&info.user),
+                Some('h') => out.push_str(info.host.split('.').next().unwrap_or_default()),
+                Some('H') => out.push_str(&info.host),
+                Some('w') => out.push_str(&tilde(&info.cwd, info.home.as_deref())),
+                Some('W') => {
+                    let w = tilde(&info.cwd, info.home.as_deref());
+                    match w.rsplit_once('/') {
+                        Some((_, last)) if !last.is_empty() => out.push_str(last),
+                        _ => out.push_str(&w),
+                    }
+                }
+                Some('s') => out.push_str(&info.name),
+                Some('$') => out.push(if info.is_root { '#' } else { '$' }),
+                Some('?') => out.push_str(&info.exit_val.to_string()),
+                Some('e') => out.push('\x1b'),
+                Some('n') => out.push('\n'),
+                Some('\\') => out.push('\\'),
+                Some('[' | ']') => (),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    if info.color {
+        out
+    } else {
+        strip_sgr(&out)
+    }
+}
+
+/// 色を指定するエスケープシーケンス `ESC [ ... m` を取り除く
+fn strip_sgr(s: &str) -> String {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find("\x1b[") {
+        out.push_str(&rest[..i]);
+        let params = &rest[i + 2..];
+        match params.find(|c: char| !(c.is_ascii_digit() || c == ';')) {
+            Some(j) if params[j..].starts_with('m') => rest = &params[j + 1..],
+            _ => {
+                out.push_str("\x1b[");
+                rest = params;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+#[cfg(test)]
+mod render {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let info = Info {
+            name: "zerosh".to_string(),
+            user: "alice".to_string(),
+            host: "box.example.com".to_string(),
+            cwd: PathBuf::from("/home/alice/src/zerosh"),
+            home: Some(PathBuf::from("/home/alice")),
+            is_root: false,
+            exit_val: 1,
+            color: true,
+        };
+        assert_eq!(render("\\u@\\h:\\w\\$ ", &info), "alice@box:~/src/zerosh$ ");
+        assert_eq!(
+            render("\\H \\W \\s", &info),
+            "box.example.com zerosh zerosh"
+        );
+        assert_eq!(render("[$?] [\\?] > ", &info), "[1] [1] > ");
+        assert_eq!(
+            render("\\[\\e[31m\\]red\\[\\e[0m\\]", &info),
+            "\x1b[31mred\x1b[0m"
+        );
+        assert_eq!(render("a\\nb \\\\ \\x $ \\", &info), "a\nb \\ \\x $ \\");
+
+        let root = Info {
+            cwd: PathBuf::from("/home/alice"),
+            is_root: true,
+            ..info.clone()
+        };
+        assert_eq!(render("\\w \\W \\$", &root), "~ ~ #");
+        let other = Info {
+            cwd: PathBuf::from("/"),
+            ..info
+        };
+        assert_eq!(render("\\w \\W", &other), "/ /");
+
+        // 色を付けない場合は色の指定を取り除く
+        let plain = Info {
+            color: false,
+            ..other
+        };
+        assert_eq!(
+            render("\\[\\e[1;31m\\]red\\e[0m \\e[2K", &plain),
+            "red \x1b[2K"
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Ctrl-R によるヒストリの正規表現検索
+//!
+//! Ctrl-R を押すと検索モードとなり、入力したクエリをワークスペースの regex クレートで
+//! コンパイルしてヒストリを新しい順に検索する。クエリが正規表現としてコンパイルできない場合は
+//! 部分文字列として検索する。
+//!
+//! 検索モードでは、クエリの後ろにヒントとして最もよくマッチした (最も新しい) 行を表示する。
+//! 検索モード中に Ctrl-R を押すと 1 つ古いマッチに移り、 Enter で確定、 Ctrl-C で中止する。
+use rustyline::{
+    hint::Hinter,
+    history::{History, SearchDirection},
+    Cmd, ConditionalEventHandler, Context, Event, EventContext, RepeatCount,
+};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+/// 検索モードのプロンプト。ヒントと合わせて (regex-i-search)`クエリ': 行 と表示する
+pub const SEARCH_PROMPT: &str = "(regex-i-search)`";
+
+/// 検索の状態
+#[derive(Debug, Default)]
+pub struct SearchState {
+    active: bool,                    // 検索モード中か
+    skip: usize,                     // 読み飛ばすマッチの数。 Ctrl-R を押すごとに増える
+    query: String,                   // 最後に検索したクエリ
+    origin: Option<(String, usize)>, // 検索開始時の入力行とカーソル位置
+}
+
+impl SearchState {
+    /// 検索開始時の入力行とカーソル位置を取り出す。検索を開始していない場合は None
+    pub fn take_origin(&mut self) -> Option<(String, usize)> {
+        self.origin.take()
+    }
+
+    /// 検索を終了し、 history から query に最もよくマッチした行を返す
+    pub fn finish(&mut self, query: &str, history: &dyn History) -> Option<String> {
+        let found = self.current(query, history);
+        *self = Self::default();
+        found
+    }
+
+    /// 現在選択しているマッチを返す
+    /// 読み飛ばす数がマッチの数以上の場合は、最も古いマッチにとどまる
+    fn current(&mut self, query: &str, history: &dyn History) -> Option<String> {
+        if query != self.query {
+            // クエリが変わったら最も新しいマッチから探し直す
+            self.query = query.to_string();
+            self.skip = 0;
+        }
+
+        let entries = history_entries(history);
+        loop {
+            match find_match(query, entries.iter().map(|s| s.as_str()), self.skip) {
+                Some(line) => return Some(line.to_string()),
+                None if self.skip > 0 => self.skip -= 1,
+                None => return None,
+            }
+        }
+    }
+}
+
+/// main スレッドと rustyline のハンドラで共有する検索の状態
+pub type SharedSearch = Arc<Mutex<SearchState>>;
+
+/// ヒストリの行を新しい順に返す。同じ行は最も新しいもののみ返す
+fn history_entries(history: &dyn History) -> Vec<String> {
+    let mut seen = HashSet::new();
+    (0..history.len())
+        .rev()
+        .filter_map(|i| history.get(i, SearchDirection::Reverse).ok().flatten())
+        .map(|r| r.entry.into_owned())
+        .filter(|line| seen.insert(line.clone()))
+        .collect()
+}
+
+/// 検索モード中はマッチした行をヒントとして表示する。行エディタのヘルパ ShellHelper から用いる
+pub struct SearchHelper {
+    search: SharedSearch,
+}
+
+impl SearchHelper {
+    pub fn new(search: SharedSearch) -> Self {
+        Self { search }
+    }
+}
+
+impl Hinter for SearchHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, _pos: usize, ctx: &Context<'_>) -> Option<String> {
+        let mut search = self.search.lock().unwrap();
+        if !search.active {
+            return None;
+        }
+        match search.current(line, ctx.history()) {
+            Some(found) => Some(format!("': {found}")),
+            None if line.is_empty() => Some("'".to_string()),
+            None => Some("' (no match)".to_string()),
+        }
+    }
+}
+
+/// Ctrl-R のキーハンドラ
+///
+/// 検索モードでなければ入力中の行を保存して readline を中断させ、
+/// main スレッドは検索用のプロンプトで読み込みを再開する。
+/// 検索モード中であれば 1 つ古いマッチに移る。
+pub struct SearchHandler {
+    search: SharedSearch,
+}
+
+impl SearchHandler {
+    pub fn new(search: SharedSearch) -> Self {
+        Self { search }
+    }
+}
+
+impl ConditionalEventHandler for SearchHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        let mut search = self.search.lock().unwrap();
+        if search.active {
+            search.skip += 1;
+            Some(Cmd::Repaint)
+        } else {
+            *search = SearchState {
+                active: true,
+                origin: Some((ctx.line().to_string(), ctx.pos())),
+                ..Default::default()
+            };
+            Some(Cmd::Interrupt)
+        }
+    }
+}
+
+/// entries のうち query にマッチする skip + 1 番目の行を返す
+///
+/// query は正規表現としてコンパイルし、失敗した場合は部分文字列として検索する。
+/// query が空の場合は何にもマッチしない。
+pub fn find_match<'a, I>(query: &str, entries: I, skip: usize) -> Option<&'a str>
+where
+    I: Iterator<Item = &'a str>,
+{
+    if query.is_empty() {
+        return None;
+    }
+
+    let re = regex::Regex::new(query).ok();
+    let is_match = |line: &str| match &re {
+        Some(re) => re.is_match(line).unwrap_or(false),
+        None => line.contains(query),
+    };
+    entries.filter(|line| is_match(line)).nth(skip)
+}
+#[cfg(test)]
+mod find_match {
+    use super::*;
+
+    #[test]
+    fn test() {
+        // 新しい順
+        let history = ["ls -l", "git log", "cargo test", "git status"];
+
+        assert_eq!(
+            find_match("git", history.iter().copied(), 0),
+            Some("git log")
+        );
+        assert_eq!(
+            find_match("gi+t (l|s)", history.iter().copied(), 1),
+            Some("git status")
+        );
+        assert_eq!(find_match("gi+t (l|s)", history.iter().copied(), 2), None);
+        assert_eq!(
+            find_match("o (t|s)", history.iter().copied(), 0),
+            Some("cargo test")
+        );
+        assert_eq!(
+            find_match("s+ -l", history.iter().copied(), 0),
+            Some("ls -l")
+        );
+
+        // 正規表現としてコンパイルできない場合は部分文字列として検索
+        let history = ["echo (a", "echo b"];
+        assert_eq!(
+            find_match("(a", history.iter().copied(), 0),
+            Some("echo (a")
+        );
+
+        assert_eq!(find_match("", history.iter().copied(), 0), None);
+        assert_eq!(find_match("zzz", history.iter().copied(), 0), None);
+    }
+}
@@ -1,30 +1,69 @@
+//! シェル
+//!
+//! main スレッドは行を読み込んで worker スレッドに送り、 worker スレッドはジョブを実行する。
+//!
+//! - worker : worker スレッドとジョブの実行順の制御
+//! - jobs : ジョブとプロセスの管理表 JobTable
+//! - spawn : 子プロセスの生成
+//! - builtins : 組み込みコマンド
+//! - control : 外部から操作するための制御用ソケット
+//! - timing : time と times による実行時間の計測
+use crate::complete::{SharedCompletion, ShellHelper};
 use crate::helper::DynError;
+use crate::heredoc;
+use crate::history;
+use crate::keybind::{KeyBindHandler, PendingCmd};
 use crate::model;
-use crate::model::ExternalCmd;
-use crate::parser;
-use nix::{
-    libc::{self, tcgetpgrp, tcsetpgrp},
-    sys::{
-        signal::{killpg, signal, SigHandler, Signal},
-        wait::{waitpid, WaitPidFlag, WaitStatus},
-    },
-    unistd::{close, dup2, execvp, fork, getpgid, getpid, pipe, setpgid, ForkResult, Pid},
+use crate::parser::{self, ShellParseError};
+use crate::prompt;
+use crate::search::{self, SearchHandler, SearchHelper, SharedSearch};
+use crate::sigpipe::{self, Waker};
+use crate::status::{SharedStatus, StatusLine};
+use control::ControlSocket;
+use nix::sys::signal::{signal, SigHandler, Signal};
+use rustyline::{
+    error::ReadlineError, history::DefaultHistory, Editor, Event, EventHandler, KeyEvent,
 };
-use rustyline::{error::ReadlineError, DefaultEditor};
-use signal_hook::{consts::*, iterator::Signals};
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
-    ffi::CString,
-    mem::replace,
-    os::fd::AsRawFd,
-    path::PathBuf,
-    process::exit,
-    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender},
-    thread,
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
 };
+use worker::Worker;
 
 const NAME: &str = "zerosh";
 
+/// シェルが常に受信するシグナル
+/// SIGINT, SIGTSTP は Ctrl-C や Ctrl-Z が入力されてシェルが終了・停止するのを防ぐために受信している
+/// SIGCHLD を受信しているのが重要で、子プロセスの状態変化を検知するために必要
+const SHELL_SIGNALS: [Signal; 3] = [Signal::SIGINT, Signal::SIGTSTP, Signal::SIGCHLD];
+
+/// シェルの名前を前置してエラーメッセージを表示
+/// 色を付けられる端末では名前を赤で表示する
+macro_rules! error {
+    ($($arg:tt)*) => {
+        eprintln!(
+            "{}: {}",
+            $crate::term::caps().paint($crate::term::Style::Red, $crate::shell::NAME),
+            format_args!($($arg)*)
+        )
+    };
+}
+
+mod builtins;
+mod control;
+mod jobs;
+mod spawn;
+mod timing;
+mod worker;
+
+/// 補完とヒストリ検索のヘルパを設定した行エディタ
+type ShellEditor = Editor<ShellHelper, DefaultHistory>;
+
 /// システムコール呼び出しのラッパ。 EINTR ならリトライ。
 fn syscall<F, T>(f: F) -> Result<T, nix::Error>
 where
@@ -40,50 +79,265 @@ where
 
 /// worker スレッドが受信するメッセージ
 enum WorkerMsg {
-    Signal(i32),
     Cmd(String),
+    Exit, // worker スレッドを終了する
+}
+
+/// worker スレッドへの送信側
+///
+/// worker スレッドはシグナルと同じ self-pipe でメッセージの到着を待つため、
+/// 送信した後に self-pipe に書き込んで worker スレッドを起こす。
+struct WorkerTx {
+    tx: Sender<WorkerMsg>,
+    waker: Waker,
+    handle: Option<JoinHandle<()>>, // worker スレッド
+}
+
+impl WorkerTx {
+    fn send(&self, msg: WorkerMsg) {
+        self.tx.send(msg).unwrap();
+        self.waker.wake();
+    }
+}
+
+impl Drop for WorkerTx {
+    /// worker スレッドを終了させて待ち、シグナルの受信を解除する
+    fn drop(&mut self) {
+        if self.tx.send(WorkerMsg::Exit).is_ok() {
+            self.waker.wake();
+        }
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        if let Err(e) = sigpipe::uninstall(&SHELL_SIGNALS) {
+            error!("failed to restore signal handlers: {e}");
+        }
+    }
 }
 
 /// main スレッドが受信するメッセージ
 enum ShellMsg {
     Continue(i32),
     Quit(i32),
+    BindKey(Vec<KeyEvent>, String), // キーシーケンスにコマンドを割り当てる
 }
 
+/// シェル。 logfile はヒストリのファイル、 rcfile は起動時に読み込む設定ファイル
 pub struct Shell {
-    logfile: String, // ログファイル
+    logfile: String,          // ログファイル
+    rcfile: Option<PathBuf>,  // 起動時に読み込む設定ファイル
+    control: Option<PathBuf>, // 端末の代わりにコマンドを読み込む制御用ソケット
 }
 
 impl Shell {
-    pub fn new(logfile: &str) -> Self {
+    pub fn new(logfile: &str, rcfile: Option<PathBuf>, control: Option<PathBuf>) -> Self {
         Self {
             logfile: logfile.to_string(),
+            rcfile,
+            control,
+        }
+    }
+
+    /// 起動時の設定ファイルを読み込み、各行を入力された行と同様に worker スレッドで実行する
+    ///
+    /// `#` で始まる行と空行は無視する。ヒアドキュメントの本文は続く行から読み込む。
+    /// ファイルがない場合は何もしない。
+    /// 最後に実行した行の終了コードを返す。 exit で終了した場合は Err(終了コード) を返す。
+    fn source_rc(
+        &self,
+        rl: &mut ShellEditor,
+        worker_tx: &WorkerTx,
+        shell_rx: &Receiver<ShellMsg>,
+        pending: &PendingCmd,
+    ) -> Result<i32, i32> {
+        let Some(rcfile) = &self.rcfile else {
+            return Ok(0);
+        };
+        let content = match std::fs::read_to_string(rcfile) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                error!("{}: {e}", rcfile.display());
+                return Ok(0);
+            }
+        };
+
+        let mut prev = 0;
+        let mut lines = content.lines();
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let text = match heredoc::read_bodies(line, || lines.next().map(str::to_string)) {
+                Ok(text) => text,
+                Err(tag) => {
+                    error!(
+                        "{}: here-document delimited by end-of-file (wanted `{tag}')",
+                        rcfile.display()
+                    );
+                    return Ok(1);
+                }
+            };
+            worker_tx.send(WorkerMsg::Cmd(text));
+            match recv_shell_msg(rl, shell_rx, pending) {
+                ShellMsg::Quit(n) => return Err(n),
+                ShellMsg::Continue(n) => prev = n,
+                ShellMsg::BindKey(..) => unreachable!(),
+            }
+        }
+        Ok(prev)
+    }
+
+    /// 制御用ソケットから読み込んだコマンドを、入力された行と同様に worker スレッドで実行する
+    ///
+    /// 実行するコマンドはプロンプトとともに端末に表示し、終了コードを接続元に返す。
+    /// prev は直前の終了コード。 exit で終了した場合や接続を待てなくなった場合は終了コードを返す。
+    fn serve_control(
+        &self,
+        sock: &ControlSocket,
+        rl: &mut ShellEditor,
+        worker_tx: &WorkerTx,
+        shell_rx: &Receiver<ShellMsg>,
+        pending: &PendingCmd,
+        mut prev: i32,
+    ) -> i32 {
+        loop {
+            let stream = match sock.accept() {
+                Ok(stream) => stream,
+                Err(e) => {
+                    error!("control: {e}");
+                    return 1;
+                }
+            };
+            let mut reader = BufReader::new(&stream);
+            let mut writer = &stream;
+            while let Some(text) = control::read_command(&mut reader) {
+                match text {
+                    Ok(text) => {
+                        println!("{}{text}", prompt::prompt(NAME, prev));
+                        worker_tx.send(WorkerMsg::Cmd(text));
+                        match recv_shell_msg(rl, shell_rx, pending) {
+                            ShellMsg::Quit(n) => {
+                                let _ = writeln!(writer, "{n}");
+                                return n;
+                            }
+                            ShellMsg::Continue(n) => prev = n,
+                            ShellMsg::BindKey(..) => unreachable!(),
+                        }
+                    }
+                    Err(tag) => {
+                        error!("control: here-document delimited by end-of-file (wanted `{tag}')");
+                        prev = 1;
+                    }
+                }
+                if writeln!(writer, "{prev}").is_err() {
+                    break; // 接続元が閉じた
+                }
+            }
         }
     }
 
     /// main スレッド
-    pub fn run(&self) -> Result<(), DynError> {
+    ///
+    /// シェルが終了した場合は終了コードを返す。プロセスは終了しないため、呼び出し側で exit する。
+    /// 戻る前に worker スレッドを終了させてシグナルの受信を解除するため、続けて呼び出すことができる。
+    /// 同時に実行することはできない。
+    pub fn run(&self) -> Result<i32, DynError> {
         // SIGTTOU を無視に設定しないと、 SIGTSTP が配送されてシェルが停止してしまう
         unsafe { signal(Signal::SIGTTOU, SigHandler::SigIgn).unwrap() };
 
-        let mut rl = DefaultEditor::new()?;
+        // 制御用ソケットは umask を一時的に変更して作成するため、 worker スレッドを起動する前に作成する
+        let control = self
+            .control
+            .as_deref()
+            .map(|path: &Path| {
+                ControlSocket::bind(path).map_err(|e| format!("{}: {e}", path.display()))
+            })
+            .transpose()?;
+
+        let mut rl = ShellEditor::new()?;
         if let Err(e) = rl.load_history(&self.logfile) {
-            eprintln!("{NAME}: failed to load history: {e}");
+            error!("failed to load history: {e}");
         }
 
-        // チャネルを生成して signal_handler と worker スレッドを生成
-        let (worker_tx, worker_rx) = channel();
+        // Tab で補完し、 Ctrl-R で正規表現によるヒストリ検索を行う
+        // 補完に用いる関数名は worker スレッドが登録する
+        let search: SharedSearch = Arc::new(Mutex::new(Default::default()));
+        let completion: SharedCompletion = Arc::new(Mutex::new(Default::default()));
+        rl.set_helper(Some(ShellHelper::new(
+            SearchHelper::new(search.clone()),
+            completion.clone(),
+        )));
+        rl.bind_sequence(
+            KeyEvent::ctrl('r'),
+            EventHandler::Conditional(Box::new(SearchHandler::new(search.clone()))),
+        );
+
+        let (sig_pipe, waker) = sigpipe::install(&SHELL_SIGNALS)?;
+
+        // チャネルを生成して worker スレッドを生成
+        let (tx, worker_rx) = channel();
         let (shell_tx, shell_rx) = sync_channel(0);
-        spawn_sig_handler(worker_tx.clone())?;
-        Worker::new().spawn(worker_rx, shell_tx);
+
+        // ステータスラインは main スレッドが表示し、 worker スレッドがジョブの状態の変化に応じて更新する
+        let status: SharedStatus = Arc::new(Mutex::new(StatusLine::default()));
+        let handle = Worker::new(status.clone(), sigpipe::cancel_token(), completion)
+            .spawn(worker_rx, sig_pipe, shell_tx);
+        // run から戻る際に worker_tx を破棄して worker スレッドを終了させる
+        let worker_tx = WorkerTx {
+            tx,
+            waker,
+            handle: Some(handle),
+        };
+
+        // キーバインドで実行が要求されたコマンド
+        let pending: PendingCmd = Arc::new(Mutex::new(None));
+        // キーバインドのコマンド実行後に復元する入力行とカーソル位置
+        let mut initial: Option<(String, usize)> = None;
+
+        // 対話的な読み込みを始める前に設定ファイルを実行する
+        let mut prev = match self.source_rc(&mut rl, &worker_tx, &shell_rx, &pending) {
+            Ok(n) => n,             // 直前の終了コード
+            Err(n) => return Ok(n), // 設定ファイルで exit した
+        };
+
+        // 制御用ソケットを指定した場合は、端末から読み込まずにソケットから読み込む
+        if let Some(sock) = control {
+            return Ok(self.serve_control(&sock, &mut rl, &worker_tx, &shell_rx, &pending, prev));
+        }
 
         let exit_val; // 終了コード
-        let mut prev = 0; // 直前の終了コード
         loop {
             // 1 行読み込んで、その行を worker スレッドに送信
-            let face = if prev == 0 { '\u{1F642}' } else { '\u{1F480}' };
-            match rl.readline(&format!("{NAME} {face} > ")) {
-                Ok(line) => {
+            let prompt = prompt::prompt(NAME, prev);
+            {
+                // set -b で通知を直ちに表示するため、行エディタの外部出力を渡す
+                let mut status = status.lock().unwrap();
+                status.sync_printer(|| rl.create_external_printer().ok());
+                status.show();
+            }
+            let result = match initial.take() {
+                Some((line, pos)) => rl.readline_with_initial(&prompt, line.split_at(pos)),
+                None => rl.readline(&prompt),
+            };
+            status.lock().unwrap().hide();
+            match result {
+                Ok(mut line) => {
+                    // ヒストリ展開。展開した場合は展開後の行を表示して実行する
+                    match history::expand(&line, &history::entries(rl.history())) {
+                        Ok(Some(expanded)) => {
+                            println!("{expanded}");
+                            line = expanded;
+                        }
+                        Ok(None) => (),
+                        Err(event) => {
+                            error!("{event}: event not found");
+                            prev = 1;
+                            continue;
+                        }
+                    }
+
                     let line_trimed = line.trim(); // 行頭と行末の空白を削除
                     if line_trimed.is_empty() {
                         continue; // 空行の場合は再読み込み
@@ -91,24 +345,59 @@ impl Shell {
                         rl.add_history_entry(line_trimed)?; // ヒストリファイルに追加
                     }
 
+                    // ヒアドキュメントの本文を読み込み、行に連結する
+                    let line = match heredoc::read_bodies(&line, || rl.readline("> ").ok()) {
+                        Ok(text) => text,
+                        Err(tag) => {
+                            error!("here-document delimited by end-of-file (wanted `{tag}')");
+                            prev = 1;
+                            continue;
+                        }
+                    };
+
                     // worker スレッドに送信
-                    worker_tx.send(WorkerMsg::Cmd(line)).unwrap();
-                    match shell_rx.recv().unwrap() {
-                        ShellMsg::Continue(n) => prev = n, // 読み込み再開
+                    worker_tx.send(WorkerMsg::Cmd(line));
+                    match recv_shell_msg(&mut rl, &shell_rx, &pending) {
                         ShellMsg::Quit(n) => {
                             // シェルを終了
                             exit_val = n;
                             break;
                         }
+                        ShellMsg::Continue(n) => prev = n, // 読み込み再開
+                        ShellMsg::BindKey(..) => unreachable!(),
+                    }
+                }
+                Err(ReadlineError::Interrupted) => {
+                    let bound = pending.lock().unwrap().take();
+                    let origin = search.lock().unwrap().take_origin();
+                    if let Some(bound) = bound {
+                        // キーバインドによる割り込みの場合は、割り当てられたコマンドを実行し、
+                        // 入力中だった行を復元して読み込みを再開する
+                        worker_tx.send(WorkerMsg::Cmd(bound.cmd));
+                        match recv_shell_msg(&mut rl, &shell_rx, &pending) {
+                            ShellMsg::Quit(n) => {
+                                // シェルを終了
+                                exit_val = n;
+                                break;
+                            }
+                            ShellMsg::Continue(n) => prev = n, // 読み込み再開
+                            ShellMsg::BindKey(..) => unreachable!(),
+                        }
+                        initial = Some((bound.line, bound.pos));
+                    } else if let Some(origin) = origin {
+                        // Ctrl-R による割り込みの場合は、ヒストリを検索して
+                        // 見つかった行を入力中の行として読み込みを再開する
+                        initial = Some(search_history(&mut rl, &search, origin));
+                    } else {
+                        // コマンド読み込み時に割り込みが発生した場合は再実行する
+                        // これは主に Ctrl-C が入力された場合に発生し、誤ってシェルが終了しないようにする
+                        eprintln!("{NAME}: press Ctrl-D to exit");
                     }
                 }
-                // コマンド読み込み時に割り込みが発生した場合は再実行する
-                // これは主に Ctrl-C が入力された場合に発生し、誤ってシェルが終了しないようにする
-                Err(ReadlineError::Interrupted) => eprintln!("{NAME}: press Ctrl-D to exit"),
                 // Ctrl-D が入力された場合はシェルを終了する
                 Err(ReadlineError::Eof) => {
-                    worker_tx.send(WorkerMsg::Cmd("exit".to_string())).unwrap();
-                    match shell_rx.recv().unwrap() {
+                    worker_tx.send(WorkerMsg::Cmd("exit".to_string()));
+                    match recv_shell_msg(&mut rl, &shell_rx, &pending) {
                         ShellMsg::Quit(n) => {
                             // シェルを終了
                             exit_val = n;
@@ -121,7 +410,7 @@ impl Shell {
                 }
                 // なんらかの理由で読み込みに失敗した場合もシェルを終了する
                 Err(e) => {
-                    eprintln!("{NAME}: readline error\n{e}");
+                    error!("readline error\n{e}");
                     exit_val = 1;
                     break;
                 }
@@ -129,639 +418,63 @@ impl Shell {
         }
 
         if let Err(e) = rl.save_history(&self.logfile) {
-            eprintln!("{NAME}: failed to save history: {e}");
-        }
-        exit(exit_val);
-    }
-}
-
-/// signal_handler スレッド
-fn spawn_sig_handler(tx: Sender<WorkerMsg>) -> Result<(), DynError> {
-    // SIGINT, SIGTSTP は Ctrl-C や Ctrl-Z が入力されてシェルが終了・停止するのを防ぐために受信している
-    // SIGCHLD を受信しているのが重要で、子プロセスの状態変化を検知するために必要
-    let mut signals = Signals::new(&[SIGINT, SIGTSTP, SIGCHLD])?;
-    thread::spawn(move || {
-        for sig in signals.forever() {
-            // シグナルを受信し worker スレッドに転送する
-            tx.send(WorkerMsg::Signal(sig)).unwrap();
-        }
-    });
-
-    Ok(())
-}
-
-#[derive(Debug, PartialEq, Eq, Clone)]
-enum ProcState {
-    Run,  // 実行中
-    Stop, // 停止中
-}
-
-#[derive(Debug, Clone)]
-struct ProcInfo {
-    state: ProcState, // 実行状態
-    pgid: Pid,        // プロセスグループ ID
-}
-
-#[derive(Debug)]
-struct Worker {
-    exit_val: i32,   // 終了コード
-    fg: Option<Pid>, // フォアグラウンドプロセスのプロセスグループ ID
-
-    // ジョブID から (プロセスグループ ID, 実行コマンド) へのマップ
-    jobs: BTreeMap<usize, (Pid, String)>,
-
-    // プロセスグループ ID から (ジョブID, プロセスID) へのマップ
-    pgid_to_pids: HashMap<Pid, (usize, HashSet<Pid>)>,
-
-    pid_to_info: HashMap<Pid, ProcInfo>, // プロセスID からプロセスグループID へのマップ
-    shell_pgid: Pid,                     // シェルのプロセスグループ ID
-}
-
-impl Worker {
-    fn new() -> Self {
-        let pid = unsafe { tcgetpgrp(libc::STDIN_FILENO) };
-        Self {
-            exit_val: 0,
-            fg: None,
-            jobs: BTreeMap::new(),
-            pgid_to_pids: HashMap::new(),
-            pid_to_info: HashMap::new(),
-
-            // libc::STDIN_FILENO に関連付けられた、フォアグラウンドプロセスのプロセスグループID
-            // つまりシェルのプロセスグループIDを取得する
-            // getpgid でも可能だが、シェルがフォアグラウンドであるかも検査できるので tcgetpgrp を利用している
-            // したがって zerosh は制御端末を利用した実行のみをサポートすることになる
-            shell_pgid: Pid::from_raw(pid),
-        }
-    }
-
-    /// worker スレッドを起動
-    fn spawn(mut self, worker_rx: Receiver<WorkerMsg>, shell_tx: SyncSender<ShellMsg>) {
-        thread::spawn(move || {
-            for msg in worker_rx.iter() {
-                match msg {
-                    WorkerMsg::Cmd(line) => {
-                        match parse_cmd(&line) {
-                            Ok(jobs) => {
-                                for job in jobs {
-                                    match job {
-                                        model::Job::BuiltIn { cmd, is_bg } => {
-                                            self.built_in_cmd(&cmd, is_bg, &shell_tx);
-                                            // 組み込みコマンドなら worker_rx から受信
-                                            continue;
-                                        }
-                                        model::Job::External { mut cmds, is_bg } => {
-                                            if !self.spawn_child(&mut cmds, is_bg, &shell_tx) {
-                                                // 子プロセス生成に失敗した場合、シェルからの入力を再開
-                                                shell_tx
-                                                    .send(ShellMsg::Continue(self.exit_val))
-                                                    .unwrap();
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("{NAME}: {e}");
-                                // コマンドのパースに失敗した場合はシェルからの入力を再開するため
-                                // main スレッドに通知する
-                                shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap();
-                            }
-                        }
-                    }
-                    WorkerMsg::Signal(SIGCHLD) => {
-                        self.wait_child(&shell_tx); // 子プロセスの状態変化を管理
-                    }
-                    WorkerMsg::Signal(sig) => {
-                        // 無視
-                        eprintln!("signal: {sig:?} received and ignore it");
-                    }
-                }
-            }
-        });
-    }
-
-    /// 組み込みコマンドの場合は true を返す
-    fn built_in_cmd(
-        &mut self,
-        cmd: &model::BuiltInCmd,
-        _is_bg: bool,
-        shell_tx: &SyncSender<ShellMsg>,
-    ) {
-        match cmd {
-            model::BuiltInCmd::Exit(n) => self.run_exit(&n, shell_tx),
-            model::BuiltInCmd::Jobs => self.run_jobs(shell_tx),
-            model::BuiltInCmd::Fg(n) => self.run_fg(&n, shell_tx),
-            model::BuiltInCmd::Cd(path) => self.run_cd(path, shell_tx),
-        };
-    }
-
-    /// 終了コマンドを実行
-    fn run_exit(&mut self, n: &Option<i32>, shell_tx: &SyncSender<ShellMsg>) -> bool {
-        // 実行中のジョブがある場合は終了しない
-        if !self.jobs.is_empty() {
-            eprintln!("{NAME}: Couldn't quit, there are some running jobs");
-            self.exit_val = 1; // 失敗
-            shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルからの入力を再開
-            return true;
-        }
-
-        // 終了コードを取得
-        let exit_val = n.unwrap_or(self.exit_val);
-
-        shell_tx.send(ShellMsg::Quit(exit_val)).unwrap(); // シェルを終了
-        true
-    }
-
-    /// ジョブ一覧を表示
-    fn run_jobs(&mut self, shell_tx: &SyncSender<ShellMsg>) -> bool {
-        for (job_id, (pgid, cmd)) in &self.jobs {
-            let state = if self.is_group_stop(*pgid).unwrap() {
-                "Stopped"
-            } else {
-                "Running"
-            };
-            println!("[{job_id}] {state}\t{cmd}");
-        }
-
-        self.exit_val = 0; // 成功
-        shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルからの入力を再開
-        true
-    }
-
-    /// フォアグラウンド実行
-    fn run_fg(&mut self, n: &i32, shell_tx: &SyncSender<ShellMsg>) -> bool {
-        self.exit_val = 1; // とりあえず失敗に設定
-        if let Some((pgid, cmd)) = self.jobs.get(&(*n as usize)) {
-            eprintln!("[{n}]: Restart\t{cmd}");
-
-            // フォアグラウンドプロセスに設定
-            self.fg = Some(*pgid);
-            unsafe { tcsetpgrp(libc::STDIN_FILENO, (*pgid).as_raw()) };
-
-            // ジョブの実行を再開
-            killpg(*pgid, Signal::SIGCONT).unwrap();
-            return true;
-        }
-
-        // 失敗
-        eprintln!("job {n} not found");
-        shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルからの入力を再開
-        true
-    }
-
-    /// ディレクトリ移動
-    fn run_cd(&mut self, path: &Option<String>, shell_tx: &SyncSender<ShellMsg>) -> bool {
-        let path = match path {
-            // 引数が指定されていない場合、ホームディレクトリか / に移動
-            None => dirs::home_dir()
-                .or_else(|| Some(PathBuf::from("/")))
-                .unwrap(),
-            Some(path) => PathBuf::from(path),
-        };
-
-        // カレントディレクトリを変更
-        if let Err(e) = std::env::set_current_dir(&path) {
-            self.exit_val = 1; // 失敗
-            eprintln!("failed to change directory to {path:?}: {e}");
-        } else {
-            self.exit_val = 0; // 成功
-        }
-
-        shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルからの入力を再開
-        true
-    }
-
-    /// 子プロセスを生成。失敗した場合はシェルからの入力を再開する必要がある
-    fn spawn_child(
-        &mut self,
-        cmd: &mut model::Pipeline,
-        is_bg: bool,
-        shell_tx: &SyncSender<ShellMsg>,
-    ) -> bool {
-        // ジョブ ID を取得
-        let job_id = if let Some(id) = self.get_new_job_id() {
-            id
-        } else {
-            eprintln!("{NAME}: Couldn't spawn child process, too many jobs already exists");
-            return false;
-        };
-
-        let pgid;
-        let mut pids = HashMap::new();
-        // ジョブを処理するベースとなるプロセスを生成
-        match fork_exec(Pid::from_raw(0), cmd, &mut pids) {
-            Ok(child) => {
-                pgid = child;
-            }
-            Err(e) => {
-                eprintln!("{NAME}: Failed to fork: {e}");
-                return false;
-            }
-        }
-
-        // ジョブ情報を追加
-        self.insert_job(job_id, pgid, pids, &cmd.to_string());
-
-        if is_bg {
-            // 子プロセスをバックグラウンドプロセスグループにする
-            self.set_shell_fg(shell_tx);
-        } else {
-            // 子プロセスをフォアグラウンドプロセスグループにする
-            self.fg = Some(pgid);
-            unsafe { tcsetpgrp(libc::STDIN_FILENO, pgid.as_raw()) };
-        }
-
-        true
-    }
-
-    /// ジョブの管理
-    /// 引数には変化のあったジョブとプロセスグループを指定
-    ///
-    /// - フォアグラウンドプロセスが空の場合、シェルをフォアグラウンドに設定
-    /// - フォアグラウンドプロセスがすべて停止中の場合、シェルをフォアグラウンドに設定
-    fn manage_job(&mut self, job_id: usize, pgid: Pid, shell_tx: &SyncSender<ShellMsg>) {
-        let is_fg = self.fg.map_or(false, |x| pgid == x); // フォアグラウンドのプロセスか?
-        let line = &self.jobs.get(&job_id).unwrap().1;
-        if is_fg {
-            // 状態が変化したプロセスはフォアグラウンドに設定
-            if self.is_group_empty(pgid) {
-                // フォアグラウンドプロセスが空の場合、
-                // ジョブ情報を削除してシェルをフォアグラウンドに設定
-                eprintln!("\n[{job_id}] Done\t{line}");
-                self.remove_job(job_id);
-                self.set_shell_fg(shell_tx);
-            } else if self.is_group_stop(pgid).unwrap() {
-                // フォアグラウンドプロセスがすべて停止中の場合、シェルをフォアグラウンドに設定
-                eprintln!("\n[{job_id}] Stopped\t{line}");
-                self.set_shell_fg(shell_tx);
-            }
-        } else {
-            // プロセスグループが空の場合、ジョブ情報を削除
-            if self.is_group_empty(pgid) {
-                eprintln!("\n[{job_id}] Done\t{line}");
-                self.remove_job(job_id);
-            }
-        }
-    }
-
-    /// 新たなジョブ情報を追加
-    fn insert_job(&mut self, job_id: usize, pgid: Pid, pids: HashMap<Pid, ProcInfo>, line: &str) {
-        assert!(!self.jobs.contains_key(&job_id));
-        self.jobs.insert(job_id, (pgid, line.to_string())); // ジョブ情報を追加
-
-        let mut procs = HashSet::new(); // pgid_to_pids へ追加するプロセス
-        for (pid, info) in pids {
-            procs.insert(pid);
-
-            assert!(!self.pid_to_info.contains_key(&pid));
-            self.pid_to_info.insert(pid, info); // プロセス情報を追加
-        }
-
-        assert!(!self.pgid_to_pids.contains_key(&pgid));
-        self.pgid_to_pids.insert(pgid, (job_id, procs)); // プロセスグループ情報を追加
-    }
-
-    /// プロセスの実行状態を設定し、以前の状態を返す
-    /// pid が存在しない場合は None を返す
-    fn set_pid_state(&mut self, pid: Pid, state: ProcState) -> Option<ProcState> {
-        let info = self.pid_to_info.get_mut(&pid)?;
-        Some(replace(&mut info.state, state))
-    }
-
-    /// プロセスの情報を削除し、削除できた場合はプロセスの所属する
-    /// (ジョブ ID, プロセスグループ ID) を返す
-    /// 存在しない場合は None を返す
-    fn remove_pid(&mut self, pid: Pid) -> Option<(usize, Pid)> {
-        let pgid = self.pid_to_info.get(&pid)?.pgid;
-        let it = self.pgid_to_pids.get_mut(&pgid)?;
-        it.1.remove(&pid); // プロセスグループから pid を削除
-        let job_id = it.0; // ジョブ ID を取得
-        Some((job_id, pgid))
-    }
-
-    /// ジョブ情報を削除し、関連するプロセスグループの情報も削除
-    fn remove_job(&mut self, job_id: usize) {
-        if let Some((pgid, _)) = self.jobs.remove(&job_id) {
-            if let Some((_, pids)) = self.pgid_to_pids.remove(&pgid) {
-                assert!(pids.is_empty()); // ジョブを削除するときはプロセスグループも空のはず
-            }
-        }
-    }
-
-    /// 空のプロセスグループなら真
-    fn is_group_empty(&self, pgid: Pid) -> bool {
-        self.pgid_to_pids.get(&pgid).unwrap().1.is_empty()
-    }
-
-    /// プロセスグループのプロセス全部が停止中なら真
-    fn is_group_stop(&self, pgid: Pid) -> Option<bool> {
-        for pid in self.pgid_to_pids.get(&pgid)?.1.iter() {
-            if self.pid_to_info.get(pid).unwrap().state == ProcState::Run {
-                return Some(false);
-            }
+            error!("failed to save history: {e}");
         }
-        Some(true)
-    }
-
-    /// シェルをフォアグラウンドに設定
-    fn set_shell_fg(&mut self, shell_tx: &SyncSender<ShellMsg>) {
-        self.fg = None;
-        unsafe { tcsetpgrp(libc::STDIN_FILENO, self.shell_pgid.as_raw()) };
-        shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap();
-    }
-
-    fn get_new_job_id(&self) -> Option<usize> {
-        for i in 0..=usize::MAX {
-            if !self.jobs.contains_key(&i) {
-                return Some(i);
-            }
-        }
-        None
-    }
-
-    /// 子プロセスの状態変化を管理
-    fn wait_child(&mut self, shell_tx: &SyncSender<ShellMsg>) {
-        // WUNTRACED: 子プロセスの停止
-        // WNOHANG: ブロックしない
-        // WCONTINUED: 実行再開
-        let flag = Some(WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG | WaitPidFlag::WCONTINUED);
-
-        loop {
-            // pid = -1 指定によりすべての子プロセスの状態変化を待つ
-            // waitpid は終了したプロセスのリソース開放も行う
-            // これを忘れるとゾンビプロセスになり無駄にリソースを消費する
-            // WNOHANG を指定しているので、子プロセスの状態に変化がない場合は即座に返る
-            // これにより worker はシグナルとコマンドライン実行の両方を並行に処理できる
-            match syscall(|| waitpid(Pid::from_raw(-1), flag)) {
-                Ok(WaitStatus::Exited(pid, status)) => {
-                    // プロセスが終了
-                    self.exit_val = status; // 終了コードを保存
-                    self.process_term(pid, shell_tx);
-                }
-                Ok(WaitStatus::Signaled(pid, sig, core)) => {
-                    // プロセスがシグナルにより終了
-                    eprintln!(
-                        "\n{NAME}: Child process terminated by signal{}: pid = {pid}, signal = {sig}",
-			if core { " (core dumped)" } else { "" },
-                    );
-                    self.exit_val = sig as i32 + 128; // 終了コードを保存
-                    self.process_term(pid, shell_tx);
-                }
-                // プロセスが停止
-                Ok(WaitStatus::Stopped(pid, _sig)) => self.process_stop(pid, shell_tx),
-                Ok(WaitStatus::Continued(pid)) => self.process_continue(pid),
-                Ok(WaitStatus::StillAlive) => return, // wait すべき子プロセスはいない
-                Err(nix::Error::ECHILD) => return,    // 子プロセスはいない
-                Err(e) => {
-                    eprintln!("\n{NAME}: Failed to wait: {e}");
-                    exit(1);
-                }
-                #[cfg(any(target_os = "linux", target_os = "android"))]
-                Ok(WaitStatus::PtraceEvent(pid, _, _) | WaitStatus::PtraceSyscall(pid)) => {
-                    self.process_stop(pid, shell_tx)
-                }
-            }
-        }
-    }
-
-    // プロセスの終了処理
-    fn process_term(&mut self, pid: Pid, shell_tx: &SyncSender<ShellMsg>) {
-        // プロセス ID を削除し、必要ならフォアグラウンドプロセスをシェルに設定
-        if let Some((job_id, pgid)) = self.remove_pid(pid) {
-            self.manage_job(job_id, pgid, shell_tx);
-        }
-    }
-
-    // プロセスの停止処理
-    fn process_stop(&mut self, pid: Pid, shell_tx: &SyncSender<ShellMsg>) {
-        self.set_pid_state(pid, ProcState::Stop); // プロセスを停止中に設定
-        let pgid = self.pid_to_info.get(&pid).unwrap().pgid; // プロセスグループ ID を取得
-        let job_id = self.pgid_to_pids.get(&pgid).unwrap().0; // ジョブ ID を取得
-        self.manage_job(job_id, pgid, shell_tx); // 必要ならフォアグラウンドプロセスをシェルに設定
-    }
-
-    // プロセスの再開処理
-    fn process_continue(&mut self, pid: Pid) {
-        self.set_pid_state(pid, ProcState::Run); // プロセスを実行中に設定
+        Ok(exit_val)
     }
 }
 
-fn do_pipeline(cmds: &mut model::Pipeline, pids: &mut HashMap<Pid, ProcInfo>) {
-    fn handle_redirect(cmd: &model::ExternalCmd) {
-        match cmd.redirect {
-            Some(model::Redirection::StdOut(ref out)) => {
-                let fd = syscall(move || {
-                    nix::fcntl::open(
-                        out.as_str(),
-                        nix::fcntl::OFlag::O_WRONLY | nix::fcntl::OFlag::O_CREAT,
-                        nix::sys::stat::Mode::S_IRWXU,
-                    )
-                })
-                .unwrap();
-                syscall(|| {
-                    close(libc::STDOUT_FILENO).unwrap();
-                    dup2(fd, libc::STDOUT_FILENO).unwrap();
-                    close(fd)
-                })
-                .unwrap();
-            }
-            Some(model::Redirection::Both(ref out)) => {
-                let fd = syscall(move || {
-                    nix::fcntl::open(
-                        out.as_str(),
-                        nix::fcntl::OFlag::O_WRONLY | nix::fcntl::OFlag::O_CREAT,
-                        nix::sys::stat::Mode::S_IRWXU,
-                    )
-                })
-                .unwrap();
-                syscall(|| {
-                    close(libc::STDOUT_FILENO).unwrap();
-                    close(libc::STDERR_FILENO).unwrap();
-                    dup2(fd, libc::STDOUT_FILENO).unwrap();
-                    dup2(fd, libc::STDERR_FILENO).unwrap();
-                    close(fd)
-                })
-                .unwrap();
-            }
-            Some(model::Redirection::Append(ref out)) => {
-                let fd = syscall(move || {
-                    nix::fcntl::open(
-                        out.as_str(),
-                        nix::fcntl::OFlag::O_WRONLY | nix::fcntl::OFlag::O_APPEND,
-                        nix::sys::stat::Mode::S_IRWXU,
-                    )
-                })
-                .unwrap();
-                syscall(|| {
-                    close(libc::STDOUT_FILENO).unwrap();
-                    dup2(fd, libc::STDOUT_FILENO).unwrap();
-                    close(fd)
-                })
-                .unwrap();
-            }
-            None => {}
+/// 検索用のプロンプトでクエリを読み込み、ヒストリを検索
+///
+/// Enter で確定した場合は見つかった行を、見つからなかった場合や中止した場合は
+/// 検索開始時の行を、カーソル位置とともに返す。
+fn search_history(
+    rl: &mut ShellEditor,
+    search: &SharedSearch,
+    origin: (String, usize),
+) -> (String, usize) {
+    // Ctrl-C などで中止した場合は空のクエリとして扱い、何にもマッチさせない
+    let query = rl.readline(search::SEARCH_PROMPT).unwrap_or_default();
+    let found = search.lock().unwrap().finish(&query, rl.history());
+    match found {
+        Some(line) => {
+            let pos = line.len();
+            (line, pos)
         }
+        None => origin,
     }
-    fn get_filename_and_args(cmd: &ExternalCmd) -> (CString, Vec<CString>) {
-        let filename = CString::new(cmd.filename()).unwrap();
-        let args = cmd
-            .args
-            .iter()
-            .map(|s| CString::new(s.as_str()).unwrap())
-            .collect::<Vec<_>>();
-        (filename, args)
-    }
-
-    match cmds {
-        model::Pipeline::Src(cmd) => {
-            // リダイレクト処理
-            handle_redirect(cmd);
-            let (filename, args) = get_filename_and_args(cmd);
-
-            match execvp(&filename, &args) {
-                Err(e) => {
-                    eprintln!("{NAME}: Failed to exec: {e}");
-                    exit(1);
-                }
-                Ok(_) => unreachable!(),
-            }
-        }
-        model::Pipeline::Out(cmds, cmd) => {
-            let p = pipe().unwrap();
-            let (filename, args) = get_filename_and_args(cmd);
-
-            match syscall(|| unsafe { fork() }).unwrap() {
-                ForkResult::Child => {
-                    // 子プロセスならパイプを stdout に dup2 して再帰
-                    syscall(|| {
-                        close(p.0.as_raw_fd()).unwrap();
-                        dup2(p.1.as_raw_fd(), libc::STDOUT_FILENO).unwrap();
-                        close(p.1.as_raw_fd())
-                    })
-                    .unwrap();
-
-                    do_pipeline(cmds, pids);
-                }
-                ForkResult::Parent { child } => {
-                    // リダイレクト処理
-                    handle_redirect(cmd);
-
-                    // 親プロセスならパイプを stdin に dup2 して最後のコマンドを execvp
-                    syscall(|| {
-                        close(p.1.as_raw_fd()).unwrap();
-                        dup2(p.0.as_raw_fd(), libc::STDIN_FILENO).unwrap();
-                        close(p.0.as_raw_fd())
-                    })
-                    .unwrap();
-
-                    pids.insert(
-                        child,
-                        ProcInfo {
-                            state: ProcState::Run,
-                            pgid: getpgid(None).unwrap(),
-                        },
-                    );
-                    match execvp(&filename, &args) {
-                        Err(e) => {
-                            eprintln!("{NAME}: Failed to exec: {e}");
-                            exit(1);
-                        }
-                        Ok(_) => unreachable!(),
-                    }
-                }
-            }
-        }
-        model::Pipeline::Both(cmds, cmd) => {
-            let p = pipe().unwrap();
-            let (filename, args) = get_filename_and_args(cmd);
-
-            match syscall(|| unsafe { fork() }).unwrap() {
-                ForkResult::Child => {
-                    // 子プロセスならパイプを stdout と stderr に dup2 して再帰
-                    syscall(|| {
-                        close(p.0.as_raw_fd()).unwrap();
-                        dup2(p.1.as_raw_fd(), libc::STDOUT_FILENO).unwrap();
-                        dup2(p.1.as_raw_fd(), libc::STDERR_FILENO).unwrap();
-                        close(p.1.as_raw_fd())
-                    })
-                    .unwrap();
-
-                    do_pipeline(cmds, pids);
-                }
-                ForkResult::Parent { child } => {
-                    // リダイレクト処理
-                    handle_redirect(cmd);
-
-                    // 親プロセスならパイプを stdin に dup2 して最後のコマンドを execvp
-                    syscall(|| {
-                        close(p.1.as_raw_fd()).unwrap();
-                        dup2(p.0.as_raw_fd(), libc::STDIN_FILENO).unwrap();
-                        close(p.0.as_raw_fd())
-                    })
-                    .unwrap();
-
-                    pids.insert(
-                        child,
-                        ProcInfo {
-                            state: ProcState::Run,
-                            pgid: getpgid(None).unwrap(),
-                        },
-                    );
-                    match execvp(&filename, &args) {
-                        Err(e) => {
-                            eprintln!("{NAME}: Failed to exec: {e}");
-                            exit(1);
-                        }
-                        Ok(_) => unreachable!(),
-                    }
-                }
-            }
-        }
-    };
 }
 
-/// プロセスグループ ID を指定して fork & exec
-/// pgid が 0 の場合は子プロセスのプロセス ID がプロセスグループ ID となる
+/// worker スレッドからのメッセージを受信
 ///
-/// - input が Some(fd) の場合は、標準入力を fd と設定
-/// - output が Some(fd) の場合は、標準出力を fd と設定
-fn fork_exec(
-    pgid: Pid,
-    cmds: &mut model::Pipeline,
-    pids: &mut HashMap<Pid, ProcInfo>,
-) -> Result<Pid, DynError> {
-    match syscall(|| unsafe { fork() })? {
-        ForkResult::Parent { child } => {
-            // 子プロセスのプロセスグループ ID を pgid に設定
-            setpgid(child, pgid).unwrap();
-            pids.insert(
-                child,
-                ProcInfo {
-                    state: ProcState::Run,
-                    pgid: child,
-                },
-            );
-
-            Ok(child)
-        }
-        ForkResult::Child => {
-            // 子プロセスのプロセスグループ ID を pgid に設定
-            setpgid(Pid::from_raw(0), pgid).unwrap();
-
-            do_pipeline(cmds, pids);
-
-            Ok(getpid())
+/// BindKey を受信した場合はキーバインドを登録して受信を続け、
+/// Continue か Quit を受信したらそれを返す。
+fn recv_shell_msg(
+    rl: &mut ShellEditor,
+    shell_rx: &Receiver<ShellMsg>,
+    pending: &PendingCmd,
+) -> ShellMsg {
+    loop {
+        match shell_rx.recv().unwrap() {
+            ShellMsg::BindKey(keys, cmd) => {
+                let handler = KeyBindHandler::new(cmd, pending.clone());
+                rl.bind_sequence(
+                    Event::KeySeq(keys),
+                    EventHandler::Conditional(Box::new(handler)),
+                );
+            }
+            msg => return msg,
         }
     }
 }
 
-type CmdResult<'a> = Result<Vec<model::Job>, DynError>;
-
 /// コマンドをパース
-fn parse_cmd(line: &str) -> CmdResult {
-    match parser::parse(line) {
-        Ok((_, jobs)) => Ok(jobs),
-        Err(e) => Err(e.into()),
-    }
+///
+/// 2 行目以降はヒアドキュメントの本文とし、先頭の行のヒアドキュメントに出現順に設定する。
+/// エラーの位置は先頭の行におけるバイト位置とする。
+fn parse_cmd(text: &str) -> Result<Vec<model::Job>, ShellParseError> {
+    let (line, bodies) = text.split_once('\n').unwrap_or((text, ""));
+    let mut jobs = parser::parse(line)?;
+    heredoc::fill(&mut jobs, bodies);
+    Ok(jobs)
 }
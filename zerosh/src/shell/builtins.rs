@@ -0,0 +1,922 @@
+//! 組み込みコマンド
+//!
+//! worker スレッドで実行し、続けて次のジョブを実行するかを resume に返す。
+use super::control;
+use super::jobs::{self, Origin, ProcState};
+use super::spawn;
+use super::timing;
+use super::worker::Worker;
+use super::{parse_cmd, ShellMsg, NAME, SHELL_SIGNALS};
+use crate::cancel::{CancelToken, Cancelled};
+use crate::complete::BUILTINS;
+use crate::expand;
+use crate::keybind;
+use crate::local::{self, ActiveLocal};
+use crate::model;
+use crate::sigpipe;
+use crate::term::{self, Style};
+use crate::vars;
+use nix::{
+    libc::{self, tcsetpgrp},
+    sys::{
+        signal::{killpg, signal, SigHandler, Signal},
+        stat::{umask, Mode},
+    },
+};
+use std::{
+    ffi::CString,
+    fs::File,
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::SyncSender,
+};
+
+impl Worker {
+    /// 組み込みコマンドを実行。戻り値は、続けて次のジョブを実行するか
+    ///
+    /// ブロックする組み込みコマンドは CancelToken で中断を確認し、中断された場合は on_cancelled を呼び出す。
+    pub(super) fn built_in_cmd(
+        &mut self,
+        cmd: &model::BuiltInCmd,
+        _is_bg: bool,
+        shell_tx: &SyncSender<ShellMsg>,
+    ) -> bool {
+        match cmd {
+            model::BuiltInCmd::Exit(n) => self.run_exit(n, shell_tx),
+            model::BuiltInCmd::Jobs(long, verbose) => self.run_jobs(*long, *verbose),
+            model::BuiltInCmd::JobsTree(n) => self.run_jobs_tree(n),
+            model::BuiltInCmd::Fg(n) => self.run_fg(n),
+            model::BuiltInCmd::Bg(n) => self.run_bg(n),
+            model::BuiltInCmd::Wait(n) => self.run_wait(n),
+            model::BuiltInCmd::Cd(path) => self.run_cd(path),
+            model::BuiltInCmd::BindKey(bind) => self.run_bindkey(bind, shell_tx),
+            model::BuiltInCmd::Set(opt) => self.run_set(opt),
+            model::BuiltInCmd::Export(vars) => self.run_export(vars),
+            model::BuiltInCmd::Local(vars) => self.run_local(vars),
+            model::BuiltInCmd::Array(name, values) => self.run_array(name, values),
+            model::BuiltInCmd::Echo(args) => self.run_echo(args),
+            model::BuiltInCmd::Pwd => self.run_pwd(),
+            model::BuiltInCmd::True => self.run_status(0),
+            model::BuiltInCmd::False => self.run_status(1),
+            model::BuiltInCmd::Type(names) => self.run_type(names),
+            model::BuiltInCmd::Trap(args) => self.run_trap(args),
+            model::BuiltInCmd::Times => self.run_times(),
+            model::BuiltInCmd::Read(args) => self.run_read(args),
+            model::BuiltInCmd::Umask(args) => self.run_umask(args),
+            model::BuiltInCmd::Exec(args) => self.run_exec(args),
+            model::BuiltInCmd::Source(args) => self.run_source(args),
+        }
+    }
+
+    /// SIGINT で中断された場合に呼び出す
+    /// 中断状態を解除し、同じ行の残りのジョブは実行しない
+    pub(super) fn on_cancelled(&mut self) {
+        self.cancel.reset();
+        eprintln!();
+        self.exit_val = 128 + Signal::SIGINT as i32;
+        self.clear_queue();
+    }
+
+    /// 終了コマンドを実行
+    fn run_exit(&mut self, n: &Option<i32>, shell_tx: &SyncSender<ShellMsg>) -> bool {
+        // 実行中のジョブがある場合は終了しない
+        if !self.jobs.is_empty() {
+            error!("Couldn't quit, there are some running jobs");
+            self.exit_val = 1; // 失敗
+            return true;
+        }
+
+        // 終了コードを取得
+        let exit_val = n.unwrap_or(self.exit_val);
+        self.clear_queue(); // 残りのジョブは実行しない
+
+        shell_tx.send(ShellMsg::Quit(exit_val)).unwrap(); // シェルを終了
+        false
+    }
+
+    /// ジョブ一覧を表示
+    /// long の場合は、パイプラインの各段のプロセス ID と状態も表示する
+    /// verbose の場合は、ジョブを起動した時点のカレントディレクトリ, umask,
+    /// 起動した後に変更された環境変数の起動時の値も表示する
+    fn run_jobs(&mut self, long: bool, verbose: bool) -> bool {
+        let now = Origin::capture();
+        for (job_id, pgid, cmd) in self.jobs.iter() {
+            let caps = term::caps();
+            let state = if self.jobs.is_group_stop(pgid).unwrap() {
+                caps.paint(Style::Yellow, "Stopped")
+            } else {
+                caps.paint(Style::Green, "Running")
+            };
+            println!("[{job_id}] {state}\t{cmd}");
+
+            // パイプラインの各段のプロセス ID と状態。終了した段は終了コードを表示する
+            if long {
+                for (pid, state) in self.jobs.stage_states(job_id) {
+                    println!("    {pid} {state}");
+                }
+            }
+
+            if let Some(origin) = self.jobs.origin(job_id).filter(|_| verbose) {
+                println!("    cwd: {}", origin.cwd.display());
+                println!("    umask: {:04o}", origin.umask);
+                for (name, value) in origin.env_changes(&now.env) {
+                    match value {
+                        Some(value) => println!("    env: {name}={value}"),
+                        None => println!("    env: {name} (unset)"),
+                    }
+                }
+            }
+        }
+
+        self.exit_val = 0; // 成功
+        true
+    }
+
+    /// ジョブのパイプラインの構造を木の形で表示
+    /// n が None の場合はすべてのジョブを表示する
+    fn run_jobs_tree(&mut self, n: &Option<i32>) -> bool {
+        let job_ids: Vec<usize> = match n {
+            Some(n) if self.jobs.get(*n as usize).is_none() => {
+                error!("jobs: job {n} not found");
+                self.exit_val = 1; // 失敗
+                return true;
+            }
+            Some(n) => vec![*n as usize],
+            None => self.jobs.iter().map(|(job_id, _, _)| job_id).collect(),
+        };
+
+        for job_id in job_ids {
+            let (pgid, cmd) = self.jobs.get(job_id).unwrap();
+            let state = if self.jobs.is_group_stop(pgid).unwrap() {
+                term::caps().paint(Style::Yellow, "Stopped")
+            } else {
+                term::caps().paint(Style::Green, "Running")
+            };
+            println!("[{job_id}] {state}\t{cmd}");
+            for line in self.jobs.tree(job_id) {
+                println!("    {line}");
+            }
+        }
+
+        self.exit_val = 0; // 成功
+        true
+    }
+
+    /// フォアグラウンド実行
+    fn run_fg(&mut self, n: &i32) -> bool {
+        self.exit_val = 1; // とりあえず失敗に設定
+        if let Some((pgid, cmd)) = self.jobs.get(*n as usize) {
+            eprintln!("[{n}]: Restart\t{cmd}");
+
+            // フォアグラウンドプロセスに設定
+            self.set_title(cmd);
+            self.fg = Some(pgid);
+            unsafe { tcsetpgrp(libc::STDIN_FILENO, pgid.as_raw()) };
+
+            // ジョブの実行を再開
+            killpg(pgid, Signal::SIGCONT).unwrap();
+            return false; // 終了・停止を待つ
+        }
+
+        // 失敗
+        eprintln!("job {n} not found");
+        true
+    }
+
+    /// 停止中のジョブをバックグラウンドで再開
+    ///
+    /// fg と異なり端末の制御は移さず、シェルは入力の受付を続ける。
+    fn run_bg(&mut self, n: &i32) -> bool {
+        self.exit_val = 1; // とりあえず失敗に設定
+        if let Some((pgid, cmd)) = self.jobs.get(*n as usize) {
+            if self.jobs.is_group_stop(pgid) == Some(true) {
+                eprintln!("[{n}]: Restart\t{cmd} &");
+
+                // SIGCONT による WaitStatus::Continued を待たずに、実行中として扱う
+                // こうしないと直後の jobs で Stopped と表示されてしまう
+                for pid in self.jobs.pids(pgid) {
+                    self.jobs.set_pid_state(pid, ProcState::Run);
+                }
+                self.update_status();
+
+                // ジョブの実行を再開
+                killpg(pgid, Signal::SIGCONT).unwrap();
+            } else {
+                eprintln!("job {n} already in background");
+            }
+            self.exit_val = 0; // 成功
+        } else {
+            // 失敗
+            eprintln!("job {n} not found");
+        }
+
+        true
+    }
+
+    /// バックグラウンドのジョブの終了を待つ。 n が None の場合は実行中のジョブすべてを待つ
+    ///
+    /// worker スレッドは待っている間も SIGCHLD を処理する必要があるため、ブロックはしない。
+    /// 待つジョブがあれば false を返し、ジョブが終了・停止した時点で check_wait が再開する。
+    fn run_wait(&mut self, n: &Option<i32>) -> bool {
+        if let Some(n) = n {
+            if self.jobs.get(*n as usize).is_none() {
+                error!("wait: job {n} not found");
+                self.exit_val = 127; // 失敗
+                return true;
+            }
+        }
+
+        self.exit_val = 0; // 成功
+        self.waiting = Some(n.map(|n| n as usize));
+        self.finish_wait()
+    }
+
+    /// wait で待っているジョブがすべて終了・停止していれば、次のジョブかシェルからの入力を再開
+    pub(super) fn check_wait(&mut self, shell_tx: &SyncSender<ShellMsg>) {
+        if self.finish_wait() {
+            self.resume(shell_tx); // 次のジョブかシェルからの入力を再開
+        }
+    }
+
+    /// wait で待っているジョブがすべて終了・停止していれば、待つのをやめて true を返す
+    /// 特定のジョブを待っていた場合は、そのジョブの終了コードとする
+    fn finish_wait(&mut self) -> bool {
+        let done = match self.waiting {
+            None => return false,
+            Some(None) => {
+                self.exit_val = 0;
+                self.jobs.counts().0 == 0
+            }
+            Some(Some(job_id)) => match self.jobs.get(job_id) {
+                Some((pgid, _)) => self.jobs.is_group_stop(pgid) == Some(true),
+                None => true,
+            },
+        };
+        if done {
+            self.waiting = None;
+        }
+        done
+    }
+
+    /// wait で待っている間に SIGINT を受信した場合は、待つのをやめる
+    pub(super) fn cancel_wait(&mut self, shell_tx: &SyncSender<ShellMsg>) {
+        if self.waiting.take().is_some() {
+            self.on_cancelled();
+            self.resume(shell_tx); // シェルからの入力を再開
+        }
+    }
+
+    /// ディレクトリ移動
+    fn run_cd(&mut self, path: &Option<String>) -> bool {
+        let path = match path {
+            // 引数が指定されていない場合、ホームディレクトリか / に移動
+            None => dirs::home_dir()
+                .or_else(|| Some(PathBuf::from("/")))
+                .unwrap(),
+            Some(path) => PathBuf::from(expand::expand_vars(path)),
+        };
+
+        // カレントディレクトリを変更
+        if let Err(e) = std::env::set_current_dir(&path) {
+            self.exit_val = 1; // 失敗
+            eprintln!("failed to change directory to {path:?}: {e}");
+        } else {
+            self.exit_val = 0; // 成功
+            if self.update_local().is_err() {
+                self.on_cancelled();
+            }
+        }
+
+        true
+    }
+
+    /// カレントディレクトリに応じて、ローカル設定ファイルを適用・解除する
+    ///
+    /// 適用中のディレクトリの外に出た場合は、環境変数を元に戻す。
+    /// 移動先に .zerosh.local がある場合は、信頼済みか確認してから適用する。
+    /// ファイル中のコマンドは、同じ行の残りのジョブより先に実行する。
+    /// 信頼するかの問い合わせ中に中断された場合は適用せずに Err を返す。
+    fn update_local(&mut self) -> Result<(), Cancelled> {
+        let Ok(cwd) = std::env::current_dir() else {
+            return Ok(());
+        };
+        let file = cwd.join(local::LOCAL_FILE);
+        let has_local = file.is_file();
+
+        if let Some(active) = &self.local {
+            // 適用中のディレクトリの中にいて、別の .zerosh.local もなければそのまま
+            if cwd.starts_with(&active.dir) && (!has_local || cwd == active.dir) {
+                return Ok(());
+            }
+            let active = self.local.take().unwrap();
+            eprintln!(
+                "{NAME}: unloading {}",
+                active.dir.join(local::LOCAL_FILE).display()
+            );
+            active.restore();
+        }
+        if !has_local {
+            return Ok(());
+        }
+
+        let content = match std::fs::read(&file) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("{}: {e}", file.display());
+                return Ok(());
+            }
+        };
+        let h = local::hash(&content);
+        if !self.trust.is_trusted(&file, h) {
+            if !confirm_trust(&file, &self.cancel)? {
+                error!("{} is not trusted, skipped", file.display());
+                return Ok(());
+            }
+            if let Err(e) = self.trust.trust(&file, h) {
+                error!("failed to save trusted files: {e}");
+            }
+        }
+
+        eprintln!("{NAME}: loading {}", file.display());
+        let rc = local::parse_local(&String::from_utf8_lossy(&content));
+        self.local = Some(ActiveLocal::apply(cwd, &rc));
+
+        let mut jobs = Vec::new();
+        for cmd in &rc.cmds {
+            match parse_cmd(cmd) {
+                Ok(j) => jobs.extend(j),
+                Err(e) => error!("{}: {e}", file.display()),
+            }
+        }
+        self.push_jobs(jobs);
+        Ok(())
+    }
+
+    /// キーバインドの設定。引数がない場合は一覧を表示
+    fn run_bindkey(
+        &mut self,
+        bind: &Option<(String, String)>,
+        shell_tx: &SyncSender<ShellMsg>,
+    ) -> bool {
+        match bind {
+            None => {
+                for (key, cmd) in &self.key_binds {
+                    println!("'{key}' '{cmd}'");
+                }
+                self.exit_val = 0; // 成功
+            }
+            Some((key, cmd)) => {
+                if let Some(keys) = keybind::parse_keyseq(key) {
+                    // main スレッドでキーバインドを登録
+                    shell_tx.send(ShellMsg::BindKey(keys, cmd.clone())).unwrap();
+                    self.key_binds.insert(key.clone(), cmd.clone());
+                    self.exit_val = 0; // 成功
+                } else {
+                    error!("bindkey: invalid key sequence: {key}");
+                    self.exit_val = 1; // 失敗
+                }
+            }
+        }
+
+        true
+    }
+
+    /// シェルのオプションを設定。引数がない場合は一覧を表示
+    fn run_set(&mut self, opt: &Option<(bool, String)>) -> bool {
+        match opt {
+            None => {
+                for (name, on) in self.opts.list() {
+                    println!("set {}o {name}", if on { '-' } else { '+' });
+                }
+                self.exit_val = 0; // 成功
+            }
+            Some((on, name)) => {
+                if self.opts.set(name, *on).is_some() {
+                    let mut status = self.status.lock().unwrap();
+                    status.enabled = self.opts.status;
+                    status.immediate = self.opts.notify;
+                    self.exit_val = 0; // 成功
+                } else {
+                    error!("set: unknown option: {name}");
+                    self.exit_val = 1; // 失敗
+                }
+            }
+        }
+
+        true
+    }
+
+    /// 環境変数を設定。子プロセスは execvp の際にシェルの環境変数を引き継ぐ
+    /// 引数がない場合は設定済みの環境変数を一覧表示
+    fn run_export(&mut self, vars: &[String]) -> bool {
+        self.exit_val = 0; // 成功
+        if vars.is_empty() {
+            let mut list: Vec<_> = std::env::vars_os().collect();
+            list.sort();
+            for (name, value) in list {
+                println!(
+                    "export {}={}",
+                    name.to_string_lossy(),
+                    value.to_string_lossy()
+                );
+            }
+        }
+
+        for var in vars {
+            // 値のない NAME はすでに環境変数であれば何もしない
+            let (name, value) = var.split_once('=').unwrap_or((var, ""));
+            if !expand::is_var_name(name) {
+                error!("export: `{var}': not a valid identifier");
+                self.exit_val = 1; // 失敗
+            } else if var.contains('=') {
+                let value = expand::expand_vars(value);
+                vars::remove_array(name);
+                std::env::set_var(name, value);
+            }
+        }
+
+        true
+    }
+
+    /// 呼び出し中の関数のローカル変数を宣言し、値があれば設定する
+    /// 関数の終了時に元の値に戻す
+    fn run_local(&mut self, vars: &[String]) -> bool {
+        self.exit_val = 0; // 成功
+        for var in vars {
+            let (name, value) = var.split_once('=').unwrap_or((var, ""));
+            if !expand::is_var_name(name) {
+                error!("local: `{var}': not a valid identifier");
+                self.exit_val = 1; // 失敗
+            } else if !vars::make_local(name) {
+                error!("local: can only be used in a function");
+                self.exit_val = 1; // 失敗
+                break;
+            } else if var.contains('=') {
+                let value = expand::expand_vars(value);
+                vars::remove_array(name);
+                std::env::set_var(name, value);
+            }
+        }
+
+        true
+    }
+
+    /// 配列を設定。要素は外部コマンドの引数と同様に展開する
+    /// 同じ名前の環境変数は削除する
+    fn run_array(&mut self, name: &str, values: &[String]) -> bool {
+        vars::set_array(name, expand::expand_args(values));
+        self.exit_val = 0; // 成功
+        true
+    }
+
+    /// 引数を空白で区切って表示。引数は外部コマンドと同様に展開する
+    /// -n は末尾の改行を出力せず、 -e はバックスラッシュによるエスケープを解釈する
+    fn run_echo(&mut self, args: &[String]) -> bool {
+        let args = expand::expand_args(args);
+        let (mut newline, mut escape) = (true, false);
+        let mut words = args.iter().peekable();
+        while let Some(opt) = words.next_if(|w| is_echo_opt(w)) {
+            for c in opt[1..].chars() {
+                match c {
+                    'n' => newline = false,
+                    'e' => escape = true,
+                    _ => escape = false, // E
+                }
+            }
+        }
+
+        let mut out = words.cloned().collect::<Vec<_>>().join(" ");
+        if escape {
+            let (s, stop) = unescape(&out);
+            out = s;
+            newline &= !stop;
+        }
+        if newline {
+            out.push('\n');
+        }
+
+        let mut stdout = io::stdout().lock();
+        self.exit_val = match stdout
+            .write_all(out.as_bytes())
+            .and_then(|_| stdout.flush())
+        {
+            Ok(()) => 0, // 成功
+            Err(e) => {
+                error!("echo: {e}");
+                1 // 失敗
+            }
+        };
+        true
+    }
+
+    /// シェル自身と回収済みの子プロセスの、ユーザーモードとカーネルモードの CPU 時間を表示
+    fn run_times(&mut self) -> bool {
+        println!("{}", timing::times());
+        self.exit_val = 0; // 成功
+        true
+    }
+
+    /// カレントディレクトリを表示
+    fn run_pwd(&mut self) -> bool {
+        match std::env::current_dir() {
+            Ok(dir) => {
+                println!("{}", dir.display());
+                self.exit_val = 0; // 成功
+            }
+            Err(e) => {
+                error!("pwd: {e}");
+                self.exit_val = 1; // 失敗
+            }
+        }
+
+        true
+    }
+
+    /// 標準入力から 1 行読み込み、 IFS で分割して変数に設定する
+    /// 変数名を省略した場合は REPLY に設定し、最後の変数には残りのすべてを設定する
+    /// -p PROMPT は読み込む前に標準エラー出力に表示する。バックスラッシュは常にそのままの文字とし、 -r は無視する
+    /// 行を読み込む前に入力が終わった場合は失敗とする
+    fn run_read(&mut self, args: &[String]) -> bool {
+        let args = expand::expand_args(args);
+        let mut prompt = None;
+        let mut words = args.iter();
+        let mut names = Vec::new();
+        while let Some(word) = words.next() {
+            match word.as_str() {
+                "-p" if names.is_empty() => prompt = words.next(),
+                "-r" if names.is_empty() => (),
+                name => names.push(name),
+            }
+        }
+        if names.is_empty() {
+            names.push("REPLY");
+        }
+        if let Some(name) = names.iter().find(|name| !expand::is_var_name(name)) {
+            error!("read: `{name}': not a valid identifier");
+            self.exit_val = 1; // 失敗
+            return true;
+        }
+
+        if let Some(prompt) = prompt {
+            eprint!("{prompt}");
+        }
+        match read_line(&self.cancel) {
+            Ok(Some(line)) => {
+                for (name, value) in names.iter().zip(expand::split_ifs(&line, names.len())) {
+                    vars::remove_array(name);
+                    std::env::set_var(name, value);
+                }
+                self.exit_val = 0; // 成功
+            }
+            Ok(None) => self.exit_val = 1, // 入力の終わり
+            Err(Cancelled) => self.on_cancelled(),
+        }
+
+        true
+    }
+
+    /// ファイル作成マスクを 8 進数で設定する。引数がない場合は 4 桁の 8 進数で表示
+    fn run_umask(&mut self, args: &[String]) -> bool {
+        let args = expand::expand_args(args);
+        self.exit_val = 0; // 成功
+        match args.first() {
+            None => match jobs::current_umask() {
+                Some(mask) => println!("{mask:04o}"),
+                None => {
+                    error!("umask: can't read the current mask");
+                    self.exit_val = 1; // 失敗
+                }
+            },
+            Some(arg) => match parse_umask(arg) {
+                Some(mask) => {
+                    umask(mask);
+                }
+                None => {
+                    error!("umask: `{arg}': invalid octal number");
+                    self.exit_val = 1; // 失敗
+                }
+            },
+        }
+
+        true
+    }
+
+    /// シェルをコマンドで置き換える。引数がない場合は何もしない
+    ///
+    /// コマンドが見つからない場合は終了コードを 127 としてシェルを続ける。
+    /// 見つかった場合は、シェルが受信するよう設定したシグナルと trap をデフォルトに戻してから exec する。
+    /// 端末のフォアグラウンドはシェルのプロセスグループのまま引き継ぐ。
+    /// バックグラウンドのジョブは残り、 exec に失敗した場合は子プロセスと同様に終了する。
+    fn run_exec(&mut self, args: &[String]) -> bool {
+        let args = expand::expand_args(args);
+        let Some(name) = args.first() else {
+            self.exit_val = 0; // 成功
+            return true;
+        };
+        if self.hash.lookup(name).is_none() {
+            error!("exec: {name}: not found");
+            self.exit_val = 127;
+            return true;
+        }
+
+        // ジョブ制御のために設定したシグナルの処理を、子プロセスと同様にデフォルトに戻す
+        for sig in SHELL_SIGNALS.iter().chain(self.traps.keys()) {
+            let _ = sigpipe::unwatch(*sig);
+        }
+        for sig in [Signal::SIGTTOU, Signal::SIGPIPE] {
+            let _ = unsafe { signal(sig, SigHandler::SigDfl) };
+        }
+        let _ = io::stdout().flush();
+
+        let args: Vec<CString> = args
+            .into_iter()
+            .map(|arg| CString::new(arg).unwrap_or_default())
+            .collect();
+        spawn::exec_cmd(&args[0], &args, &self.hash)
+    }
+
+    /// ファイルのコマンドを現在のシェルで実行する
+    ///
+    /// ファイルのコマンドは、同じ行の残りのジョブより先に実行する。
+    /// 空行と `#` で始まる行は飛ばし、パースできない行はエラーを表示して飛ばす。
+    fn run_source(&mut self, args: &[String]) -> bool {
+        let args = expand::expand_args(args);
+        let Some(file) = args.first() else {
+            error!("source: filename argument required");
+            self.exit_val = 2; // 使い方の誤り
+            return true;
+        };
+        let mut reader = match File::open(file) {
+            Ok(f) => BufReader::new(f),
+            Err(e) => {
+                error!("source: {file}: {e}");
+                self.exit_val = 1; // 失敗
+                return true;
+            }
+        };
+
+        let mut jobs = Vec::new();
+        while let Some(text) = control::read_command(&mut reader) {
+            match text {
+                Ok(text) => match parse_cmd(&text) {
+                    Ok(j) => jobs.extend(j),
+                    Err(e) => error!("{file}: {e}"),
+                },
+                Err(tag) => {
+                    error!("{file}: here-document delimited by end-of-file (wanted `{tag}')")
+                }
+            }
+        }
+        self.exit_val = 0; // コマンドがない場合は成功
+        self.push_jobs(jobs);
+
+        true
+    }
+
+    /// 何もせずに終了コードを exit_val とする。 true と false に用いる
+    fn run_status(&mut self, exit_val: i32) -> bool {
+        self.exit_val = exit_val;
+        true
+    }
+
+    /// コマンド名が組み込みコマンド、関数、外部コマンドのいずれかを表示
+    /// 外部コマンドは PATH から検索したパスを表示し、見つからない名前があれば失敗とする
+    fn run_type(&mut self, names: &[String]) -> bool {
+        self.exit_val = 0; // 成功
+        for name in expand::expand_args(names) {
+            if BUILTINS.contains(&name.as_str()) {
+                println!("{name} is a shell builtin");
+            } else if self.functions.contains_key(&name) {
+                println!("{name} is a function");
+            } else if let Some(path) = self.hash.lookup(&name).filter(|p| p.is_file()) {
+                println!("{name} is {}", path.display());
+            } else {
+                error!("type: {name}: not found");
+                self.exit_val = 1; // 失敗
+            }
+        }
+
+        true
+    }
+
+    /// シグナルを受信した場合に実行するコマンドを設定
+    ///
+    /// - `trap` : 設定済みのコマンドを一覧表示
+    /// - `trap -l` : シグナル名を一覧表示
+    /// - `trap CMD SIG...` : SIG を受信した場合に CMD を実行。 CMD が空の場合はシグナルを無視する
+    /// - `trap - SIG...` : 設定を解除
+    fn run_trap(&mut self, args: &[String]) -> bool {
+        let args = expand::expand_args(args);
+        self.exit_val = 0; // 成功
+        match args.as_slice() {
+            [] => {
+                for (sig, cmd) in &self.traps {
+                    println!("trap -- '{cmd}' {sig}");
+                }
+            }
+            [opt] if opt == "-l" => print!("{}", signal_list()),
+            [_] => {
+                error!("trap: usage: trap [-l] [CMD SIG...]");
+                self.exit_val = 2; // 失敗
+            }
+            [cmd, sigs @ ..] => {
+                for name in sigs {
+                    let Some(sig) = parse_signal(name) else {
+                        error!("trap: {name}: invalid signal specification");
+                        self.exit_val = 1; // 失敗
+                        continue;
+                    };
+                    if let Err(e) = self.set_trap(sig, cmd) {
+                        error!("trap: {name}: {e}");
+                        self.exit_val = 1; // 失敗
+                    }
+                }
+            }
+        }
+
+        true
+    }
+
+    /// sig の trap を設定し、 cmd が - の場合は解除する
+    /// シェルが常に受信するシグナル以外は、設定の際に受信を開始し、解除の際にデフォルトに戻す
+    fn set_trap(&mut self, sig: Signal, cmd: &str) -> nix::Result<()> {
+        let is_shell_sig = SHELL_SIGNALS.contains(&sig);
+        if cmd == "-" {
+            if self.traps.remove(&sig).is_some() && !is_shell_sig {
+                sigpipe::unwatch(sig)?;
+            }
+        } else {
+            if !is_shell_sig && !self.traps.contains_key(&sig) {
+                sigpipe::watch(sig)?;
+            }
+            self.traps.insert(sig, cmd.to_string());
+        }
+        Ok(())
+    }
+}
+
+/// 信頼していないローカル設定ファイルを実行するか問い合わせる
+/// main スレッドは worker スレッドの処理を待っているため、標準入力から直接読み込む
+/// 入力を待っている間に Ctrl-C が入力された場合は Err を返す
+fn confirm_trust(file: &Path, cancel: &CancelToken) -> Result<bool, Cancelled> {
+    eprint!(
+        "{NAME}: {} is not trusted. Trust and run it? [y/N] ",
+        file.display()
+    );
+    cancel.wait_readable(libc::STDIN_FILENO)?;
+    let mut answer = String::new();
+    Ok(io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// read で標準入力から 1 行を読み込む。末尾の改行は含めない
+///
+/// main スレッドは worker スレッドの処理を待っているため、標準入力から直接読み込む。
+/// 続く入力を外部コマンドや行エディタが読めるよう、バッファを介さずに 1 バイトずつ読み込む。
+/// 何も読み込まずに入力が終わった場合は None を、入力を待っている間に Ctrl-C が入力された場合は Err を返す。
+fn read_line(cancel: &CancelToken) -> Result<Option<String>, Cancelled> {
+    let mut buf = Vec::new();
+    loop {
+        cancel.wait_readable(libc::STDIN_FILENO)?;
+        let mut byte = [0; 1];
+        match nix::unistd::read(libc::STDIN_FILENO, &mut byte) {
+            Ok(1) if byte[0] != b'\n' => buf.push(byte[0]),
+            Ok(1) => break,
+            Err(nix::Error::EINTR) => continue,
+            _ if buf.is_empty() => return Ok(None),
+            _ => break,
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&buf).into_owned()))
+}
+
+/// umask の引数の 8 進数をマスクに変換する。 0777 を超える場合は None
+fn parse_umask(s: &str) -> Option<Mode> {
+    let bits = u32::from_str_radix(s, 8)
+        .ok()
+        .filter(|bits| *bits <= 0o777)?;
+    Mode::from_bits(bits)
+}
+#[cfg(test)]
+mod parse_umask {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(parse_umask("022"), Some(Mode::from_bits_truncate(0o022)));
+        assert_eq!(parse_umask("0777"), Some(Mode::from_bits_truncate(0o777)));
+        assert_eq!(parse_umask("1000"), None);
+        assert_eq!(parse_umask("8"), None);
+        assert_eq!(parse_umask("u=rwx"), None);
+        assert_eq!(parse_umask("-1"), None);
+    }
+}
+
+/// シグナル名か番号をシグナルに変換する。名前の SIG は省略でき、大文字と小文字は区別しない
+fn parse_signal(name: &str) -> Option<Signal> {
+    if let Ok(n) = name.parse::<i32>() {
+        return Signal::try_from(n).ok();
+    }
+    let name = name.to_ascii_uppercase();
+    if name.starts_with("SIG") {
+        name.parse().ok()
+    } else {
+        format!("SIG{name}").parse().ok()
+    }
+}
+
+/// trap -l で表示するシグナルの一覧。 1 行に 5 つずつ、番号と名前を並べる
+fn signal_list() -> String {
+    let items: Vec<String> = Signal::iterator()
+        .map(|sig| format!("{:2}) {:<10}", sig as i32, sig.as_str()))
+        .collect();
+    items
+        .chunks(5)
+        .map(|line| line.join("").trim_end().to_string() + "\n")
+        .collect()
+}
+#[cfg(test)]
+mod parse_signal {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(parse_signal("INT"), Some(Signal::SIGINT));
+        assert_eq!(parse_signal("sigterm"), Some(Signal::SIGTERM));
+        assert_eq!(parse_signal("1"), Some(Signal::SIGHUP));
+        assert_eq!(parse_signal("FOO"), None);
+        assert_eq!(parse_signal("0"), None);
+
+        let list = signal_list();
+        assert!(list.starts_with(" 1) SIGHUP     2) SIGINT"));
+        assert!(list.contains("15) SIGTERM"));
+    }
+}
+
+/// echo のオプションか判定。 - の後に n, e, E のみが 1 文字以上続く
+fn is_echo_opt(word: &str) -> bool {
+    word.len() > 1
+        && word.starts_with('-')
+        && word[1..].chars().all(|c| matches!(c, 'n' | 'e' | 'E'))
+}
+
+/// echo -e のエスケープを解釈する
+///
+/// `\c` 以降は出力しない。その場合は 2 番目の値を true とする。
+/// 解釈できないエスケープはそのまま残す。
+fn unescape(s: &str) -> (String, bool) {
+    let mut out = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('a') => out.push('\x07'),
+            Some('b') => out.push('\x08'),
+            Some('e') => out.push('\x1b'),
+            Some('f') => out.push('\x0c'),
+            Some('v') => out.push('\x0b'),
+            Some('\\') => out.push('\\'),
+            Some('c') => return (out, true),
+            Some('0') => {
+                // \\0nnn : 8 進数で最大 3 桁
+                let mut n = 0;
+                for _ in 0..3 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(d) => {
+                            n = n * 8 + d;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                out.push(char::from_u32(n).unwrap_or('\0'));
+            }
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+    (out, false)
+}
+#[cfg(test)]
+mod unescape {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(unescape("a\\tb\\n"), ("a\tb\n".to_string(), false));
+        assert_eq!(unescape("a\\cb"), ("a".to_string(), true));
+        assert_eq!(unescape("\\0101\\\\"), ("A\\".to_string(), false));
+        assert_eq!(unescape("\\q\\"), ("\\q\\".to_string(), false));
+        assert!(is_echo_opt("-ne"));
+        assert!(!is_echo_opt("-"));
+        assert!(!is_echo_opt("-x"));
+    }
+}
@@ -0,0 +1,124 @@
+//! 外部から操作するための制御用ソケット
+//!
+//! `zerosh --control PATH` で起動すると、端末から読み込む代わりに PATH の Unix ドメインソケットで
+//! 接続を待ち、受信したコマンドを入力された行と同様に実行する。
+//! 各コマンドの終了コードは 10 進数の 1 行で返す。ヒアドキュメントの本文は続く行で送る。
+//!
+//! ```text
+//! $ printf 'echo hello\nfalse\n' | nc -U /tmp/zerosh.sock
+//! 0
+//! 1
+//! ```
+//!
+//! ソケットは所有者のみが読み書きできるパーミッションで作成し、接続できるかはファイルシステムの
+//! パーミッションで決める。接続は 1 つずつ順に処理する。
+use crate::heredoc;
+use nix::sys::stat::{umask, Mode};
+use std::{
+    fs,
+    io::{self, BufRead},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+};
+
+/// 制御用ソケット。 drop した場合はソケットのファイルを削除する
+#[derive(Debug)]
+pub(super) struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// path にソケットを作成する
+    ///
+    /// 既にファイルがある場合は、他のシェルが使用中の可能性があるため削除せずにエラーとする。
+    pub(super) fn bind(path: &Path) -> io::Result<Self> {
+        // 作成した時点から所有者以外が接続できないよう、 umask を設定して作成する
+        let old = umask(Mode::from_bits_truncate(0o177));
+        let result = UnixListener::bind(path);
+        umask(old);
+        Ok(Self {
+            listener: result?,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// 次の接続を待つ
+    pub(super) fn accept(&self) -> io::Result<UnixStream> {
+        self.listener.accept().map(|(stream, _)| stream)
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// reader から 1 つのコマンドを読み込む。ヒアドキュメントの本文は続く行から読み込む
+///
+/// 空行と `#` で始まる行は飛ばす。接続が閉じられた場合は None を返す。
+/// 本文の途中で閉じられた場合は、その終端の文字列を Err で返す。
+pub(super) fn read_command(reader: &mut impl BufRead) -> Option<Result<String, String>> {
+    let mut read = || {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+        }
+    };
+    loop {
+        let line = read()?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        return Some(heredoc::read_bodies(line, &mut read));
+    }
+}
+#[cfg(test)]
+mod read_command {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let mut input = "echo a\n\n# comment\ncat <<EOF\nx\nEOF\r\n  false  \n".as_bytes();
+        assert_eq!(read_command(&mut input), Some(Ok("echo a".to_string())));
+        assert_eq!(
+            read_command(&mut input),
+            Some(Ok("cat <<EOF\nx\nEOF".to_string()))
+        );
+        assert_eq!(read_command(&mut input), Some(Ok("false".to_string())));
+        assert_eq!(read_command(&mut input), None);
+
+        // 本文の途中で閉じられた
+        let mut input = "cat <<END\nx\n".as_bytes();
+        assert_eq!(read_command(&mut input), Some(Err("END".to_string())));
+    }
+}
+#[cfg(test)]
+mod control_socket {
+    use super::*;
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    #[test]
+    fn test() {
+        let path = std::env::temp_dir().join(format!("zerosh-control-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let sock = ControlSocket::bind(&path).unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        assert!(meta.file_type().is_socket());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+
+        // 使用中のソケットは削除しない
+        assert!(ControlSocket::bind(&path).is_err());
+        assert!(path.exists());
+
+        let _client = UnixStream::connect(&path).unwrap();
+        sock.accept().unwrap();
+
+        drop(sock);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,774 @@
+//! ジョブの管理表
+//!
+//! ジョブ ID, プロセスグループ ID, プロセス ID の対応と各プロセスの実行状態を保持する。
+//! worker スレッドが子プロセスの生成と状態の変化に応じて更新し、組み込みコマンドや
+//! ステータスラインはジョブの一覧や状態を問い合わせる。
+//!
+//! プロセス ID は終了したプロセスが回収されると再利用されるため、各プロセスの開始時刻も記録する。
+//! 管理表を経由せずに回収されたプロセスの記録が残っていても、新たな子プロセスと取り違えないよう、
+//! 子プロセスを生成する前に [JobTable::prune] で開始時刻の異なる記録を捨てる。
+use super::timing::{CpuTime, Timer};
+use crate::model::{Pipe, Pipeline};
+use nix::unistd::Pid;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env, fs,
+    mem::replace,
+    path::PathBuf,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ProcState {
+    Run,  // 実行中
+    Stop, // 停止中
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcInfo {
+    pub state: ProcState,   // 実行状態
+    pub pgid: Pid,          // プロセスグループ ID
+    pub stage: usize,       // パイプラインの何段目のコマンドか。 0 始まり
+    pub start: Option<u64>, // 開始時刻。同じプロセス ID の別のプロセスと区別する。取得できない場合は None
+}
+
+/// プロセスの開始時刻。 /proc/<pid>/stat の 22 番目のフィールドで、システムの起動からのクロック数
+/// 回収済みのプロセスなど、取得できない場合は None
+pub fn start_time(pid: Pid) -> Option<u64> {
+    parse_start_time(&fs::read_to_string(format!("/proc/{pid}/stat")).ok()?)
+}
+
+/// /proc/<pid>/stat の内容から開始時刻を取り出す
+/// 2 番目のフィールドのコマンド名は空白や括弧を含みうるため、最後の ')' の後から数える
+fn parse_start_time(stat: &str) -> Option<u64> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(19)?.parse().ok()
+}
+#[cfg(test)]
+mod start_time {
+    use super::*;
+    use nix::unistd::getpid;
+
+    #[test]
+    fn test() {
+        let stat = "42 (a) b) S 1 42 42 0 -1 4194560 100 0 0 0 1 2 0 0 20 0 1 0 12345 1000 100";
+        assert_eq!(parse_start_time(stat), Some(12345));
+        assert_eq!(parse_start_time("42 (a) S 1"), None);
+
+        // 自身の開始時刻は変わらない
+        let t = start_time(getpid());
+        assert!(t.is_some());
+        assert_eq!(start_time(getpid()), t);
+    }
+}
+
+/// パイプラインの各段の終了コードから、パイプラインの終了コードを求める
+///
+/// 最後の段の終了コードとする。 pipefail が真の場合は、 0 以外で終了した最も右の段の終了コードとし、
+/// すべて 0 の場合は 0 とする。
+pub fn pipeline_status(statuses: &[i32], pipefail: bool) -> i32 {
+    if pipefail {
+        statuses
+            .iter()
+            .rev()
+            .find(|s| **s != 0)
+            .copied()
+            .unwrap_or(0)
+    } else {
+        statuses.last().copied().unwrap_or(0)
+    }
+}
+#[cfg(test)]
+mod pipeline_status {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(pipeline_status(&[1, 0], false), 0);
+        assert_eq!(pipeline_status(&[0, 2], false), 2);
+        assert_eq!(pipeline_status(&[1, 3, 0], true), 3);
+        assert_eq!(pipeline_status(&[1, 0, 0], true), 1);
+        assert_eq!(pipeline_status(&[0, 0], true), 0);
+        assert_eq!(pipeline_status(&[141, 0], true), 141);
+    }
+}
+
+/// 現在のファイル作成マスク。 /proc/self/status の Umask の行から読み込む
+///
+/// umask(2) は設定しないと取得できず、元に戻すまでの間に他のスレッドが作成したファイルにも
+/// 影響するため用いない。読み込めない場合は None
+pub fn current_umask() -> Option<u32> {
+    parse_status_umask(&fs::read_to_string("/proc/self/status").ok()?)
+}
+
+fn parse_status_umask(status: &str) -> Option<u32> {
+    let value = status.lines().find_map(|l| l.strip_prefix("Umask:"))?;
+    u32::from_str_radix(value.trim(), 8).ok()
+}
+#[cfg(test)]
+mod current_umask {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let status = "Name:\tzerosh\nUmask:\t0027\nState:\tR (running)\n";
+        assert_eq!(parse_status_umask(status), Some(0o027));
+        assert_eq!(parse_status_umask("Name:\tzerosh\n"), None);
+
+        // 読み込んでもマスクは変わらない
+        let mask = current_umask();
+        assert!(mask.is_some());
+        assert_eq!(current_umask(), mask);
+    }
+}
+
+/// ジョブを起動した時点の環境。 jobs -v で表示する
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Origin {
+    pub cwd: PathBuf,                  // カレントディレクトリ
+    pub umask: u32,                    // ファイル作成マスク
+    pub env: BTreeMap<String, String>, // 環境変数
+}
+
+impl Origin {
+    /// 現在のシェルの環境を記録
+    pub fn capture() -> Self {
+        Self {
+            cwd: env::current_dir().unwrap_or_default(),
+            umask: current_umask().unwrap_or_default(),
+            env: env::vars_os()
+                .map(|(k, v)| {
+                    (
+                        k.to_string_lossy().into_owned(),
+                        v.to_string_lossy().into_owned(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// 起動した後に変更された環境変数の、起動した時点の値を返す。起動時に未定義の場合は None
+    pub fn env_changes<'a>(
+        &'a self,
+        now: &'a BTreeMap<String, String>,
+    ) -> Vec<(&'a str, Option<&'a str>)> {
+        let names: BTreeSet<&String> = self.env.keys().chain(now.keys()).collect();
+        names
+            .into_iter()
+            .filter(|name| self.env.get(*name) != now.get(*name))
+            .map(|name| (name.as_str(), self.env.get(name).map(|v| v.as_str())))
+            .collect()
+    }
+}
+
+/// ジョブ 1 つ分の記録
+#[derive(Debug)]
+struct JobRecord {
+    pgid: Pid,                       // プロセスグループ ID
+    line: String,                    // 実行コマンド
+    origin: Origin,                  // 起動した時点の環境
+    stages: Vec<(Pid, Option<i32>)>, // パイプラインの各段のプロセス ID と終了コード。実行中の場合は None
+    pipeline: Option<Pipeline>, // 実行したパイプライン。サブシェルで実行したリストの場合は None
+    timer: Option<Timer>,       // time を前置した場合の計測
+}
+
+/// ジョブの管理表
+///
+/// ジョブは 1 つのプロセスグループに対応し、ジョブ ID の昇順に並ぶ。
+/// プロセスが終了してもジョブはすぐには削除せず、プロセスグループが空になった時点で remove で削除する。
+#[derive(Debug, Default)]
+pub struct JobTable {
+    // ジョブID からジョブの記録へのマップ
+    jobs: BTreeMap<usize, JobRecord>,
+
+    // プロセスグループ ID から (ジョブID, プロセスID) へのマップ
+    pgid_to_pids: HashMap<Pid, (usize, HashSet<Pid>)>,
+
+    pid_to_info: HashMap<Pid, ProcInfo>, // プロセスID からプロセスグループID へのマップ
+}
+
+impl JobTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 新たなジョブ情報を追加
+    pub fn insert(
+        &mut self,
+        job_id: usize,
+        pgid: Pid,
+        pids: HashMap<Pid, ProcInfo>,
+        line: &str,
+        pipeline: Option<Pipeline>,
+        origin: Origin,
+    ) {
+        assert!(!self.jobs.contains_key(&job_id));
+        let mut stages = vec![(Pid::from_raw(0), None); pids.len()];
+        for (pid, info) in &pids {
+            stages[info.stage] = (*pid, None);
+        }
+        let record = JobRecord {
+            pgid,
+            line: line.to_string(),
+            origin,
+            stages,
+            pipeline,
+            timer: None,
+        };
+        self.jobs.insert(job_id, record); // ジョブ情報を追加
+
+        // 新たなプロセスと同じプロセス ID の記録は、既に回収されたプロセスのものなので捨てる
+        if let Some((_, stale)) = self.pgid_to_pids.get(&pgid) {
+            for pid in stale.clone() {
+                self.evict(pid);
+            }
+        }
+        let mut procs = HashSet::new(); // pgid_to_pids へ追加するプロセス
+        for (pid, info) in pids {
+            procs.insert(pid);
+
+            self.evict(pid);
+            self.pid_to_info.insert(pid, info); // プロセス情報を追加
+        }
+
+        assert!(!self.pgid_to_pids.contains_key(&pgid));
+        self.pgid_to_pids.insert(pgid, (job_id, procs)); // プロセスグループ情報を追加
+    }
+
+    /// プロセスの実行状態を設定し、以前の状態を返す
+    /// pid が存在しない場合は None を返す
+    pub fn set_pid_state(&mut self, pid: Pid, state: ProcState) -> Option<ProcState> {
+        let info = self.pid_to_info.get_mut(&pid)?;
+        Some(replace(&mut info.state, state))
+    }
+
+    /// 終了したプロセスの情報を削除して終了コード status を記録し、削除できた場合はプロセスの所属する
+    /// (ジョブ ID, プロセスグループ ID) を返す
+    /// 存在しない場合は None を返す
+    pub fn remove_pid(&mut self, pid: Pid, status: i32) -> Option<(usize, Pid)> {
+        let info = self.pid_to_info.remove(&pid)?;
+        let it = self.pgid_to_pids.get_mut(&info.pgid)?;
+        it.1.remove(&pid); // プロセスグループから pid を削除
+        let job_id = it.0; // ジョブ ID を取得
+        if let Some(record) = self.jobs.get_mut(&job_id) {
+            record.stages[info.stage].1 = Some(status);
+        }
+        Some((job_id, info.pgid))
+    }
+
+    /// 回収済みのプロセスの記録を捨てる。プロセスグループが空になった場合はジョブも削除し、
+    /// そのジョブ ID を返す
+    fn evict(&mut self, pid: Pid) -> Option<usize> {
+        let info = self.pid_to_info.remove(&pid)?;
+        let (job_id, pids) = self.pgid_to_pids.get_mut(&info.pgid)?;
+        pids.remove(&pid);
+        let job_id = *job_id;
+        if !pids.is_empty() {
+            return None;
+        }
+        self.remove(job_id);
+        Some(job_id)
+    }
+
+    /// 開始時刻が記録と異なるか、既に存在しないプロセスの記録を捨て、削除したジョブ ID を返す
+    ///
+    /// 管理表を経由せずに回収されたプロセスの記録で、そのプロセス ID は別のプロセスが再利用しうる。
+    /// start_time はプロセスの現在の開始時刻を返す関数で、通常は [start_time] とする。
+    /// 開始時刻を記録していないプロセスは判定できないため残す。
+    pub fn prune(&mut self, start_time: impl Fn(Pid) -> Option<u64>) -> Vec<usize> {
+        let stale: Vec<Pid> = self
+            .pid_to_info
+            .iter()
+            .filter(|(pid, info)| info.start.is_some_and(|t| start_time(**pid) != Some(t)))
+            .map(|(pid, _)| *pid)
+            .collect();
+        stale
+            .into_iter()
+            .filter_map(|pid| self.evict(pid))
+            .collect()
+    }
+
+    /// ジョブの実行時間の計測を開始する
+    pub fn start_timer(&mut self, job_id: usize) {
+        if let Some(record) = self.jobs.get_mut(&job_id) {
+            record.timer = Some(Timer::start());
+        }
+    }
+
+    /// 回収したプロセスの CPU 時間を、計測中のジョブに加える
+    /// remove_pid より前に呼び出す
+    pub fn add_cpu_time(&mut self, pid: Pid, cpu: CpuTime) {
+        let Some(job_id) = self.pgid_of(pid).and_then(|pgid| self.job_of(pgid)) else {
+            return;
+        };
+        if let Some(timer) = self.jobs.get_mut(&job_id).and_then(|r| r.timer.as_mut()) {
+            timer.add(cpu);
+        }
+    }
+
+    /// 計測中のジョブの実行時間。計測していない場合は None を返す
+    pub fn time_report(&self, job_id: usize) -> Option<String> {
+        self.jobs.get(&job_id)?.timer.as_ref().map(Timer::report)
+    }
+
+    /// ジョブ情報を削除し、関連するプロセスグループの情報も削除
+    pub fn remove(&mut self, job_id: usize) {
+        if let Some(record) = self.jobs.remove(&job_id) {
+            if let Some((_, pids)) = self.pgid_to_pids.remove(&record.pgid) {
+                assert!(pids.is_empty()); // ジョブを削除するときはプロセスグループも空のはず
+            }
+        }
+    }
+
+    /// ジョブがなければ真
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    /// ジョブのプロセスグループ ID と実行コマンド
+    pub fn get(&self, job_id: usize) -> Option<(Pid, &str)> {
+        self.jobs
+            .get(&job_id)
+            .map(|record| (record.pgid, record.line.as_str()))
+    }
+
+    /// ジョブを起動した時点の環境
+    pub fn origin(&self, job_id: usize) -> Option<&Origin> {
+        self.jobs.get(&job_id).map(|record| &record.origin)
+    }
+
+    /// (ジョブ ID, プロセスグループ ID, 実行コマンド) をジョブ ID の昇順に返す
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Pid, &str)> {
+        self.jobs
+            .iter()
+            .map(|(job_id, record)| (*job_id, record.pgid, record.line.as_str()))
+    }
+
+    /// プロセスの所属するプロセスグループ ID
+    pub fn pgid_of(&self, pid: Pid) -> Option<Pid> {
+        self.pid_to_info.get(&pid).map(|info| info.pgid)
+    }
+
+    /// プロセスがパイプラインの最後のコマンドなら真
+    /// pid が存在しない場合は None を返す
+    pub fn is_last(&self, pid: Pid) -> Option<bool> {
+        let info = self.pid_to_info.get(&pid)?;
+        let job_id = self.job_of(info.pgid)?;
+        Some(info.stage + 1 == self.jobs[&job_id].stages.len())
+    }
+
+    /// プロセスの実行状態。 pid が存在しない場合は None を返す
+    pub fn state_of(&self, pid: Pid) -> Option<ProcState> {
+        self.pid_to_info.get(&pid).map(|info| info.state.clone())
+    }
+
+    /// ジョブのパイプラインの各段の (プロセス ID, 終了コード) 。実行中の段の終了コードは None
+    pub fn stages(&self, job_id: usize) -> &[(Pid, Option<i32>)] {
+        self.jobs
+            .get(&job_id)
+            .map(|record| record.stages.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// ジョブのパイプラインの各段の (プロセス ID, 状態を表す文字列)
+    /// 実行中の段は Running か Stopped 、終了した段は Done か Exit と終了コードとする
+    pub fn stage_states(&self, job_id: usize) -> Vec<(Pid, String)> {
+        self.stages(job_id)
+            .iter()
+            .map(|(pid, status)| {
+                let state = match (status, self.state_of(*pid)) {
+                    (Some(0), _) => "Done".to_string(),
+                    (Some(n), _) => format!("Exit {n}"),
+                    (None, Some(ProcState::Stop)) => "Stopped".to_string(),
+                    (None, _) => "Running".to_string(),
+                };
+                (*pid, state)
+            })
+            .collect()
+    }
+
+    /// ジョブのパイプラインの構造を木の形で表した行。 jobs --tree で表示する
+    ///
+    /// 各段のプロセス ID, 状態, コマンドを並べ、その下にリダイレクトと次の段へのパイプの種類を表示する。
+    /// サブシェルで実行したリストは、サブシェルのプロセスのみとする。
+    pub fn tree(&self, job_id: usize) -> Vec<String> {
+        let states = self.stage_states(job_id);
+        let Some(pipeline) = self.jobs.get(&job_id).and_then(|r| r.pipeline.as_ref()) else {
+            return states
+                .iter()
+                .map(|(pid, state)| format!("└─ {pid} {state}: (subshell)"))
+                .collect();
+        };
+
+        let stages = pipeline.stages();
+        let mut lines = Vec::new();
+        for (i, ((cmd, pipe), (pid, state))) in stages.iter().zip(&states).enumerate() {
+            let (branch, indent) = if i + 1 == stages.len() {
+                ("└─", "   ")
+            } else {
+                ("├─", "│  ")
+            };
+            let args: Vec<&str> = cmd.args.iter().map(|w| w.raw.as_str()).collect();
+            lines.push(format!("{branch} {pid} {state}: {}", args.join(" ")));
+
+            let mut leaves: Vec<String> = cmd.redirects.iter().map(|r| r.to_string()).collect();
+            match pipe {
+                Some(Pipe::StdOut) => leaves.push("| stdout".to_string()),
+                Some(Pipe::Both) => leaves.push("|& stdout, stderr".to_string()),
+                None => (),
+            }
+            for (j, leaf) in leaves.iter().enumerate() {
+                let branch = if j + 1 == leaves.len() {
+                    "└─"
+                } else {
+                    "├─"
+                };
+                lines.push(format!("{indent}{branch} {leaf}"));
+            }
+        }
+        lines
+    }
+
+    /// ジョブの終了コード。実行中の段がある場合は None を返す
+    /// pipefail については [pipeline_status] を参照
+    pub fn status(&self, job_id: usize, pipefail: bool) -> Option<i32> {
+        let statuses = self
+            .stages(job_id)
+            .iter()
+            .map(|(_, status)| *status)
+            .collect::<Option<Vec<_>>>()?;
+        Some(pipeline_status(&statuses, pipefail))
+    }
+
+    /// プロセスグループに対応するジョブ ID
+    pub fn job_of(&self, pgid: Pid) -> Option<usize> {
+        self.pgid_to_pids.get(&pgid).map(|(job_id, _)| *job_id)
+    }
+
+    /// プロセスグループに残っているプロセス ID
+    pub fn pids(&self, pgid: Pid) -> Vec<Pid> {
+        self.pgid_to_pids
+            .get(&pgid)
+            .map(|(_, pids)| pids.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// 空のプロセスグループなら真
+    pub fn is_group_empty(&self, pgid: Pid) -> bool {
+        self.pgid_to_pids.get(&pgid).unwrap().1.is_empty()
+    }
+
+    /// プロセスグループのプロセス全部が停止中なら真
+    pub fn is_group_stop(&self, pgid: Pid) -> Option<bool> {
+        for pid in self.pgid_to_pids.get(&pgid)?.1.iter() {
+            if self.pid_to_info.get(pid).unwrap().state == ProcState::Run {
+                return Some(false);
+            }
+        }
+        Some(true)
+    }
+
+    /// (実行中, 停止中) のジョブの数
+    pub fn counts(&self) -> (usize, usize) {
+        let stopped = self
+            .jobs
+            .values()
+            .filter(|record| self.is_group_stop(record.pgid) == Some(true))
+            .count();
+        (self.jobs.len() - stopped, stopped)
+    }
+
+    /// 使われていない最小のジョブ ID
+    pub fn new_job_id(&self) -> Option<usize> {
+        (0..=usize::MAX).find(|i| !self.jobs.contains_key(i))
+    }
+
+    /// 3 つのマップが互いに矛盾しないことを確認し、管理しているプロセスの数を返す
+    ///
+    /// - 各ジョブのプロセスグループは pgid_to_pids にあり、同じジョブ ID を指す
+    /// - 各プロセスは pid_to_info にあり、所属するプロセスグループが一致する
+    /// - 終了して取り除いたプロセスは pid_to_info に残らない
+    #[cfg(test)]
+    pub fn check(&self) -> usize {
+        assert_eq!(self.jobs.len(), self.pgid_to_pids.len());
+        for (job_id, record) in &self.jobs {
+            let (id, pids) = &self.pgid_to_pids[&record.pgid];
+            assert_eq!(id, job_id);
+            for pid in pids {
+                assert_eq!(self.pid_to_info[pid].pgid, record.pgid);
+            }
+        }
+        let count: usize = self.pgid_to_pids.values().map(|(_, pids)| pids.len()).sum();
+        assert_eq!(count, self.pid_to_info.len());
+        count
+    }
+}
+#[cfg(test)]
+mod job_table {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let pid = Pid::from_raw;
+        let run = |pgid, stage| ProcInfo {
+            state: ProcState::Run,
+            pgid,
+            stage,
+            start: None,
+        };
+        let mut table = JobTable::new();
+        assert!(table.is_empty());
+        assert_eq!(table.new_job_id(), Some(0));
+
+        // ジョブ 0: プロセスグループ 10 に 10, 11
+        let pids = HashMap::from([(pid(10), run(pid(10), 0)), (pid(11), run(pid(10), 1))]);
+        let origin = Origin {
+            cwd: PathBuf::from("/tmp"),
+            ..Origin::default()
+        };
+        table.insert(0, pid(10), pids, "a | b", None, origin.clone());
+        // ジョブ 1: プロセスグループ 20 に 20
+        let pids = HashMap::from([(pid(20), run(pid(20), 0))]);
+        table.insert(1, pid(20), pids, "c", None, Origin::default());
+        assert_eq!(table.new_job_id(), Some(2));
+        assert_eq!(table.get(0), Some((pid(10), "a | b")));
+        assert_eq!(table.origin(0), Some(&origin));
+        assert_eq!(
+            table.iter().collect::<Vec<_>>(),
+            vec![(0, pid(10), "a | b"), (1, pid(20), "c")]
+        );
+        assert_eq!(table.pgid_of(pid(11)), Some(pid(10)));
+        assert_eq!(table.is_last(pid(10)), Some(false));
+        assert_eq!(table.is_last(pid(11)), Some(true));
+        assert_eq!(table.is_last(pid(99)), None);
+        assert_eq!(table.job_of(pid(20)), Some(1));
+        assert_eq!(table.counts(), (2, 0));
+
+        // 一部のプロセスの停止ではジョブは停止しない
+        assert_eq!(
+            table.set_pid_state(pid(10), ProcState::Stop),
+            Some(ProcState::Run)
+        );
+        assert_eq!(table.is_group_stop(pid(10)), Some(false));
+        table.set_pid_state(pid(11), ProcState::Stop);
+        assert_eq!(table.is_group_stop(pid(10)), Some(true));
+        assert_eq!(table.counts(), (1, 1));
+        assert_eq!(table.set_pid_state(pid(99), ProcState::Run), None);
+
+        // プロセスグループが空になったらジョブを削除できる
+        assert_eq!(table.check(), 3);
+        assert_eq!(table.status(1, false), None);
+        assert_eq!(table.remove_pid(pid(20), 4), Some((1, pid(20))));
+        assert_eq!(table.remove_pid(pid(20), 4), None);
+        assert_eq!(table.stages(1), &[(pid(20), Some(4))]);
+        assert_eq!(table.status(1, false), Some(4));
+        assert_eq!(table.pgid_of(pid(20)), None);
+        assert!(table.is_group_empty(pid(20)));
+        assert!(table.pids(pid(20)).is_empty());
+        table.remove(1);
+        assert_eq!(table.get(1), None);
+        assert_eq!(table.new_job_id(), Some(1));
+        assert!(!table.is_empty());
+        assert_eq!(table.check(), 2);
+
+        // time を前置したジョブは回収した各段の CPU 時間を合計する
+        assert_eq!(table.time_report(0), None);
+        table.start_timer(0);
+        let cpu = CpuTime {
+            user: std::time::Duration::from_millis(5),
+            sys: std::time::Duration::from_millis(1),
+        };
+        table.add_cpu_time(pid(10), cpu);
+        table.add_cpu_time(pid(11), cpu);
+        table.add_cpu_time(pid(99), cpu);
+        assert!(table
+            .time_report(0)
+            .unwrap()
+            .ends_with("user\t0m0.010s\nsys\t0m0.002s"));
+
+        // パイプラインの終了コードは各段の終了コードから求める
+        table.remove_pid(pid(10), 1);
+        assert_eq!(table.state_of(pid(10)), None);
+        assert_eq!(table.state_of(pid(11)), Some(ProcState::Stop));
+        assert_eq!(table.stages(0), &[(pid(10), Some(1)), (pid(11), None)]);
+        assert_eq!(table.status(0, true), None);
+        table.remove_pid(pid(11), 0);
+        assert_eq!(table.status(0, false), Some(0));
+        assert_eq!(table.status(0, true), Some(1));
+    }
+}
+#[cfg(test)]
+mod prune {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let pid = Pid::from_raw;
+        let info = |pgid, stage, start| ProcInfo {
+            state: ProcState::Run,
+            pgid,
+            stage,
+            start,
+        };
+        let mut table = JobTable::new();
+        // ジョブ 0: 10 と 11 は開始時刻 100 と 101
+        let pids = HashMap::from([
+            (pid(10), info(pid(10), 0, Some(100))),
+            (pid(11), info(pid(10), 1, Some(101))),
+        ]);
+        table.insert(0, pid(10), pids, "a | b", None, Origin::default());
+        // ジョブ 1: 20 は開始時刻を記録していない
+        let pids = HashMap::from([(pid(20), info(pid(20), 0, None))]);
+        table.insert(1, pid(20), pids, "c", None, Origin::default());
+
+        // 開始時刻が一致するプロセスは残す
+        let alive = |p: Pid| match p.as_raw() {
+            10 => Some(100),
+            11 => Some(101),
+            _ => None,
+        };
+        assert!(table.prune(alive).is_empty());
+        assert_eq!(table.check(), 3);
+
+        // 11 が別のプロセスに再利用された場合は、その記録のみ捨てる
+        let reused = |p: Pid| match p.as_raw() {
+            10 => Some(100),
+            11 => Some(500),
+            _ => None,
+        };
+        assert!(table.prune(reused).is_empty());
+        assert_eq!(table.check(), 2);
+        assert_eq!(table.state_of(pid(11)), None);
+
+        // すべてのプロセスが存在しない場合はジョブも削除する
+        // 開始時刻を記録していないプロセスは残す
+        assert_eq!(table.prune(|_| None), vec![0]);
+        assert_eq!(table.get(0), None);
+        assert_eq!(table.check(), 1);
+        assert_eq!(table.get(1), Some((pid(20), "c")));
+    }
+
+    #[test]
+    fn test_insert() {
+        let pid = Pid::from_raw;
+        let info = |pgid, stage| ProcInfo {
+            state: ProcState::Run,
+            pgid,
+            stage,
+            start: None,
+        };
+        let mut table = JobTable::new();
+        let pids = HashMap::from([(pid(10), info(pid(10), 0)), (pid(11), info(pid(10), 1))]);
+        table.insert(0, pid(10), pids, "a | b", None, Origin::default());
+        let pids = HashMap::from([(pid(20), info(pid(20), 0))]);
+        table.insert(1, pid(20), pids, "c", None, Origin::default());
+
+        // 残っている記録と同じプロセス ID の新たなプロセスは、古い記録を置き換える
+        let pids = HashMap::from([(pid(11), info(pid(11), 0))]);
+        table.insert(2, pid(11), pids, "d", None, Origin::default());
+        assert_eq!(table.check(), 3);
+        assert_eq!(table.pgid_of(pid(11)), Some(pid(11)));
+        assert_eq!(table.job_of(pid(11)), Some(2));
+
+        // プロセスグループ ID が重なる場合は、古いジョブの記録をすべて捨てる
+        let pids = HashMap::from([(pid(20), info(pid(20), 0)), (pid(21), info(pid(20), 1))]);
+        table.insert(3, pid(20), pids, "e | f", None, Origin::default());
+        assert_eq!(table.get(1), None);
+        assert_eq!(table.job_of(pid(20)), Some(3));
+        assert_eq!(table.check(), 4);
+
+        // 古いジョブのプロセスがすべて置き換えられた場合はジョブも削除する
+        let pids = HashMap::from([(pid(10), info(pid(10), 0))]);
+        table.insert(4, pid(10), pids, "g", None, Origin::default());
+        assert_eq!(table.get(0), None);
+        assert_eq!(table.get(4), Some((pid(10), "g")));
+        assert_eq!(table.check(), 4);
+    }
+}
+#[cfg(test)]
+mod tree {
+    use super::*;
+    use crate::model::{ExternalCmd, Redirection};
+
+    #[test]
+    fn test() {
+        let pid = Pid::from_raw;
+        let cmd = |args: &[&str], redirects| ExternalCmd {
+            args: args.iter().map(|s| (*s).into()).collect(),
+            redirects,
+        };
+        // sleep 30 | grep x 2> err |& cat > out
+        let pipeline = Pipeline::Both(
+            Box::new(Pipeline::Out(
+                Box::new(Pipeline::Src(cmd(&["sleep", "30"], vec![]))),
+                cmd(&["grep", "x"], vec![Redirection::StdErr("err".to_string())]),
+            )),
+            cmd(&["cat"], vec![Redirection::StdOut("out".to_string())]),
+        );
+        let pids = (0..3)
+            .map(|stage| {
+                let info = ProcInfo {
+                    state: ProcState::Run,
+                    pgid: pid(10),
+                    stage,
+                    start: None,
+                };
+                (pid(10 + stage as i32), info)
+            })
+            .collect();
+        let mut table = JobTable::new();
+        let line = pipeline.to_string();
+        table.insert(0, pid(10), pids, &line, Some(pipeline), Origin::default());
+        table.set_pid_state(pid(10), ProcState::Stop);
+        table.remove_pid(pid(11), 1);
+
+        assert_eq!(
+            table.tree(0),
+            vec![
+                "├─ 10 Stopped: sleep 30",
+                "│  └─ | stdout",
+                "├─ 11 Exit 1: grep x",
+                "│  ├─ 2> err",
+                "│  └─ |& stdout, stderr",
+                "└─ 12 Running: cat",
+                "   └─ > out",
+            ]
+        );
+
+        // サブシェルで実行したリスト
+        let pids = HashMap::from([(
+            pid(20),
+            ProcInfo {
+                state: ProcState::Run,
+                pgid: pid(20),
+                stage: 0,
+                start: None,
+            },
+        )]);
+        table.insert(1, pid(20), pids, "a && b", None, Origin::default());
+        assert_eq!(table.tree(1), vec!["└─ 20 Running: (subshell)"]);
+        assert!(table.tree(2).is_empty());
+    }
+}
+#[cfg(test)]
+mod env_changes {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let vars = |kvs: &[(&str, &str)]| {
+            kvs.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        let origin = Origin {
+            env: vars(&[("A", "1"), ("B", "2"), ("C", "3")]),
+            ..Origin::default()
+        };
+        let now = vars(&[("A", "1"), ("B", "x"), ("D", "4")]);
+        assert_eq!(
+            origin.env_changes(&now),
+            vec![("B", Some("2")), ("C", Some("3")), ("D", None)]
+        );
+        assert!(origin.env_changes(&origin.env).is_empty());
+    }
+}
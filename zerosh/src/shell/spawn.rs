@@ -0,0 +1,625 @@
+//! 子プロセスの生成
+//!
+//! パイプラインごとにプロセスグループを作り、各段のコマンドをシェルが fork して exec する。
+//! バックグラウンドの && と || のリストはサブシェルで実行する。
+use super::jobs::{pipeline_status, start_time, Origin, ProcInfo, ProcState};
+use super::timing::{wait4, Timer};
+use super::worker::{should_run, Worker};
+use super::{syscall, NAME, SHELL_SIGNALS};
+use crate::expand;
+use crate::hash::CommandHash;
+use crate::helper::DynError;
+use crate::model;
+use crate::model::ExternalCmd;
+use nix::{
+    libc::{self, tcsetpgrp},
+    sys::{
+        signal::{killpg, signal, SigHandler, Signal},
+        wait::WaitStatus,
+    },
+    unistd::{close, dup2, execv, execvp, fork, getpid, pipe, setpgid, ForkResult, Pid},
+};
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs::File,
+    io::Write,
+    os::fd::{AsRawFd, IntoRawFd, OwnedFd},
+    path::Path,
+    process::exit,
+};
+
+impl Worker {
+    /// 子プロセスを生成。戻り値は、続けて次のジョブを実行するか
+    ///
+    /// フォアグラウンドの子プロセスを生成した場合は、終了・停止を待つため false を返す。
+    /// パイプラインのコマンドが見つからない場合は、いずれの子プロセスも生成せずに終了コードを 127 とする。
+    /// timed が真の場合は、ジョブの終了時に実行時間を表示する。
+    /// argv は stage_args で展開した各段の引数とする。
+    pub(super) fn spawn_child(
+        &mut self,
+        cmd: &mut model::Pipeline,
+        argv: &[Vec<String>],
+        is_bg: bool,
+        timed: bool,
+    ) -> bool {
+        if let Err(name) = resolve_commands(argv, &mut self.hash) {
+            error!("command not found: {name}");
+            self.exit_val = 127;
+            return true;
+        }
+
+        // 回収済みのプロセスの記録を捨て、ジョブ ID を取得
+        self.jobs.prune(start_time);
+        let job_id = if let Some(id) = self.jobs.new_job_id() {
+            id
+        } else {
+            error!("Couldn't spawn child process, too many jobs already exists");
+            return true;
+        };
+
+        let mut pids = HashMap::new();
+        // パイプラインの各段のプロセスを生成
+        let pgid = match fork_exec(Pid::from_raw(0), cmd, argv, &mut pids, &self.hash) {
+            Ok(pgid) => pgid,
+            Err(e) => {
+                error!("Failed to fork: {e}");
+                return true;
+            }
+        };
+
+        // ジョブ情報を追加
+        self.jobs.insert(
+            job_id,
+            pgid,
+            pids,
+            &cmd.to_string(),
+            Some(cmd.clone()),
+            Origin::capture(),
+        );
+        if timed {
+            self.jobs.start_timer(job_id);
+        }
+        self.update_status();
+
+        if is_bg {
+            // 子プロセスをバックグラウンドプロセスグループにする
+            self.set_shell_fg();
+            true
+        } else {
+            // 子プロセスをフォアグラウンドプロセスグループにする
+            self.set_title(&cmd.to_string());
+            self.fg = Some(pgid);
+            unsafe { tcsetpgrp(libc::STDIN_FILENO, pgid.as_raw()) };
+            false
+        }
+    }
+
+    /// && と || で連結したジョブをバックグラウンドで実行するサブシェルを生成
+    ///
+    /// サブシェルは各パイプラインを順に実行して終了を同期的に待ち、
+    /// 最後に実行したパイプラインの終了コードで終了する。
+    /// 組み込みコマンドはサブシェルでは実行できないため、含まれる場合はエラーとする。
+    pub(super) fn spawn_list(&mut self, first: model::Job, rest: Vec<(model::Cond, model::Job)>) {
+        let jobs =
+            std::iter::once((None, first)).chain(rest.into_iter().map(|(c, j)| (Some(c), j)));
+        let mut stages = Vec::new();
+        for (cond, job) in jobs {
+            match job {
+                model::Job::External { cmds, timed, .. } => stages.push((cond, cmds, timed)),
+                _ => {
+                    eprintln!(
+                        "{NAME}: built-in commands and control structures can't be run in a background list"
+                    );
+                    self.exit_val = 1; // 失敗
+                    return;
+                }
+            }
+        }
+        let line = stages
+            .iter()
+            .map(|(cond, cmds, timed)| {
+                let time = if *timed { "time " } else { "" };
+                match cond {
+                    Some(c) => format!("{c} {time}{cmds}"),
+                    None => format!("{time}{cmds}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // 回収済みのプロセスの記録を捨て、ジョブ ID を取得
+        self.jobs.prune(start_time);
+        let job_id = if let Some(id) = self.jobs.new_job_id() {
+            id
+        } else {
+            error!("Couldn't spawn child process, too many jobs already exists");
+            return;
+        };
+
+        // サブシェルを生成し、サブシェルのプロセス ID をプロセスグループ ID とする
+        let pgid = match syscall(|| unsafe { fork() }) {
+            Ok(ForkResult::Parent { child }) => {
+                setpgid(child, child).unwrap();
+                child
+            }
+            Ok(ForkResult::Child) => {
+                setpgid(Pid::from_raw(0), Pid::from_raw(0)).unwrap();
+                let traps: Vec<Signal> = self.traps.keys().copied().collect();
+                exit(run_list(
+                    &mut stages,
+                    &mut self.hash,
+                    &traps,
+                    self.opts.pipefail,
+                ));
+            }
+            Err(e) => {
+                error!("Failed to fork: {e}");
+                return;
+            }
+        };
+
+        let pids = HashMap::from([(
+            pgid,
+            ProcInfo {
+                state: ProcState::Run,
+                pgid,
+                stage: 0,
+                start: start_time(pgid),
+            },
+        )]);
+        self.jobs
+            .insert(job_id, pgid, pids, &line, None, Origin::capture());
+        self.update_status();
+
+        // バックグラウンドで実行するため、シェルをフォアグラウンドのままにする
+        self.set_shell_fg();
+    }
+}
+
+/// サブシェルで && と || で連結したパイプラインを順に実行
+///
+/// 各パイプラインはサブシェルのプロセスグループで実行し、終了を同期的に待つ。
+/// 最後に実行したパイプラインの終了コードを返す。 time を前置したパイプラインは終了時に実行時間を表示する。
+/// traps は trap で受信するよう設定したシグナル、 pipefail はシェルの pipefail オプション
+fn run_list(
+    stages: &mut [(Option<model::Cond>, model::Pipeline, bool)],
+    hash: &mut CommandHash,
+    traps: &[Signal],
+    pipefail: bool,
+) -> i32 {
+    // シェルが受信するために設定したシグナルを、子プロセスと同様にデフォルトに戻す
+    for sig in SHELL_SIGNALS.iter().chain(traps) {
+        unsafe { signal(*sig, SigHandler::SigDfl).unwrap() };
+    }
+
+    let pgid = getpid();
+    let mut status = 0;
+    for (cond, cmds, timed) in stages.iter_mut() {
+        if !should_run(*cond, status) {
+            continue;
+        }
+        let argv = stage_args(cmds);
+        if let Err(name) = resolve_commands(&argv, hash) {
+            error!("command not found: {name}");
+            status = 127;
+            continue;
+        }
+        let mut pids = HashMap::new();
+        let mut timer = Timer::start();
+        status = match fork_exec(pgid, cmds, &argv, &mut pids, hash) {
+            Ok(_) => {
+                // すべての段の終了を待ち、各段の終了コードからパイプラインの終了コードを求める
+                let mut statuses = vec![1; pids.len()];
+                for (pid, info) in &pids {
+                    statuses[info.stage] = match syscall(|| wait4(*pid, None)) {
+                        Ok((WaitStatus::Exited(_, s), cpu)) => {
+                            timer.add(cpu);
+                            s
+                        }
+                        Ok((WaitStatus::Signaled(_, sig, _), cpu)) => {
+                            timer.add(cpu);
+                            sig as i32 + 128
+                        }
+                        _ => 1,
+                    };
+                }
+                if *timed {
+                    eprintln!("{}", timer.report());
+                }
+                pipeline_status(&statuses, pipefail)
+            }
+            Err(e) => {
+                error!("Failed to fork: {e}");
+                1
+            }
+        };
+    }
+    status
+}
+
+/// シバンのないスクリプトを実行するシェル
+const FALLBACK_SH: &str = "/bin/sh";
+
+/// パイプラインの各段の引数を展開して文字列にする
+///
+/// fork する前に展開し、コマンド置換も各段で 1 度のみ実行する。
+pub(super) fn stage_args(cmds: &model::Pipeline) -> Vec<Vec<String>> {
+    cmds.stages()
+        .into_iter()
+        .map(|(cmd, _)| expand::expand_words(&cmd.args))
+        .collect()
+}
+
+/// パイプラインの各コマンドを fork する前に PATH から検索し、 hash に保持する
+/// argv は stage_args で展開した各段の引数で、見つからないコマンドがあれば、その名前を返す
+fn resolve_commands(argv: &[Vec<String>], hash: &mut CommandHash) -> Result<(), String> {
+    for name in argv.iter().filter_map(|args| args.first()) {
+        if hash.lookup(name).is_none() {
+            return Err(name.clone());
+        }
+    }
+    Ok(())
+}
+
+/// コマンドを実行
+/// PATH から検索したパスは hash から取得する。失敗した場合はエラーを表示して終了する
+pub(super) fn exec_cmd(filename: &CString, args: &[CString], hash: &CommandHash) -> ! {
+    let path = filename.to_str().ok().and_then(|name| hash.get(name));
+    let mut err = match &path {
+        Some(path) => execv(
+            &CString::new(path.as_os_str().as_encoded_bytes()).unwrap(),
+            args,
+        ),
+        None => execvp(filename, args),
+    }
+    .unwrap_err();
+
+    // シバン (#!) のないスクリプトはカーネルが実行形式と認識できず ENOEXEC となる
+    // bash と同様に /bin/sh にスクリプトのパスと引数を渡して実行し直す
+    if err == nix::errno::Errno::ENOEXEC {
+        if let Some(path) = &path {
+            err = execv(
+                &CString::new(FALLBACK_SH).unwrap(),
+                &sh_fallback_args(path, args),
+            )
+            .unwrap_err();
+        }
+    }
+
+    error!("Failed to exec: {err}");
+    // bash と同様に、見つからない場合は 127 、実行できない場合は 126 で終了する
+    exit(if err == nix::errno::Errno::ENOENT {
+        127
+    } else {
+        126
+    });
+}
+
+/// /bin/sh でスクリプトを実行する場合の引数
+/// args[0] (コマンド名) を sh とスクリプトのパスに置き換える
+fn sh_fallback_args(path: &Path, args: &[CString]) -> Vec<CString> {
+    let mut sh_args = vec![
+        CString::new(FALLBACK_SH).unwrap(),
+        CString::new(path.as_os_str().as_encoded_bytes()).unwrap(),
+    ];
+    sh_args.extend(args.iter().skip(1).cloned());
+    sh_args
+}
+/// パイプラインの 1 段のコマンドを実行する。戻らない
+///
+/// 標準入出力はパイプに接続済みとし、リダイレクトを処理してから exec する。
+/// args は展開済みの引数とする。
+fn exec_stage(cmd: &ExternalCmd, args: &[String], hash: &CommandHash) -> ! {
+    /// リダイレクト処理
+    ///
+    /// パイプより優先するため、パイプを dup2 した後に呼び出す。
+    /// リダイレクトは指定された順に適用するため、 `> a.log 2>&1` は標準出力と標準エラー出力の
+    /// 両方を a.log に書き込み、 `2>&1 > a.log` は標準エラー出力を元の標準出力に書き込む。
+    ///
+    /// - `<` はファイルを標準入力から読み込む
+    /// - `<<` はヒアドキュメントの本文を標準入力から読み込む
+    /// - `>` と `2>` はファイルを切り詰めて標準出力と標準エラー出力に書き込む
+    /// - `>>` と `2>>` はファイルの末尾に追記する
+    /// - `>&` はファイルを切り詰めて標準出力と標準エラー出力の両方を書き込む
+    /// - `2>&1` と `>&2` は標準エラー出力と標準出力を複製する
+    ///
+    /// 書き込み先のファイルがない場合は作成する。
+    ///
+    /// ファイル名の環境変数は展開するが、分割はしない。
+    fn handle_redirects(cmd: &model::ExternalCmd) {
+        use model::Redirection;
+        use nix::fcntl::OFlag;
+
+        let write = OFlag::O_WRONLY | OFlag::O_CREAT;
+        for red in cmd.redirects.iter() {
+            let (file, flags, fds): (_, _, &[i32]) = match red {
+                Redirection::StdIn(file) => (file, OFlag::O_RDONLY, &[libc::STDIN_FILENO]),
+                Redirection::HereDoc(_, body) => {
+                    feed_stdin(body);
+                    continue;
+                }
+                Redirection::StdOut(file) => (file, write | OFlag::O_TRUNC, &[libc::STDOUT_FILENO]),
+                Redirection::Both(file) => (
+                    file,
+                    write | OFlag::O_TRUNC,
+                    &[libc::STDOUT_FILENO, libc::STDERR_FILENO],
+                ),
+                Redirection::Append(file) => {
+                    (file, write | OFlag::O_APPEND, &[libc::STDOUT_FILENO])
+                }
+                Redirection::StdErr(file) => (file, write | OFlag::O_TRUNC, &[libc::STDERR_FILENO]),
+                Redirection::ErrAppend(file) => {
+                    (file, write | OFlag::O_APPEND, &[libc::STDERR_FILENO])
+                }
+                Redirection::ErrToOut => {
+                    syscall(|| dup2(libc::STDOUT_FILENO, libc::STDERR_FILENO)).unwrap();
+                    continue;
+                }
+                Redirection::OutToErr => {
+                    syscall(|| dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO)).unwrap();
+                    continue;
+                }
+            };
+
+            // 作成するファイルのパーミッションは rw-r--r-- (umask が適用される)
+            let mode = nix::sys::stat::Mode::from_bits_truncate(0o644);
+            let file = expand::expand_vars(file);
+            let fd = match syscall(|| nix::fcntl::open(file.as_str(), flags, mode)) {
+                Ok(fd) => fd,
+                Err(e) => {
+                    error!("{file}: {e}");
+                    exit(1);
+                }
+            };
+            syscall(|| {
+                for target in fds {
+                    dup2(fd, *target)?;
+                }
+                // 閉じていた標準入出力の番号で開いた場合は、そのまま使う
+                if fds.contains(&fd) {
+                    Ok(())
+                } else {
+                    close(fd)
+                }
+            })
+            .unwrap();
+        }
+    }
+    /// body をパイプで標準入力に渡す
+    ///
+    /// 本文がパイプのバッファより大きくてもコマンドの実行を妨げないよう、
+    /// 書き込みは fork した子プロセスで行う。
+    fn feed_stdin(body: &str) {
+        let (reader, writer) = pipe().unwrap();
+        match syscall(|| unsafe { fork() }).unwrap() {
+            ForkResult::Child => {
+                drop(reader);
+                // 読み込む側が先に終了した場合は書き込みに失敗するが、そのまま終了する
+                let _ = File::from(writer).write_all(body.as_bytes());
+                exit(0);
+            }
+            ForkResult::Parent { .. } => {
+                drop(writer);
+                let reader = reader.into_raw_fd();
+                syscall(|| {
+                    dup2(reader, libc::STDIN_FILENO)?;
+                    // 閉じていた標準入力の番号で開いた場合は、そのまま使う
+                    if reader == libc::STDIN_FILENO {
+                        Ok(())
+                    } else {
+                        close(reader)
+                    }
+                })
+                .unwrap();
+            }
+        }
+    }
+    /// 展開済みの引数からコマンド名と引数を返す
+    /// 展開の結果、引数が空になった場合はリダイレクトのみ処理して終了する
+    fn get_filename_and_args(cmd: &ExternalCmd, args: &[String]) -> (CString, Vec<CString>) {
+        let args = args
+            .iter()
+            .map(|s| CString::new(s.as_str()).unwrap())
+            .collect::<Vec<_>>();
+        let Some(filename) = args.first().cloned() else {
+            handle_redirects(cmd);
+            exit(0);
+        };
+        (filename, args)
+    }
+
+    let (filename, args) = get_filename_and_args(cmd, args);
+    handle_redirects(cmd);
+    exec_cmd(&filename, &args, hash);
+}
+
+/// パイプラインの各段を fork & exec し、プロセスグループ ID を返す
+/// pgid が 0 の場合は最初の段のプロセス ID がプロセスグループ ID となる
+///
+/// 各段はすべてシェルが fork するため、生成したプロセスはすべて pids に記録される。
+/// 各段のプロセスグループは子プロセスと親プロセスの両方で設定するため、
+/// 戻った時点ですべての段が同じプロセスグループに属しており、 tcsetpgrp できる。
+/// 途中で fork に失敗した場合は、生成済みのプロセスを kill してエラーを返す。
+fn fork_exec(
+    pgid: Pid,
+    cmds: &model::Pipeline,
+    argv: &[Vec<String>],
+    pids: &mut HashMap<Pid, ProcInfo>,
+    hash: &CommandHash,
+) -> Result<Pid, DynError> {
+    let mut pgid = pgid;
+    let mut input: Option<OwnedFd> = None; // 前段の出力を読み込むパイプ
+    for (stage, (cmd, next)) in cmds.stages().into_iter().enumerate() {
+        // 最後の段以外は次の段へのパイプを作る
+        let output = match next {
+            Some(_) => Some(pipe()?),
+            None => None,
+        };
+
+        let child = match syscall(|| unsafe { fork() }) {
+            Ok(ForkResult::Parent { child }) => child,
+            Ok(ForkResult::Child) => {
+                // 子プロセスのプロセスグループ ID を pgid に設定
+                setpgid(Pid::from_raw(0), pgid).unwrap();
+                // Rust のランタイムが無視するよう設定した SIGPIPE は exec で引き継がれるため、
+                // 後段が先に終了した場合に前段が終了するようデフォルトに戻す
+                unsafe { signal(Signal::SIGPIPE, SigHandler::SigDfl).unwrap() };
+                // 前段のパイプを stdin に、次の段へのパイプを stdout (|& の場合は stderr も) に dup2
+                syscall(|| {
+                    if let Some(reader) = &input {
+                        dup2(reader.as_raw_fd(), libc::STDIN_FILENO)?;
+                    }
+                    if let Some((_, writer)) = &output {
+                        dup2(writer.as_raw_fd(), libc::STDOUT_FILENO)?;
+                        if next == Some(&model::Pipe::Both) {
+                            dup2(writer.as_raw_fd(), libc::STDERR_FILENO)?;
+                        }
+                    }
+                    Ok(())
+                })
+                .unwrap();
+                drop((input, output));
+                exec_stage(cmd, &argv[stage], hash);
+            }
+            Err(e) => {
+                if pgid.as_raw() != 0 {
+                    let _ = killpg(pgid, Signal::SIGKILL);
+                }
+                return Err(e.into());
+            }
+        };
+
+        if pgid.as_raw() == 0 {
+            pgid = child;
+        }
+        // 子プロセスのプロセスグループ ID を pgid に設定
+        // 子プロセスが先に setpgid して exec した場合は EACCES となるが、設定済みなので無視する
+        match setpgid(child, pgid) {
+            Ok(()) | Err(nix::Error::EACCES) => {}
+            Err(e) => panic!("setpgid: {e}"),
+        }
+        pids.insert(
+            child,
+            ProcInfo {
+                state: ProcState::Run,
+                pgid,
+                stage,
+                start: start_time(child),
+            },
+        );
+
+        // 書き込み側は閉じ、読み込み側を次の段の stdin とする
+        input = output.map(|(reader, _)| reader);
+    }
+
+    Ok(pgid)
+}
+#[cfg(test)]
+mod fork_exec {
+    use super::*;
+    use nix::{sys::wait::waitpid, unistd::getpgid};
+    use std::{fs::File, io::Read};
+
+    /// 子プロセスで cmds を fork_exec し、標準出力に書き込まれた内容と子プロセスの終了コードを返す
+    ///
+    /// 子プロセスはすべての段が同じプロセスグループで記録されていることを確認し、
+    /// すべての段の終了を待って最後の段の終了コードで終了する。確認に失敗した場合は 100 で終了する。
+    fn output(cmds: model::Pipeline) -> (String, i32) {
+        let (reader, writer) = pipe().unwrap();
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                dup2(writer.as_raw_fd(), libc::STDOUT_FILENO).unwrap();
+                drop((reader, writer));
+                let mut pids = HashMap::new();
+                let pgid = fork_exec(
+                    Pid::from_raw(0),
+                    &cmds,
+                    &stage_args(&cmds),
+                    &mut pids,
+                    &CommandHash::new(),
+                )
+                .unwrap();
+                let ok = pids.len() == cmds.stages().len()
+                    && pids.contains_key(&pgid)
+                    && pids
+                        .iter()
+                        .all(|(pid, info)| info.pgid == pgid && getpgid(Some(*pid)) == Ok(pgid))
+                    && pids.values().any(|info| info.stage + 1 == pids.len());
+                let mut status = 100;
+                for (pid, info) in &pids {
+                    if let Ok(WaitStatus::Exited(_, s)) = waitpid(*pid, None) {
+                        if ok && info.stage + 1 == pids.len() {
+                            status = s;
+                        }
+                    }
+                }
+                exit(status);
+            }
+            ForkResult::Parent { child } => {
+                drop(writer);
+                let mut out = String::new();
+                File::from(reader).read_to_string(&mut out).unwrap();
+                match waitpid(child, None) {
+                    Ok(WaitStatus::Exited(_, status)) => (out, status),
+                    status => panic!("unexpected status: {status:?}"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test() {
+        let cmd = |args: &[&str]| ExternalCmd {
+            args: args.iter().map(|s| (*s).into()).collect(),
+            redirects: vec![],
+        };
+        let src = || model::Pipeline::Src(cmd(&["sh", "-c", "echo out; echo err >&2; exit 3"]));
+        let filter = || cmd(&["sed", "s/^/> /"]);
+
+        // | は標準出力のみ、 |& は標準エラー出力もパイプに接続する
+        // 終了コードは最後の段のもの
+        let out = output(model::Pipeline::Out(Box::new(src()), filter()));
+        assert_eq!(out, ("> out\n".to_string(), 0));
+        let out = output(model::Pipeline::Both(Box::new(src()), filter()));
+        assert_eq!(out, ("> out\n> err\n".to_string(), 0));
+
+        // 3 段のパイプラインもすべての段を記録する
+        let cmds = model::Pipeline::Out(
+            Box::new(model::Pipeline::Out(Box::new(src()), filter())),
+            filter(),
+        );
+        assert_eq!(output(cmds), ("> > out\n".to_string(), 0));
+        assert_eq!(output(src()), ("out\n".to_string(), 3));
+    }
+}
+
+#[cfg(test)]
+mod sh_fallback_args {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test() {
+        // testdata/no_shebang.sh はシバンのない実行可能なスクリプト
+        let script = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/no_shebang.sh");
+        let args = ["no_shebang.sh", "foo", "bar"]
+            .iter()
+            .map(|s| CString::new(*s).unwrap())
+            .collect::<Vec<_>>();
+        let sh_args = sh_fallback_args(&script, &args);
+        assert_eq!(sh_args[0].to_str().unwrap(), FALLBACK_SH);
+        assert_eq!(Path::new(sh_args[1].to_str().unwrap()), script);
+        assert_eq!(sh_args[2].to_str().unwrap(), "foo");
+        assert_eq!(sh_args[3].to_str().unwrap(), "bar");
+
+        let out = Command::new(sh_args[0].to_str().unwrap())
+            .args(sh_args[1..].iter().map(|s| s.to_str().unwrap()))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap(),
+            "no shebang: foo bar\n"
+        );
+    }
+}
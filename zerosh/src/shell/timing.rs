@@ -0,0 +1,173 @@
+//! 実行時間の計測
+//!
+//! `time` を前置したパイプラインは、各段のプロセスを wait4 で回収した際の CPU 時間を合計し、
+//! 終了時に実時間とともに表示する。 `times` はシェル自身と回収済みの子プロセスの CPU 時間を表示する。
+use nix::{
+    errno::Errno,
+    libc,
+    sys::wait::{WaitPidFlag, WaitStatus},
+    unistd::Pid,
+};
+use std::{
+    mem,
+    ops::AddAssign,
+    time::{Duration, Instant},
+};
+
+/// CPU 時間
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTime {
+    pub user: Duration, // ユーザーモード
+    pub sys: Duration,  // カーネルモード
+}
+
+impl CpuTime {
+    fn from_rusage(ru: &libc::rusage) -> Self {
+        let duration = |tv: libc::timeval| {
+            Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+        };
+        Self {
+            user: duration(ru.ru_utime),
+            sys: duration(ru.ru_stime),
+        }
+    }
+}
+
+impl AddAssign for CpuTime {
+    fn add_assign(&mut self, other: Self) {
+        self.user += other.user;
+        self.sys += other.sys;
+    }
+}
+
+/// waitpid と同様に子プロセスの状態の変化を待ち、終了したプロセスの CPU 時間とともに返す
+/// 終了以外の変化の場合の CPU 時間は 0 となる
+pub fn wait4(pid: Pid, options: Option<WaitPidFlag>) -> nix::Result<(WaitStatus, CpuTime)> {
+    let mut status = 0;
+    let mut ru: libc::rusage = unsafe { mem::zeroed() };
+    let options = options.map_or(0, |o| o.bits());
+    let res = unsafe { libc::wait4(pid.as_raw(), &mut status, options, &mut ru) };
+    match Errno::result(res)? {
+        0 => Ok((WaitStatus::StillAlive, CpuTime::default())),
+        res => Ok((
+            WaitStatus::from_raw(Pid::from_raw(res), status)?,
+            CpuTime::from_rusage(&ru),
+        )),
+    }
+}
+#[cfg(test)]
+mod wait4 {
+    use super::*;
+    use nix::unistd::{fork, ForkResult};
+
+    #[test]
+    fn test() {
+        let child = match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                // CPU 時間を消費してから終了する
+                let start = Instant::now();
+                while start.elapsed() < Duration::from_millis(100) {}
+                unsafe { libc::_exit(3) };
+            }
+            ForkResult::Parent { child } => child,
+        };
+
+        let (status, cpu) = wait4(child, None).unwrap();
+        assert_eq!(status, WaitStatus::Exited(child, 3));
+        assert!(cpu.user + cpu.sys > Duration::ZERO);
+        assert_eq!(wait4(child, None), Err(Errno::ECHILD));
+    }
+}
+
+/// d を 0m0.000s の形式で表す
+fn format_time(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!("{}m{}.{:03}s", secs / 60, secs % 60, d.subsec_millis())
+}
+#[cfg(test)]
+mod format_time {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(format_time(Duration::ZERO), "0m0.000s");
+        assert_eq!(format_time(Duration::from_micros(1_234_567)), "0m1.234s");
+        assert_eq!(format_time(Duration::from_secs(125)), "2m5.000s");
+    }
+}
+
+/// time を前置したジョブの計測
+#[derive(Debug, Clone)]
+pub struct Timer {
+    start: Instant, // 開始時刻
+    cpu: CpuTime,   // 回収済みのプロセスの CPU 時間の合計
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        Self {
+            start: Instant::now(),
+            cpu: CpuTime::default(),
+        }
+    }
+
+    /// 回収したプロセスの CPU 時間を加える
+    pub fn add(&mut self, cpu: CpuTime) {
+        self.cpu += cpu;
+    }
+
+    /// 開始からの実時間と CPU 時間の合計を、 real, user, sys の 3 行で表す
+    pub fn report(&self) -> String {
+        report(self.start.elapsed(), self.cpu)
+    }
+}
+
+fn report(real: Duration, cpu: CpuTime) -> String {
+    format!(
+        "real\t{}\nuser\t{}\nsys\t{}",
+        format_time(real),
+        format_time(cpu.user),
+        format_time(cpu.sys)
+    )
+}
+#[cfg(test)]
+mod report {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let cpu = CpuTime {
+            user: Duration::from_millis(20),
+            sys: Duration::from_millis(3),
+        };
+        assert_eq!(
+            report(Duration::from_millis(1500), cpu),
+            "real\t0m1.500s\nuser\t0m0.020s\nsys\t0m0.003s"
+        );
+
+        let mut timer = Timer::start();
+        timer.add(cpu);
+        timer.add(cpu);
+        assert!(timer.report().ends_with("user\t0m0.040s\nsys\t0m0.006s"));
+    }
+}
+
+/// who の CPU 時間。 who は RUSAGE_SELF か RUSAGE_CHILDREN
+fn usage(who: libc::c_int) -> CpuTime {
+    let mut ru: libc::rusage = unsafe { mem::zeroed() };
+    if unsafe { libc::getrusage(who, &mut ru) } != 0 {
+        return CpuTime::default();
+    }
+    CpuTime::from_rusage(&ru)
+}
+
+/// times で表示する、シェル自身と回収済みの子プロセスの CPU 時間の 2 行
+/// 各行はユーザーモードとカーネルモードの時間とする
+pub fn times() -> String {
+    let line = |cpu: CpuTime| format!("{} {}", format_time(cpu.user), format_time(cpu.sys));
+    format!(
+        "{}\n{}",
+        line(usage(libc::RUSAGE_SELF)),
+        line(usage(libc::RUSAGE_CHILDREN))
+    )
+}
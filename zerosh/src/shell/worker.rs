@@ -0,0 +1,976 @@
+//! worker スレッド
+//!
+//! main スレッドから受け取った行をパースしてキューに積み、ジョブを順に実行する。
+//! 制御構文と関数呼び出しもキューで処理し、子プロセスの状態の変化は SIGCHLD を受けて管理する。
+use super::jobs::{JobTable, ProcState};
+use super::spawn;
+use super::timing;
+use super::{parse_cmd, syscall, ShellMsg, WorkerMsg, NAME, SHELL_SIGNALS};
+use crate::cancel::CancelToken;
+use crate::complete::SharedCompletion;
+use crate::expand;
+use crate::hash::CommandHash;
+use crate::local::{ActiveLocal, TrustStore};
+use crate::model;
+use crate::prompt;
+use crate::sigpipe::{self, Event, SignalPipe};
+use crate::status::SharedStatus;
+use crate::term::{self, Style};
+use crate::vars;
+use nix::{
+    libc::{self, tcgetpgrp, tcsetpgrp},
+    sys::{
+        signal::Signal,
+        wait::{WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    io::{self, Write},
+    process::exit,
+    sync::mpsc::{Receiver, SyncSender},
+    thread::{self, JoinHandle},
+};
+
+/// 関数の呼び出しの深さの上限。 bash の FUNCNEST に相当する
+const MAX_FUNC_DEPTH: usize = 1000;
+
+/// set で設定するシェルのオプション
+#[derive(Debug, Default)]
+pub(super) struct ShellOpts {
+    pub(super) title: bool,    // 実行中のコマンドを端末のタイトルに表示
+    pub(super) status: bool,   // プロンプトの上にジョブの状態を表示
+    pub(super) pipefail: bool, // パイプラインの終了コードを 0 以外で終了した最も右の段のものとする
+    pub(super) notify: bool,   // バックグラウンドのジョブの状態の変化を、プロンプトを待たずに表示
+}
+
+impl ShellOpts {
+    /// オプション名と値の一覧
+    pub(super) fn list(&self) -> Vec<(&'static str, bool)> {
+        vec![
+            ("title", self.title),
+            ("status", self.status),
+            ("pipefail", self.pipefail),
+            ("notify", self.notify),
+        ]
+    }
+
+    /// オプションを設定。存在しないオプションの場合は None を返す
+    pub(super) fn set(&mut self, name: &str, on: bool) -> Option<()> {
+        match name {
+            "title" => self.title = on,
+            "status" => self.status = on,
+            "pipefail" => self.pipefail = on,
+            "notify" => self.notify = on,
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+/// worker のキューに積む処理
+///
+/// 制御構文は、条件のジョブの後に終了コードで分岐する処理を積んで実行する。
+#[derive(Debug)]
+enum Task {
+    Job(model::Job),
+    /// if の条件の後に、 then と els のどちらを実行するか決める
+    Branch {
+        then: Vec<model::Job>,
+        els: Option<Vec<model::Job>>,
+    },
+    /// while の条件を実行する。 last はループの終了コードで、 None の場合は直前の終了コード
+    While {
+        cond: Vec<model::Job>,
+        body: Vec<model::Job>,
+        last: Option<i32>,
+    },
+    /// while の条件の後に、本体を実行するか決める
+    WhileBody {
+        cond: Vec<model::Job>,
+        body: Vec<model::Job>,
+        last: i32,
+    },
+    /// 展開済みの残りの単語 words で for の本体を実行する
+    For {
+        var: String,
+        words: VecDeque<String>,
+        body: Vec<model::Job>,
+    },
+    /// 関数の本体の後に、呼び出しのフレームを取り除く
+    Return,
+    /// trap のコマンドの後に、終了コードを実行前の値に戻す
+    Status(i32),
+}
+
+#[derive(Debug)]
+pub(super) struct Worker {
+    pub(super) exit_val: i32,   // 終了コード
+    pub(super) fg: Option<Pid>, // フォアグラウンドプロセスのプロセスグループ ID
+
+    pub(super) jobs: JobTable,  // ジョブとプロセスの管理表
+    pub(super) shell_pgid: Pid, // シェルのプロセスグループ ID
+
+    // wait で終了を待っているジョブ。 Some(None) の場合は実行中のジョブすべて
+    pub(super) waiting: Option<Option<usize>>,
+
+    pub(super) key_binds: BTreeMap<String, String>, // キーの記法からコマンドへのマップ
+    pub(super) opts: ShellOpts,                     // シェルのオプション
+
+    // 入力された行のうち、まだ実行していないジョブと、実行する条件
+    // フォアグラウンドのジョブが終了・停止するまで、次のジョブは実行しない
+    queue: VecDeque<(Option<model::Cond>, Task)>,
+
+    pub(super) status: SharedStatus, // main スレッドと共有するステータスライン
+
+    pub(super) local: Option<ActiveLocal>, // 適用中のローカル設定ファイル
+    pub(super) trust: TrustStore,          // 信頼したローカル設定ファイル
+
+    pub(super) cancel: CancelToken, // ブロックする組み込みコマンドの中断状態
+    pub(super) hash: CommandHash,   // PATH から検索したコマンドのパス
+
+    pub(super) functions: HashMap<String, Vec<model::Job>>, // 関数名から本体へのマップ
+
+    pub(super) traps: BTreeMap<Signal, String>, // trap で設定したシグナルごとのコマンド
+    pending_traps: Vec<String>,                 // 受信したシグナルの、まだ実行していないコマンド
+    pub(super) completion: SharedCompletion,    // main スレッドと共有する補完の状態
+}
+
+impl Worker {
+    pub(super) fn new(
+        status: SharedStatus,
+        cancel: CancelToken,
+        completion: SharedCompletion,
+    ) -> Self {
+        let pid = unsafe { tcgetpgrp(libc::STDIN_FILENO) };
+        Self {
+            exit_val: 0,
+            fg: None,
+            jobs: JobTable::new(),
+
+            // libc::STDIN_FILENO に関連付けられた、フォアグラウンドプロセスのプロセスグループID
+            // つまりシェルのプロセスグループIDを取得する
+            // getpgid でも可能だが、シェルがフォアグラウンドであるかも検査できるので tcgetpgrp を利用している
+            // したがって zerosh は制御端末を利用した実行のみをサポートすることになる
+            shell_pgid: Pid::from_raw(pid),
+            waiting: None,
+
+            key_binds: BTreeMap::new(),
+            opts: ShellOpts::default(),
+            queue: VecDeque::new(),
+            status,
+            local: None,
+            trust: TrustStore::load(),
+            cancel,
+            hash: CommandHash::new(),
+            functions: HashMap::new(),
+            traps: BTreeMap::new(),
+            pending_traps: Vec::new(),
+            completion,
+        }
+    }
+
+    /// worker スレッドを起動
+    ///
+    /// self-pipe から読み込んだ順にメッセージとシグナルを処理する。
+    /// メッセージは self-pipe で通知された場合に worker_rx から 1 つ受信する。
+    /// WorkerMsg::Exit を受信すると、 trap で受信を開始したシグナルをデフォルトに戻して終了する。
+    pub(super) fn spawn(
+        mut self,
+        worker_rx: Receiver<WorkerMsg>,
+        sig_pipe: SignalPipe,
+        shell_tx: SyncSender<ShellMsg>,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            match sig_pipe.recv() {
+                Event::Wake => {
+                    let Ok(msg) = worker_rx.recv() else {
+                        return; // main スレッドが終了した
+                    };
+                    match msg {
+                        WorkerMsg::Exit => {
+                            for sig in self.traps.keys() {
+                                if !SHELL_SIGNALS.contains(sig) {
+                                    let _ = sigpipe::unwatch(*sig);
+                                }
+                            }
+                            return;
+                        }
+                        WorkerMsg::Cmd(line) => {
+                            match parse_cmd(&line) {
+                                Ok(jobs) => {
+                                    // ジョブを先頭から順に実行する
+                                    self.queue = jobs
+                                        .into_iter()
+                                        .map(|job| (None, Task::Job(job)))
+                                        .collect();
+                                    // 入力を待っている間に受信した SIGINT では中断しない
+                                    self.cancel.reset();
+                                    self.resume(&shell_tx);
+                                }
+                                Err(e) => {
+                                    // 入力された行の下にエラーの位置を示す
+                                    let first = line.lines().next().unwrap_or("");
+                                    error!("syntax error: expected {}", e.expected);
+                                    eprintln!("{}", e.caret(first));
+                                    self.clear_queue();
+                                    // コマンドのパースに失敗した場合はシェルからの入力を再開するため
+                                    // main スレッドに通知する
+                                    shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap();
+                                }
+                            }
+                        }
+                    }
+                }
+                Event::Signal(sig) => self.on_signal(sig, &shell_tx),
+            }
+        })
+    }
+
+    /// 受信したシグナルを処理
+    ///
+    /// trap でコマンドを設定したシグナルは、次のジョブの前にそのコマンドを実行する。
+    /// wait で待っている場合は待つのをやめて直ちに実行し、シェルが入力を待っている場合は
+    /// 次に入力された行の前に実行する。
+    fn on_signal(&mut self, sig: Signal, shell_tx: &SyncSender<ShellMsg>) {
+        if let Some(cmd) = self.traps.get(&sig) {
+            self.pending_traps.push(cmd.clone());
+        }
+
+        match sig {
+            Signal::SIGCHLD => self.wait_child(shell_tx), // 子プロセスの状態変化を管理
+            _ if self.traps.contains_key(&sig) => {
+                if self.waiting.take().is_some() {
+                    // trap を設定した SIGINT では行の残りのジョブも実行する
+                    self.cancel.reset();
+                    self.exit_val = 128 + sig as i32;
+                    self.resume(shell_tx);
+                }
+            }
+            Signal::SIGINT => {
+                // 組み込みコマンドの中断はシグナルハンドラが CancelToken で行う
+                // wait はブロックせずにジョブの終了を待つため、ここで中断する
+                self.cancel_wait(shell_tx);
+            }
+            // 無視
+            _ => eprintln!("signal: {sig:?} received and ignore it"),
+        }
+    }
+
+    /// 受信したシグナルの trap のコマンドをキューの先頭に積む
+    /// コマンドの実行後は、実行前の終了コードに戻す
+    fn push_traps(&mut self) {
+        if self.pending_traps.is_empty() {
+            return;
+        }
+        self.queue.push_front((None, Task::Status(self.exit_val)));
+        for cmd in std::mem::take(&mut self.pending_traps).into_iter().rev() {
+            match parse_cmd(&cmd) {
+                Ok(jobs) => self.push_jobs(jobs),
+                Err(e) => error!("trap: {e}"),
+            }
+        }
+    }
+
+    /// 実行中のジョブが終了した時などに呼び出す
+    ///
+    /// 同じ行に残りのジョブがあれば順に実行し、
+    /// なければシェルからの入力を再開する
+    ///
+    /// && と || で連結したジョブは、直前のジョブが終了した時点の終了コードで
+    /// 実行するかを決める。実行しないジョブは飛ばし、終了コードはそのままとする。
+    ///
+    /// 組み込みコマンドと制御構文の分岐はループで続けて処理し、フォアグラウンドの子プロセスや
+    /// wait で待つ場合に戻る。ジョブごとに中断状態を確認し、 SIGINT を受信していれば残りのジョブは実行しない。
+    pub(super) fn resume(&mut self, shell_tx: &SyncSender<ShellMsg>) {
+        loop {
+            vars::set_status(self.exit_val); // $? で参照する
+            if self.cancel.check().is_err() {
+                self.on_cancelled();
+            }
+            self.push_traps();
+            let Some((cond, task)) = self.queue.pop_front() else {
+                shell_tx.send(ShellMsg::Continue(self.exit_val)).unwrap(); // シェルからの入力を再開
+                return;
+            };
+            if !should_run(cond, self.exit_val) {
+                continue;
+            }
+            let next = match task {
+                Task::Job(job) => self.run_job(job, shell_tx),
+                task => {
+                    self.run_task(task);
+                    true
+                }
+            };
+            if !next {
+                return;
+            }
+        }
+    }
+
+    /// ジョブを実行する。戻り値は、続けて次のジョブを実行するか
+    ///
+    /// フォアグラウンドの子プロセスの終了や wait を待つ場合は false を返し、
+    /// 待ち終えた時点で manage_job や check_wait が resume で再開する。
+    fn run_job(&mut self, job: model::Job, shell_tx: &SyncSender<ShellMsg>) -> bool {
+        match job {
+            model::Job::BuiltIn { cmd, is_bg } => self.built_in_cmd(&cmd, is_bg, shell_tx),
+            model::Job::External {
+                mut cmds,
+                is_bg,
+                timed,
+            } => {
+                // 引数は実行する直前に展開する
+                let argv = spawn::stage_args(&cmds);
+                if let Some(args) = self.function_args(&cmds, &argv) {
+                    if timed {
+                        error!("time: functions can't be timed");
+                        self.exit_val = 1; // 失敗
+                    } else {
+                        self.call_function(args, &cmds, is_bg);
+                    }
+                    true
+                } else {
+                    self.spawn_child(&mut cmds, &argv, is_bg, timed)
+                }
+            }
+            model::Job::List {
+                first,
+                rest,
+                is_bg: false,
+            } => {
+                // フォアグラウンドの場合は、各ジョブをキューの先頭に展開して順に実行する
+                for (cond, job) in rest.into_iter().rev() {
+                    self.queue.push_front((Some(cond), Task::Job(job)));
+                }
+                self.queue.push_front((None, Task::Job(*first)));
+                true
+            }
+            model::Job::Compound { cmd, is_bg: false } => {
+                self.start_compound(cmd);
+                true
+            }
+            model::Job::Function { name, body } => {
+                self.completion.lock().unwrap().add_function(&name);
+                self.functions.insert(name, body);
+                self.exit_val = 0; // 成功
+                true
+            }
+            model::Job::Compound { is_bg: true, .. } => {
+                error!("control structures can't be run in background");
+                self.exit_val = 1; // 失敗
+                true
+            }
+            model::Job::List {
+                first,
+                rest,
+                is_bg: true,
+            } => {
+                self.spawn_list(*first, rest);
+                true
+            }
+        }
+    }
+
+    /// キューを空にする。呼び出し中の関数のフレームも取り除く
+    pub(super) fn clear_queue(&mut self) {
+        for (_, task) in self.queue.drain(..) {
+            if let Task::Return = task {
+                vars::pop_frame();
+            }
+        }
+    }
+
+    /// cmds が関数の呼び出しであれば、展開した引数を返す。 argv は展開した各段の引数
+    /// パイプラインの中の関数は呼び出さない
+    fn function_args(&self, cmds: &model::Pipeline, argv: &[Vec<String>]) -> Option<Vec<String>> {
+        let model::Pipeline::Src(_) = cmds else {
+            return None;
+        };
+        let args = argv.first()?;
+        self.functions
+            .contains_key(args.first()?)
+            .then(|| args.clone())
+    }
+
+    /// 関数を呼び出す。 args[0] は関数名で、残りを位置パラメータとする
+    /// 本体のジョブと、フレームを取り除く Task::Return をキューの先頭に積む
+    /// 呼び出しの深さが MAX_FUNC_DEPTH を超える場合は、同じ行の残りのジョブを実行しない
+    fn call_function(&mut self, args: Vec<String>, cmds: &model::Pipeline, is_bg: bool) {
+        if is_bg {
+            error!("functions can't be run in background");
+            self.exit_val = 1; // 失敗
+            return;
+        }
+        if let model::Pipeline::Src(cmd) = cmds {
+            if !cmd.redirects.is_empty() {
+                error!("redirection of functions is not supported");
+                self.exit_val = 1; // 失敗
+                return;
+            }
+        }
+
+        if vars::depth() >= MAX_FUNC_DEPTH {
+            error!(
+                "{}: maximum function nesting level exceeded ({MAX_FUNC_DEPTH})",
+                args[0]
+            );
+            self.exit_val = 1; // 失敗
+            self.clear_queue();
+            return;
+        }
+
+        let body = self.functions[&args[0]].clone();
+        vars::push_frame(args[1..].to_vec());
+        self.queue.push_front((None, Task::Return));
+        self.push_jobs(body);
+    }
+
+    /// jobs をキューの先頭に積む
+    pub(super) fn push_jobs(&mut self, jobs: Vec<model::Job>) {
+        for job in jobs.into_iter().rev() {
+            self.queue.push_front((None, Task::Job(job)));
+        }
+    }
+
+    /// 制御構文の実行を開始する。条件や本体のジョブはキューの先頭に積む
+    fn start_compound(&mut self, cmd: model::Compound) {
+        match cmd {
+            model::Compound::If { cond, then, els } => {
+                self.queue.push_front((None, Task::Branch { then, els }));
+                self.push_jobs(cond);
+            }
+            model::Compound::While { cond, body } => {
+                // 本体を 1 度も実行しない場合の終了コードは 0
+                let task = Task::While {
+                    cond,
+                    body,
+                    last: Some(0),
+                };
+                self.queue.push_front((None, task));
+            }
+            model::Compound::For { var, words, body } => {
+                self.exit_val = 0; // 本体を 1 度も実行しない場合の終了コード
+                let words = expand::expand_args(&words).into();
+                self.queue
+                    .push_front((None, Task::For { var, words, body }));
+            }
+        }
+    }
+
+    /// 制御構文の分岐を処理する
+    fn run_task(&mut self, task: Task) {
+        match task {
+            Task::Job(_) => unreachable!(),
+            Task::Return => vars::pop_frame(),
+            Task::Status(n) => {
+                self.exit_val = n;
+                vars::set_status(n);
+            }
+            Task::Branch { then, els } => match (self.exit_val, els) {
+                (0, _) => self.push_jobs(then),
+                (_, Some(els)) => self.push_jobs(els),
+                (_, None) => self.exit_val = 0,
+            },
+            Task::While { cond, body, last } => {
+                let last = last.unwrap_or(self.exit_val);
+                let task = Task::WhileBody {
+                    cond: cond.clone(),
+                    body,
+                    last,
+                };
+                self.queue.push_front((None, task));
+                self.push_jobs(cond);
+            }
+            Task::WhileBody { cond, body, last } => {
+                if self.exit_val == 0 {
+                    let task = Task::While {
+                        cond,
+                        body: body.clone(),
+                        last: None,
+                    };
+                    self.queue.push_front((None, task));
+                    self.push_jobs(body);
+                } else {
+                    self.exit_val = last;
+                }
+            }
+            Task::For {
+                var,
+                mut words,
+                body,
+            } => {
+                if let Some(word) = words.pop_front() {
+                    vars::remove_array(&var);
+                    std::env::set_var(&var, word);
+                    let task = Task::For {
+                        var,
+                        words,
+                        body: body.clone(),
+                    };
+                    self.queue.push_front((None, task));
+                    self.push_jobs(body);
+                }
+            }
+        }
+    }
+
+    /// title オプションが有効なら、端末のタイトルを設定
+    pub(super) fn set_title(&self, title: &str) {
+        if self.opts.title && unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1 {
+            // xterm のタイトル設定用のエスケープシーケンス
+            print!("\x1b]0;{title}\x07");
+            io::stdout().flush().ok();
+        }
+    }
+
+    /// ジョブの管理
+    /// 引数には変化のあったジョブとプロセスグループを指定
+    ///
+    /// - フォアグラウンドプロセスが空の場合、シェルをフォアグラウンドに設定
+    /// - フォアグラウンドプロセスがすべて停止中の場合、シェルをフォアグラウンドに設定
+    ///
+    /// ジョブが終了した場合、フォアグラウンドのジョブと wait で待っているジョブの終了コードを
+    /// シェルの終了コードとする。バックグラウンドのジョブの終了では $? を変更しない。
+    fn manage_job(&mut self, job_id: usize, pgid: Pid, shell_tx: &SyncSender<ShellMsg>) {
+        let is_fg = self.fg == Some(pgid); // フォアグラウンドのプロセスか?
+        if is_fg || self.waiting == Some(Some(job_id)) {
+            if let Some(status) = self.jobs.status(job_id, self.opts.pipefail) {
+                self.exit_val = status;
+            }
+        }
+        let line = self.jobs.get(job_id).unwrap().1;
+        if is_fg {
+            // 状態が変化したプロセスはフォアグラウンドに設定
+            if self.jobs.is_group_empty(pgid) {
+                // フォアグラウンドプロセスが空の場合、
+                // ジョブ情報を削除してシェルをフォアグラウンドに設定
+                // フォアグラウンドのジョブの終了は通知しない
+                if let Some(report) = self.jobs.time_report(job_id) {
+                    eprintln!("\n{report}");
+                }
+                self.jobs.remove(job_id);
+                self.set_shell_fg();
+                self.resume(shell_tx);
+            } else if self.jobs.is_group_stop(pgid).unwrap() {
+                // フォアグラウンドプロセスがすべて停止中の場合、シェルをフォアグラウンドに設定
+                eprintln!(
+                    "\n[{job_id}] {}\t{line}",
+                    term::caps().paint(Style::Yellow, "Stopped")
+                );
+                self.set_shell_fg();
+                self.resume(shell_tx);
+            }
+        } else {
+            // プロセスグループが空の場合、ジョブ情報を削除
+            // 終了と停止は notify で通知する
+            if self.jobs.is_group_empty(pgid) {
+                let done = term::caps().paint(Style::Green, "Done");
+                let mut notice = format!("[{job_id}] {done}\t{line}");
+                if let Some(report) = self.jobs.time_report(job_id) {
+                    notice = format!("{notice}\n{report}");
+                }
+                self.notify(notice);
+                self.jobs.remove(job_id);
+            } else if self.jobs.is_group_stop(pgid).unwrap() {
+                let stopped = term::caps().paint(Style::Yellow, "Stopped");
+                self.notify(format!("[{job_id}] {stopped}\t{line}"));
+            }
+        }
+    }
+
+    /// バックグラウンドのジョブの通知
+    ///
+    /// 入力中の行に割り込まないよう、通常は次のプロンプトの直前に表示する。
+    /// notify オプションが有効な場合は直ちに表示し、入力中の行はプロンプトとともに再描画する。
+    pub(super) fn notify(&self, notice: String) {
+        self.status.lock().unwrap().notify(notice, self.opts.notify);
+    }
+
+    /// 実行中と停止中のジョブの数を数えて、ステータスラインを更新
+    pub(super) fn update_status(&self) {
+        let (running, stopped) = self.jobs.counts();
+        self.status.lock().unwrap().update(running, stopped);
+    }
+
+    /// シェルをフォアグラウンドに設定し、端末のタイトルをプロンプトに戻す
+    pub(super) fn set_shell_fg(&mut self) {
+        if self.opts.title {
+            self.set_title(&prompt::title(NAME, self.exit_val));
+        }
+        self.fg = None;
+        unsafe { tcsetpgrp(libc::STDIN_FILENO, self.shell_pgid.as_raw()) };
+    }
+
+    /// 子プロセスの状態変化を管理
+    fn wait_child(&mut self, shell_tx: &SyncSender<ShellMsg>) {
+        // WUNTRACED: 子プロセスの停止
+        // WNOHANG: ブロックしない
+        // WCONTINUED: 実行再開
+        let flag = Some(WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG | WaitPidFlag::WCONTINUED);
+
+        loop {
+            // pid = -1 指定によりすべての子プロセスの状態変化を待つ
+            // waitpid は終了したプロセスのリソース開放も行う
+            // これを忘れるとゾンビプロセスになり無駄にリソースを消費する
+            // WNOHANG を指定しているので、子プロセスの状態に変化がない場合は即座に返る
+            // これにより worker はシグナルとコマンドライン実行の両方を並行に処理できる
+            // wait4 は waitpid と同様だが、 time で計測するために終了したプロセスの CPU 時間も返す
+            match syscall(|| timing::wait4(Pid::from_raw(-1), flag)) {
+                Ok((WaitStatus::StillAlive, _)) => return, // wait すべき子プロセスはいない
+                Ok((status, cpu)) => {
+                    if let Some(pid) = status.pid() {
+                        self.jobs.add_cpu_time(pid, cpu);
+                    }
+                    self.on_wait_status(status, shell_tx)
+                }
+                Err(nix::Error::ECHILD) => return, // 子プロセスはいない
+                Err(e) => {
+                    eprintln!("\n{NAME}: Failed to wait: {e}");
+                    exit(1);
+                }
+            }
+        }
+    }
+
+    /// waitpid で取得した子プロセスの状態の変化を処理
+    /// ジョブの管理表にないプロセスの状態の変化は無視する
+    fn on_wait_status(&mut self, status: WaitStatus, shell_tx: &SyncSender<ShellMsg>) {
+        match status {
+            WaitStatus::Exited(pid, status) => {
+                // プロセスが終了
+                self.process_term(pid, status, shell_tx);
+            }
+            WaitStatus::Signaled(pid, sig, core) => {
+                // プロセスがシグナルにより終了
+                // バックグラウンドのジョブの場合は、ジョブの終了と同様に次のプロンプトの直前に表示する
+                // ジョブの終了コードと同様に、パイプラインの最後のコマンドのみ表示する
+                // `yes | head -1` の前段の SIGPIPE や、 Ctrl-C による各段の終了を重ねて表示しない
+                if self.jobs.is_last(pid).unwrap_or(true) {
+                    let msg = format!(
+                        "{NAME}: Child process terminated by signal{}: pid = {pid}, signal = {sig}",
+                        if core { " (core dumped)" } else { "" },
+                    );
+                    if self.jobs.pgid_of(pid) == self.fg {
+                        eprintln!("\n{msg}");
+                    } else {
+                        self.notify(msg);
+                    }
+                }
+                // フォアグラウンドのジョブが Ctrl-C で終了した場合は、ループを抜けるため
+                // 同じ行の残りのジョブは実行しない
+                if sig == Signal::SIGINT && self.jobs.pgid_of(pid) == self.fg {
+                    self.clear_queue();
+                }
+                self.process_term(pid, sig as i32 + 128, shell_tx);
+            }
+            // プロセスが停止
+            WaitStatus::Stopped(pid, sig) => {
+                // フォアグラウンドのジョブが停止した場合は、後に続く && と || のために
+                // シグナルによる終了と同様の終了コードとする
+                if self.jobs.pgid_of(pid) == self.fg {
+                    self.exit_val = sig as i32 + 128;
+                }
+                self.process_stop(pid, shell_tx)
+            }
+            WaitStatus::Continued(pid) => self.process_continue(pid),
+            WaitStatus::StillAlive => (),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            WaitStatus::PtraceEvent(pid, _, _) | WaitStatus::PtraceSyscall(pid) => {
+                self.process_stop(pid, shell_tx)
+            }
+        }
+    }
+
+    // プロセスの終了処理。 status は終了コードで、シグナルによる場合は 128 + シグナル番号
+    fn process_term(&mut self, pid: Pid, status: i32, shell_tx: &SyncSender<ShellMsg>) {
+        // プロセス ID を削除し、必要ならフォアグラウンドプロセスをシェルに設定
+        if let Some((job_id, pgid)) = self.jobs.remove_pid(pid, status) {
+            self.manage_job(job_id, pgid, shell_tx);
+        }
+        self.update_status();
+        self.check_wait(shell_tx);
+    }
+
+    // プロセスの停止処理
+    fn process_stop(&mut self, pid: Pid, shell_tx: &SyncSender<ShellMsg>) {
+        // プロセスを停止中に設定
+        if self.jobs.set_pid_state(pid, ProcState::Stop).is_none() {
+            return;
+        }
+        let pgid = self.jobs.pgid_of(pid).unwrap(); // プロセスグループ ID を取得
+        let job_id = self.jobs.job_of(pgid).unwrap(); // ジョブ ID を取得
+        self.manage_job(job_id, pgid, shell_tx); // 必要ならフォアグラウンドプロセスをシェルに設定
+        self.update_status();
+        self.check_wait(shell_tx);
+    }
+
+    // プロセスの再開処理
+    fn process_continue(&mut self, pid: Pid) {
+        self.jobs.set_pid_state(pid, ProcState::Run); // プロセスを実行中に設定
+        self.update_status();
+    }
+}
+
+/// && と || の条件 cond と直前の終了コード exit_val から、ジョブを実行するかを判定
+/// cond が None の場合は常に実行する
+pub(super) fn should_run(cond: Option<model::Cond>, exit_val: i32) -> bool {
+    match cond {
+        Some(model::Cond::And) => exit_val == 0,
+        Some(model::Cond::Or) => exit_val != 0,
+        None => true,
+    }
+}
+#[cfg(test)]
+mod should_run {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert!(should_run(None, 0));
+        assert!(should_run(None, 1));
+        assert!(should_run(Some(model::Cond::And), 0));
+        assert!(!should_run(Some(model::Cond::And), 1));
+        assert!(!should_run(Some(model::Cond::Or), 0));
+        assert!(should_run(Some(model::Cond::Or), 130));
+    }
+}
+
+/// 端末を用いずに Worker を生成し、 waitpid の結果の代わりに WaitStatus の列を与えて
+/// ジョブの管理表の状態と通知を確認する
+#[cfg(test)]
+mod on_wait_status {
+    use super::*;
+    use crate::shell::jobs::{Origin, ProcInfo};
+    use crate::status::StatusLine;
+    use std::sync::{
+        mpsc::{sync_channel, Receiver},
+        Arc, Mutex,
+    };
+
+    struct Harness {
+        worker: Worker,
+        shell_tx: SyncSender<ShellMsg>,
+        shell_rx: Receiver<ShellMsg>,
+    }
+
+    impl Harness {
+        fn new() -> Self {
+            let status = Arc::new(Mutex::new(StatusLine::default()));
+            let worker = Worker::new(status, CancelToken::new(), Default::default());
+            // main スレッドの代わりに受信するため、送信でブロックしないようにする
+            let (shell_tx, shell_rx) = sync_channel(16);
+            Self {
+                worker,
+                shell_tx,
+                shell_rx,
+            }
+        }
+
+        /// pids の先頭をプロセスグループ ID 、末尾をパイプラインの最後のコマンドとするジョブを追加し、ジョブ ID を返す
+        fn spawn(&mut self, pids: &[i32], line: &str, is_fg: bool) -> usize {
+            let pgid = Pid::from_raw(pids[0]);
+            let procs = pids
+                .iter()
+                .enumerate()
+                .map(|(stage, pid)| {
+                    let info = ProcInfo {
+                        state: ProcState::Run,
+                        pgid,
+                        stage,
+                        start: None,
+                    };
+                    (Pid::from_raw(*pid), info)
+                })
+                .collect();
+            let job_id = self.worker.jobs.new_job_id().unwrap();
+            self.worker
+                .jobs
+                .insert(job_id, pgid, procs, line, None, Origin::default());
+            if is_fg {
+                self.worker.fg = Some(pgid);
+            }
+            job_id
+        }
+
+        fn feed(&mut self, statuses: &[WaitStatus]) {
+            for status in statuses {
+                self.worker.on_wait_status(*status, &self.shell_tx);
+            }
+        }
+
+        /// main スレッドに送信された入力の再開の終了コード
+        fn continued(&self) -> Vec<i32> {
+            self.shell_rx
+                .try_iter()
+                .map(|msg| match msg {
+                    ShellMsg::Continue(n) => n,
+                    _ => panic!("unexpected message"),
+                })
+                .collect()
+        }
+
+        /// 溜まっている Done の通知の数
+        fn done_count(&self) -> usize {
+            let status = self.worker.status.lock().unwrap();
+            status
+                .notices()
+                .iter()
+                .filter(|n| n.contains("Done"))
+                .count()
+        }
+
+        /// 溜まっている Stopped の通知の数
+        fn stopped_count(&self) -> usize {
+            let status = self.worker.status.lock().unwrap();
+            status
+                .notices()
+                .iter()
+                .filter(|n| n.contains("Stopped"))
+                .count()
+        }
+    }
+
+    #[test]
+    fn background() {
+        let pid = Pid::from_raw;
+        let mut h = Harness::new();
+        let job_id = h.spawn(&[100, 101], "a | b", false);
+        assert_eq!(h.worker.jobs.check(), 2);
+
+        // 停止と再開ではジョブは残る
+        // すべてのプロセスが停止した時点で 1 度だけ Stopped を通知する
+        h.feed(&[WaitStatus::Stopped(pid(100), Signal::SIGTSTP)]);
+        assert_eq!(h.stopped_count(), 0);
+        h.feed(&[WaitStatus::Stopped(pid(101), Signal::SIGTSTP)]);
+        assert_eq!(h.worker.jobs.counts(), (0, 1));
+        assert_eq!(h.stopped_count(), 1);
+        h.feed(&[
+            WaitStatus::Continued(pid(100)),
+            WaitStatus::Continued(pid(101)),
+        ]);
+        assert_eq!(h.worker.jobs.counts(), (1, 0));
+
+        // 一部のプロセスが終了しても Done は通知しない
+        h.feed(&[WaitStatus::Exited(pid(100), 0)]);
+        assert_eq!(h.worker.jobs.check(), 1);
+        assert_eq!(h.done_count(), 0);
+
+        // 最後のプロセスが終了すると 1 度だけ通知し、同じ pid の終了が重なっても通知しない
+        h.feed(&[
+            WaitStatus::Exited(pid(101), 1),
+            WaitStatus::Exited(pid(101), 1),
+        ]);
+        assert_eq!(h.worker.jobs.get(job_id), None);
+        assert_eq!(h.worker.jobs.check(), 0);
+        assert_eq!(h.done_count(), 1);
+
+        // バックグラウンドのジョブの終了では入力を再開しない
+        assert!(h.continued().is_empty());
+    }
+
+    #[test]
+    fn foreground() {
+        let pid = Pid::from_raw;
+        let mut h = Harness::new();
+        h.spawn(&[200], "sleep 10", true);
+
+        // 停止するとシェルに戻り、ジョブは停止中として残る
+        h.feed(&[WaitStatus::Stopped(pid(200), Signal::SIGTSTP)]);
+        assert_eq!(h.continued(), vec![128 + Signal::SIGTSTP as i32]);
+        assert_eq!(h.worker.fg, None);
+        assert_eq!(h.worker.jobs.counts(), (0, 1));
+
+        // fg で再開した後、シグナルで終了するとジョブを削除して入力を再開する
+        h.worker.fg = Some(pid(200));
+        h.feed(&[
+            WaitStatus::Continued(pid(200)),
+            WaitStatus::Signaled(pid(200), Signal::SIGINT, false),
+        ]);
+        assert_eq!(h.continued(), vec![128 + Signal::SIGINT as i32]);
+        assert!(h.worker.jobs.is_empty());
+        assert_eq!(h.worker.jobs.check(), 0);
+
+        // フォアグラウンドのジョブの終了は通知しない
+        assert_eq!(h.done_count(), 0);
+    }
+
+    #[test]
+    fn pipeline() {
+        let pid = Pid::from_raw;
+        let mut h = Harness::new();
+        h.spawn(&[600, 601], "yes | head -1", true);
+
+        // 前段が後から終了しても、終了コードは最後のコマンドのものとする
+        // 前段のシグナルによる終了は通知しない
+        h.feed(&[
+            WaitStatus::Exited(pid(601), 0),
+            WaitStatus::Signaled(pid(600), Signal::SIGPIPE, false),
+        ]);
+        assert_eq!(h.continued(), vec![0]);
+        assert_eq!(h.worker.exit_val, 0);
+        assert!(h.worker.jobs.is_empty());
+        assert!(h.worker.status.lock().unwrap().notices().is_empty());
+
+        // pipefail の場合は、 0 以外で終了した最も右の段の終了コードとする
+        h.worker.opts.pipefail = true;
+        h.spawn(&[700, 701, 702], "false | grep x | true", true);
+        h.feed(&[
+            WaitStatus::Exited(pid(700), 1),
+            WaitStatus::Exited(pid(702), 0),
+            WaitStatus::Exited(pid(701), 2),
+        ]);
+        assert_eq!(h.continued(), vec![2]);
+        assert_eq!(h.worker.exit_val, 2);
+    }
+
+    #[test]
+    fn background_status() {
+        let pid = Pid::from_raw;
+        let mut h = Harness::new();
+        h.worker.exit_val = 5;
+        h.spawn(&[800], "false", false);
+
+        // バックグラウンドのジョブの終了は $? を変更しない
+        h.feed(&[WaitStatus::Exited(pid(800), 1)]);
+        assert_eq!(h.worker.exit_val, 5);
+        assert_eq!(h.done_count(), 1);
+    }
+
+    #[test]
+    fn unknown_pid() {
+        let pid = Pid::from_raw;
+        let mut h = Harness::new();
+        h.spawn(&[300], "sleep 10", false);
+
+        // 管理表にないプロセスの状態の変化は無視する
+        h.feed(&[
+            WaitStatus::Stopped(pid(999), Signal::SIGTSTP),
+            WaitStatus::Continued(pid(999)),
+            WaitStatus::Exited(pid(999), 0),
+        ]);
+        assert_eq!(h.worker.jobs.check(), 1);
+        assert_eq!(h.worker.jobs.counts(), (1, 0));
+        assert_eq!(h.done_count(), 0);
+    }
+
+    #[test]
+    fn wait() {
+        let pid = Pid::from_raw;
+        let mut h = Harness::new();
+        let a = h.spawn(&[400], "a", false);
+        h.spawn(&[500], "b", false);
+
+        // 待っているジョブが終了した時点で、そのジョブの終了コードで入力を再開する
+        h.worker.waiting = Some(Some(a));
+        h.feed(&[WaitStatus::Exited(pid(500), 0)]);
+        assert!(h.continued().is_empty());
+        h.feed(&[WaitStatus::Exited(pid(400), 3)]);
+        assert_eq!(h.continued(), vec![3]);
+        assert_eq!(h.worker.waiting, None);
+        assert_eq!(h.done_count(), 2);
+        assert_eq!(h.worker.jobs.check(), 0);
+    }
+}
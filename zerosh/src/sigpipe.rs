@@ -0,0 +1,271 @@
+//! self-pipe によるシグナルの受信
+//!
+//! シグナルハンドラはシグナル番号の 1 バイトをパイプに書き込むだけとし、
+//! worker スレッドはパイプから 1 バイトずつ読み込んでシグナルを処理する。
+//! main スレッドからのコマンドの送信も同じパイプに 0 を書き込んで通知するため、
+//! worker スレッドはシグナルとコマンドを到着した順に 1 つずつ処理できる。
+//!
+//! 同じシグナルは worker スレッドが読み込むまで 1 つにまとめる。
+//! SIGCHLD が大量に届いてもパイプが溢れることはなく、 worker スレッドは 1 回の waitpid の
+//! ループですべての子プロセスの状態変化を処理する。
+//!
+//! シグナルハンドラでは write と atomic 変数の操作のみを行い、 async-signal-safe とする。
+//!
+//! uninstall でシグナルの処理を元に戻してパイプを閉じると、再び install できる。
+use crate::cancel::CancelToken;
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, OFlag},
+    libc,
+    sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+    unistd::{pipe2, read, write},
+};
+use std::{
+    os::fd::{AsRawFd, OwnedFd},
+    sync::{
+        atomic::{AtomicI32, AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+};
+
+/// シグナルハンドラが書き込むパイプ。 install するまでと uninstall した後は -1
+static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// シグナルハンドラが書き込むパイプの書き込み側。 uninstall するまで閉じない
+static WRITER: Mutex<Option<OwnedFd>> = Mutex::new(None);
+
+/// パイプに書き込み済みで、まだ読み込まれていないシグナルの集合。シグナル番号 n を n - 1 ビット目とする
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// SIGINT を受信した場合に中断状態にする。 install をまたいでプロセスで共有する
+static CANCEL: OnceLock<CancelToken> = OnceLock::new();
+
+/// コマンドの送信を表すバイト。シグナル番号は 1 以上のため区別できる
+const WAKE: u8 = 0;
+
+/// パイプから読み込んだイベント
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Event {
+    Wake,           // Waker::wake による通知
+    Signal(Signal), // シグナルの受信
+}
+
+/// worker スレッドが保持するパイプの読み込み側
+#[derive(Debug)]
+pub struct SignalPipe {
+    reader: OwnedFd,
+}
+
+/// パイプの書き込み側。 main スレッドがコマンドの送信を通知する
+///
+/// シグナルハンドラとは別に書き込み側を複製して保持するため、 uninstall した後も書き込める。
+#[derive(Debug)]
+pub struct Waker(OwnedFd);
+
+/// signals のシグナルハンドラを設定し、パイプの両端を返す
+///
+/// SIGINT を受信した場合は、 worker スレッドが読み込むのを待たずに cancel_token を中断状態にする。
+/// uninstall するまでは、 2 回目以降の呼び出しは EBUSY を返す。
+pub fn install(signals: &[Signal]) -> nix::Result<(SignalPipe, Waker)> {
+    let mut slot = WRITER.lock().unwrap();
+    if slot.is_some() {
+        return Err(Errno::EBUSY);
+    }
+
+    // exec した子プロセスには引き継がない
+    // シグナルハンドラはブロックしないよう、書き込み側のみノンブロッキングとする
+    let (reader, writer) = pipe2(OFlag::O_CLOEXEC)?;
+    fcntl(writer.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+    let waker = writer
+        .try_clone()
+        .map_err(|e| Errno::from_raw(e.raw_os_error().unwrap_or(libc::EIO)))?;
+    PENDING.store(0, Ordering::SeqCst);
+    WRITE_FD.store(writer.as_raw_fd(), Ordering::SeqCst);
+    *slot = Some(writer);
+    drop(slot);
+    CANCEL.get_or_init(CancelToken::new);
+
+    for sig in signals {
+        watch(*sig)?;
+    }
+
+    Ok((SignalPipe { reader }, Waker(waker)))
+}
+
+/// signals の処理をデフォルトに戻し、シグナルハンドラが書き込むパイプを閉じる
+/// 閉じた後は再び install できる
+pub fn uninstall(signals: &[Signal]) -> nix::Result<()> {
+    for sig in signals {
+        unwatch(*sig)?;
+    }
+    WRITE_FD.store(-1, Ordering::SeqCst);
+    WRITER.lock().unwrap().take();
+    Ok(())
+}
+
+/// SIGINT を受信した場合に中断状態になる CancelToken
+pub fn cancel_token() -> CancelToken {
+    CANCEL.get_or_init(CancelToken::new).clone()
+}
+
+/// sig を受信した場合にパイプに書き込むようにする。 install した後に追加する場合に用いる
+pub fn watch(sig: Signal) -> nix::Result<()> {
+    // SA_RESTART により、 main スレッドの読み込みなどを EINTR で失敗させない
+    let action = SigAction::new(
+        SigHandler::Handler(on_signal),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+    unsafe { sigaction(sig, &action) }.map(|_| ())
+}
+
+/// sig の処理をデフォルトに戻す
+pub fn unwatch(sig: Signal) -> nix::Result<()> {
+    let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+    unsafe { sigaction(sig, &action) }.map(|_| ())
+}
+
+/// シグナルハンドラ
+extern "C" fn on_signal(sig: libc::c_int) {
+    // write が errno を変更しても、割り込まれた処理に影響しないよう元に戻す
+    let errno = Errno::last_raw();
+
+    if sig == libc::SIGINT {
+        if let Some(cancel) = CANCEL.get() {
+            cancel.cancel();
+        }
+    }
+
+    // 未読の同じシグナルがあれば書き込まない
+    let bit = 1 << (sig - 1);
+    if PENDING.fetch_or(bit, Ordering::SeqCst) & bit == 0 {
+        let fd = WRITE_FD.load(Ordering::SeqCst);
+        let byte = sig as u8;
+        // パイプが一杯の場合は書き込めないが、未読のバイトがあるため worker スレッドは必ず起きる
+        unsafe { libc::write(fd, &byte as *const u8 as *const libc::c_void, 1) };
+    }
+
+    Errno::set_raw(errno);
+}
+
+impl SignalPipe {
+    /// 次のイベントを読み込む。イベントがなければブロックする
+    pub fn recv(&self) -> Event {
+        let mut buf = [0u8; 1];
+        loop {
+            match read(self.reader.as_raw_fd(), &mut buf) {
+                Ok(1) => (),
+                Err(Errno::EINTR) => continue,
+                result => panic!("failed to read the signal pipe: {result:?}"),
+            }
+
+            if buf[0] == WAKE {
+                return Event::Wake;
+            }
+            // 読み込んだ時点で未読を解除し、以降に受信した同じシグナルは改めて書き込ませる
+            let sig = buf[0] as i32;
+            PENDING.fetch_and(!(1 << (sig - 1)), Ordering::SeqCst);
+            if let Ok(sig) = Signal::try_from(sig) {
+                return Event::Signal(sig);
+            }
+        }
+    }
+}
+
+impl Waker {
+    /// worker スレッドを起こす。 recv は Event::Wake を返す
+    pub fn wake(&self) {
+        loop {
+            match write(&self.0, &[WAKE]) {
+                Ok(_) => return,
+                // パイプが一杯の場合は worker スレッドが読み込むまで待つ
+                Err(Errno::EINTR | Errno::EAGAIN) => std::thread::yield_now(),
+                Err(e) => panic!("failed to write the signal pipe: {e}"),
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod install {
+    use super::*;
+    use nix::{
+        sys::{
+            signal::raise,
+            wait::{waitpid, WaitStatus},
+        },
+        unistd::{fork, ForkResult},
+    };
+
+    /// 読み込み可能なイベントをすべて読み込む
+    fn drain(pipe: &SignalPipe) -> Vec<Event> {
+        let mut events = Vec::new();
+        loop {
+            let mut pfd = libc::pollfd {
+                fd: pipe.reader.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            match unsafe { libc::poll(&mut pfd, 1, 100) } {
+                1 => events.push(pipe.recv()),
+                _ => return events,
+            }
+        }
+    }
+
+    #[test]
+    fn test() {
+        let signals = [Signal::SIGUSR1, Signal::SIGUSR2, Signal::SIGCHLD];
+        let (pipe, waker) = install(&signals).unwrap();
+        assert_eq!(install(&signals).unwrap_err(), Errno::EBUSY);
+        drain(&pipe);
+
+        // 通知とシグナルは到着した順に読み込み、未読の同じシグナルは 1 つにまとめる
+        waker.wake();
+        for _ in 0..1000 {
+            raise(Signal::SIGUSR1).unwrap();
+        }
+        raise(Signal::SIGUSR2).unwrap();
+        waker.wake();
+        raise(Signal::SIGUSR1).unwrap();
+        assert_eq!(
+            drain(&pipe),
+            vec![
+                Event::Wake,
+                Event::Signal(Signal::SIGUSR1),
+                Event::Signal(Signal::SIGUSR2),
+                Event::Wake,
+            ]
+        );
+
+        // 読み込んだ後に受信したシグナルは改めて読み込める
+        raise(Signal::SIGUSR1).unwrap();
+        assert_eq!(drain(&pipe), vec![Event::Signal(Signal::SIGUSR1)]);
+
+        // 大量の子プロセスが終了しても、 SIGCHLD は 1 つにまとまる
+        let pids: Vec<_> = (0..200)
+            .map(|_| match unsafe { fork() }.unwrap() {
+                ForkResult::Child => unsafe { libc::_exit(0) },
+                ForkResult::Parent { child } => child,
+            })
+            .collect();
+        for pid in pids {
+            assert_eq!(waitpid(pid, None), Ok(WaitStatus::Exited(pid, 0)));
+        }
+        assert_eq!(drain(&pipe), vec![Event::Signal(Signal::SIGCHLD)]);
+        assert!(cancel_token().check().is_ok());
+
+        // uninstall した後は、再び install できる
+        // 未読のまま閉じたシグナルも、新しいパイプには改めて書き込む
+        raise(Signal::SIGUSR2).unwrap();
+        uninstall(&signals).unwrap();
+        drop(pipe);
+        let (pipe, waker) = install(&signals).unwrap();
+        waker.wake();
+        raise(Signal::SIGUSR2).unwrap();
+        assert_eq!(
+            drain(&pipe),
+            vec![Event::Wake, Event::Signal(Signal::SIGUSR2)]
+        );
+        uninstall(&signals).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! プロンプトの上に表示する、バックグラウンドジョブの状態の要約
+//!
+//! `set -o status` で有効にすると、 main スレッドがプロンプトを表示する直前に
+//! 実行中と停止中のジョブの数を 1 行で表示する。
+//! プロンプトの表示中にジョブの状態が変化した場合は、 worker スレッドが
+//! ANSI エスケープシーケンスでカーソル位置を保存・復元し、表示済みの行をその場で書き換える。
+//! 色を付けない端末では反転表示を行わない。
+//!
+//! バックグラウンドジョブの終了の通知も、入力中の行に割り込まないよう worker スレッドが溜めておき、
+//! main スレッドが次のプロンプトの直前に表示する。 `set -b` (`set -o notify`) を有効にした場合は
+//! 行エディタの外部出力を用いて直ちに表示し、行エディタが入力中の行を再描画する。
+//! rustyline 14 は外部出力がある間、まとめて届いた入力 (貼り付けなど) を 1 文字ずつしか処理しないため、
+//! 外部出力は `set -b` が有効な間のみ作成する。
+use crate::term::{self, Caps, Style};
+use rustyline::ExternalPrinter;
+use std::{
+    fmt,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+/// main スレッドと worker スレッドで共有するステータスライン
+pub type SharedStatus = Arc<Mutex<StatusLine>>;
+
+/// 入力中の行を壊さずに出力するための、行エディタの外部出力
+pub struct Printer(Box<dyn ExternalPrinter + Send>);
+
+impl Printer {
+    pub fn new(printer: impl ExternalPrinter + Send + 'static) -> Self {
+        Self(Box::new(printer))
+    }
+}
+
+impl fmt::Debug for Printer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Printer")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StatusLine {
+    pub enabled: bool, // ステータスラインを表示するか
+    running: usize,    // 実行中のジョブの数
+    stopped: usize,    // 停止中のジョブの数
+
+    // プロンプトの直上にステータスラインが表示されているか
+    // プロンプトの表示後に他の出力があった場合は false にし、書き換えを行わない
+    shown: bool,
+
+    notices: Vec<String>, // 次のプロンプトの直前に表示するジョブの通知
+
+    pub immediate: bool, // set -b が有効か。有効な間のみ main スレッドが外部出力を作成する
+    pub printer: Option<Printer>, // 通知を直ちに表示する場合の出力先。端末でない場合は None
+}
+
+impl StatusLine {
+    /// ステータスラインの文字列
+    fn text(&self, caps: &Caps) -> String {
+        let text = format!("[jobs] {} running, {} stopped", self.running, self.stopped);
+        caps.paint(Style::Reverse, &text)
+    }
+
+    /// 表示済みのステータスラインを書き換えるための文字列
+    ///
+    /// カーソル位置を保存し、 1 行上に移動して行を消去してから書き込み、カーソル位置を復元する
+    fn redraw(&self, caps: &Caps) -> String {
+        format!("\x1b7\x1b[1A\r\x1b[2K{}\x1b8", self.text(caps))
+    }
+
+    /// プロンプトの表示直前に main スレッドから呼び出し、溜まった通知とステータスラインを表示
+    pub fn show(&mut self) {
+        for notice in self.notices.drain(..) {
+            eprintln!("{notice}");
+        }
+        if self.enabled {
+            println!("{}", self.text(&term::caps()));
+            self.shown = true;
+        }
+    }
+
+    /// プロンプトの表示直前に main スレッドから呼び出し、 immediate に合わせて外部出力を作成・破棄する
+    pub fn sync_printer<P>(&mut self, create: impl FnOnce() -> Option<P>)
+    where
+        P: ExternalPrinter + Send + 'static,
+    {
+        if self.immediate != self.printer.is_some() {
+            self.printer = if self.immediate {
+                create().map(Printer::new)
+            } else {
+                None
+            };
+        }
+    }
+
+    /// 入力の読み込み後に main スレッドから呼び出す
+    pub fn hide(&mut self) {
+        self.shown = false;
+    }
+
+    /// 通知を追加。 worker スレッドから呼び出す
+    ///
+    /// immediate が真で外部出力がある場合は直ちに表示し、それ以外は次のプロンプトの直前に表示する。
+    pub fn notify(&mut self, notice: String, immediate: bool) {
+        if let Some(Printer(printer)) = self.printer.as_mut().filter(|_| immediate) {
+            if printer.print(format!("{notice}\n")).is_ok() {
+                // 通知はプロンプトの上に出力されるため、ステータスラインは直上にない
+                self.shown = false;
+                return;
+            }
+        }
+        self.notices.push(notice);
+    }
+
+    /// 表示していない通知
+    #[cfg(test)]
+    pub fn notices(&self) -> &[String] {
+        &self.notices
+    }
+
+    /// ジョブの数を更新
+    ///
+    /// 変化があり、かつステータスラインが表示中の場合は、その場で書き換える
+    pub fn update(&mut self, running: usize, stopped: usize) {
+        if (self.running, self.stopped) == (running, stopped) {
+            return;
+        }
+        self.running = running;
+        self.stopped = stopped;
+
+        if self.enabled && self.shown {
+            let mut out = io::stdout();
+            write!(out, "{}", self.redraw(&term::caps())).ok();
+            out.flush().ok();
+        }
+    }
+}
+#[cfg(test)]
+mod update {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let mut status = StatusLine::default();
+        status.update(2, 1);
+        let caps = Caps {
+            unicode: true,
+            color: true,
+        };
+        assert_eq!(
+            status.text(&caps),
+            "\x1b[7m[jobs] 2 running, 1 stopped\x1b[0m"
+        );
+        assert_eq!(
+            status.redraw(&caps),
+            "\x1b7\x1b[1A\r\x1b[2K\x1b[7m[jobs] 2 running, 1 stopped\x1b[0m\x1b8"
+        );
+        let caps = Caps {
+            color: false,
+            ..caps
+        };
+        assert_eq!(status.text(&caps), "[jobs] 2 running, 1 stopped");
+
+        // 無効の場合は表示しない
+        status.show();
+        assert!(!status.shown);
+
+        status.enabled = true;
+        status.show();
+        assert!(status.shown);
+        status.hide();
+        assert!(!status.shown);
+
+        // 通知は次の show で 1 度だけ表示する
+        status.notify("[1] Done\tsleep 1".to_string(), false);
+        assert_eq!(status.notices.len(), 1);
+        status.show();
+        assert!(status.notices.is_empty());
+    }
+}
+#[cfg(test)]
+mod notify {
+    use super::*;
+
+    /// 出力した文字列を記録する外部出力
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl ExternalPrinter for Recorder {
+        fn print(&mut self, msg: String) -> rustyline::Result<()> {
+            self.0.lock().unwrap().push(msg);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let mut status = StatusLine {
+            enabled: true,
+            shown: true,
+            printer: Some(Printer::new(Recorder(printed.clone()))),
+            ..StatusLine::default()
+        };
+
+        // 直ちに表示しない場合は溜めておく
+        status.notify("[0] Done\tsleep 1".to_string(), false);
+        assert_eq!(status.notices(), ["[0] Done\tsleep 1"]);
+        assert!(printed.lock().unwrap().is_empty());
+        assert!(status.shown);
+
+        // 直ちに表示した場合は、ステータスラインを書き換えない
+        status.notify("[1] Stopped\tcat".to_string(), true);
+        assert_eq!(*printed.lock().unwrap(), ["[1] Stopped\tcat\n"]);
+        assert_eq!(status.notices().len(), 1);
+        assert!(!status.shown);
+
+        // 外部出力がない場合は溜めておく
+        status.printer = None;
+        status.notify("[2] Done\tls".to_string(), true);
+        assert_eq!(status.notices().len(), 2);
+    }
+
+    #[test]
+    fn test_sync_printer() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let mut status = StatusLine::default();
+        status.sync_printer(|| Some(Recorder(printed.clone())));
+        assert!(status.printer.is_none());
+
+        status.immediate = true;
+        status.sync_printer(|| Some(Recorder(printed.clone())));
+        assert!(status.printer.is_some());
+        status.sync_printer::<Recorder>(|| panic!("作成済み"));
+
+        status.immediate = false;
+        status.sync_printer(|| Some(Recorder(printed.clone())));
+        assert!(status.printer.is_none());
+    }
+}
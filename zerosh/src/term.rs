@@ -0,0 +1,128 @@
+//! 端末の機能の判定と表示の切り替え
+//!
+//! 表示に関する判定はこのモジュールに集め、プロンプト, ステータスライン, jobs の出力,
+//! エラーメッセージから用いる。
+//!
+//! - ロケール (LC_ALL, LC_CTYPE, LANG のうち最初に設定されているもの) が UTF-8 でない場合や、
+//!   TERM が dumb か linux (コンソール) の場合は、絵文字の代わりに ASCII で表示する
+//! - NO_COLOR が空でない値に設定されている場合や、 TERM が dumb の場合は色を付けない
+//!
+//! 環境変数は export で変更できるため、表示のたびに判定する。
+use std::env;
+
+/// 端末の機能
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Caps {
+    pub unicode: bool, // 絵文字を表示できるか
+    pub color: bool,   // 色を付けるか
+}
+
+/// 文字の装飾
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Reverse, // 反転
+    Red,
+    Green,
+    Yellow,
+}
+
+impl Style {
+    /// SGR のパラメータ
+    fn sgr(&self) -> &'static str {
+        match self {
+            Style::Reverse => "7",
+            Style::Red => "31",
+            Style::Green => "32",
+            Style::Yellow => "33",
+        }
+    }
+}
+
+/// 現在の環境変数から端末の機能を判定する
+pub fn caps() -> Caps {
+    Caps::from_env(|name| env::var(name).ok())
+}
+
+impl Caps {
+    /// var で得た環境変数の値から端末の機能を判定する
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default();
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|name| var(name).filter(|v| !v.is_empty()))
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let utf8 = locale.contains("utf-8") || locale.contains("utf8");
+        Self {
+            unicode: utf8 && term != "dumb" && term != "linux",
+            color: var("NO_COLOR").is_none_or(|v| v.is_empty()) && term != "dumb",
+        }
+    }
+
+    /// 直前の終了コードに応じた顔
+    pub fn face(&self, exit_val: i32) -> &'static str {
+        match (self.unicode, exit_val == 0) {
+            (true, true) => "\u{1F642}",
+            (true, false) => "\u{1F480}",
+            (false, true) => ":)",
+            (false, false) => ":(",
+        }
+    }
+
+    /// text を style で装飾する。色を付けない場合はそのまま返す
+    pub fn paint(&self, style: Style, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{text}\x1b[0m", style.sgr())
+        } else {
+            text.to_string()
+        }
+    }
+}
+#[cfg(test)]
+mod from_env {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let caps = |vars: &[(&str, &str)]| {
+            Caps::from_env(|name| {
+                vars.iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, v)| v.to_string())
+            })
+        };
+
+        let full = caps(&[("LANG", "ja_JP.UTF-8"), ("TERM", "xterm-256color")]);
+        assert_eq!(
+            full,
+            Caps {
+                unicode: true,
+                color: true
+            }
+        );
+        assert_eq!(full.face(0), "\u{1F642}");
+        assert_eq!(full.paint(Style::Red, "x"), "\x1b[31mx\x1b[0m");
+
+        // LC_ALL が優先される
+        let c = caps(&[("LC_ALL", "C"), ("LANG", "en_US.utf8")]);
+        assert!(!c.unicode);
+        assert_eq!(c.face(1), ":(");
+        assert!(caps(&[("LC_ALL", ""), ("LANG", "en_US.utf8")]).unicode);
+
+        // コンソールでは絵文字を表示しない
+        assert!(!caps(&[("LANG", "C.UTF-8"), ("TERM", "linux")]).unicode);
+
+        // NO_COLOR や dumb では色を付けない
+        let c = caps(&[("LANG", "C.UTF-8"), ("NO_COLOR", "1")]);
+        assert!(c.unicode && !c.color);
+        assert_eq!(c.paint(Style::Red, "x"), "x");
+        assert!(caps(&[("NO_COLOR", "")]).color);
+        assert_eq!(
+            caps(&[("LANG", "C.UTF-8"), ("TERM", "dumb")]),
+            Caps {
+                unicode: false,
+                color: false
+            }
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! 配列変数の表
+//!
+//! `arr=(a b c)` で定義する配列は環境変数として扱えないため、シェルのプロセス内の表で管理する。
+//! 外部コマンドの引数は fork した子プロセスで展開するが、子プロセスは fork した時点の表の複製を持つため、
+//! 環境変数と同様に参照できる。表を更新するのは worker スレッドのみとする。
+//!
+//! 同じ名前の環境変数と配列は共存させず、一方を設定すると他方は削除する。
+//!
+//! 関数の呼び出しごとにフレームを積み、位置パラメータ `$1..$n` と `local` で上書きした変数の
+//! 元の値を保持する。位置パラメータは名前が `@` の配列として参照する。
+//! フレームを取り除くと、上書きした変数を元の値に戻す。
+//!
+//! 直前に実行したジョブの終了コードも保持し、 `$?` で参照する。
+use std::{
+    collections::BTreeMap,
+    env,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Mutex,
+    },
+};
+
+/// 配列名から要素へのマップ
+static ARRAYS: Mutex<BTreeMap<String, Vec<String>>> = Mutex::new(BTreeMap::new());
+
+/// 位置パラメータを表す配列名
+pub const POSITIONAL: &str = "@";
+
+/// local で上書きする前の変数の値
+#[derive(Debug)]
+enum Saved {
+    Unset,
+    Scalar(String),
+    Array(Vec<String>),
+}
+
+/// 関数呼び出しのフレーム
+#[derive(Debug)]
+struct Frame {
+    args: Vec<String>,           // 位置パラメータ
+    saved: Vec<(String, Saved)>, // local で上書きした変数の元の値
+}
+
+/// 呼び出し中の関数のフレーム。末尾が最も内側の呼び出し
+static FRAMES: Mutex<Vec<Frame>> = Mutex::new(Vec::new());
+
+/// 直前に実行したジョブの終了コード
+static STATUS: AtomicI32 = AtomicI32::new(0);
+
+/// 直前に実行したジョブの終了コードを設定する
+pub fn set_status(n: i32) {
+    STATUS.store(n, Ordering::Relaxed);
+}
+
+/// 直前に実行したジョブの終了コードを返す
+pub fn status() -> i32 {
+    STATUS.load(Ordering::Relaxed)
+}
+
+/// 配列を設定し、同じ名前の環境変数を削除する
+pub fn set_array(name: &str, values: Vec<String>) {
+    env::remove_var(name);
+    ARRAYS.lock().unwrap().insert(name.to_string(), values);
+}
+
+/// 配列の要素を返す。配列でない場合は None
+/// name が POSITIONAL の場合は位置パラメータを返し、関数の外では None
+pub fn array(name: &str) -> Option<Vec<String>> {
+    if name == POSITIONAL {
+        return FRAMES.lock().unwrap().last().map(|f| f.args.clone());
+    }
+    ARRAYS.lock().unwrap().get(name).cloned()
+}
+
+/// 配列を削除する。環境変数を設定する前に呼び出す
+pub fn remove_array(name: &str) {
+    ARRAYS.lock().unwrap().remove(name);
+}
+#[cfg(test)]
+mod set_array {
+    use super::*;
+
+    #[test]
+    fn test() {
+        env::set_var("ZEROSH_VARS_TEST", "x");
+        set_array("ZEROSH_VARS_TEST", vec!["a".to_string(), "b".to_string()]);
+        assert!(env::var("ZEROSH_VARS_TEST").is_err());
+        assert_eq!(
+            array("ZEROSH_VARS_TEST"),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+
+        remove_array("ZEROSH_VARS_TEST");
+        assert_eq!(array("ZEROSH_VARS_TEST"), None);
+    }
+}
+
+/// 単語の区切りに用いる変数
+pub const IFS: &str = "IFS";
+
+/// IFS が未設定の場合の区切り文字
+pub const DEFAULT_IFS: &str = " \t\n";
+
+/// IFS の値。未設定の場合は DEFAULT_IFS
+pub fn ifs() -> String {
+    scalar(IFS).unwrap_or_else(|| DEFAULT_IFS.to_string())
+}
+
+/// `$NAME` として参照する値。環境変数の値、配列の場合は最初の要素とする
+fn scalar(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .or_else(|| array(name)?.into_iter().next())
+}
+#[cfg(test)]
+mod scalar {
+    use super::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(scalar("ZEROSH_SCALAR_TEST"), None);
+        env::set_var("ZEROSH_SCALAR_TEST", ":");
+        assert_eq!(scalar("ZEROSH_SCALAR_TEST"), Some(":".to_string()));
+        set_array("ZEROSH_SCALAR_TEST", vec![",".to_string(), ";".to_string()]);
+        assert_eq!(scalar("ZEROSH_SCALAR_TEST"), Some(",".to_string()));
+        set_array("ZEROSH_SCALAR_TEST", vec![]);
+        assert_eq!(scalar("ZEROSH_SCALAR_TEST"), None);
+        remove_array("ZEROSH_SCALAR_TEST");
+    }
+}
+
+/// 関数の呼び出しを開始し、 args を位置パラメータとする
+pub fn push_frame(args: Vec<String>) {
+    FRAMES.lock().unwrap().push(Frame {
+        args,
+        saved: Vec::new(),
+    });
+}
+
+/// 呼び出し中の関数の数
+pub fn depth() -> usize {
+    FRAMES.lock().unwrap().len()
+}
+
+/// 関数の呼び出しを終了し、 local で上書きした変数を元の値に戻す
+pub fn pop_frame() {
+    let Some(frame) = FRAMES.lock().unwrap().pop() else {
+        return;
+    };
+    for (name, saved) in frame.saved.into_iter().rev() {
+        match saved {
+            Saved::Unset => {
+                env::remove_var(&name);
+                remove_array(&name);
+            }
+            Saved::Scalar(value) => {
+                remove_array(&name);
+                env::set_var(&name, value);
+            }
+            Saved::Array(values) => set_array(&name, values),
+        }
+    }
+}
+
+/// name を呼び出し中の関数のローカル変数とし、関数の終了時に元の値に戻す
+/// 関数の外では false を返す
+pub fn make_local(name: &str) -> bool {
+    let saved = match (env::var(name), array(name)) {
+        (Ok(value), _) => Saved::Scalar(value),
+        (_, Some(values)) => Saved::Array(values),
+        _ => Saved::Unset,
+    };
+    let mut frames = FRAMES.lock().unwrap();
+    let Some(frame) = frames.last_mut() else {
+        return false;
+    };
+    // 同じフレームで 2 度目以降は、最初の値を保持したままとする
+    if !frame.saved.iter().any(|(n, _)| n == name) {
+        frame.saved.push((name.to_string(), saved));
+    }
+    true
+}
+#[cfg(test)]
+mod make_local {
+    use super::*;
+
+    #[test]
+    fn test() {
+        // 関数の外
+        assert!(!make_local("ZEROSH_LOCAL_TEST_A"));
+        assert_eq!(array(POSITIONAL), None);
+
+        env::set_var("ZEROSH_LOCAL_TEST_A", "outer");
+        set_array("ZEROSH_LOCAL_TEST_B", vec!["x".to_string()]);
+        push_frame(vec!["1".to_string(), "2".to_string()]);
+        assert_eq!(depth(), 1);
+        assert_eq!(
+            array(POSITIONAL),
+            Some(vec!["1".to_string(), "2".to_string()])
+        );
+
+        assert!(make_local("ZEROSH_LOCAL_TEST_A"));
+        env::set_var("ZEROSH_LOCAL_TEST_A", "inner");
+        assert!(make_local("ZEROSH_LOCAL_TEST_A"));
+        env::set_var("ZEROSH_LOCAL_TEST_A", "inner2");
+        assert!(make_local("ZEROSH_LOCAL_TEST_B"));
+        env::set_var("ZEROSH_LOCAL_TEST_B", "scalar");
+        remove_array("ZEROSH_LOCAL_TEST_B");
+        assert!(make_local("ZEROSH_LOCAL_TEST_C"));
+        env::set_var("ZEROSH_LOCAL_TEST_C", "new");
+
+        pop_frame();
+        assert_eq!(env::var("ZEROSH_LOCAL_TEST_A").as_deref(), Ok("outer"));
+        assert!(env::var("ZEROSH_LOCAL_TEST_B").is_err());
+        assert_eq!(array("ZEROSH_LOCAL_TEST_B"), Some(vec!["x".to_string()]));
+        assert!(env::var("ZEROSH_LOCAL_TEST_C").is_err());
+        assert_eq!(array(POSITIONAL), None);
+        assert_eq!(depth(), 0);
+
+        env::remove_var("ZEROSH_LOCAL_TEST_A");
+        remove_array("ZEROSH_LOCAL_TEST_B");
+    }
+}
@@ -0,0 +1,3 @@
+# シバンのないスクリプト
+# zerosh は ENOEXEC の場合に /bin/sh で実行し直す
+echo "no shebang: $*"
@@ -0,0 +1,275 @@
+//! 疑似端末で zerosh を起動する結合テスト
+//!
+//! openpty で作成した端末を制御端末として zerosh を起動し、入力を書き込んで出力と終了コードを確認する。
+//! TERM=dumb とし、行エディタが装飾しない出力とする。プロンプトは `zerosh :) > ` の形式となり、
+//! 直前の終了コードが 0 以外の場合は `:(` となる。
+//! HOME は空の一時ディレクトリとし、設定ファイルとヒストリを読み込まない。
+use nix::{libc, pty::openpty};
+use std::{
+    env, fs,
+    fs::File,
+    io::{self, Read, Write},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// 出力や終了を待つ時間の上限
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 疑似端末で起動した zerosh
+struct Session {
+    master: File,
+    output: Arc<Mutex<String>>, // 端末に出力されたすべての文字列
+    read: usize,                // 確認済みの出力の位置
+    child: Child,
+    reader: Option<JoinHandle<()>>, // 出力を読み込むスレッド
+    home: PathBuf,
+}
+
+impl Session {
+    /// zerosh を起動する。 name は HOME とする一時ディレクトリの名前に用いる
+    fn spawn(name: &str) -> Self {
+        let home = env::temp_dir().join(format!("zerosh-pty-{name}-{}", std::process::id()));
+        fs::create_dir_all(&home).unwrap();
+
+        let pty = openpty(None, None).unwrap();
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_zerosh"));
+        cmd.env("TERM", "dumb")
+            .env("HOME", &home)
+            .env_remove("IFS")
+            .stdin(Stdio::from(pty.slave.try_clone().unwrap()))
+            .stdout(Stdio::from(pty.slave.try_clone().unwrap()))
+            .stderr(Stdio::from(pty.slave));
+        // 新しいセッションを作り、端末を制御端末とする
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = cmd.spawn().unwrap();
+        // 子プロセスの終了後に読み込みが終わるよう、端末の子側を閉じる
+        drop(cmd);
+
+        let master = File::from(pty.master);
+        let output = Arc::new(Mutex::new(String::new()));
+        let mut reader = master.try_clone().unwrap();
+        let out = output.clone();
+        let reader = thread::spawn(move || {
+            let mut buf = [0; 4096];
+            // 子プロセスが終了して端末が閉じられると EIO となる
+            while let Ok(n @ 1..) = reader.read(&mut buf) {
+                out.lock()
+                    .unwrap()
+                    .push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+        });
+
+        Self {
+            master,
+            output,
+            read: 0,
+            child,
+            reader: Some(reader),
+            home,
+        }
+    }
+
+    /// 端末に入力する
+    fn send(&mut self, input: &str) {
+        self.master.write_all(input.as_bytes()).unwrap();
+    }
+
+    /// 次のプロンプトが出力されるまで待つ
+    ///
+    /// 前回のプロンプトからの出力と、プロンプトが示す直前の終了コードが 0 かを返す。
+    /// 出力の改行は \r\n を \n にする。
+    fn prompt(&mut self) -> (String, bool) {
+        let start = Instant::now();
+        loop {
+            {
+                let out = self.output.lock().unwrap();
+                let rest = &out[self.read..];
+                let found = [("zerosh :) > ", true), ("zerosh :( > ", false)]
+                    .into_iter()
+                    .filter_map(|(p, ok)| rest.find(p).map(|i| (i, p.len(), ok)))
+                    .min();
+                if let Some((i, len, ok)) = found {
+                    let text = rest[..i].replace("\r\n", "\n");
+                    self.read += i + len;
+                    return (text, ok);
+                }
+                assert!(start.elapsed() < TIMEOUT, "no prompt: {rest:?}");
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// 1 行を入力して次のプロンプトまで待ち、入力の行を除いた出力と終了コードが 0 かを返す
+    fn run(&mut self, line: &str) -> (String, bool) {
+        self.send(&format!("{line}\n"));
+        let (text, ok) = self.prompt();
+        let text = text
+            .strip_prefix(&format!("{line}\n"))
+            .unwrap_or(&text)
+            .to_string();
+        (text, ok)
+    }
+
+    /// zerosh の終了を待ち、終了コードを返す。終了までの出力をすべて読み込む
+    fn wait(&mut self) -> Option<i32> {
+        let start = Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                if let Some(reader) = self.reader.take() {
+                    reader.join().unwrap();
+                }
+                return status.code();
+            }
+            assert!(start.elapsed() < TIMEOUT, "zerosh did not exit");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// 確認済みの位置より後の出力。出力の改行は \r\n を \n にする
+    fn rest(&self) -> String {
+        self.output.lock().unwrap()[self.read..].replace("\r\n", "\n")
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.home);
+    }
+}
+
+#[test]
+fn prompt_and_exit_code() {
+    let mut sh = Session::spawn("exit");
+    // ヒストリのファイルはまだないため、読み込みの失敗のみを表示する
+    let (out, ok) = sh.prompt();
+    assert!(out.contains("failed to load history"), "{out:?}");
+    assert!(ok);
+
+    assert_eq!(sh.run("echo hello"), ("hello\n".to_string(), true));
+    assert_eq!(sh.run("false"), (String::new(), false));
+    assert_eq!(sh.run("true && echo $?"), ("0\n".to_string(), true));
+
+    sh.send("exit 3\n");
+    assert_eq!(sh.wait(), Some(3));
+    // 終了する際に worker スレッドが異常終了しない
+    assert_eq!(sh.rest(), "exit 3\n");
+}
+
+#[test]
+fn eof_exits_with_last_status() {
+    let mut sh = Session::spawn("eof");
+    sh.prompt();
+    sh.run("false");
+
+    // Ctrl-D
+    sh.send("\x04");
+    assert_eq!(sh.wait(), Some(1));
+}
+
+#[test]
+fn syntax_error() {
+    let mut sh = Session::spawn("syntax");
+    sh.prompt();
+
+    let (out, _) = sh.run("ls | | wc");
+    assert_eq!(
+        out,
+        "zerosh: syntax error: expected a command after `|`\nls | | wc\n   ^\n"
+    );
+}
+
+#[test]
+fn job_control() {
+    let mut sh = Session::spawn("jobs");
+    sh.prompt();
+
+    // Ctrl-Z で停止する
+    sh.send("sleep 10\n");
+    thread::sleep(Duration::from_millis(300));
+    sh.send("\x1a");
+    let (out, ok) = sh.prompt();
+    assert!(out.contains("[0] Stopped\tsleep 10"), "{out:?}");
+    assert!(!ok);
+
+    let (out, _) = sh.run("jobs");
+    assert_eq!(out, "[0] Stopped\tsleep 10\n");
+
+    // ジョブが残っている場合は終了しない
+    let (out, ok) = sh.run("exit");
+    assert!(out.contains("there are some running jobs"), "{out:?}");
+    assert!(!ok);
+
+    // フォアグラウンドに戻して Ctrl-C で終了させる
+    sh.send("fg 0\n");
+    thread::sleep(Duration::from_millis(300));
+    sh.send("\x03");
+    let (_, ok) = sh.prompt();
+    assert!(!ok);
+    assert_eq!(sh.run("jobs"), (String::new(), true));
+
+    // バックグラウンドのジョブの終了を待つ
+    assert_eq!(sh.run("sleep 0.1 &"), (String::new(), true));
+    sh.run("wait");
+    let (out, _) = sh.run("jobs");
+    assert!(!out.contains("Running"), "{out:?}");
+
+    sh.send("exit\n");
+    assert_eq!(sh.wait(), Some(0));
+}
+
+#[test]
+fn long_loops() {
+    let mut sh = Session::spawn("loops");
+    sh.prompt();
+
+    // 組み込みコマンドだけのループを 10000 回繰り返してもスタックが溢れない
+    let digits = "0 1 2 3 4 5 6 7 8 9";
+    let line = format!(
+        "for a in {digits}; do for b in {digits}; do for c in {digits}; do for d in {digits}; do true; done; done; done; done; echo done"
+    );
+    let (out, ok) = sh.run(&line);
+    assert_eq!(out, "done\n");
+    assert!(ok);
+
+    // 終わらないループは Ctrl-C で中断し、同じ行の残りのジョブは実行しない
+    let line = "while true; do true; done; echo after\n";
+    sh.send(line);
+    thread::sleep(Duration::from_millis(300));
+    sh.send("\x03");
+    let (out, ok) = sh.prompt();
+    assert!(!out[line.len()..].contains("after"), "{out:?}");
+    assert!(!ok);
+    assert_eq!(sh.run("echo $?"), ("130\n".to_string(), true));
+}
+
+#[test]
+fn function_nesting() {
+    let mut sh = Session::spawn("funcnest");
+    sh.prompt();
+
+    // 終わらない再帰呼び出しは上限で打ち切り、同じ行の残りのジョブは実行しない
+    let (out, ok) = sh.run("f() { f; echo unreachable; }; f; echo after");
+    assert_eq!(
+        out,
+        "zerosh: f: maximum function nesting level exceeded (1000)\n"
+    );
+    assert!(!ok);
+
+    // 打ち切った後は呼び出しのフレームが残らない
+    assert_eq!(sh.run("echo $#"), ("0\n".to_string(), true));
+    assert_eq!(sh.run("g() { echo $1; }; g a"), ("a\n".to_string(), true));
+}
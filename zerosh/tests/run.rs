@@ -0,0 +1,21 @@
+//! 1 つのプロセスで Shell::run を続けて呼び出す結合テスト
+//!
+//! 設定ファイルの exit で終了させ、端末から読み込まずに終了コードを確認する。
+use std::{env, fs};
+use zerosh::Shell;
+
+#[test]
+fn run_twice() {
+    let dir = env::temp_dir().join(format!("zerosh-run-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let history = dir.join("history");
+
+    for (i, n) in [3, 4].into_iter().enumerate() {
+        let rcfile = dir.join(format!("rc{i}"));
+        fs::write(&rcfile, format!("trap 'true' USR1\nexit {n}\n")).unwrap();
+        let sh = Shell::new(history.to_str().unwrap(), Some(rcfile), None);
+        assert_eq!(sh.run().unwrap(), n);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}